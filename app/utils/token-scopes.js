@@ -2,6 +2,7 @@ import { htmlSafe } from '@ember/template';
 
 const DESCRIPTIONS = {
   'change-owners': 'Invite new crate owners or remove existing ones',
+  download: 'Download private crates',
   'publish-new': 'Publish new crates',
   'publish-update': 'Publish new versions of existing crates',
   yank: 'Yank and unyank crate versions',
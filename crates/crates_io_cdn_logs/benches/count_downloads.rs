@@ -1,4 +1,4 @@
-use crates_io_cdn_logs::{cloudfront, fastly};
+use crates_io_cdn_logs::{cloudfront, fastly, SourceClassifier};
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 use std::io::Cursor;
 
@@ -9,11 +9,12 @@ fn criterion_benchmark(c: &mut Criterion) {
         .unwrap();
 
     let bytes = include_bytes!("../test_data/cloudfront/basic.log");
+    let classifier = SourceClassifier::default();
     c.bench_function("cloudfront", |b| {
         // Insert a call to `to_async` to convert the bencher to async mode.
         // The timing loops are the same as with the normal bencher.
         b.to_async(&rt)
-            .iter(|| cloudfront::count_downloads(black_box(Cursor::new(bytes))));
+            .iter(|| cloudfront::count_downloads(black_box(Cursor::new(bytes)), &classifier));
     });
 
     let bytes = include_bytes!("../test_data/fastly/basic.log");
@@ -1,6 +1,6 @@
 use anyhow::Context;
 use clap::Parser;
-use crates_io_cdn_logs::{count_downloads, Decompressor};
+use crates_io_cdn_logs::{count_downloads, Decompressor, SourceClassifier};
 use std::path::PathBuf;
 use tokio::fs::File;
 use tokio::io::BufReader;
@@ -31,13 +31,14 @@ async fn main() -> anyhow::Result<()> {
         .and_then(|ext| ext.to_str())
         .unwrap_or_default();
 
+    let classifier = SourceClassifier::default();
     let downloads = match extension {
         "gz" | "zst" => {
             let decompressor = Decompressor::from_extension(reader, Some(extension))?;
             let reader = BufReader::new(decompressor);
-            count_downloads(reader).await?
+            count_downloads(reader, &classifier).await?
         }
-        _ => count_downloads(reader).await?,
+        _ => count_downloads(reader, &classifier).await?,
     };
     println!("{downloads:?}");
     println!();
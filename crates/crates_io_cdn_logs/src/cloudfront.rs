@@ -3,9 +3,9 @@
 //! see <https://docs.aws.amazon.com/AmazonCloudFront/latest/DeveloperGuide/AccessLogs.html#LogFileFormat>
 //! and <https://www.w3.org/TR/WD-logfile.html>.
 
-use crate::paths::parse_path;
-use crate::DownloadsMap;
-use chrono::NaiveDate;
+use crate::paths::{parse_path, parse_target};
+use crate::{DownloadsMap, SourceClassifier};
+use chrono::{NaiveDate, NaiveTime};
 use std::borrow::Cow;
 use tokio::io::{AsyncBufRead, AsyncBufReadExt};
 use tracing::{instrument, warn};
@@ -15,17 +15,26 @@ const HEADER_VERSION: &str = "#Version:";
 const HEADER_FIELDS: &str = "#Fields:";
 
 const FIELD_DATE: &str = "date";
+const FIELD_TIME: &str = "time";
 const FIELD_METHOD: &str = "cs-method";
 const FIELD_PATH: &str = "cs-uri-stem";
+const FIELD_QUERY: &str = "cs-uri-query";
 const FIELD_STATUS: &str = "sc-status";
+const FIELD_USER_AGENT: &str = "cs(User-Agent)";
 
 #[instrument(level = "debug", skip(reader))]
-pub async fn count_downloads(reader: impl AsyncBufRead + Unpin) -> anyhow::Result<DownloadsMap> {
+pub async fn count_downloads(
+    reader: impl AsyncBufRead + Unpin,
+    classifier: &SourceClassifier,
+) -> anyhow::Result<DownloadsMap> {
     let mut num_fields = 0;
     let mut date_index = None;
+    let mut time_index = None;
     let mut method_index = None;
     let mut path_index = None;
+    let mut query_index = None;
     let mut status_index = None;
+    let mut user_agent_index = None;
 
     let mut downloads = DownloadsMap::new();
 
@@ -44,9 +53,12 @@ pub async fn count_downloads(reader: impl AsyncBufRead + Unpin) -> anyhow::Resul
 
             num_fields = fields.len();
             date_index = fields.iter().position(|f| f == &FIELD_DATE);
+            time_index = fields.iter().position(|f| f == &FIELD_TIME);
             method_index = fields.iter().position(|f| f == &FIELD_METHOD);
             path_index = fields.iter().position(|f| f == &FIELD_PATH);
+            query_index = fields.iter().position(|f| f == &FIELD_QUERY);
             status_index = fields.iter().position(|f| f == &FIELD_STATUS);
+            user_agent_index = fields.iter().position(|f| f == &FIELD_USER_AGENT);
 
             continue;
         }
@@ -99,6 +111,28 @@ pub async fn count_downloads(reader: impl AsyncBufRead + Unpin) -> anyhow::Resul
             }
         };
 
+        // The query field is optional, so we don't use `get_value()` here to
+        // avoid warnings for log files without it.
+        let query = query_index.and_then(|i| values.get(i));
+        if let Some(target) = query.and_then(|query| parse_target(query)) {
+            downloads.add_target(name.clone(), version.clone(), target, date);
+        }
+
+        // The user agent field is optional too, and CloudFront is
+        // percent-encoding its value.
+        let user_agent = user_agent_index.and_then(|i| values.get(i));
+        let user_agent = user_agent.map(|user_agent| decode_path(user_agent));
+        if let Some(source) = user_agent.and_then(|user_agent| classifier.classify(&user_agent)) {
+            downloads.add_source(name.clone(), version.clone(), source, date);
+        }
+
+        // The time field is optional as well, and is only used for the
+        // hourly downloads.
+        let time = time_index.and_then(|i| values.get(i));
+        if let Some(time) = time.and_then(|time| time.parse::<NaiveTime>().ok()) {
+            downloads.add_hour(name.clone(), version.clone(), date.and_time(time));
+        }
+
         downloads.add(name, version, date);
     }
 
@@ -133,7 +167,7 @@ mod tests {
         let _guard = enable_tracing_output();
 
         let mut cursor = Cursor::new(include_bytes!("../test_data/cloudfront/basic.log"));
-        let downloads = assert_ok!(count_downloads(&mut cursor).await);
+        let downloads = assert_ok!(count_downloads(&mut cursor, &no_sources()).await);
 
         assert_debug_snapshot!(downloads, @r###"
         DownloadsMap {
@@ -160,6 +194,39 @@ mod tests {
         "###);
     }
 
+    #[tokio::test]
+    async fn test_hours() {
+        let _guard = enable_tracing_output();
+
+        let mut cursor = Cursor::new(include_bytes!("../test_data/cloudfront/basic.log"));
+        let mut downloads = assert_ok!(count_downloads(&mut cursor, &no_sources()).await);
+
+        let mut hours = downloads.take_hours();
+        let total: u64 = hours.iter().map(|(_, _, _, downloads)| downloads).sum();
+        assert_eq!(total, downloads.sum_downloads());
+
+        hours.retain(|(name, _, _, _)| name == "quick-error");
+        hours.sort();
+
+        let hours = hours
+            .into_iter()
+            .map(|(_, _, hour, downloads)| (hour.to_string(), downloads))
+            .collect::<Vec<_>>();
+
+        assert_debug_snapshot!(hours, @r###"
+        [
+            (
+                "2024-01-16 23:00:00",
+                2,
+            ),
+            (
+                "2024-01-17 00:00:00",
+                1,
+            ),
+        ]
+        "###);
+    }
+
     #[tokio::test]
     async fn test_percent_encoding() {
         let _guard = enable_tracing_output();
@@ -167,7 +234,7 @@ mod tests {
         let mut cursor = Cursor::new(include_bytes!(
             "../test_data/cloudfront/percent-encoding.log"
         ));
-        let downloads = assert_ok!(count_downloads(&mut cursor).await);
+        let downloads = assert_ok!(count_downloads(&mut cursor, &no_sources()).await);
 
         assert_debug_snapshot!(downloads, @r###"
         DownloadsMap {
@@ -176,6 +243,40 @@ mod tests {
         "###);
     }
 
+    #[tokio::test]
+    async fn test_targets() {
+        let _guard = enable_tracing_output();
+
+        let mut cursor = Cursor::new(include_bytes!("../test_data/cloudfront/targets.log"));
+        let downloads = assert_ok!(count_downloads(&mut cursor, &no_sources()).await);
+
+        assert_debug_snapshot!(downloads, @r###"
+        DownloadsMap {
+            2024-01-16  bindgen@0.65.1 .. 5
+            2024-01-16  bindgen@0.65.1 (aarch64-apple-darwin) .. 1
+            2024-01-16  bindgen@0.65.1 (x86_64-unknown-linux-gnu) .. 2
+        }
+        "###);
+    }
+
+    #[tokio::test]
+    async fn test_sources() {
+        let _guard = enable_tracing_output();
+
+        let mut cursor = Cursor::new(include_bytes!("../test_data/cloudfront/sources.log"));
+        let classifier = SourceClassifier::default();
+        let downloads = assert_ok!(count_downloads(&mut cursor, &classifier).await);
+
+        assert_debug_snapshot!(downloads, @r###"
+        DownloadsMap {
+            2024-01-16  bindgen@0.65.1 .. 7
+            2024-01-16  bindgen@0.65.1 [human] .. 3
+            2024-01-16  bindgen@0.65.1 [ci] .. 1
+            2024-01-16  bindgen@0.65.1 [mirror] .. 1
+        }
+        "###);
+    }
+
     #[tokio::test]
     async fn test_unrelated_traffic() {
         let _guard = enable_tracing_output();
@@ -183,7 +284,7 @@ mod tests {
         let mut cursor = Cursor::new(include_bytes!(
             "../test_data/cloudfront/unrelated-traffic.log"
         ));
-        let downloads = assert_ok!(count_downloads(&mut cursor).await);
+        let downloads = assert_ok!(count_downloads(&mut cursor, &no_sources()).await);
 
         assert_debug_snapshot!(downloads, @r###"
         DownloadsMap {
@@ -199,7 +300,7 @@ mod tests {
         let mut cursor = Cursor::new(include_bytes!(
             "../test_data/cloudfront/recoverable-errors.log"
         ));
-        let downloads = assert_ok!(count_downloads(&mut cursor).await);
+        let downloads = assert_ok!(count_downloads(&mut cursor, &no_sources()).await);
 
         assert_debug_snapshot!(downloads, @r###"
         DownloadsMap {
@@ -215,7 +316,7 @@ mod tests {
         let mut cursor = Cursor::new(include_bytes!(
             "../test_data/cloudfront/unknown-version.log"
         ));
-        let error = assert_err!(count_downloads(&mut cursor).await);
+        let error = assert_err!(count_downloads(&mut cursor, &no_sources()).await);
 
         assert_snapshot!(error, @"Unsupported version: 2.0");
     }
@@ -1,45 +1,138 @@
-use chrono::NaiveDate;
-use derive_deref::Deref;
+use crate::sources::DownloadSource;
+use chrono::{DurationRound, NaiveDate, NaiveDateTime, TimeDelta};
 use semver::Version;
 use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
+use std::ops::Deref;
 
-#[derive(Clone, Default, Deref)]
-pub struct DownloadsMap(HashMap<(String, Version, NaiveDate), u64>);
+#[derive(Clone, Default)]
+pub struct DownloadsMap {
+    downloads: HashMap<(String, Version, NaiveDate), u64>,
+    /// Downloads of the crate versions that specified a `target`, which are
+    /// counted in addition to the regular `downloads`.
+    targets: HashMap<(String, Version, String, NaiveDate), u64>,
+    /// Downloads of the crate versions that could be classified by their
+    /// [`DownloadSource`], which are counted in addition to the regular
+    /// `downloads`.
+    sources: HashMap<(String, Version, DownloadSource, NaiveDate), u64>,
+    /// Downloads of the crate versions per hour, which are counted in
+    /// addition to the regular `downloads`.
+    hours: HashMap<(String, Version, NaiveDateTime), u64>,
+}
 
 impl DownloadsMap {
     pub fn new() -> Self {
-        Self(HashMap::new())
+        Self::default()
     }
 
     /// Increments the download count for the given crate version on the given date.
     pub fn add(&mut self, name: String, version: Version, date: NaiveDate) {
-        *self.0.entry((name, version, date)).or_default() += 1;
+        *self.downloads.entry((name, version, date)).or_default() += 1;
+    }
+
+    /// Increments the download count for the given crate version and target
+    /// on the given date.
+    ///
+    /// This does not increment the regular download count, so [`add()`](Self::add)
+    /// has to be called in addition to this method.
+    pub fn add_target(&mut self, name: String, version: Version, target: String, date: NaiveDate) {
+        let key = (name, version, target, date);
+        *self.targets.entry(key).or_default() += 1;
+    }
+
+    /// Increments the download count for the given crate version and source
+    /// on the given date.
+    ///
+    /// This does not increment the regular download count, so [`add()`](Self::add)
+    /// has to be called in addition to this method.
+    pub fn add_source(
+        &mut self,
+        name: String,
+        version: Version,
+        source: DownloadSource,
+        date: NaiveDate,
+    ) {
+        let key = (name, version, source, date);
+        *self.sources.entry(key).or_default() += 1;
+    }
+
+    /// Increments the download count for the given crate version in the hour
+    /// of the given time.
+    ///
+    /// This does not increment the regular download count, so [`add()`](Self::add)
+    /// has to be called in addition to this method.
+    pub fn add_hour(&mut self, name: String, version: Version, time: NaiveDateTime) {
+        let hour = time.duration_trunc(TimeDelta::hours(1)).unwrap_or(time);
+        *self.hours.entry((name, version, hour)).or_default() += 1;
     }
 
     /// Returns a [HashSet] of all crate names in the map.
     pub fn unique_crates(&self) -> HashSet<&str> {
-        self.0.keys().map(|(krate, _, _)| krate.as_str()).collect()
+        self.downloads
+            .keys()
+            .map(|(krate, _, _)| krate.as_str())
+            .collect()
     }
 
     /// Returns the total number of downloads across all crates and versions.
     pub fn sum_downloads(&self) -> u64 {
-        self.0.values().sum()
+        self.downloads.values().sum()
+    }
+
+    /// Removes the per-target downloads from the map and returns them as a
+    /// vector of `(crate, version, target, date, downloads)` tuples.
+    pub fn take_targets(&mut self) -> Vec<(String, Version, String, NaiveDate, u64)> {
+        self.targets
+            .drain()
+            .map(|((name, version, target, date), downloads)| {
+                (name, version, target, date, downloads)
+            })
+            .collect()
+    }
+
+    /// Removes the per-source downloads from the map and returns them as a
+    /// vector of `(crate, version, source, date, downloads)` tuples.
+    pub fn take_sources(&mut self) -> Vec<(String, Version, DownloadSource, NaiveDate, u64)> {
+        self.sources
+            .drain()
+            .map(|((name, version, source, date), downloads)| {
+                (name, version, source, date, downloads)
+            })
+            .collect()
+    }
+
+    /// Removes the hourly downloads from the map and returns them as a
+    /// vector of `(crate, version, hour, downloads)` tuples.
+    pub fn take_hours(&mut self) -> Vec<(String, Version, NaiveDateTime, u64)> {
+        self.hours
+            .drain()
+            .map(|((name, version, hour), downloads)| (name, version, hour, downloads))
+            .collect()
     }
 
     /// Converts the map into a vector of `(crate, version, date, downloads)` tuples.
     pub fn into_vec(self) -> Vec<(String, Version, NaiveDate, u64)> {
-        self.0
+        self.downloads
             .into_iter()
             .map(|((name, version, date), downloads)| (name, version, date, downloads))
             .collect()
     }
 }
 
+impl Deref for DownloadsMap {
+    type Target = HashMap<(String, Version, NaiveDate), u64>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.downloads
+    }
+}
+
 impl Debug for DownloadsMap {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // The hourly downloads are not included in the output, since they are
+        // only a finer-grained copy of the regular downloads.
         let mut downloads = self
-            .0
+            .downloads
             .iter()
             .map(|((krate, version, date), downloads)| (date, krate, version, downloads))
             .collect::<Vec<_>>();
@@ -52,6 +145,42 @@ impl Debug for DownloadsMap {
             f.write_fmt(format_args!("{date}  {krate}@{version} .. {downloads}"))?;
             f.write_str("\n")?;
         }
+
+        let mut targets = self
+            .targets
+            .iter()
+            .map(|((krate, version, target, date), downloads)| {
+                (date, krate, version, target, downloads)
+            })
+            .collect::<Vec<_>>();
+
+        targets.sort();
+
+        for (date, krate, version, target, downloads) in targets {
+            f.write_str("    ")?;
+            f.write_fmt(format_args!(
+                "{date}  {krate}@{version} ({target}) .. {downloads}"
+            ))?;
+            f.write_str("\n")?;
+        }
+
+        let mut sources = self
+            .sources
+            .iter()
+            .map(|((krate, version, source, date), downloads)| {
+                (date, krate, version, source, downloads)
+            })
+            .collect::<Vec<_>>();
+
+        sources.sort();
+
+        for (date, krate, version, source, downloads) in sources {
+            f.write_str("    ")?;
+            f.write_fmt(format_args!(
+                "{date}  {krate}@{version} [{source}] .. {downloads}"
+            ))?;
+            f.write_str("\n")?;
+        }
         f.write_str("}")?;
 
         Ok(())
@@ -106,4 +235,98 @@ mod tests {
         }
         "###);
     }
+
+    #[test]
+    fn test_downloads_map_targets() {
+        let mut downloads = DownloadsMap::new();
+
+        let version = "1.0.0".parse::<Version>().unwrap();
+        let date = "2023-12-25".parse::<NaiveDate>().unwrap();
+        let target = "x86_64-unknown-linux-gnu".to_string();
+
+        add(&mut downloads, "xmas", "1.0.0", "2023-12-25");
+        add(&mut downloads, "xmas", "1.0.0", "2023-12-25");
+        downloads.add_target("xmas".into(), version.clone(), target.clone(), date);
+        assert_debug_snapshot!(downloads, @r###"
+        DownloadsMap {
+            2023-12-25  xmas@1.0.0 .. 2
+            2023-12-25  xmas@1.0.0 (x86_64-unknown-linux-gnu) .. 1
+        }
+        "###);
+
+        // Per-target downloads are not included in the totals
+        assert_eq!(downloads.sum_downloads(), 2);
+
+        let targets = downloads.take_targets();
+        assert_eq!(targets, vec![("xmas".into(), version, target, date, 1)]);
+        assert_debug_snapshot!(downloads, @r###"
+        DownloadsMap {
+            2023-12-25  xmas@1.0.0 .. 2
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_downloads_map_sources() {
+        let mut downloads = DownloadsMap::new();
+
+        let version = "1.0.0".parse::<Version>().unwrap();
+        let date = "2023-12-25".parse::<NaiveDate>().unwrap();
+        let source = DownloadSource::Ci;
+
+        add(&mut downloads, "xmas", "1.0.0", "2023-12-25");
+        add(&mut downloads, "xmas", "1.0.0", "2023-12-25");
+        downloads.add_source("xmas".into(), version.clone(), source, date);
+        assert_debug_snapshot!(downloads, @r###"
+        DownloadsMap {
+            2023-12-25  xmas@1.0.0 .. 2
+            2023-12-25  xmas@1.0.0 [ci] .. 1
+        }
+        "###);
+
+        // Per-source downloads are not included in the totals
+        assert_eq!(downloads.sum_downloads(), 2);
+
+        let sources = downloads.take_sources();
+        assert_eq!(sources, vec![("xmas".into(), version, source, date, 1)]);
+        assert_debug_snapshot!(downloads, @r###"
+        DownloadsMap {
+            2023-12-25  xmas@1.0.0 .. 2
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_downloads_map_hours() {
+        let mut downloads = DownloadsMap::new();
+
+        let version = "1.0.0".parse::<Version>().unwrap();
+        let time = |s: &str| s.parse::<NaiveDateTime>().unwrap();
+
+        add(&mut downloads, "xmas", "1.0.0", "2023-12-25");
+        add(&mut downloads, "xmas", "1.0.0", "2023-12-25");
+        add(&mut downloads, "xmas", "1.0.0", "2023-12-25");
+        downloads.add_hour("xmas".into(), version.clone(), time("2023-12-25T10:05:00"));
+        downloads.add_hour("xmas".into(), version.clone(), time("2023-12-25T10:59:59"));
+        downloads.add_hour("xmas".into(), version.clone(), time("2023-12-25T11:00:00"));
+
+        // Hourly downloads are not included in the totals
+        assert_eq!(downloads.sum_downloads(), 3);
+
+        let mut hours = downloads.take_hours();
+        hours.sort();
+        assert_eq!(
+            hours,
+            vec![
+                (
+                    "xmas".into(),
+                    version.clone(),
+                    time("2023-12-25T10:00:00"),
+                    2
+                ),
+                ("xmas".into(), version, time("2023-12-25T11:00:00"), 1),
+            ]
+        );
+        assert!(downloads.take_hours().is_empty());
+    }
 }
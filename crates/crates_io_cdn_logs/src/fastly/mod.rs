@@ -4,7 +4,7 @@
 
 mod json;
 
-use crate::paths::parse_path;
+use crate::paths::{parse_path, parse_target};
 use crate::DownloadsMap;
 use std::borrow::Cow;
 use tokio::io::{AsyncBufRead, AsyncBufReadExt};
@@ -52,8 +52,14 @@ pub async fn count_downloads(reader: impl AsyncBufRead + Unpin) -> anyhow::Resul
             continue;
         };
 
-        let date = json.date_time().date_naive();
+        let date_time = json.date_time().naive_utc();
+        let date = date_time.date();
 
+        if let Some(target) = parse_target(&url) {
+            downloads.add_target(name.clone(), version.clone(), target, date);
+        }
+
+        downloads.add_hour(name.clone(), version.clone(), date_time);
         downloads.add(name, version, date);
     }
 
@@ -124,6 +130,18 @@ mod tests {
         "###);
     }
 
+    #[tokio::test]
+    async fn test_hours() {
+        let _guard = enable_tracing_output();
+
+        let mut cursor = Cursor::new(include_bytes!("../../test_data/fastly/basic.log"));
+        let mut downloads = assert_ok!(count_downloads(&mut cursor).await);
+
+        let hours = downloads.take_hours();
+        let total: u64 = hours.iter().map(|(_, _, _, downloads)| downloads).sum();
+        assert_eq!(total, downloads.sum_downloads());
+    }
+
     #[tokio::test]
     async fn test_percent_encoding() {
         let _guard = enable_tracing_output();
@@ -140,6 +158,21 @@ mod tests {
         "###);
     }
 
+    #[tokio::test]
+    async fn test_targets() {
+        let _guard = enable_tracing_output();
+
+        let mut cursor = Cursor::new(include_bytes!("../../test_data/fastly/targets.log"));
+        let downloads = assert_ok!(count_downloads(&mut cursor).await);
+
+        assert_debug_snapshot!(downloads, @r###"
+        DownloadsMap {
+            2024-01-16  strsim@0.10.0 .. 3
+            2024-01-16  strsim@0.10.0 (x86_64-pc-windows-msvc) .. 2
+        }
+        "###);
+    }
+
     #[tokio::test]
     async fn test_unrelated_traffic() {
         let _guard = enable_tracing_output();
@@ -3,17 +3,29 @@ mod compression;
 mod download_map;
 pub mod fastly;
 mod paths;
+mod sources;
+mod targets;
 #[cfg(test)]
 mod test_utils;
 
 pub use crate::compression::Decompressor;
 pub use crate::download_map::DownloadsMap;
+pub use crate::paths::is_valid_target;
+pub use crate::sources::{DownloadSource, SourceClassifier};
+pub use crate::targets::OTHER_TARGET;
 use std::io::Cursor;
 use tokio::io::{AsyncBufRead, AsyncReadExt};
 use tracing::instrument;
 
+/// Counts the downloads in the given CloudFront or Fastly log file.
+///
+/// The `classifier` is used to count the downloads per [`DownloadSource`],
+/// if the log format includes the `User-Agent` of the requests.
 #[instrument(skip_all)]
-pub async fn count_downloads<R>(mut reader: R) -> anyhow::Result<DownloadsMap>
+pub async fn count_downloads<R>(
+    mut reader: R,
+    classifier: &SourceClassifier,
+) -> anyhow::Result<DownloadsMap>
 where
     R: AsyncBufRead + Unpin,
 {
@@ -25,7 +37,7 @@ where
             // not support it, but we can use `Cursor` to prepend the `#` back
             // onto the reader.
             let reader = Cursor::new(b"#").chain(reader);
-            cloudfront::count_downloads(reader).await
+            cloudfront::count_downloads(reader, classifier).await
         }
         // Fastly log lines start with a `<123>` field.
         b'<' => {
@@ -56,7 +68,7 @@ mod tests {
         let _guard = enable_tracing_output();
 
         let mut cursor = Cursor::new(include_bytes!("../test_data/cloudfront/basic.log"));
-        let downloads = assert_ok!(count_downloads(&mut cursor).await);
+        let downloads = assert_ok!(count_downloads(&mut cursor, &no_sources()).await);
 
         assert_debug_snapshot!(downloads, @r###"
         DownloadsMap {
@@ -92,7 +104,7 @@ mod tests {
         let decompressor = assert_ok!(Decompressor::from_extension(cursor, Some("gz")));
         let reader = tokio::io::BufReader::new(decompressor);
 
-        let downloads = assert_ok!(count_downloads(reader).await);
+        let downloads = assert_ok!(count_downloads(reader, &no_sources()).await);
 
         assert_debug_snapshot!(downloads, @r###"
         DownloadsMap {
@@ -124,7 +136,7 @@ mod tests {
         let _guard = enable_tracing_output();
 
         let mut cursor = Cursor::new(include_bytes!("../test_data/fastly/basic.log"));
-        let downloads = assert_ok!(count_downloads(&mut cursor).await);
+        let downloads = assert_ok!(count_downloads(&mut cursor, &no_sources()).await);
 
         assert_debug_snapshot!(downloads, @r###"
         DownloadsMap {
@@ -163,7 +175,7 @@ mod tests {
         let decompressor = assert_ok!(Decompressor::from_extension(cursor, Some("zst")));
         let reader = tokio::io::BufReader::new(decompressor);
 
-        let downloads = assert_ok!(count_downloads(reader).await);
+        let downloads = assert_ok!(count_downloads(reader, &no_sources()).await);
 
         assert_debug_snapshot!(downloads, @r###"
         DownloadsMap {
@@ -198,7 +210,7 @@ mod tests {
         let _guard = enable_tracing_output();
 
         let mut cursor = Cursor::new(b"foo");
-        let error = assert_err!(count_downloads(&mut cursor).await);
+        let error = assert_err!(count_downloads(&mut cursor, &no_sources()).await);
         assert_snapshot!(error, @"Failed to determine log file format. Unrecognized first byte: 102.");
     }
 }
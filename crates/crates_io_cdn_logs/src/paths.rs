@@ -1,3 +1,4 @@
+use crate::targets;
 use semver::Version;
 use tracing::instrument;
 
@@ -36,6 +37,39 @@ pub fn parse_path(mut path: &str) -> Option<(String, Version)> {
     Some((folder.to_owned(), version))
 }
 
+/// Maximum length of a `target` query parameter value.
+const MAX_TARGET_LENGTH: usize = 64;
+
+/// Parse the `target` query parameter from a download URL, URL path or
+/// query string.
+///
+/// Only values that look like target triples (ASCII alphanumeric characters,
+/// `-`, `_` and `.`) are returned, to avoid counting arbitrary garbage.
+/// Targets that are not tier 1 or tier 2 targets of the Rust compiler are
+/// returned as [`OTHER_TARGET`](targets::OTHER_TARGET).
+#[instrument(level = "debug")]
+pub fn parse_target(url: &str) -> Option<String> {
+    let query = match url.find('?') {
+        Some(pos) => &url[pos + 1..],
+        None => url,
+    };
+
+    let target = query
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("target="))?;
+
+    is_valid_target(target).then(|| targets::count_as(target).to_owned())
+}
+
+/// Checks whether the given value is acceptable as a download `target`.
+pub fn is_valid_target(target: &str) -> bool {
+    !target.is_empty()
+        && target.len() <= MAX_TARGET_LENGTH
+        && target
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -140,4 +174,24 @@ mod tests {
     fn test_parse_path_invalid_version() {
         assert_none!(parse_path("/crates/foo/foo-1.2.3§foo.crate"));
     }
+
+    #[test]
+    fn test_parse_target() {
+        let result = assert_some!(parse_target(
+            "/crates/foo/foo-1.2.3.crate?target=x86_64-unknown-linux-gnu"
+        ));
+        assert_eq!(result, "x86_64-unknown-linux-gnu");
+
+        let result = assert_some!(parse_target("param=value&target=wasm32-wasi"));
+        assert_eq!(result, "wasm32-wasi");
+
+        let result = assert_some!(parse_target("target=my-custom-target"));
+        assert_eq!(result, "other");
+
+        assert_none!(parse_target("/crates/foo/foo-1.2.3.crate"));
+        assert_none!(parse_target("-"));
+        assert_none!(parse_target("target="));
+        assert_none!(parse_target("target=foo%20bar"));
+        assert_none!(parse_target(&format!("target={}", "x".repeat(65))));
+    }
 }
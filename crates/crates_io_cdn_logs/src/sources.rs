@@ -0,0 +1,137 @@
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+/// The kind of client that a download was requested by, as derived from the
+/// `User-Agent` of the request.
+///
+/// Downloads by clients that could not be classified are not represented
+/// here, and should be treated as "unknown".
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum DownloadSource {
+    Human,
+    Ci,
+    Mirror,
+}
+
+impl DownloadSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DownloadSource::Human => "human",
+            DownloadSource::Ci => "ci",
+            DownloadSource::Mirror => "mirror",
+        }
+    }
+}
+
+impl Display for DownloadSource {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for DownloadSource {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "human" => Ok(DownloadSource::Human),
+            "ci" => Ok(DownloadSource::Ci),
+            "mirror" => Ok(DownloadSource::Mirror),
+            _ => anyhow::bail!("Unknown download source: {s:?}"),
+        }
+    }
+}
+
+/// Classifies `User-Agent` values into [`DownloadSource`]s using a list of
+/// case-insensitive substring patterns.
+///
+/// The patterns are checked in order, and the first matching pattern wins.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceClassifier {
+    patterns: Vec<(String, DownloadSource)>,
+}
+
+impl SourceClassifier {
+    pub fn new(patterns: Vec<(String, DownloadSource)>) -> Self {
+        let patterns = patterns
+            .into_iter()
+            .map(|(pattern, source)| (pattern.to_lowercase(), source))
+            .collect();
+
+        Self { patterns }
+    }
+
+    /// Returns the [`DownloadSource`] of the first pattern that is contained
+    /// in the given `User-Agent`, or `None` if no pattern matches.
+    pub fn classify(&self, user_agent: &str) -> Option<DownloadSource> {
+        let user_agent = user_agent.to_lowercase();
+        self.patterns
+            .iter()
+            .find(|(pattern, _)| user_agent.contains(pattern.as_str()))
+            .map(|(_, source)| *source)
+    }
+}
+
+impl Default for SourceClassifier {
+    fn default() -> Self {
+        // CI and mirror patterns have to come first, since these clients are
+        // often using cargo under the hood.
+        let patterns = [
+            ("github-actions", DownloadSource::Ci),
+            ("gitlab-runner", DownloadSource::Ci),
+            ("buildkite", DownloadSource::Ci),
+            ("circleci", DownloadSource::Ci),
+            ("travis", DownloadSource::Ci),
+            ("jenkins", DownloadSource::Ci),
+            ("panamax", DownloadSource::Mirror),
+            ("romt", DownloadSource::Mirror),
+            ("artifactory", DownloadSource::Mirror),
+            ("nexus", DownloadSource::Mirror),
+            ("cargo", DownloadSource::Human),
+            ("mozilla", DownloadSource::Human),
+        ];
+
+        let patterns = patterns
+            .into_iter()
+            .map(|(pattern, source)| (pattern.to_string(), source))
+            .collect();
+
+        Self::new(patterns)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use claims::{assert_none, assert_some_eq};
+
+    #[test]
+    fn test_classify() {
+        let classifier = SourceClassifier::default();
+
+        let cargo = "cargo 1.74.0 (ecb9851af 2023-10-18)";
+        assert_some_eq!(classifier.classify(cargo), DownloadSource::Human);
+
+        let browser = "Mozilla/5.0 (X11; Linux x86_64; rv:122.0) Gecko/20100101 Firefox/122.0";
+        assert_some_eq!(classifier.classify(browser), DownloadSource::Human);
+
+        let ci = "cargo 1.76.0 (c84b36747 2024-01-18) GitHub-Actions";
+        assert_some_eq!(classifier.classify(ci), DownloadSource::Ci);
+
+        let mirror = "panamax/1.0.3";
+        assert_some_eq!(classifier.classify(mirror), DownloadSource::Mirror);
+
+        assert_none!(classifier.classify("curl/8.5.0"));
+        assert_none!(classifier.classify(""));
+    }
+
+    #[test]
+    fn test_custom_patterns() {
+        let patterns = vec![("Internal-Bot".to_string(), DownloadSource::Ci)];
+        let classifier = SourceClassifier::new(patterns);
+
+        let user_agent = "internal-bot/2.0";
+        assert_some_eq!(classifier.classify(user_agent), DownloadSource::Ci);
+        assert_none!(classifier.classify("cargo 1.74.0 (ecb9851af 2023-10-18)"));
+    }
+}
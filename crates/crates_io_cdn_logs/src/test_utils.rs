@@ -1,3 +1,4 @@
+use crate::SourceClassifier;
 use tracing::dispatcher::DefaultGuard;
 use tracing::subscriber;
 use tracing_subscriber::fmt;
@@ -9,3 +10,9 @@ use tracing_subscriber::fmt;
 pub fn enable_tracing_output() -> DefaultGuard {
     subscriber::set_default(fmt().compact().with_test_writer().finish())
 }
+
+/// Returns a [SourceClassifier] without any patterns, for tests that are not
+/// interested in the per-source downloads.
+pub fn no_sources() -> SourceClassifier {
+    SourceClassifier::new(vec![])
+}
@@ -0,0 +1 @@
+alter table versions drop column yank_reason;
@@ -0,0 +1 @@
+alter table versions add column yank_reason text;
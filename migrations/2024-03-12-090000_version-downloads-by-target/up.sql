@@ -0,0 +1,18 @@
+create table version_downloads_by_target
+(
+    version_id integer           not null
+        constraint version_downloads_by_target_versions_id_fk
+            references versions
+            on delete cascade,
+    target     varchar           not null,
+    date       date              not null,
+    downloads  integer default 0 not null,
+    constraint version_downloads_by_target_pk
+        primary key (version_id, target, date)
+);
+
+comment on table version_downloads_by_target is 'Number of downloads per version, target and day, for downloads that specified a `target`.';
+comment on column version_downloads_by_target.version_id is 'Reference to the version that this row belongs to.';
+comment on column version_downloads_by_target.target is 'The target triple that was passed to the download endpoint.';
+comment on column version_downloads_by_target.date is 'The day that the downloads were counted on.';
+comment on column version_downloads_by_target.downloads is 'The number of downloads of this version for this target on this day.';
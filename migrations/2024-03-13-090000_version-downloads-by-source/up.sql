@@ -0,0 +1,18 @@
+create table version_downloads_by_source
+(
+    version_id integer           not null
+        constraint version_downloads_by_source_versions_id_fk
+            references versions
+            on delete cascade,
+    source     varchar           not null,
+    date       date              not null,
+    downloads  integer default 0 not null,
+    constraint version_downloads_by_source_pk
+        primary key (version_id, source, date)
+);
+
+comment on table version_downloads_by_source is 'Number of downloads per version, source and day, for downloads whose `User-Agent` could be classified.';
+comment on column version_downloads_by_source.version_id is 'Reference to the version that this row belongs to.';
+comment on column version_downloads_by_source.source is 'The kind of client that requested the downloads (`human`, `ci` or `mirror`).';
+comment on column version_downloads_by_source.date is 'The day that the downloads were counted on.';
+comment on column version_downloads_by_source.downloads is 'The number of downloads of this version from this source on this day.';
@@ -0,0 +1,3 @@
+alter table versions add column extra_downloads integer not null default 0;
+
+comment on column versions.extra_downloads is 'Number of downloads of the version before it was imported from another registry. These downloads are not part of `version_downloads`.';
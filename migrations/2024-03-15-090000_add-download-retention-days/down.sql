@@ -0,0 +1 @@
+alter table crates drop column download_retention_days;
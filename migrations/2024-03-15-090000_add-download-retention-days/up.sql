@@ -0,0 +1,3 @@
+alter table crates add column download_retention_days integer;
+
+comment on column crates.download_retention_days is 'Number of days of download history that are returned for the versions of this crate by default. Falls back to the server default if `NULL`.';
@@ -0,0 +1,3 @@
+drop table version_download_milestones;
+
+alter table crates drop column download_milestone_webhook_url;
@@ -0,0 +1,20 @@
+alter table crates add column download_milestone_webhook_url varchar;
+
+comment on column crates.download_milestone_webhook_url is 'Opt-in URL that receives a `POST` request whenever a version of this crate reaches a download milestone.';
+
+create table version_download_milestones
+(
+    version_id integer                 not null
+        constraint version_download_milestones_versions_id_fk
+            references versions
+            on delete cascade,
+    milestone  bigint                  not null,
+    reached_at timestamp default now() not null,
+    constraint version_download_milestones_pk
+        primary key (version_id, milestone)
+);
+
+comment on table version_download_milestones is 'Download milestones that versions of crates with a `download_milestone_webhook_url` have reached. Used to notify the webhook only once per milestone.';
+comment on column version_download_milestones.version_id is 'Reference to the version that reached the milestone.';
+comment on column version_download_milestones.milestone is 'The number of downloads of the milestone, e.g. `1000000`.';
+comment on column version_download_milestones.reached_at is 'The time at which the milestone was detected.';
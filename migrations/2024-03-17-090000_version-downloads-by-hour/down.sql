@@ -0,0 +1 @@
+drop table version_downloads_by_hour;
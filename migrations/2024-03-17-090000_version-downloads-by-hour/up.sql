@@ -0,0 +1,19 @@
+create table version_downloads_by_hour
+(
+    version_id integer           not null
+        constraint version_downloads_by_hour_versions_id_fk
+            references versions
+            on delete cascade,
+    hour       timestamp         not null,
+    downloads  integer default 0 not null,
+    constraint version_downloads_by_hour_pk
+        primary key (version_id, hour)
+);
+
+create index version_downloads_by_hour_hour_index
+    on version_downloads_by_hour (hour);
+
+comment on table version_downloads_by_hour is 'Number of downloads per version and hour. Only the last 48 hours are retained.';
+comment on column version_downloads_by_hour.version_id is 'Reference to the version that this row belongs to.';
+comment on column version_downloads_by_hour.hour is 'The start of the hour (in UTC) that the downloads were counted in.';
+comment on column version_downloads_by_hour.downloads is 'The number of downloads of this version in this hour.';
@@ -0,0 +1 @@
+alter table versions drop column deleted;
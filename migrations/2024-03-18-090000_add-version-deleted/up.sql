@@ -0,0 +1,3 @@
+alter table versions add column deleted boolean not null default false;
+
+comment on column versions.deleted is 'Whether the version was permanently deleted, e.g. for legal or security reasons. Unlike yanked versions, deleted versions can not be downloaded anymore.';
@@ -0,0 +1 @@
+drop table crate_owner_actions;
@@ -0,0 +1,29 @@
+create table crate_owner_actions
+(
+    id         serial
+        constraint crate_owner_actions_pk
+            primary key,
+    crate_id   integer                 not null
+        constraint crate_owner_actions_crates_id_fk
+            references crates
+            on delete cascade,
+    owner_id   integer                 not null,
+    owner_kind integer                 not null,
+    user_id    integer                 not null
+        constraint crate_owner_actions_users_id_fk
+            references users,
+    action     integer                 not null,
+    time       timestamp default now() not null
+);
+
+create index crate_owner_actions_crate_id_index
+    on crate_owner_actions (crate_id);
+
+comment on table crate_owner_actions is 'Audit log of the owners that were added to or removed from crates.';
+comment on column crate_owner_actions.id is 'Unique identifier of the action.';
+comment on column crate_owner_actions.crate_id is 'Reference to the crate whose owners were modified.';
+comment on column crate_owner_actions.owner_id is 'Reference to the user or team that was added or removed, depending on `owner_kind`.';
+comment on column crate_owner_actions.owner_kind is 'Whether the owner is a user (`0`) or a team (`1`).';
+comment on column crate_owner_actions.user_id is 'Reference to the user that performed the action.';
+comment on column crate_owner_actions.action is 'Whether the owner was added (`0`) or removed (`1`).';
+comment on column crate_owner_actions.time is 'Time at which the action was performed.';
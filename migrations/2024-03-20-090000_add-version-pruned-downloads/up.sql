@@ -0,0 +1,3 @@
+alter table versions add column pruned_downloads integer not null default 0;
+
+comment on column versions.pruned_downloads is 'Number of downloads of the version that were pruned from `version_downloads` after their retention period.';
@@ -0,0 +1,3 @@
+alter table versions add column edition varchar;
+
+comment on column versions.edition is 'The Rust edition of the version, as declared in its `Cargo.toml` file, or `2015` if none was declared. `NULL` for versions that were published before the edition was recorded.';
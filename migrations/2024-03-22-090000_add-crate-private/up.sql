@@ -0,0 +1,3 @@
+alter table crates add column private boolean not null default false;
+
+comment on column crates.private is 'Whether downloads of this crate require an API token with the `download` scope, e.g. for private crates of a self-hosted registry.';
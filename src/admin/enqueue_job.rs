@@ -23,6 +23,11 @@ pub enum Command {
         target_name: String,
     },
     DailyDbMaintenance,
+    PruneVersionDownloads {
+        /// Delete the `version_downloads` rows older than this number of days
+        #[arg(long, default_value_t = jobs::DEFAULT_DOWNLOADS_RETENTION_DAYS)]
+        older_than_days: i32,
+    },
     SquashIndex,
     NormalizeIndex {
         #[arg(long = "dry-run")]
@@ -94,6 +99,16 @@ pub fn run(command: Command) -> Result<()> {
         Command::DailyDbMaintenance => {
             jobs::DailyDbMaintenance.enqueue(conn)?;
         }
+        Command::PruneVersionDownloads { older_than_days } => {
+            if older_than_days < jobs::DEFAULT_DOWNLOADS_RETENTION_DAYS {
+                anyhow::bail!(
+                    "cannot prune downloads that are younger than {} days, since they are still served by the API",
+                    jobs::DEFAULT_DOWNLOADS_RETENTION_DAYS
+                );
+            }
+
+            jobs::PruneVersionDownloads::new(older_than_days).enqueue(conn)?;
+        }
         Command::ProcessCdnLogQueue(job) => {
             job.enqueue(conn)?;
         }
@@ -2,21 +2,27 @@
 
 use crate::config;
 use crate::db::{connection_url, ConnectionConfig, DieselPool, DieselPooledConn, PoolError};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
 use std::ops::Deref;
 use std::sync::atomic::AtomicUsize;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
+use crate::download_events::{DownloadEvents, StdoutSink};
 use crate::email::Emails;
 use crate::metrics::{InstanceMetrics, ServiceMetrics};
-use crate::rate_limiter::RateLimiter;
+use crate::rate_limiter::{IpRateLimiter, RateLimiter};
 use crate::storage::Storage;
 use axum::extract::{FromRef, FromRequestParts, State};
+use chrono::{DateTime, Utc};
 use crates_io_github::GitHubClient;
 use deadpool_diesel::postgres::{Manager as DeadpoolManager, Pool as DeadpoolPool};
 use deadpool_diesel::Runtime;
 use diesel::r2d2;
 use oauth2::basic::BasicClient;
 use scheduled_thread_pool::ScheduledThreadPool;
+use uuid::Uuid;
 
 type DeadpoolResult = Result<deadpool_diesel::postgres::Connection, deadpool_diesel::PoolError>;
 
@@ -63,6 +69,26 @@ pub struct App {
 
     /// Rate limit select actions.
     pub rate_limiter: RateLimiter,
+
+    /// Per-IP rate limit of the download endpoint.
+    pub download_rate_limiter: IpRateLimiter,
+
+    /// Cached crate download rankings for the `downloads/rank` endpoint.
+    pub download_rank_cache: DownloadStatsCache<DownloadRanking>,
+
+    /// Cached download sums per edition for the `downloads/by_edition`
+    /// endpoint.
+    pub downloads_by_edition_cache: DownloadStatsCache<EditionDownloads>,
+
+    /// Channel of raw download events for internal analytics.
+    pub download_events: DownloadEvents,
+
+    /// Recently seen `X-Download-Id` values of the download endpoint.
+    pub recent_download_ids: RecentDownloadIds,
+
+    /// Cached time of the latest persisted download data, which is polled by
+    /// the `downloads/watch` endpoint.
+    pub last_persisted_at: LastPersistedAtCache,
 }
 
 impl App {
@@ -199,6 +225,15 @@ impl App {
             instance_metrics,
             balance_capacity: Default::default(),
             rate_limiter: RateLimiter::new(config.rate_limiter.clone()),
+            download_rate_limiter: IpRateLimiter::new(config.download_rate_limit),
+            download_rank_cache: Default::default(),
+            downloads_by_edition_cache: Default::default(),
+            recent_download_ids: Default::default(),
+            last_persisted_at: Default::default(),
+            download_events: match config.download_events {
+                true => DownloadEvents::new(StdoutSink),
+                false => DownloadEvents::disabled(),
+            },
             config: Arc::new(config),
         }
     }
@@ -347,6 +382,152 @@ pub struct BalanceCapacityState {
     pub in_flight_non_dl_requests: AtomicUsize,
 }
 
+/// The summed downloads of all crates that were downloaded within a window of
+/// days, ordered from the most to the least downloaded crate.
+#[derive(Debug)]
+pub struct DownloadRanking {
+    /// The total number of crates, including the ones without downloads in
+    /// the window.
+    pub total_crates: i64,
+    /// Pairs of crate ids and their downloads within the window.
+    pub downloads: Vec<(i32, i64)>,
+}
+
+/// The downloads of all crates within a window, summed by the edition of the
+/// downloaded versions. Versions without a recorded edition are summed as
+/// `None`.
+pub type EditionDownloads = Vec<(Option<String>, i64)>;
+
+/// A short-lived cache of download stats across all crates, like
+/// [`DownloadRanking`]s, keyed by the size of their window in days.
+#[derive(Debug)]
+pub struct DownloadStatsCache<T> {
+    stats: Mutex<HashMap<i64, (Instant, Arc<T>)>>,
+}
+
+impl<T> Default for DownloadStatsCache<T> {
+    fn default() -> Self {
+        let stats = Mutex::new(HashMap::new());
+        Self { stats }
+    }
+}
+
+impl<T> DownloadStatsCache<T> {
+    /// Returns the cached stats for the given window, or calculates and
+    /// caches new stats if there are none or they are older than `ttl`.
+    ///
+    /// The cache is locked while the stats are calculated, so that
+    /// concurrent requests don't calculate the same stats multiple times.
+    pub fn get_or_try_insert_with<E>(
+        &self,
+        window: i64,
+        ttl: Duration,
+        calculate: impl FnOnce() -> Result<T, E>,
+    ) -> Result<Arc<T>, E> {
+        let mut stats = self.stats.lock().unwrap_or_else(|e| e.into_inner());
+
+        if let Some((calculated_at, cached)) = stats.get(&window) {
+            if calculated_at.elapsed() < ttl {
+                return Ok(cached.clone());
+            }
+        }
+
+        let calculated = Arc::new(calculate()?);
+        stats.insert(window, (Instant::now(), calculated.clone()));
+        Ok(calculated)
+    }
+}
+
+/// A short-lived cache of the time at which download data was last persisted.
+#[derive(Debug, Default)]
+pub struct LastPersistedAtCache {
+    cached: Mutex<Option<(Instant, Option<DateTime<Utc>>)>>,
+}
+
+impl LastPersistedAtCache {
+    /// Returns the cached time, or queries and caches it again if it was
+    /// queried more than `ttl` ago.
+    ///
+    /// The cache is locked while the time is queried, so that concurrent
+    /// requests share a single query.
+    pub fn get_or_try_insert_with<E>(
+        &self,
+        ttl: Duration,
+        query: impl FnOnce() -> Result<Option<DateTime<Utc>>, E>,
+    ) -> Result<Option<DateTime<Utc>>, E> {
+        let mut cached = self.cached.lock().unwrap_or_else(|e| e.into_inner());
+
+        if let Some((queried_at, time)) = *cached {
+            if queried_at.elapsed() < ttl {
+                return Ok(time);
+            }
+        }
+
+        let time = query()?;
+        *cached = Some((Instant::now(), time));
+        Ok(time)
+    }
+}
+
+/// Maximum number of download ids that [`RecentDownloadIds`] keeps in
+/// memory. When this limit is reached, new ids are not recorded until the
+/// expired ones have been evicted.
+const MAX_RECENT_DOWNLOAD_IDS: usize = 100_000;
+
+/// The client-supplied ids of recent downloads, which allow retried
+/// downloads to be counted only once.
+#[derive(Debug, Default)]
+pub struct RecentDownloadIds {
+    inner: Mutex<RecentDownloadIdsInner>,
+}
+
+#[derive(Debug, Default)]
+struct RecentDownloadIdsInner {
+    ids: HashMap<Uuid, Instant>,
+    /// When expired ids were last evicted from `ids`.
+    evicted_at: Option<Instant>,
+}
+
+impl RecentDownloadIds {
+    /// Records the given download id, and returns `false` if it was already
+    /// recorded within the last `ttl`.
+    ///
+    /// If [`MAX_RECENT_DOWNLOAD_IDS`] ids are recorded already, `true` is
+    /// returned without recording the id, so that retries of such downloads
+    /// are counted again.
+    pub fn insert(&self, id: Uuid, ttl: Duration) -> bool {
+        self.insert_at(id, ttl, Instant::now())
+    }
+
+    fn insert_at(&self, id: Uuid, ttl: Duration, now: Instant) -> bool {
+        let is_recent = |seen_at: &Instant| now.saturating_duration_since(*seen_at) < ttl;
+
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+
+        // Expired ids are evicted at most once per `ttl`, so that inserts
+        // don't have to scan all ids
+        let evicted_at = inner.evicted_at.get_or_insert(now);
+        if now.saturating_duration_since(*evicted_at) >= ttl {
+            *evicted_at = now;
+            inner.ids.retain(|_, seen_at| is_recent(seen_at));
+        }
+
+        let full = inner.ids.len() >= MAX_RECENT_DOWNLOAD_IDS;
+        match inner.ids.entry(id) {
+            Entry::Occupied(entry) if is_recent(entry.get()) => false,
+            Entry::Occupied(mut entry) => {
+                entry.insert(now);
+                true
+            }
+            Entry::Vacant(_) if full => true,
+            Entry::Vacant(entry) => {
+                entry.insert(now);
+                true
+            }
+        }
+    }
+}
+
 #[derive(Clone, FromRequestParts)]
 #[from_request(via(State))]
 pub struct AppState(pub Arc<App>);
@@ -365,3 +546,64 @@ impl FromRef<AppState> for cookie::Key {
         app.session_key().clone()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn last_persisted_at_is_cached() {
+        let cache = LastPersistedAtCache::default();
+        let time = Some(Utc::now());
+        let query = || Ok::<_, ()>(time);
+
+        assert_eq!(cache.get_or_try_insert_with(Duration::MAX, query), Ok(time));
+        let cached = cache.get_or_try_insert_with(Duration::MAX, || Ok::<_, ()>(None));
+        assert_eq!(cached, Ok(time));
+
+        // Outdated times are queried again
+        let queried = cache.get_or_try_insert_with(Duration::ZERO, || Ok::<_, ()>(None));
+        assert_eq!(queried, Ok(None));
+    }
+
+    #[test]
+    fn recent_download_ids_expire() {
+        let ids = RecentDownloadIds::default();
+        let ttl = Duration::from_secs(60);
+        let id = Uuid::from_u128(1);
+        let other_id = Uuid::from_u128(2);
+        let now = Instant::now();
+
+        assert!(ids.insert_at(id, ttl, now));
+        assert!(!ids.insert_at(id, ttl, now + Duration::from_secs(30)));
+
+        // Other ids are recorded separately
+        assert!(ids.insert_at(other_id, ttl, now + Duration::from_secs(30)));
+
+        // Retries after the `ttl` are counted again
+        assert!(ids.insert_at(id, ttl, now + ttl));
+        assert!(!ids.insert_at(id, ttl, now + ttl));
+    }
+
+    #[test]
+    fn recent_download_ids_are_limited() {
+        let ids = RecentDownloadIds::default();
+        let ttl = Duration::from_secs(60);
+        let now = Instant::now();
+
+        for id in 0..MAX_RECENT_DOWNLOAD_IDS as u128 {
+            assert!(ids.insert_at(Uuid::from_u128(id), ttl, now));
+        }
+
+        // Ids beyond the limit are not recorded
+        let id = Uuid::from_u128(u128::MAX);
+        assert!(ids.insert_at(id, ttl, now));
+        assert!(ids.insert_at(id, ttl, now));
+
+        // ... until the expired ids have been evicted
+        let later = now + ttl;
+        assert!(ids.insert_at(id, ttl, later));
+        assert!(!ids.insert_at(id, ttl, later));
+        assert_eq!(ids.inner.lock().unwrap().ids.len(), 1);
+    }
+}
@@ -100,6 +100,7 @@ fn main() -> anyhow::Result<()> {
         .deadpool(deadpool)
         .emails(emails)
         .team_repo(Box::new(team_repo))
+        .http_client(client)
         .build()?;
 
     let environment = Arc::new(environment);
@@ -12,4 +12,4 @@ pub use self::cdn_log_queue::CdnLogQueueConfig;
 pub use self::cdn_log_storage::CdnLogStorageConfig;
 pub use self::database_pools::{DatabasePools, DbPoolConfig};
 pub use self::sentry::SentryConfig;
-pub use self::server::Server;
+pub use self::server::{AllowedOrigins, Server};
@@ -12,6 +12,7 @@ use crate::config::cdn_log_storage::CdnLogStorageConfig;
 use crate::config::CdnLogQueueConfig;
 use crate::middleware::cargo_compat::StatusCodeConfig;
 use crate::storage::StorageConfig;
+use crates_io_cdn_logs::{DownloadSource, SourceClassifier};
 use crates_io_env_vars::{list, list_parsed, required_var, var, var_parsed};
 use http::HeaderValue;
 use std::collections::{HashMap, HashSet};
@@ -63,6 +64,53 @@ pub struct Server {
     pub version_id_cache_size: u64,
     pub version_id_cache_ttl: Duration,
     pub cdn_user_agent: String,
+    /// Alternate base URLs for crate downloads, keyed by mirror id.
+    pub download_mirrors: HashMap<String, String>,
+    /// Externally reachable base URL that relative crate download locations
+    /// are joined with, e.g. when running behind a reverse proxy.
+    pub download_base_url: Option<String>,
+    /// Patterns that are used to classify the `User-Agent` of downloads in
+    /// the CDN logs.
+    pub download_source_classifier: SourceClassifier,
+    /// Whether the download endpoint responds with `307 Temporary Redirect`
+    /// instead of `302 Found`, so that clients keep the request method.
+    pub download_redirect_307: bool,
+    /// Whether the download endpoint serves the crate files of local storage
+    /// backends itself instead of redirecting to them. Redirects are still
+    /// used for S3.
+    pub proxy_download: bool,
+    /// Per-IP rate limit of the download endpoint. Requests that are
+    /// authenticated with an API token are exempt.
+    pub download_rate_limit: Option<RateLimiterConfig>,
+    /// Download stats queries that take longer than this are logged with a
+    /// warning.
+    pub slow_downloads_query_threshold: Duration,
+    /// How long the `downloads/watch` endpoint waits for new download data
+    /// before responding with `204 No Content`.
+    pub downloads_watch_timeout: Duration,
+    /// How long the `X-Download-Id` of a download is remembered, so that
+    /// retries with the same id are not counted again.
+    pub download_id_ttl: Duration,
+    /// Whether redirect responses of the download endpoint look up the version
+    /// in the database, to include the `X-Crate-Checksum` header and to
+    /// respond with `410 Gone` for deleted versions. This adds a database
+    /// query to every download.
+    pub download_version_lookup: bool,
+    /// Whether the crate files of crates flagged as `private` can only be
+    /// downloaded by their owners, with an API token with the `download`
+    /// scope. This adds a database query to every download.
+    ///
+    /// Only the crate files are protected. The metadata, versions and
+    /// download stats of private crates are still public, as are their index
+    /// entries and their rows in the database dump.
+    pub private_crate_files: bool,
+    /// Whether raw download events are written to stdout as JSON lines, for
+    /// internal analytics.
+    pub download_events: bool,
+    /// Origins that browsers may fetch the read-only download stats
+    /// endpoints from. Requests from other origins are limited to the same
+    /// origin.
+    pub downloads_cors_origins: AllowedOrigins,
     pub balance_capacity: BalanceCapacityConfig,
 
     /// Instructs the `cargo_compat` middleware whether to adjust response
@@ -111,6 +159,33 @@ impl Server {
     ///   endpoint even with a healthy database pool.
     /// - `BLOCKED_ROUTES`: A comma separated list of HTTP route patterns that are manually blocked
     ///   by an operator (e.g. `/crates/:crate_id/:version/download`).
+    /// - `DOWNLOAD_MIRRORS`: A comma separated list of `ID=BASE_URL` pairs. The download endpoint
+    ///   redirects to `BASE_URL` instead of the default CDN when `?mirror=ID` is passed.
+    /// - `DOWNLOAD_BASE_URL`: If set, relative download locations of the storage backend are
+    ///   turned into absolute URLs starting with this base URL.
+    /// - `DOWNLOAD_REDIRECT_307`: If set, the download endpoint redirects with
+    ///   `307 Temporary Redirect` instead of `302 Found`.
+    /// - `DOWNLOAD_VERSION_LOOKUP`: If set, download redirects include the `X-Crate-Checksum`
+    ///   header and deleted versions respond with `410 Gone`, at the cost of a database query.
+    /// - `DOWNLOAD_RATE_LIMIT_PER_MINUTE`: The number of download requests per minute that
+    ///   each client IP address is allowed to make. If not set, downloads are not rate limited.
+    /// - `DOWNLOAD_RATE_LIMIT_BURST`: The number of download requests that can be made in a
+    ///   burst before the rate limit applies. Defaults to `DOWNLOAD_RATE_LIMIT_PER_MINUTE`.
+    /// - `DOWNLOADS_WATCH_TIMEOUT_SECONDS`: How long the `downloads/watch` endpoint waits for new
+    ///   download data. Defaults to 30 seconds.
+    /// - `PROXY_DOWNLOAD`: If set, the download endpoint serves the crate files of local storage
+    ///   backends itself instead of redirecting to them.
+    /// - `SLOW_DOWNLOADS_QUERY_THRESHOLD_MS`: Download stats queries that take longer than this
+    ///   are logged with a warning. Defaults to 1000 ms.
+    /// - `DOWNLOAD_ID_TTL_SECONDS`: How long the `X-Download-Id` of a download is remembered, so
+    ///   that retries are not counted again. Defaults to 60 seconds.
+    /// - `PRIVATE_CRATE_FILES`: If set, the crate files of private crates can only be downloaded
+    ///   by their owners with an API token that has the `download` endpoint scope.
+    /// - `DOWNLOAD_EVENTS`: If set, raw download events are written to stdout as JSON lines.
+    /// - `DOWNLOAD_SOURCE_PATTERNS`: A comma separated list of `SOURCE=PATTERN` pairs that are used
+    ///   to classify the `User-Agent` of downloads in the CDN logs.
+    /// - `DOWNLOADS_CORS_ALLOWED_ORIGINS`: A comma separated list of origins that browsers may
+    ///   fetch the read-only download stats endpoints from.
     ///
     /// # Panics
     ///
@@ -139,6 +214,16 @@ impl Server {
 
         let max_blocking_threads = var_parsed("SERVER_THREADS")?;
 
+        let download_mirrors =
+            HashMap::from_iter(list_parsed("DOWNLOAD_MIRRORS", parse_download_mirror)?);
+
+        let download_source_patterns =
+            list_parsed("DOWNLOAD_SOURCE_PATTERNS", parse_download_source_pattern)?;
+        let download_source_classifier = match download_source_patterns.is_empty() {
+            true => SourceClassifier::default(),
+            false => SourceClassifier::new(download_source_patterns),
+        };
+
         // Dynamically load the configuration for all the rate limiting actions. See
         // `src/rate_limiter.rs` for their definition.
         let mut rate_limiter = HashMap::new();
@@ -157,6 +242,17 @@ impl Server {
             );
         }
 
+        let download_rate_limit = var_parsed::<u32>("DOWNLOAD_RATE_LIMIT_PER_MINUTE")?
+            .filter(|per_minute| *per_minute > 0)
+            .map(|per_minute| -> anyhow::Result<_> {
+                let default_burst = i32::try_from(per_minute).unwrap_or(i32::MAX);
+                Ok(RateLimiterConfig {
+                    rate: Duration::from_secs(60) / per_minute,
+                    burst: var_parsed("DOWNLOAD_RATE_LIMIT_BURST")?.unwrap_or(default_burst),
+                })
+            })
+            .transpose()?;
+
         let storage = StorageConfig::from_environment();
 
         // `sha256-dbf9FMl76C7BnK1CC3eWb3pvsQAUaTYSHAlBy9tNTG0=` refers to
@@ -212,6 +308,26 @@ impl Server {
             ),
             cdn_user_agent: var("WEB_CDN_USER_AGENT")?
                 .unwrap_or_else(|| "Amazon CloudFront".into()),
+            download_mirrors,
+            download_base_url: var("DOWNLOAD_BASE_URL")?
+                .map(|base_url| base_url.trim_end_matches('/').to_string()),
+            download_source_classifier,
+            download_redirect_307: var("DOWNLOAD_REDIRECT_307")?.is_some(),
+            proxy_download: var("PROXY_DOWNLOAD")?.is_some(),
+            download_rate_limit,
+            slow_downloads_query_threshold: var_parsed("SLOW_DOWNLOADS_QUERY_THRESHOLD_MS")?
+                .map(Duration::from_millis)
+                .unwrap_or(Duration::from_secs(1)),
+            downloads_watch_timeout: var_parsed("DOWNLOADS_WATCH_TIMEOUT_SECONDS")?
+                .map(Duration::from_secs)
+                .unwrap_or(Duration::from_secs(30)),
+            download_id_ttl: var_parsed("DOWNLOAD_ID_TTL_SECONDS")?
+                .map(Duration::from_secs)
+                .unwrap_or(Duration::from_secs(60)),
+            download_version_lookup: var("DOWNLOAD_VERSION_LOOKUP")?.is_some(),
+            private_crate_files: var("PRIVATE_CRATE_FILES")?.is_some(),
+            download_events: var("DOWNLOAD_EVENTS")?.is_some(),
+            downloads_cors_origins: AllowedOrigins::new(list("DOWNLOADS_CORS_ALLOWED_ORIGINS")?),
             balance_capacity: BalanceCapacityConfig::from_environment()?,
             cargo_compat_status_code_config: var_parsed("CARGO_COMPAT_STATUS_CODES")?
                 .unwrap_or(StatusCodeConfig::AdjustAll),
@@ -254,6 +370,31 @@ fn parse_cidr_block(block: &str) -> anyhow::Result<IpNetwork> {
     Ok(cidr)
 }
 
+/// Parses a `ID=BASE_URL` pair of the `DOWNLOAD_MIRRORS` environment variable.
+fn parse_download_mirror(mirror: &str) -> anyhow::Result<(String, String)> {
+    match mirror.split_once('=') {
+        Some((id, base_url)) if !id.is_empty() && !base_url.is_empty() => {
+            Ok((id.to_string(), base_url.trim_end_matches('/').to_string()))
+        }
+        _ => Err(anyhow!(
+            "DOWNLOAD_MIRRORS must be in the form ID=BASE_URL, got invalid mirror {mirror}"
+        )),
+    }
+}
+
+/// Parses a `SOURCE=PATTERN` pair of the `DOWNLOAD_SOURCE_PATTERNS`
+/// environment variable.
+fn parse_download_source_pattern(pattern: &str) -> anyhow::Result<(String, DownloadSource)> {
+    match pattern.split_once('=') {
+        Some((source, pattern)) if !pattern.is_empty() => {
+            Ok((pattern.to_string(), source.parse()?))
+        }
+        _ => Err(anyhow!(
+            "DOWNLOAD_SOURCE_PATTERNS must be in the form SOURCE=PATTERN, got invalid pattern {pattern}"
+        )),
+    }
+}
+
 fn blocked_traffic() -> Vec<(String, Vec<String>)> {
     let pattern_list = dotenvy::var("BLOCKED_TRAFFIC").unwrap_or_default();
     parse_traffic_patterns(&pattern_list)
@@ -280,6 +421,10 @@ fn parse_traffic_patterns(patterns: &str) -> impl Iterator<Item = (&str, &str)>
 pub struct AllowedOrigins(Vec<String>);
 
 impl AllowedOrigins {
+    pub fn new(origins: Vec<String>) -> Self {
+        Self(origins)
+    }
+
     pub fn from_default_env() -> anyhow::Result<Self> {
         let allowed_origins = required_var("WEB_ALLOWED_ORIGINS")?
             .split(',')
@@ -313,6 +458,31 @@ mod tests {
         assert_none!(parse_traffic_patterns(pattern_string_3).next());
     }
 
+    #[test]
+    fn parse_download_mirror_splits_on_equal_sign() {
+        assert_ok_eq!(
+            parse_download_mirror("internal=https://mirror.example.com/"),
+            (
+                "internal".to_string(),
+                "https://mirror.example.com".to_string()
+            )
+        );
+        assert_err!(parse_download_mirror("internal"));
+        assert_err!(parse_download_mirror("=https://mirror.example.com"));
+        assert_err!(parse_download_mirror("internal="));
+    }
+
+    #[test]
+    fn parse_download_source_pattern_splits_on_equal_sign() {
+        assert_ok_eq!(
+            parse_download_source_pattern("ci=github-actions"),
+            ("github-actions".to_string(), DownloadSource::Ci)
+        );
+        assert_err!(parse_download_source_pattern("ci"));
+        assert_err!(parse_download_source_pattern("ci="));
+        assert_err!(parse_download_source_pattern("robot=github-actions"));
+    }
+
     #[test]
     fn parse_cidr_block_list_successfully() {
         assert_ok_eq!(
@@ -29,6 +29,12 @@ mod prelude {
         (StatusCode::FOUND, [(header::LOCATION, url)]).into_response()
     }
 
+    /// Like [`redirect`], but responds with `307 Temporary Redirect`, which
+    /// requires clients to keep the request method when following it.
+    pub fn temporary_redirect(url: String) -> Response {
+        (StatusCode::TEMPORARY_REDIRECT, [(header::LOCATION, url)]).into_response()
+    }
+
     pub trait RequestUtils {
         fn query(&self) -> IndexMap<String, String>;
         fn wants_json(&self) -> bool;
@@ -70,6 +76,7 @@ pub mod github;
 pub mod keyword;
 pub mod krate;
 pub mod metrics;
+pub mod openapi;
 pub mod site_metadata;
 pub mod summary;
 pub mod team;
@@ -1,9 +1,17 @@
 use super::helpers::pagination::*;
+use super::helpers::window::{parse_window, DEFAULT_WINDOW_DAYS, MAX_WINDOW_DAYS};
 use super::prelude::*;
 
 use crate::models::Category;
-use crate::schema::categories;
+use crate::schema::{categories, crates, crates_categories, version_downloads, versions};
 use crate::views::{EncodableCategory, EncodableCategoryWithSubcategories};
+use chrono::{Duration, Utc};
+use diesel::dsl::sql;
+use diesel::sql_types::{BigInt, Nullable};
+
+/// Maximum number of crates listed in the `top_crates` of the
+/// `GET /categories/:category_id/downloads` endpoint.
+const MAX_TOP_CRATES: i64 = 25;
 
 /// Handles the `GET /categories` route.
 pub async fn index(app: AppState, req: Parts) -> AppResult<Json<Value>> {
@@ -67,6 +75,62 @@ pub async fn show(state: AppState, Path(slug): Path<String>) -> AppResult<Json<V
     .await
 }
 
+/// Handles the `GET /categories/:category_id/downloads` route.
+///
+/// Sums the downloads within the last `window` days (including today) of all
+/// crates that are directly assigned to the category, and lists the crates
+/// that contributed the most downloads.
+pub async fn downloads(
+    state: AppState,
+    Path(slug): Path<String>,
+    req: Parts,
+) -> AppResult<Json<Value>> {
+    spawn_blocking(move || {
+        let window = match req.query().get("window") {
+            Some(window) => parse_window(window, MAX_WINDOW_DAYS)?,
+            None => DEFAULT_WINDOW_DAYS,
+        };
+
+        let conn = &mut *state.db_read()?;
+        let category: Category = Category::by_slug(&slug).first(conn)?;
+
+        let today = Utc::now().date_naive();
+        let start = today - Duration::days(window - 1);
+
+        let category_downloads = crates_categories::table
+            .inner_join(
+                crates::table.inner_join(versions::table.inner_join(version_downloads::table)),
+            )
+            .filter(crates_categories::category_id.eq(category.id))
+            .filter(version_downloads::date.between(start, today));
+
+        let total_downloads: Option<i64> = category_downloads
+            .select(sql::<Nullable<BigInt>>("SUM(version_downloads.downloads)"))
+            .get_result(conn)?;
+
+        let sum_downloads = sql::<BigInt>("SUM(version_downloads.downloads)");
+        let top_crates: Vec<CrateDownloads> = category_downloads
+            .group_by((crates::id, crates::name))
+            .select((crates::name, sum_downloads.clone()))
+            .order((sum_downloads.desc(), crates::name.asc()))
+            .limit(MAX_TOP_CRATES)
+            .load(conn)?;
+
+        Ok(Json(json!({
+            "total_downloads": total_downloads.unwrap_or_default(),
+            "top_crates": top_crates,
+            "meta": { "window": window },
+        })))
+    })
+    .await
+}
+
+#[derive(Serialize, Queryable)]
+struct CrateDownloads {
+    name: String,
+    downloads: i64,
+}
+
 /// Handles the `GET /category_slugs` route.
 pub async fn slugs(state: AppState) -> AppResult<Json<Value>> {
     spawn_blocking(move || {
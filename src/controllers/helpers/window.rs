@@ -0,0 +1,22 @@
+//! Parsing of the `window` query parameter of the download stats endpoints,
+//! which selects the number of days (including today) that are summed up.
+
+use crate::util::errors::{bad_request, AppResult};
+
+/// Number of days used by most endpoints with a `window` parameter when no
+/// `window` is passed.
+pub(crate) const DEFAULT_WINDOW_DAYS: i64 = 30;
+
+/// Maximum `window` of most endpoints, since daily download counts are only
+/// retained for 90 days.
+pub(crate) const MAX_WINDOW_DAYS: i64 = 90;
+
+/// Parses a `window` query parameter, clamping it to `max_days`.
+pub(crate) fn parse_window(window: &str, max_days: i64) -> AppResult<i64> {
+    match window.parse::<i64>() {
+        Ok(window) if window > 0 => Ok(window.min(max_days)),
+        _ => Err(bad_request(format_args!(
+            "invalid `window` parameter `{window}`, expected a positive integer"
+        ))),
+    }
+}
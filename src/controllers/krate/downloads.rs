@@ -4,69 +4,1069 @@
 //! download counts are located in `version::downloads`.
 
 use std::cmp;
+use std::collections::{BTreeMap, HashMap, HashSet};
 
+use chrono::{Duration, NaiveDate, Utc};
+use diesel::connection::DefaultLoadingMode;
+use indexmap::IndexMap;
+
+use crate::app::{DownloadRanking, EditionDownloads};
 use crate::controllers::frontend_prelude::*;
+use crate::controllers::helpers::window::{parse_window, DEFAULT_WINDOW_DAYS, MAX_WINDOW_DAYS};
+use crate::controllers::version::downloads::DEFAULT_DOWNLOADS_DAYS;
+use crate::controllers::version::version_and_crate;
 
-use crate::models::{Crate, Version, VersionDownload};
-use crate::schema::{crates, version_downloads, versions};
+use crate::models::{Crate, DependencyKind, Version, VersionDownload};
+use crate::schema::{crate_downloads, crates, dependencies, version_downloads, versions};
 use crate::sql::to_char;
-use crate::util::errors::crate_not_found;
+use crate::util::errors::{crate_not_found, custom};
 use crate::views::EncodableVersionDownload;
 
+/// Maximum number of crates that can be requested at once from the
+/// `POST /crates/downloads` endpoint.
+const MAX_BATCH_CRATES: usize = 50;
+
+/// Number of days in each of the two windows compared by the
+/// `GET /crates/:crate_id/downloads/trend` endpoint.
+const TREND_WINDOW_DAYS: i64 = 7;
+
+/// Maximum number of versions returned by the
+/// `GET /crates/:crate_id/downloads/matrix` endpoint.
+const MAX_MATRIX_VERSIONS: usize = 20;
+
+/// Number of days before the latest finalized day that the `anomaly_score`
+/// of the `GET /crates/:crate_id/downloads` endpoint is compared against.
+const ANOMALY_BASELINE_DAYS: i64 = 30;
+
+/// Minimum number of days with downloads within the baseline that are needed
+/// to compute an `anomaly_score`.
+const ANOMALY_MIN_DAYS: usize = 7;
+
+/// Number of days used by the `GET /crates/:crate_id/downloads/rank` endpoint
+/// when no `window` parameter is passed.
+const DEFAULT_RANK_WINDOW_DAYS: i64 = 7;
+
+/// The windows that the `GET /crates/:crate_id/downloads/rank` endpoint ranks
+/// crates by. Other requested windows are rounded up to the next of these, so
+/// that at most this many rankings have to be calculated per cache period.
+const RANK_WINDOWS_DAYS: [i64; 3] = [DEFAULT_RANK_WINDOW_DAYS, 30, DEFAULT_DOWNLOADS_DAYS];
+
+/// Maximum number of days that can be requested from the
+/// `GET /crates/:crate_id/downloads/by_major` endpoint. Larger values are
+/// clamped to this limit.
+const MAX_MAJOR_WINDOW_DAYS: i64 = 365;
+
+/// The editions that are always included in the response of the
+/// `GET /crates/downloads/by_edition` endpoint, even without downloads.
+const EDITIONS: &[&str] = &["2015", "2018", "2021", "2024"];
+
+/// Maximum number of direct dependents whose downloads are included by the
+/// `GET /crates/:crate_id/downloads/effective` endpoint.
+const MAX_EFFECTIVE_DEPENDENTS: i64 = 100;
+
+/// Time for which the rankings of the `GET /crates/:crate_id/downloads/rank`
+/// endpoint are cached, since ranking all crates is expensive.
+const RANK_CACHE_TTL: std::time::Duration = std::time::Duration::from_secs(5 * 60);
+
+/// Time for which the sums of the `GET /crates/downloads/by_edition` endpoint
+/// are cached, since they include the downloads of all crates.
+const EDITION_CACHE_TTL: std::time::Duration = std::time::Duration::from_secs(5 * 60);
+
+/// Looks up the id of the crate with the given name, or returns a
+/// `404 Not Found` error if there is no such crate.
+fn find_crate_id(conn: &mut PgConnection, crate_name: &str) -> AppResult<i32> {
+    Crate::by_name(crate_name)
+        .select(crates::id)
+        .first(conn)
+        .optional()?
+        .ok_or_else(|| crate_not_found(crate_name))
+}
+
 /// Handles the `GET /crates/:crate_id/downloads` route.
-pub async fn downloads(state: AppState, Path(crate_name): Path<String>) -> AppResult<Json<Value>> {
+///
+/// Passing `split_yanked=true` additionally returns the download totals of the
+/// last 90 days in `meta`, split into downloads of live and yanked versions.
+///
+/// Passing `anomaly=true` additionally returns an `anomaly_score` in `meta`,
+/// which is the number of standard deviations that yesterday's downloads are
+/// away from the mean of the 30 days before.
+///
+/// Passing `min_downloads=N` omits the days with less than `N` downloads from
+/// the `version_downloads` series.
+///
+/// Passing a semver requirement via `req` (e.g. `req=^1.0`) instead returns a
+/// single daily series with the summed downloads of all matching versions.
+///
+/// Passing `format=prometheus` instead returns the download total of the last
+/// 90 days as a single metric line in the Prometheus text exposition format.
+pub async fn downloads(
+    state: AppState,
+    Path(crate_name): Path<String>,
+    req: Parts,
+) -> AppResult<Response> {
     spawn_blocking(move || {
-        use diesel::dsl::*;
-        use diesel::sql_types::BigInt;
-
         let conn = &mut *state.db_read()?;
-        let crate_id: i32 = Crate::by_name(&crate_name)
-            .select(crates::id)
+        let (crate_id, canonical_name): (i32, String) = Crate::by_name(&crate_name)
+            .select((crates::id, crates::name))
             .first(conn)
             .optional()?
             .ok_or_else(|| crate_not_found(&crate_name))?;
 
+        match req.query().get("format").map(String::as_str) {
+            None | Some("json") => {}
+            Some("prometheus") => {
+                let (live, yanked) = crate_downloads_split_yanked(conn, crate_id)?;
+                // Crate names can't contain any characters that would have to
+                // be escaped in a label value. The lookup is case-insensitive,
+                // so the canonical name avoids separate series for one crate.
+                let body = format!(
+                    "crate_downloads_total{{crate=\"{canonical_name}\"}} {}\n",
+                    live + yanked
+                );
+                let headers = [(header::CONTENT_TYPE, prometheus::TEXT_FORMAT)];
+                return Ok((headers, body).into_response());
+            }
+            Some(format) => {
+                return Err(bad_request(format_args!(
+                    "invalid `format` parameter `{format}`, expected `json` or `prometheus`"
+                )));
+            }
+        }
+
+        if let Some(version_req) = req.query().get("req") {
+            let Ok(parsed_req) = semver::VersionReq::parse(version_req) else {
+                return Err(bad_request(format_args!(
+                    "invalid `req` parameter `{version_req}`, expected a semver requirement"
+                )));
+            };
+
+            let downloads = matching_versions_downloads(conn, crate_id, &parsed_req)?;
+            let json = json!({ "req": version_req, "downloads": downloads });
+            return Ok(Json(json).into_response());
+        }
+
+        let min_downloads = req
+            .query()
+            .get("min_downloads")
+            .map(|value| parse_min_downloads(value))
+            .transpose()?;
+
+        let anomaly = req.query().get("anomaly").is_some_and(|a| a == "true");
+        let mut downloads = crate_downloads(conn, crate_id, min_downloads, anomaly)?;
+
+        if req.query().get("split_yanked").is_some_and(|s| s == "true") {
+            let (live, yanked) = crate_downloads_split_yanked(conn, crate_id)?;
+            downloads.meta.downloads = Some(live);
+            downloads.meta.yanked_downloads = Some(yanked);
+        }
+
+        Ok(Json(downloads).into_response())
+    })
+    .await
+}
+
+#[derive(Deserialize)]
+pub struct BatchDownloadsRequest {
+    crates: Vec<String>,
+}
+
+/// Handles the `POST /crates/downloads` route.
+///
+/// Returns the same data as the `GET /crates/:crate_id/downloads` endpoint
+/// for each of the requested crates, keyed by the requested crate name.
+/// Missing crates don't fail the whole request, but are reported inline with
+/// an `errors` object in place of their downloads.
+pub async fn batch_downloads(
+    state: AppState,
+    Json(body): Json<BatchDownloadsRequest>,
+) -> AppResult<Json<Value>> {
+    spawn_blocking(move || {
+        if body.crates.len() > MAX_BATCH_CRATES {
+            return Err(bad_request(format_args!(
+                "too many crates requested, the maximum is {MAX_BATCH_CRATES}"
+            )));
+        }
+
+        let conn = &mut *state.db_read()?;
+
+        let mut results = IndexMap::new();
+        for crate_name in body.crates {
+            let crate_id: Option<i32> = Crate::by_name(&crate_name)
+                .select(crates::id)
+                .first(conn)
+                .optional()?;
+
+            let result = match crate_id {
+                Some(crate_id) => {
+                    serde_json::to_value(crate_downloads(conn, crate_id, None, false)?)?
+                }
+                None => {
+                    let detail = format!("crate `{crate_name}` does not exist");
+                    json!({ "errors": [{ "detail": detail }] })
+                }
+            };
+
+            results.insert(crate_name, result);
+        }
+
+        Ok(Json(json!({ "crates": results })))
+    })
+    .await
+}
+
+/// Parses the `min_downloads` query parameter as a non-negative integer.
+fn parse_min_downloads(value: &str) -> AppResult<i32> {
+    match value.parse::<i32>() {
+        Ok(min_downloads) if min_downloads >= 0 => Ok(min_downloads),
+        _ => Err(bad_request(format_args!(
+            "invalid `min_downloads` parameter `{value}`, expected a non-negative integer"
+        ))),
+    }
+}
+
+#[derive(Serialize)]
+struct CrateDownloads {
+    version_downloads: Vec<EncodableVersionDownload>,
+    meta: CrateDownloadsMeta,
+}
+
+#[derive(Serialize)]
+struct CrateDownloadsMeta {
+    extra_downloads: Vec<ExtraDownload>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    downloads: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    yanked_downloads: Option<i64>,
+    /// Only included when requested via `anomaly=true`, and `null` if there
+    /// is not enough data to compute it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    anomaly_score: Option<Option<f64>>,
+}
+
+#[derive(Serialize, Queryable)]
+struct ExtraDownload {
+    date: String,
+    downloads: i64,
+}
+
+/// Loads the daily downloads of the last 90 days for the five latest versions
+/// of the crate, and the daily sums for all the other versions.
+///
+/// If `min_downloads` is set, the daily downloads of the latest versions that
+/// are below that threshold are left out.
+///
+/// If `anomaly` is set, the [`anomaly_score()`] of the daily totals of all
+/// versions is included in the `meta` object.
+///
+/// The downloads of all versions are loaded in a single query, so that the
+/// number of queries doesn't grow with the number of versions.
+fn crate_downloads(
+    conn: &mut PgConnection,
+    crate_id: i32,
+    min_downloads: Option<i32>,
+    anomaly: bool,
+) -> QueryResult<CrateDownloads> {
+    let mut versions: Vec<Version> = versions::table
+        .filter(versions::crate_id.eq(crate_id))
+        .load(conn)?;
+    versions.sort_by_cached_key(|version| cmp::Reverse(semver::Version::parse(&version.num).ok()));
+    let (latest_five, _) = versions.split_at(cmp::min(5, versions.len()));
+    let latest_five_ids = latest_five.iter().map(|v| v.id).collect::<HashSet<_>>();
+
+    let today = Utc::now().date_naive();
+    let window = (today - Duration::days(89))..=today;
+    let min_downloads = min_downloads.unwrap_or(0);
+
+    let mut downloads = Vec::new();
+    let mut daily_extra_downloads = BTreeMap::<NaiveDate, i64>::new();
+    let mut daily_totals = HashMap::<NaiveDate, i64>::new();
+    for download in VersionDownload::belonging_to_crate(crate_id, window, conn)? {
+        *daily_totals.entry(download.date).or_default() += i64::from(download.downloads);
+
+        if !latest_five_ids.contains(&download.version_id) {
+            *daily_extra_downloads.entry(download.date).or_default() +=
+                i64::from(download.downloads);
+        } else if download.downloads >= min_downloads {
+            downloads.push(EncodableVersionDownload::from(download));
+        }
+    }
+
+    let extra_downloads = daily_extra_downloads
+        .into_iter()
+        .map(|(date, downloads)| ExtraDownload {
+            date: date.to_string(),
+            downloads,
+        })
+        .collect();
+
+    Ok(CrateDownloads {
+        version_downloads: downloads,
+        meta: CrateDownloadsMeta {
+            extra_downloads,
+            downloads: None,
+            yanked_downloads: None,
+            anomaly_score: anomaly.then(|| anomaly_score(&daily_totals, today)),
+        },
+    })
+}
+
+/// Returns by how many standard deviations the downloads of the latest
+/// finalized day (yesterday) differ from the mean of the
+/// [`ANOMALY_BASELINE_DAYS`] days before it. Days without downloads count as
+/// zero downloads.
+///
+/// `None` is returned if fewer than [`ANOMALY_MIN_DAYS`] days of the
+/// baseline have downloads, or if the baseline has no variance at all.
+fn anomaly_score(daily_totals: &HashMap<NaiveDate, i64>, today: NaiveDate) -> Option<f64> {
+    let latest_day = today - Duration::days(1);
+    let baseline = (1..=ANOMALY_BASELINE_DAYS)
+        .map(|days_ago| latest_day - Duration::days(days_ago))
+        .map(|date| daily_totals.get(&date).copied())
+        .collect::<Vec<_>>();
+
+    let days_with_data = baseline.iter().flatten().count();
+    if days_with_data < ANOMALY_MIN_DAYS {
+        return None;
+    }
+
+    let baseline = baseline
+        .into_iter()
+        .map(|downloads| downloads.unwrap_or(0) as f64)
+        .collect::<Vec<_>>();
+
+    let count = baseline.len() as f64;
+    let mean = baseline.iter().sum::<f64>() / count;
+    let variance = baseline.iter().map(|d| (d - mean).powi(2)).sum::<f64>() / count;
+    let std_dev = variance.sqrt();
+    if std_dev == 0.0 {
+        return None;
+    }
+
+    let latest = daily_totals.get(&latest_day).copied().unwrap_or(0) as f64;
+    Some((latest - mean) / std_dev)
+}
+
+/// Loads the daily sums of the downloads of the last 90 days for all versions
+/// of the crate that match the given requirement.
+fn matching_versions_downloads(
+    conn: &mut PgConnection,
+    crate_id: i32,
+    version_req: &semver::VersionReq,
+) -> QueryResult<Vec<ExtraDownload>> {
+    use diesel::dsl::*;
+    use diesel::sql_types::BigInt;
+
+    let versions: Vec<(i32, String)> = versions::table
+        .filter(versions::crate_id.eq(crate_id))
+        .select((versions::id, versions::num))
+        .load(conn)?;
+
+    let version_ids = versions
+        .into_iter()
+        .filter(|(_, num)| {
+            semver::Version::parse(num).is_ok_and(|version| version_req.matches(&version))
+        })
+        .map(|(id, _)| id)
+        .collect::<Vec<_>>();
+
+    let sum_downloads = sql::<BigInt>("SUM(version_downloads.downloads)");
+    version_downloads::table
+        .select((
+            to_char(version_downloads::date, "YYYY-MM-DD"),
+            sum_downloads,
+        ))
+        .filter(version_downloads::version_id.eq_any(version_ids))
+        .filter(version_downloads::date.gt(date(now - DEFAULT_DOWNLOADS_DAYS.days())))
+        .group_by(version_downloads::date)
+        .order(version_downloads::date.asc())
+        .load(conn)
+}
+
+/// Sums up the downloads of the last 90 days for all versions of the crate,
+/// split into `(live, yanked)` downloads.
+///
+/// This includes the `extra_downloads` of versions that were created within
+/// the last 90 days.
+fn crate_downloads_split_yanked(conn: &mut PgConnection, crate_id: i32) -> QueryResult<(i64, i64)> {
+    use diesel::dsl::*;
+    use diesel::sql_types::BigInt;
+
+    let sum_downloads = sql::<BigInt>("SUM(version_downloads.downloads)");
+    let totals: Vec<(bool, i64)> = versions::table
+        .inner_join(version_downloads::table)
+        .filter(versions::crate_id.eq(crate_id))
+        .filter(version_downloads::date.gt(date(now - DEFAULT_DOWNLOADS_DAYS.days())))
+        .group_by(versions::yanked)
+        .select((versions::yanked, sum_downloads))
+        .load(conn)?;
+
+    // Downloads from before a version was imported from another registry
+    // are counted on the day that the version was created
+    let sum_extra_downloads = sql::<BigInt>("SUM(versions.extra_downloads)");
+    let extra_totals: Vec<(bool, i64)> = versions::table
+        .filter(versions::crate_id.eq(crate_id))
+        .filter(date(versions::created_at).gt(date(now - DEFAULT_DOWNLOADS_DAYS.days())))
+        .group_by(versions::yanked)
+        .select((versions::yanked, sum_extra_downloads))
+        .load(conn)?;
+
+    let total = |yanked| {
+        totals
+            .iter()
+            .chain(&extra_totals)
+            .filter(|(is_yanked, _)| *is_yanked == yanked)
+            .map(|(_, downloads)| downloads)
+            .sum()
+    };
+
+    Ok((total(false), total(true)))
+}
+
+/// The response of the `downloads_by_version` endpoint.
+///
+/// This is not serialized via [`Value`] to retain the order of the versions.
+#[derive(Serialize)]
+pub struct DownloadsByVersion {
+    versions: IndexMap<String, i64>,
+}
+
+/// Handles the `GET /crates/:crate_id/downloads/by_version` route.
+///
+/// Returns the download totals of the last 90 days for each version of the
+/// crate that was downloaded at least once in that window, ordered by the
+/// number of downloads.
+pub async fn downloads_by_version(
+    state: AppState,
+    Path(crate_name): Path<String>,
+) -> AppResult<Json<DownloadsByVersion>> {
+    spawn_blocking(move || {
+        use diesel::dsl::*;
+        use diesel::sql_types::BigInt;
+
+        let conn = &mut *state.db_read()?;
+        let crate_id = find_crate_id(conn, &crate_name)?;
+
+        let sum_downloads = sql::<BigInt>("SUM(version_downloads.downloads)");
+        let versions = versions::table
+            .inner_join(version_downloads::table)
+            .filter(versions::crate_id.eq(crate_id))
+            .filter(version_downloads::date.gt(date(now - DEFAULT_DOWNLOADS_DAYS.days())))
+            .group_by(versions::num)
+            .select((versions::num, sum_downloads.clone()))
+            .having(sum_downloads.clone().gt(0))
+            .order((sum_downloads.desc(), versions::num.asc()))
+            .load_iter::<(String, i64), DefaultLoadingMode>(conn)?
+            .collect::<QueryResult<_>>()?;
+
+        Ok(Json(DownloadsByVersion { versions }))
+    })
+    .await
+}
+
+/// Handles the `GET /crates/:crate_id/downloads/by_major` route.
+///
+/// Sums the downloads of the crate within the last `window` days (including
+/// today), grouped by the major version of the downloaded versions. Versions
+/// before `1.0.0` are grouped as major version `0`. Major versions without
+/// downloads in the window are omitted.
+pub async fn downloads_by_major(
+    state: AppState,
+    Path(crate_name): Path<String>,
+    req: Parts,
+) -> AppResult<Json<Value>> {
+    spawn_blocking(move || {
+        use diesel::dsl::sql;
+        use diesel::sql_types::BigInt;
+
+        let window = match req.query().get("window") {
+            Some(window) => parse_window(window, MAX_MAJOR_WINDOW_DAYS)?,
+            None => DEFAULT_DOWNLOADS_DAYS,
+        };
+
+        let conn = &mut *state.db_read()?;
+        let crate_id = find_crate_id(conn, &crate_name)?;
+
+        let today = Utc::now().date_naive();
+        let start = today - Duration::days(window - 1);
+
+        let downloads: Vec<(String, i64)> = versions::table
+            .inner_join(version_downloads::table)
+            .filter(versions::crate_id.eq(crate_id))
+            .filter(version_downloads::date.between(start, today))
+            .group_by(versions::num)
+            .select((
+                versions::num,
+                sql::<BigInt>("SUM(version_downloads.downloads)"),
+            ))
+            .load(conn)?;
+
+        let mut majors = BTreeMap::<u64, i64>::new();
+        for (num, downloads) in downloads {
+            if let Ok(version) = semver::Version::parse(&num) {
+                *majors.entry(version.major).or_default() += downloads;
+            }
+        }
+
+        let majors = majors
+            .into_iter()
+            .filter(|(_, downloads)| *downloads > 0)
+            .map(|(major, downloads)| json!({ "major": major, "downloads": downloads }))
+            .collect::<Vec<_>>();
+
+        Ok(Json(json!({
+            "majors": majors,
+            "meta": { "window": window },
+        })))
+    })
+    .await
+}
+
+/// The response of the `downloads_matrix` endpoint.
+///
+/// This is not serialized via [`Value`] to retain the order of the versions.
+#[derive(Serialize)]
+pub struct DownloadsMatrix {
+    dates: Vec<String>,
+    versions: IndexMap<String, Vec<i32>>,
+}
+
+/// Handles the `GET /crates/:crate_id/downloads/matrix` route.
+///
+/// Returns the daily downloads of the last 90 days for the newest versions of
+/// the crate. The download counts of each version are aligned with the shared
+/// `dates` axis, and days without downloads are filled with zeros.
+pub async fn downloads_matrix(
+    state: AppState,
+    Path(crate_name): Path<String>,
+) -> AppResult<Json<DownloadsMatrix>> {
+    spawn_blocking(move || {
+        let conn = &mut *state.db_read()?;
+        let crate_id = find_crate_id(conn, &crate_name)?;
+
         let mut versions: Vec<Version> = versions::table
             .filter(versions::crate_id.eq(crate_id))
             .load(conn)?;
         versions
             .sort_by_cached_key(|version| cmp::Reverse(semver::Version::parse(&version.num).ok()));
-        let (latest_five, rest) = versions.split_at(cmp::min(5, versions.len()));
+        versions.truncate(MAX_MATRIX_VERSIONS);
 
-        let downloads = VersionDownload::belonging_to(latest_five)
-            .filter(version_downloads::date.gt(date(now - 90.days())))
-            .order((
-                version_downloads::date.asc(),
-                version_downloads::version_id.desc(),
+        let today = Utc::now().date_naive();
+        let start_date = today - Duration::days(DEFAULT_DOWNLOADS_DAYS - 1);
+
+        let downloads: Vec<(i32, NaiveDate, i32)> = VersionDownload::belonging_to(&versions)
+            .filter(version_downloads::date.between(start_date, today))
+            .select((
+                version_downloads::version_id,
+                version_downloads::date,
+                version_downloads::downloads,
             ))
-            .load(conn)?
+            .load(conn)?;
+
+        let mut counts = versions
+            .iter()
+            .map(|version| (version.id, vec![0; DEFAULT_DOWNLOADS_DAYS as usize]))
+            .collect::<HashMap<_, _>>();
+
+        for (version_id, date, downloads) in downloads {
+            let index = (date - start_date).num_days() as usize;
+            if let Some(count) = counts.get_mut(&version_id).and_then(|c| c.get_mut(index)) {
+                *count = downloads;
+            }
+        }
+
+        let dates = start_date
+            .iter_days()
+            .take(DEFAULT_DOWNLOADS_DAYS as usize)
+            .map(|date| date.to_string())
+            .collect();
+
+        let versions = versions
+            .into_iter()
+            .map(|version| {
+                let counts = counts.remove(&version.id).unwrap_or_default();
+                (version.num, counts)
+            })
+            .collect();
+
+        Ok(Json(DownloadsMatrix { dates, versions }))
+    })
+    .await
+}
+
+/// The response of the `compare_downloads` endpoint.
+#[derive(Serialize)]
+pub struct DownloadsComparison {
+    dates: Vec<String>,
+    a: ComparedVersion,
+    b: ComparedVersion,
+}
+
+#[derive(Serialize)]
+struct ComparedVersion {
+    version: String,
+    downloads: Vec<i32>,
+    total: i64,
+}
+
+/// Handles the `GET /crates/:crate_id/compare-downloads?a=:version&b=:version`
+/// route.
+///
+/// Returns the daily downloads of the last 90 days for the two versions. Like
+/// in the `downloads_matrix` endpoint, the download counts are aligned with
+/// the shared `dates` axis, and days without downloads are filled with zeros.
+/// The `total` of each version is the sum of its downloads in that window.
+pub async fn compare_downloads(
+    state: AppState,
+    Path(crate_name): Path<String>,
+    req: Parts,
+) -> AppResult<Json<DownloadsComparison>> {
+    spawn_blocking(move || {
+        let query = req.query();
+        let param = |name: &str| {
+            query
+                .get(name)
+                .ok_or_else(|| bad_request(format_args!("missing `{name}` parameter")))
+        };
+        let (a, b) = (param("a")?, param("b")?);
+
+        let conn = &mut *state.db_read()?;
+        let (a, _) = version_and_crate(conn, &crate_name, a)?;
+        let (b, _) = version_and_crate(conn, &crate_name, b)?;
+
+        let today = Utc::now().date_naive();
+        let start_date = today - Duration::days(DEFAULT_DOWNLOADS_DAYS - 1);
+
+        let dates = start_date
+            .iter_days()
+            .take(DEFAULT_DOWNLOADS_DAYS as usize)
+            .map(|date| date.to_string())
+            .collect();
+
+        let mut compared_version = |version: Version| -> QueryResult<_> {
+            let downloads: Vec<(NaiveDate, i32)> = VersionDownload::belonging_to(&version)
+                .filter(version_downloads::date.between(start_date, today))
+                .select((version_downloads::date, version_downloads::downloads))
+                .load(conn)?;
+
+            let mut counts = vec![0; DEFAULT_DOWNLOADS_DAYS as usize];
+            for (date, downloads) in downloads {
+                counts[(date - start_date).num_days() as usize] = downloads;
+            }
+
+            Ok(ComparedVersion {
+                version: version.num,
+                total: counts.iter().copied().map(i64::from).sum(),
+                downloads: counts,
+            })
+        };
+
+        let a = compared_version(a)?;
+        let b = compared_version(b)?;
+
+        Ok(Json(DownloadsComparison { dates, a, b }))
+    })
+    .await
+}
+
+#[derive(Serialize, Queryable)]
+struct VersionDownloadsOnDate {
+    version: String,
+    downloads: i32,
+}
+
+/// Handles the `GET /crates/:crate_id/downloads/on/:date` route.
+///
+/// Returns the downloads of each version of the crate that was downloaded on
+/// the given day, ordered by the number of downloads.
+pub async fn downloads_on_date(
+    state: AppState,
+    Path((crate_name, date)): Path<(String, String)>,
+) -> AppResult<Json<Value>> {
+    spawn_blocking(move || {
+        let Ok(date) = NaiveDate::parse_from_str(&date, "%F") else {
+            return Err(bad_request(format_args!(
+                "invalid date `{date}`, expected a date in `YYYY-MM-DD` format"
+            )));
+        };
+
+        let today = Utc::now().date_naive();
+        if date > today || date <= today - Duration::days(DEFAULT_DOWNLOADS_DAYS) {
+            let detail =
+                format!("downloads are only available for the last {DEFAULT_DOWNLOADS_DAYS} days");
+            return Err(custom(StatusCode::NOT_FOUND, detail));
+        }
+
+        let conn = &mut *state.db_read()?;
+        let crate_id = find_crate_id(conn, &crate_name)?;
+
+        let versions: Vec<VersionDownloadsOnDate> = versions::table
+            .inner_join(version_downloads::table)
+            .filter(versions::crate_id.eq(crate_id))
+            .filter(version_downloads::date.eq(date))
+            .select((versions::num, version_downloads::downloads))
+            .order((version_downloads::downloads.desc(), versions::num.asc()))
+            .load(conn)?;
+
+        Ok(Json(
+            json!({ "date": date.to_string(), "versions": versions }),
+        ))
+    })
+    .await
+}
+
+/// Handles the `GET /crates/:crate_id/downloads/rank` route.
+///
+/// Ranks the crate among all crates by the sum of its downloads within the
+/// last `window` days (including today). Crates with the same number of
+/// downloads share the same rank. The `window` is rounded up to 7, 30 or 90
+/// days, and the window that was used is returned in the response.
+pub async fn downloads_rank(
+    state: AppState,
+    Path(crate_name): Path<String>,
+    req: Parts,
+) -> AppResult<Json<Value>> {
+    spawn_blocking(move || {
+        let window = match req.query().get("window") {
+            Some(window) => parse_window(window, MAX_WINDOW_DAYS)?,
+            None => DEFAULT_RANK_WINDOW_DAYS,
+        };
+        let window = RANK_WINDOWS_DAYS
             .into_iter()
-            .map(VersionDownload::into)
-            .collect::<Vec<EncodableVersionDownload>>();
+            .find(|days| *days >= window)
+            .unwrap_or(DEFAULT_DOWNLOADS_DAYS);
+
+        let crate_id = find_crate_id(&mut *state.db_read()?, &crate_name)?;
+
+        // The connection is only checked out on cache misses, so that requests
+        // waiting for another request to calculate the ranking don't hold one
+        let ranking = state.download_rank_cache.get_or_try_insert_with(
+            window,
+            RANK_CACHE_TTL,
+            || -> AppResult<_> { Ok(download_ranking(&mut *state.db_read()?, window)?) },
+        )?;
+
+        let downloads = ranking
+            .downloads
+            .iter()
+            .find(|(id, _)| *id == crate_id)
+            .map(|(_, downloads)| *downloads)
+            .unwrap_or(0);
+
+        let rank = ranking
+            .downloads
+            .partition_point(|(_, other)| *other > downloads)
+            + 1;
+
+        Ok(Json(json!({
+            "rank": rank,
+            "total_crates": ranking.total_crates,
+            "downloads": downloads,
+            "window": window,
+        })))
+    })
+    .await
+}
+
+/// Sums the downloads of each crate within the last `window` days and orders
+/// the crates by their downloads.
+fn download_ranking(conn: &mut PgConnection, window: i64) -> QueryResult<DownloadRanking> {
+    use diesel::dsl::sql;
+    use diesel::sql_types::BigInt;
+
+    let today = Utc::now().date_naive();
+    let start = today - Duration::days(window - 1);
+
+    let sum_downloads = sql::<BigInt>("SUM(version_downloads.downloads)");
+    let downloads = versions::table
+        .inner_join(version_downloads::table)
+        .filter(version_downloads::date.between(start, today))
+        .group_by(versions::crate_id)
+        .select((versions::crate_id, sum_downloads.clone()))
+        .order((sum_downloads.desc(), versions::crate_id.asc()))
+        .load(conn)?;
+
+    let total_crates = crates::table.count().get_result(conn)?;
+
+    Ok(DownloadRanking {
+        total_crates,
+        downloads,
+    })
+}
+
+/// Handles the `GET /crates/:crate_id/downloads/trend` route.
+///
+/// Compares the downloads of the last seven days (including today) with the
+/// seven days before that. `pct_change` is `null` if there were no downloads
+/// in the previous window.
+pub async fn downloads_trend(
+    state: AppState,
+    Path(crate_name): Path<String>,
+) -> AppResult<Json<Value>> {
+    spawn_blocking(move || {
+        use diesel::dsl::sql;
+        use diesel::sql_types::BigInt;
+
+        let conn = &mut *state.db_read()?;
+        let crate_id = find_crate_id(conn, &crate_name)?;
+
+        let today = Utc::now().date_naive();
+        let current_start = today - Duration::days(TREND_WINDOW_DAYS - 1);
+        let previous_start = current_start - Duration::days(TREND_WINDOW_DAYS);
 
         let sum_downloads = sql::<BigInt>("SUM(version_downloads.downloads)");
-        let extra: Vec<ExtraDownload> = VersionDownload::belonging_to(rest)
-            .select((
-                to_char(version_downloads::date, "YYYY-MM-DD"),
-                sum_downloads,
-            ))
-            .filter(version_downloads::date.gt(date(now - 90.days())))
+        let daily_downloads: Vec<(NaiveDate, i64)> = versions::table
+            .inner_join(version_downloads::table)
+            .filter(versions::crate_id.eq(crate_id))
+            .filter(version_downloads::date.between(previous_start, today))
             .group_by(version_downloads::date)
-            .order(version_downloads::date.asc())
+            .select((version_downloads::date, sum_downloads))
             .load(conn)?;
 
-        #[derive(Serialize, Queryable)]
-        struct ExtraDownload {
-            date: String,
-            downloads: i64,
+        let (current, previous): (Vec<_>, Vec<_>) = daily_downloads
+            .into_iter()
+            .partition(|(date, _)| *date >= current_start);
+
+        let current_7d: i64 = current.into_iter().map(|(_, downloads)| downloads).sum();
+        let previous_7d: i64 = previous.into_iter().map(|(_, downloads)| downloads).sum();
+
+        let pct_change = (previous_7d != 0)
+            .then(|| (current_7d - previous_7d) as f64 / previous_7d as f64 * 100.0);
+
+        Ok(Json(json!({
+            "current_7d": current_7d,
+            "previous_7d": previous_7d,
+            "pct_change": pct_change,
+        })))
+    })
+    .await
+}
+
+/// Handles the `GET /crates/downloads/by_edition` route.
+///
+/// Sums the downloads of all crates within the last `window` days (including
+/// today), grouped by the Rust edition of the downloaded versions. Downloads
+/// of versions that were published before the edition was recorded are
+/// counted as `unknown`.
+///
+/// The sums are cached for a few minutes.
+pub async fn downloads_by_edition(state: AppState, req: Parts) -> AppResult<Json<Value>> {
+    spawn_blocking(move || {
+        let window = match req.query().get("window") {
+            Some(window) => parse_window(window, MAX_WINDOW_DAYS)?,
+            None => DEFAULT_WINDOW_DAYS,
+        };
+
+        let downloads = state.downloads_by_edition_cache.get_or_try_insert_with(
+            window,
+            EDITION_CACHE_TTL,
+            || -> AppResult<_> { Ok(edition_downloads(&mut *state.db_read()?, window)?) },
+        )?;
+
+        let mut editions = EDITIONS
+            .iter()
+            .map(|edition| (edition.to_string(), 0))
+            .collect::<BTreeMap<_, i64>>();
+        let mut unknown = 0;
+        for (edition, downloads) in downloads.iter() {
+            match edition {
+                Some(edition) => *editions.entry(edition.clone()).or_default() += downloads,
+                None => unknown += downloads,
+            }
         }
 
         Ok(Json(json!({
-            "version_downloads": downloads,
+            "editions": editions,
+            "unknown": unknown,
+            "meta": { "window": window },
+        })))
+    })
+    .await
+}
+
+/// Sums the downloads of all crates within the last `window` days by the
+/// edition of the downloaded versions.
+fn edition_downloads(conn: &mut PgConnection, window: i64) -> QueryResult<EditionDownloads> {
+    use diesel::dsl::sql;
+    use diesel::sql_types::BigInt;
+
+    let today = Utc::now().date_naive();
+    let start = today - Duration::days(window - 1);
+
+    versions::table
+        .inner_join(version_downloads::table)
+        .filter(version_downloads::date.between(start, today))
+        .group_by(versions::edition)
+        .select((
+            versions::edition,
+            sql::<BigInt>("SUM(version_downloads.downloads)"),
+        ))
+        .load(conn)
+}
+
+/// Handles the `GET /crates/:crate_id/downloads/version-spread` route.
+///
+/// Returns the number of distinct versions of the crate that were downloaded
+/// on each day of the last 90 days. Days without any downloads are omitted.
+pub async fn version_spread(
+    state: AppState,
+    Path(crate_name): Path<String>,
+) -> AppResult<Json<Value>> {
+    spawn_blocking(move || {
+        use diesel::dsl::count_distinct;
+
+        let conn = &mut *state.db_read()?;
+        let crate_id = find_crate_id(conn, &crate_name)?;
+
+        let today = Utc::now().date_naive();
+        let start = today - Duration::days(DEFAULT_DOWNLOADS_DAYS - 1);
+
+        let spread: Vec<(NaiveDate, i64)> = version_downloads::table
+            .inner_join(versions::table)
+            .filter(versions::crate_id.eq(crate_id))
+            .filter(version_downloads::date.between(start, today))
+            .filter(version_downloads::downloads.gt(0))
+            .group_by(version_downloads::date)
+            .select((
+                version_downloads::date,
+                count_distinct(version_downloads::version_id),
+            ))
+            .order(version_downloads::date)
+            .load(conn)?;
+
+        let spread = spread
+            .into_iter()
+            .map(|(date, versions)| json!({ "date": date.to_string(), "versions": versions }))
+            .collect::<Vec<_>>();
+
+        Ok(Json(json!({
+            "version_spread": spread,
+            "meta": { "days": DEFAULT_DOWNLOADS_DAYS },
+        })))
+    })
+    .await
+}
+
+/// Handles the `GET /crates/:crate_id/downloads/effective` route.
+///
+/// Returns the downloads of the crate within the last 90 days, together with
+/// its "effective" downloads, which additionally include the downloads of the
+/// crates that directly depend on it within the same window.
+///
+/// Only direct dependents are considered (a depth of one), i.e. crates with a
+/// non-yanked version that depends on this crate. Transitive dependents and
+/// crates that only use this crate as a dev-dependency are not included. Only
+/// the 100 dependents with the most all-time downloads are summed up, in which
+/// case `meta.dependents_capped` is `true`.
+pub async fn effective_downloads(
+    state: AppState,
+    Path(crate_name): Path<String>,
+) -> AppResult<Json<Value>> {
+    spawn_blocking(move || {
+        let conn = &mut *state.db_read()?;
+        let crate_id = find_crate_id(conn, &crate_name)?;
+
+        let dependent_crate_ids = dependencies::table
+            .inner_join(versions::table)
+            .filter(dependencies::crate_id.eq(crate_id))
+            .filter(versions::crate_id.ne(crate_id))
+            .filter(versions::yanked.eq(false))
+            .filter(dependencies::kind.ne(DependencyKind::Dev))
+            .select(versions::crate_id);
+
+        let mut dependents: Vec<i32> = crate_downloads::table
+            .filter(crate_downloads::crate_id.eq_any(dependent_crate_ids))
+            .order((crate_downloads::downloads.desc(), crate_downloads::crate_id))
+            .select(crate_downloads::crate_id)
+            .limit(MAX_EFFECTIVE_DEPENDENTS + 1)
+            .load(conn)?;
+
+        let dependents_capped = dependents.len() as i64 > MAX_EFFECTIVE_DEPENDENTS;
+        dependents.truncate(MAX_EFFECTIVE_DEPENDENTS as usize);
+
+        let today = Utc::now().date_naive();
+        let start = today - Duration::days(DEFAULT_DOWNLOADS_DAYS - 1);
+
+        let direct = windowed_downloads(conn, &[crate_id], start, today)?;
+        let dependent_downloads = windowed_downloads(conn, &dependents, start, today)?;
+
+        Ok(Json(json!({
+            "direct_downloads": direct,
+            "effective_downloads": direct + dependent_downloads,
             "meta": {
-                "extra_downloads": extra,
+                "days": DEFAULT_DOWNLOADS_DAYS,
+                "depth": 1,
+                "dependents": dependents.len(),
+                "dependents_capped": dependents_capped,
             },
         })))
     })
     .await
 }
+
+/// Sums the downloads of all versions of the given crates within the
+/// inclusive date window.
+fn windowed_downloads(
+    conn: &mut PgConnection,
+    crate_ids: &[i32],
+    start: NaiveDate,
+    end: NaiveDate,
+) -> QueryResult<i64> {
+    use diesel::dsl::sql;
+    use diesel::sql_types::BigInt;
+
+    versions::table
+        .inner_join(version_downloads::table)
+        .filter(versions::crate_id.eq_any(crate_ids))
+        .filter(version_downloads::date.between(start, end))
+        .select(sql::<BigInt>(
+            "COALESCE(SUM(version_downloads.downloads), 0)",
+        ))
+        .get_result(conn)
+}
+
+/// Handles the `GET /crates/:crate_id/downloads/by_feature/:feature` route.
+///
+/// Sums the downloads within the last 90 days across all versions of the
+/// crate whose `features` declare the given feature. Versions without the
+/// feature are excluded, and are not part of the returned `versions` list.
+pub async fn downloads_by_feature(
+    state: AppState,
+    Path((crate_name, feature)): Path<(String, String)>,
+) -> AppResult<Json<Value>> {
+    spawn_blocking(move || {
+        use diesel::dsl::sql;
+        use diesel::sql_types::BigInt;
+
+        let conn = &mut *state.db_read()?;
+        let crate_id = find_crate_id(conn, &crate_name)?;
+
+        let mut versions: Vec<(i32, String)> = versions::table
+            .filter(versions::crate_id.eq(crate_id))
+            .filter(versions::features.has_key(&feature))
+            .select((versions::id, versions::num))
+            .load(conn)?;
+        versions.sort_by_cached_key(|(_, num)| cmp::Reverse(semver::Version::parse(num).ok()));
+
+        let today = Utc::now().date_naive();
+        let start = today - Duration::days(DEFAULT_DOWNLOADS_DAYS - 1);
+
+        let version_ids = versions.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+        let downloads: i64 = version_downloads::table
+            .filter(version_downloads::version_id.eq_any(&version_ids))
+            .filter(version_downloads::date.between(start, today))
+            .select(sql::<BigInt>(
+                "COALESCE(SUM(version_downloads.downloads), 0)",
+            ))
+            .get_result(conn)?;
+
+        let versions = versions.into_iter().map(|(_, num)| num).collect::<Vec<_>>();
+
+        Ok(Json(json!({
+            "feature": feature,
+            "downloads": downloads,
+            "versions": versions,
+            "meta": { "days": DEFAULT_DOWNLOADS_DAYS },
+        })))
+    })
+    .await
+}
@@ -4,15 +4,19 @@
 //! index or cached metadata which was extracted (client side) from the
 //! `Cargo.toml` file.
 
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::dsl::min;
 use std::cmp::Reverse;
+use std::collections::HashMap;
 use std::str::FromStr;
 
 use crate::controllers::frontend_prelude::*;
 use crate::controllers::helpers::pagination::PaginationOptions;
+use crate::controllers::version::downloads::DEFAULT_DOWNLOADS_DAYS;
 
 use crate::models::{
     Category, Crate, CrateCategory, CrateKeyword, CrateVersions, Keyword, RecentCrateDownloads,
-    User, Version, VersionOwnerAction,
+    User, Version, VersionDownload, VersionOwnerAction,
 };
 use crate::schema::*;
 use crate::util::errors::crate_not_found;
@@ -25,7 +29,19 @@ pub async fn show_new(app: AppState, req: Parts) -> AppResult<Json<Value>> {
     show(app, Path("new".to_string()), req).await
 }
 
+/// Handles the `GET /crates/downloads` special case.
+///
+/// This path is used by the `POST /crates/downloads` batch endpoint, so the
+/// metadata of a crate called `downloads` has to be routed explicitly.
+pub async fn show_downloads(app: AppState, req: Parts) -> AppResult<Json<Value>> {
+    show(app, Path("downloads".to_string()), req).await
+}
+
 /// Handles the `GET /crates/:crate_id` route.
+///
+/// If `normalized=true` is requested, the crate additionally includes its
+/// `downloads_per_day_since_publish`, which allows comparing crates of
+/// different ages.
 pub async fn show(app: AppState, Path(name): Path<String>, req: Parts) -> AppResult<Json<Value>> {
     spawn_blocking(move || {
         let include = req
@@ -34,6 +50,7 @@ pub async fn show(app: AppState, Path(name): Path<String>, req: Parts) -> AppRes
             .map(|mode| ShowIncludeMode::from_str(mode))
             .transpose()?
             .unwrap_or_default();
+        let normalized = req.query().get("normalized").is_some_and(|n| n == "true");
 
         let conn = &mut *app.db_read()?;
         let (krate, downloads): (Crate, i64) = Crate::by_name(&name)
@@ -44,27 +61,7 @@ pub async fn show(app: AppState, Path(name): Path<String>, req: Parts) -> AppRes
             .ok_or_else(|| crate_not_found(&name))?;
 
         let versions_publishers_and_audit_actions = if include.versions {
-            let mut versions_and_publishers: Vec<(Version, Option<User>)> = krate
-                .all_versions()
-                .left_outer_join(users::table)
-                .select((versions::all_columns, users::all_columns.nullable()))
-                .load(conn)?;
-            versions_and_publishers.sort_by_cached_key(|(version, _)| {
-                Reverse(semver::Version::parse(&version.num).ok())
-            });
-
-            let versions = versions_and_publishers
-                .iter()
-                .map(|(v, _)| v)
-                .cloned()
-                .collect::<Vec<_>>();
-            Some(
-                versions_and_publishers
-                    .into_iter()
-                    .zip(VersionOwnerAction::for_versions(conn, &versions)?)
-                    .map(|((v, pb), aas)| (v, pb, aas))
-                    .collect::<Vec<_>>(),
-            )
+            Some(load_versions_with_publishers(&krate, conn)?)
         } else {
             None
         };
@@ -109,7 +106,15 @@ pub async fn show(app: AppState, Path(name): Path<String>, req: Parts) -> AppRes
             None
         };
 
-        let encodable_crate = EncodableCrate::from(
+        let first_published_at = if normalized {
+            Version::belonging_to(&krate)
+                .select(min(versions::created_at))
+                .get_result(conn)?
+        } else {
+            None
+        };
+
+        let mut encodable_crate = EncodableCrate::from(
             krate.clone(),
             top_versions.as_ref(),
             ids,
@@ -120,6 +125,13 @@ pub async fn show(app: AppState, Path(name): Path<String>, req: Parts) -> AppRes
             downloads,
             recent_downloads,
         );
+        if let Some(first_published_at) = first_published_at {
+            encodable_crate.downloads_per_day_since_publish = Some(downloads_per_day(
+                encodable_crate.downloads,
+                first_published_at,
+            ));
+        }
+
         let encodable_versions = versions_publishers_and_audit_actions.map(|vpa| {
             vpa.into_iter()
                 .map(|(v, pb, aas)| EncodableVersion::from(v, &krate.name, pb, aas))
@@ -145,6 +157,173 @@ pub async fn show(app: AppState, Path(name): Path<String>, req: Parts) -> AppRes
     .await
 }
 
+/// Handles the `GET /crates/:crate_id/summary` route.
+///
+/// Returns the crate metadata, all of its versions and the download totals of
+/// each version within the last 90 days in a single response, so that crate
+/// pages don't have to request them separately.
+pub async fn summary(app: AppState, Path(name): Path<String>) -> AppResult<Json<Value>> {
+    spawn_blocking(move || {
+        let conn = &mut *app.db_read()?;
+        let (krate, downloads): (Crate, i64) = Crate::by_name(&name)
+            .inner_join(crate_downloads::table)
+            .select((Crate::as_select(), crate_downloads::downloads))
+            .first(conn)
+            .optional()?
+            .ok_or_else(|| crate_not_found(&name))?;
+
+        let versions = load_versions_with_publishers(&krate, conn)?;
+        let ids = versions.iter().map(|(v, _, _)| v.id).collect();
+
+        let recent_downloads = RecentCrateDownloads::belonging_to(&krate)
+            .select(recent_crate_downloads::downloads)
+            .get_result(conn)
+            .optional()?;
+
+        let end_date = Utc::now().date_naive();
+        let start_date = end_date - Duration::days(DEFAULT_DOWNLOADS_DAYS - 1);
+        let window = start_date..=end_date;
+
+        let mut version_totals = HashMap::<i32, i64>::new();
+        for download in VersionDownload::belonging_to_crate(krate.id, window, conn)? {
+            *version_totals.entry(download.version_id).or_default() +=
+                i64::from(download.downloads);
+        }
+
+        let version_downloads = versions
+            .iter()
+            .map(|(v, _, _)| {
+                let downloads = version_totals.get(&v.id).copied().unwrap_or(0);
+                json!({ "version": v.id, "downloads": downloads })
+            })
+            .collect::<Vec<_>>();
+
+        let top_versions = krate.top_versions(conn)?;
+        let encodable_crate = EncodableCrate::from(
+            krate.clone(),
+            Some(&top_versions),
+            Some(ids),
+            None,
+            None,
+            None,
+            false,
+            downloads,
+            recent_downloads,
+        );
+        let encodable_versions = versions
+            .into_iter()
+            .map(|(v, pb, aas)| EncodableVersion::from(v, &krate.name, pb, aas))
+            .collect::<Vec<_>>();
+
+        Ok(Json(json!({
+            "crate": encodable_crate,
+            "versions": encodable_versions,
+            "version_downloads": version_downloads,
+            "meta": { "days": DEFAULT_DOWNLOADS_DAYS },
+        })))
+    })
+    .await
+}
+
+/// Handles the `GET /crates/:crate_id/health` route.
+///
+/// Summarizes the health of a crate in a single response: its downloads
+/// within the last 90 days, the share of its versions that are yanked, the
+/// number of days since its newest version was published and the number of
+/// its versions. Deleted versions are not taken into account.
+pub async fn health(app: AppState, Path(name): Path<String>) -> AppResult<Json<Value>> {
+    spawn_blocking(move || {
+        use diesel::dsl::{count_star, max};
+
+        let conn = &mut *app.db_read()?;
+        let crate_id: i32 = Crate::by_name(&name)
+            .select(crates::id)
+            .first(conn)
+            .optional()?
+            .ok_or_else(|| crate_not_found(&name))?;
+
+        let end_date = Utc::now().date_naive();
+        let start_date = end_date - Duration::days(DEFAULT_DOWNLOADS_DAYS - 1);
+        let recent_downloads: i64 =
+            VersionDownload::belonging_to_crate(crate_id, start_date..=end_date, conn)?
+                .iter()
+                .map(|download| i64::from(download.downloads))
+                .sum();
+
+        let version_counts: Vec<(bool, i64, Option<NaiveDateTime>)> = versions::table
+            .filter(versions::crate_id.eq(crate_id))
+            .filter(versions::deleted.eq(false))
+            .group_by(versions::yanked)
+            .select((versions::yanked, count_star(), max(versions::created_at)))
+            .load(conn)?;
+
+        let version_count: i64 = version_counts.iter().map(|(_, count, _)| count).sum();
+        let yanked_count: i64 = version_counts
+            .iter()
+            .filter(|(yanked, _, _)| *yanked)
+            .map(|(_, count, _)| count)
+            .sum();
+        let yanked_version_ratio = match version_count {
+            0 => 0.0,
+            _ => yanked_count as f64 / version_count as f64,
+        };
+
+        let last_published_at = version_counts
+            .iter()
+            .filter_map(|(_, _, created_at)| *created_at)
+            .max();
+        let days_since_last_publish =
+            last_published_at.map(|created_at| (Utc::now().naive_utc() - created_at).num_days());
+
+        Ok(Json(json!({
+            "recent_downloads": recent_downloads,
+            "yanked_version_ratio": yanked_version_ratio,
+            "days_since_last_publish": days_since_last_publish,
+            "version_count": version_count,
+            "meta": { "days": DEFAULT_DOWNLOADS_DAYS },
+        })))
+    })
+    .await
+}
+
+/// Divides the lifetime downloads by the number of full days since the first
+/// version was published. Crates that were published less than a day ago
+/// count as one day old, to avoid dividing by zero.
+fn downloads_per_day(downloads: i64, first_published_at: NaiveDateTime) -> f64 {
+    let age = Utc::now().naive_utc() - first_published_at;
+    let days = age.num_days().max(1);
+    downloads as f64 / days as f64
+}
+
+type VersionWithPublisher = (Version, Option<User>, Vec<(VersionOwnerAction, User)>);
+
+/// Loads all versions of the crate, sorted by their semver version in
+/// descending order, together with their publishers and audit actions.
+fn load_versions_with_publishers(
+    krate: &Crate,
+    conn: &mut PgConnection,
+) -> QueryResult<Vec<VersionWithPublisher>> {
+    let mut versions_and_publishers: Vec<(Version, Option<User>)> = krate
+        .all_versions()
+        .left_outer_join(users::table)
+        .select((versions::all_columns, users::all_columns.nullable()))
+        .load(conn)?;
+    versions_and_publishers
+        .sort_by_cached_key(|(version, _)| Reverse(semver::Version::parse(&version.num).ok()));
+
+    let versions = versions_and_publishers
+        .iter()
+        .map(|(v, _)| v)
+        .cloned()
+        .collect::<Vec<_>>();
+
+    Ok(versions_and_publishers
+        .into_iter()
+        .zip(VersionOwnerAction::for_versions(conn, &versions)?)
+        .map(|((v, pb), aas)| (v, pb, aas))
+        .collect())
+}
+
 #[derive(Debug)]
 struct ShowIncludeMode {
     versions: bool,
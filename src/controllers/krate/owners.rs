@@ -3,9 +3,14 @@
 use crate::auth::AuthCheck;
 use crate::controllers::prelude::*;
 use crate::models::token::EndpointScope;
-use crate::models::{Crate, Owner, Rights, Team, User};
+use crate::models::{
+    insert_crate_owner_action, Crate, CrateOwnerAction, Owner, OwnerAction, OwnerKind, Rights,
+    Team, User,
+};
+use crate::schema::{teams, users};
 use crate::util::errors::{bad_request, crate_not_found, custom};
-use crate::views::EncodableOwner;
+use crate::views::{EncodableOwner, EncodableOwnerAction};
+use std::collections::HashMap;
 use tokio::runtime::Handle;
 
 /// Handles the `GET /crates/:crate_id/owners` route.
@@ -66,6 +71,80 @@ pub async fn owner_user(state: AppState, Path(crate_name): Path<String>) -> AppR
     .await
 }
 
+/// Handles the `GET /crates/:crate_id/owner-history` route.
+///
+/// Returns the owners that were added to or removed from the crate in
+/// chronological order, together with the users that performed the changes.
+/// Only owners of the crate and admins are allowed to see the history.
+pub async fn owner_history(
+    app: AppState,
+    Path(crate_name): Path<String>,
+    req: Parts,
+) -> AppResult<Json<Value>> {
+    spawn_blocking(move || {
+        let conn = &mut *app.db_read_prefer_primary()?;
+        let auth = AuthCheck::default().check(&req, conn)?;
+        let user = auth.user();
+
+        let krate: Crate = Crate::by_name(&crate_name)
+            .first(conn)
+            .optional()?
+            .ok_or_else(|| crate_not_found(&crate_name))?;
+
+        if !user.is_admin {
+            let owners = krate.owners(conn)?;
+            if Handle::current().block_on(user.rights(&app, &owners))? != Rights::Full {
+                return Err(custom(
+                    StatusCode::FORBIDDEN,
+                    "only owners have permission to view the ownership history",
+                ));
+            }
+        }
+
+        let actions = CrateOwnerAction::by_crate(conn, krate.id)?;
+
+        let ids_of_kind = |kind: OwnerKind| {
+            actions
+                .iter()
+                .filter(|(action, _)| action.owner_kind == kind)
+                .map(|(action, _)| action.owner_id)
+                .collect::<Vec<_>>()
+        };
+
+        let mut owners = HashMap::new();
+        let user_ids = ids_of_kind(OwnerKind::User);
+        for owner in users::table
+            .filter(users::id.eq_any(user_ids))
+            .load::<User>(conn)?
+        {
+            owners.insert((OwnerKind::User, owner.id), Owner::User(owner).into());
+        }
+        let team_ids = ids_of_kind(OwnerKind::Team);
+        for owner in teams::table
+            .filter(teams::id.eq_any(team_ids))
+            .load::<Team>(conn)?
+        {
+            owners.insert((OwnerKind::Team, owner.id), Owner::Team(owner).into());
+        }
+
+        let history = actions
+            .into_iter()
+            .filter_map(|(action, user)| {
+                let owner: &EncodableOwner = owners.get(&(action.owner_kind, action.owner_id))?;
+                Some(EncodableOwnerAction {
+                    action: action.action.into(),
+                    owner: owner.clone(),
+                    user: user.into(),
+                    time: action.time,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        Ok(Json(json!({ "history": history })))
+    })
+    .await
+}
+
 /// Handles the `PUT /crates/:crate_id/owners` route.
 pub async fn add_owners(
     app: AppState,
@@ -148,7 +227,15 @@ fn modify_owners(
             msgs.join(",")
         } else {
             for login in &logins {
-                krate.owner_remove(conn, login)?;
+                let Some(owner) = krate.owner_remove(conn, login)? else {
+                    continue;
+                };
+                let owner_kind = match owner {
+                    Owner::User(_) => OwnerKind::User,
+                    Owner::Team(_) => OwnerKind::Team,
+                };
+                let action = OwnerAction::Remove;
+                insert_crate_owner_action(conn, krate.id, owner.id(), owner_kind, user.id, action)?;
             }
             if User::owning(&krate, conn)?.is_empty() {
                 return Err(bad_request(
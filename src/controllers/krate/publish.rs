@@ -3,7 +3,7 @@
 use crate::auth::AuthCheck;
 use crate::worker::jobs::{self, CheckTyposquat};
 use axum::body::Bytes;
-use cargo_manifest::{Dependency, DepsSet, TargetDepsSet};
+use cargo_manifest::{Dependency, DepsSet, Edition, TargetDepsSet};
 use crates_io_tarball::{process_tarball, TarballError};
 use crates_io_worker::BackgroundJob;
 use diesel::connection::DefaultLoadingMode;
@@ -141,6 +141,7 @@ pub async fn publish(app: AppState, req: BytesRequest) -> AppResult<Json<GoodCra
         let documentation = package.documentation.map(|it| it.as_local().unwrap());
         let repository = package.repository.map(|it| it.as_local().unwrap());
         let rust_version = package.rust_version.map(|rv| rv.as_local().unwrap());
+        let edition = package.edition.map(|it| it.as_local().unwrap()).unwrap_or_default();
 
         // Make sure required fields are provided
         fn empty(s: Option<&String>) -> bool {
@@ -346,6 +347,7 @@ pub async fn publish(app: AppState, req: BytesRequest) -> AppResult<Json<GoodCra
                 hex_cksum,
                 package.links,
                 rust_version,
+                Some(edition_name(edition).to_string()),
             )?
             .save(conn, &verified_email_address)?;
 
@@ -511,6 +513,15 @@ fn missing_metadata_error_message(missing: &[&str]) -> String {
     )
 }
 
+/// Returns the name of the edition as it is written in `Cargo.toml` files.
+fn edition_name(edition: Edition) -> &'static str {
+    match edition {
+        Edition::E2015 => "2015",
+        Edition::E2018 => "2018",
+        Edition::E2021 => "2021",
+    }
+}
+
 fn validate_rust_version(value: &str) -> AppResult<()> {
     match semver::VersionReq::parse(value) {
         // Exclude semver operators like `^` and pre-release identifiers
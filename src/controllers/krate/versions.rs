@@ -1,15 +1,18 @@
 //! Endpoint for versions of a crate
 
 use std::cmp::Reverse;
+use std::collections::HashMap;
 
+use chrono::{Duration, Utc};
 use diesel::connection::DefaultLoadingMode;
 use indexmap::IndexMap;
 
 use crate::controllers::frontend_prelude::*;
 use crate::controllers::helpers::pagination::{encode_seek, Page, PaginationOptions};
+use crate::controllers::helpers::window::{parse_window, MAX_WINDOW_DAYS};
 
 use crate::models::{Crate, User, Version, VersionOwnerAction};
-use crate::schema::{crates, users, versions};
+use crate::schema::{crates, users, version_downloads, versions};
 use crate::util::errors::crate_not_found;
 use crate::views::EncodableVersion;
 
@@ -68,6 +71,91 @@ pub async fn versions(
     .await
 }
 
+/// Number of days used by the `popular_versions` endpoint when no `window`
+/// parameter is passed.
+const DEFAULT_POPULAR_WINDOW_DAYS: i64 = 7;
+
+#[derive(Serialize)]
+struct PopularVersion {
+    #[serde(flatten)]
+    version: EncodableVersion,
+    recent_downloads: i64,
+}
+
+/// Handles the `GET /crates/:crate_id/versions/popular` route.
+///
+/// Returns the versions of the crate that were downloaded in the last `window`
+/// days (including today), ordered by the number of downloads in that window.
+pub async fn popular_versions(
+    state: AppState,
+    Path(crate_name): Path<String>,
+    req: Parts,
+) -> AppResult<Json<Value>> {
+    spawn_blocking(move || {
+        use diesel::dsl::sql;
+        use diesel::sql_types::BigInt;
+
+        let conn = &mut *state.db_read()?;
+
+        let crate_id: i32 = Crate::by_name(&crate_name)
+            .select(crates::id)
+            .first(conn)
+            .optional()?
+            .ok_or_else(|| crate_not_found(&crate_name))?;
+
+        let window = match req.query().get("window") {
+            Some(window) => parse_window(window, MAX_WINDOW_DAYS)?,
+            None => DEFAULT_POPULAR_WINDOW_DAYS,
+        };
+
+        let today = Utc::now().date_naive();
+        let start_date = today - Duration::days(window - 1);
+
+        let sum_downloads = sql::<BigInt>("SUM(version_downloads.downloads)");
+        let recent_downloads: Vec<(i32, i64)> = versions::table
+            .inner_join(version_downloads::table)
+            .filter(versions::crate_id.eq(crate_id))
+            .filter(version_downloads::date.between(start_date, today))
+            .group_by(versions::id)
+            .select((versions::id, sum_downloads.clone()))
+            .order((sum_downloads.desc(), versions::id.desc()))
+            .load(conn)?;
+
+        let version_ids = recent_downloads.iter().map(|(id, _)| *id);
+        let mut versions_and_publishers: HashMap<i32, (Version, Option<User>)> = versions::table
+            .left_outer_join(users::table)
+            .select((versions::all_columns, users::all_columns.nullable()))
+            .filter(versions::id.eq_any(version_ids))
+            .load::<(Version, Option<User>)>(conn)?
+            .into_iter()
+            .map(|(version, user)| (version.id, (version, user)))
+            .collect();
+
+        let (versions, publishers): (Vec<_>, Vec<_>) = recent_downloads
+            .iter()
+            .filter_map(|(id, _)| versions_and_publishers.remove(id))
+            .unzip();
+
+        let versions = versions
+            .iter()
+            .cloned()
+            .zip(publishers)
+            .zip(VersionOwnerAction::for_versions(conn, &versions)?)
+            .zip(recent_downloads)
+            .map(|(((version, publisher), actions), (_, recent_downloads))| {
+                let version = EncodableVersion::from(version, &crate_name, publisher, actions);
+                PopularVersion {
+                    version,
+                    recent_downloads,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        Ok(Json(json!({ "versions": versions })))
+    })
+    .await
+}
+
 /// Seek-based pagination of versions by date
 ///
 /// # Panics
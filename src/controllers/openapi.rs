@@ -0,0 +1,181 @@
+//! Machine-readable OpenAPI descriptions of parts of the API.
+//!
+//! The response schemas and query parameters are derived via `schemars` from
+//! the view types and the query types next to the handlers, so that their
+//! descriptions stay close to the code that produces and parses them. The
+//! status codes are listed manually and have to be kept in sync with the
+//! handlers.
+
+use crate::controllers::version::downloads::{DownloadQuery, DownloadsQuery};
+use crate::views::DownloadsResponse;
+use axum::response::IntoResponse;
+use axum::Json;
+use schemars::gen::{SchemaGenerator, SchemaSettings};
+use schemars::JsonSchema;
+use serde_json::Value;
+
+/// Handles the `GET /openapi/downloads.json` route.
+///
+/// Returns an OpenAPI 3.0 document describing the download endpoints.
+pub async fn downloads() -> impl IntoResponse {
+    Json(downloads_document())
+}
+
+fn downloads_document() -> Value {
+    let mut generator = SchemaSettings::openapi3().into_generator();
+    let downloads_response = generator.subschema_for::<DownloadsResponse>();
+    let download_params = query_parameters::<DownloadQuery>(&mut generator);
+    let downloads_params = query_parameters::<DownloadsQuery>(&mut generator);
+    let schemas = generator.take_definitions();
+
+    let path_params = json!([
+        path_parameter("crate_id", "Name of the crate"),
+        path_parameter("version", "Version of the crate, or `latest`"),
+    ]);
+
+    let with_path_params = |params: &[Value]| {
+        let mut all = path_params.as_array().cloned().unwrap_or_default();
+        all.extend_from_slice(params);
+        all
+    };
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "crates.io downloads API",
+            "version": "1",
+        },
+        "paths": {
+            "/api/v1/crates/{crate_id}/{version}/download": {
+                "get": {
+                    "summary": "Download the `.crate` file of a version",
+                    "operationId": "download",
+                    "parameters": with_path_params(&download_params),
+                    "responses": {
+                        "200": {
+                            "description": "The download URL, for requests that accept JSON or pass `no_redirect`, or the crate file, if it is proxied from a local storage backend",
+                            "content": {
+                                "application/json": {
+                                    "schema": download_url_schema(),
+                                },
+                                "text/plain": {
+                                    "schema": { "type": "string" },
+                                },
+                                "application/gzip": {
+                                    "schema": { "type": "string", "format": "binary" },
+                                },
+                            },
+                        },
+                        "302": {
+                            "description": "Redirect to the download URL",
+                        },
+                        "307": {
+                            "description": "Redirect to the download URL, if `download_redirect_307` is enabled",
+                        },
+                        "400": {
+                            "description": "Unknown mirror or invalid target",
+                        },
+                        "403": {
+                            "description": "The crate is private and the request is not authorized to download it",
+                        },
+                        "404": {
+                            "description": "The crate or version does not exist",
+                        },
+                        "410": {
+                            "description": "The version was deleted",
+                        },
+                        "429": {
+                            "description": "The download rate limit was exceeded",
+                        },
+                    },
+                },
+            },
+            "/api/v1/crates/{crate_id}/{version}/downloads": {
+                "get": {
+                    "summary": "Daily download counts of a version",
+                    "operationId": "version_downloads",
+                    "parameters": with_path_params(&downloads_params),
+                    "responses": {
+                        "200": {
+                            "description": "The download counts",
+                            "content": {
+                                "application/json": {
+                                    "schema": downloads_response,
+                                },
+                                "application/javascript": {
+                                    "schema": { "type": "string" },
+                                },
+                            },
+                        },
+                        "304": {
+                            "description": "The download counts did not change",
+                        },
+                        "308": {
+                            "description": "Redirect to the canonical version",
+                        },
+                        "400": {
+                            "description": "Invalid query parameters",
+                        },
+                        "404": {
+                            "description": "The crate or version does not exist",
+                        },
+                    },
+                },
+            },
+        },
+        "components": {
+            "schemas": schemas,
+        },
+    })
+}
+
+fn path_parameter(name: &str, description: &str) -> Value {
+    json!({
+        "name": name,
+        "in": "path",
+        "required": true,
+        "description": description,
+        "schema": { "type": "string" },
+    })
+}
+
+/// Describes the fields of `T` as query parameters, using the doc comments of
+/// the fields as their descriptions.
+fn query_parameters<T: JsonSchema>(generator: &mut SchemaGenerator) -> Vec<Value> {
+    let schema = generator.root_schema_for::<T>().schema;
+    let object = schema.object.unwrap_or_default();
+
+    object
+        .properties
+        .into_iter()
+        .map(|(name, schema)| {
+            let mut schema = schema.into_object();
+            let description = schema.metadata.take().and_then(|m| m.description);
+            // Missing parameters are described by `required` instead
+            schema.extensions.remove("nullable");
+
+            json!({
+                "name": name,
+                "in": "query",
+                "required": object.required.contains(&name),
+                "description": description,
+                "schema": schema,
+            })
+        })
+        .collect()
+}
+
+/// The JSON response of the download endpoint is built ad hoc, so its schema
+/// is written out manually.
+fn download_url_schema() -> Value {
+    json!({
+        "type": "object",
+        "required": ["url"],
+        "properties": {
+            "url": { "type": "string" },
+            "backend": { "type": "string" },
+            "checksum": { "type": "string" },
+            "expires_at": { "type": "string", "format": "date-time" },
+        },
+    })
+}
@@ -1,11 +1,17 @@
 use crate::controllers::frontend_prelude::*;
+use crate::controllers::helpers::window::{parse_window, DEFAULT_WINDOW_DAYS, MAX_WINDOW_DAYS};
 use bigdecimal::{BigDecimal, ToPrimitive};
+use chrono::{Duration, Utc};
 
 use crate::models::{CrateOwner, OwnerKind, User};
-use crate::schema::{crate_downloads, crate_owners, crates, users};
+use crate::schema::{crate_downloads, crate_owners, crates, users, version_downloads, versions};
 use crate::sql::lower;
 use crate::views::EncodablePublicUser;
 
+/// Maximum number of crates returned by the `GET /users/:user_id/downloads`
+/// endpoint.
+const MAX_TOP_CRATES: i64 = 10;
+
 /// Handles the `GET /users/:user_id` route.
 pub async fn show(state: AppState, Path(user_name): Path<String>) -> AppResult<Json<Value>> {
     spawn_blocking(move || {
@@ -43,3 +49,68 @@ pub async fn stats(state: AppState, Path(user_id): Path<i32>) -> AppResult<Json<
     })
     .await
 }
+
+/// Handles the `GET /users/:user_id/downloads` route.
+///
+/// Sums the downloads within the last `window` days (including today) across
+/// all crates that the user currently owns, and lists the crates that
+/// contributed the most downloads.
+pub async fn downloads(
+    state: AppState,
+    Path(user_id): Path<i32>,
+    req: Parts,
+) -> AppResult<Json<Value>> {
+    spawn_blocking(move || {
+        use diesel::dsl::sql;
+        use diesel::sql_types::BigInt;
+
+        let window = match req.query().get("window") {
+            Some(window) => parse_window(window, MAX_WINDOW_DAYS)?,
+            None => DEFAULT_WINDOW_DAYS,
+        };
+
+        let today = Utc::now().date_naive();
+        let start = today - Duration::days(window - 1);
+
+        let conn = &mut *state.db_read()?;
+
+        let owned_downloads = || {
+            crate_owners::table
+                .inner_join(crates::table)
+                .inner_join(versions::table.on(crates::id.eq(versions::crate_id)))
+                .inner_join(
+                    version_downloads::table.on(versions::id.eq(version_downloads::version_id)),
+                )
+                .filter(crate_owners::deleted.eq(false))
+                .filter(crate_owners::owner_kind.eq(OwnerKind::User))
+                .filter(crate_owners::owner_id.eq(user_id))
+                .filter(version_downloads::date.between(start, today))
+        };
+
+        let total_downloads: i64 = owned_downloads()
+            .select(sql::<BigInt>(
+                "COALESCE(SUM(version_downloads.downloads), 0)",
+            ))
+            .get_result(conn)?;
+
+        let sum_downloads = sql::<BigInt>("SUM(version_downloads.downloads)");
+        let top_crates: Vec<(String, i64)> = owned_downloads()
+            .group_by(crates::id)
+            .select((crates::name, sum_downloads.clone()))
+            .order((sum_downloads.desc(), crates::name.asc()))
+            .limit(MAX_TOP_CRATES)
+            .load(conn)?;
+
+        let crates = top_crates
+            .into_iter()
+            .map(|(name, downloads)| json!({ "name": name, "downloads": downloads }))
+            .collect::<Vec<_>>();
+
+        Ok(Json(json!({
+            "total_downloads": total_downloads,
+            "crates": crates,
+            "meta": { "window": window },
+        })))
+    })
+    .await
+}
@@ -5,9 +5,14 @@ pub mod yank;
 use super::prelude::*;
 
 use crate::models::{Crate, Version};
-use crate::util::errors::crate_not_found;
+use crate::schema::{crates, versions};
+use crate::util::errors::{crate_not_found, version_deleted};
 
-fn version_and_crate(
+/// Looks up a version and its crate.
+///
+/// Versions that were permanently deleted result in a `410 Gone` error,
+/// while versions that never existed result in a `404 Not Found` error.
+pub(crate) fn version_and_crate(
     conn: &mut PgConnection,
     crate_name: &str,
     semver: &str,
@@ -18,6 +23,77 @@ fn version_and_crate(
         .ok_or_else(|| crate_not_found(crate_name))?;
 
     let version = krate.find_version(conn, semver)?;
+    if version.deleted {
+        return Err(version_deleted(&krate.name, &version.num));
+    }
 
     Ok((version, krate))
 }
+
+/// Looks up the ids of a version and its crate in a single query, for
+/// callers that don't need the full models.
+///
+/// Unlike [`version_and_crate`] the crate name has to match exactly.
+#[instrument("db.query", skip(conn), fields(message = "SELECT ... FROM versions"))]
+pub(crate) fn version_and_crate_ids(
+    krate: &str,
+    version: &str,
+    conn: &mut PgConnection,
+) -> QueryResult<(i32, i32)> {
+    versions::table
+        .inner_join(crates::table)
+        .select((versions::id, crates::id))
+        .filter(crates::name.eq(krate))
+        .filter(versions::num.eq(version))
+        .first(conn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::email::Emails;
+    use crate::models::{NewCrate, NewUser, NewVersion};
+    use crate::test_util::test_db_connection;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_version_and_crate_ids() {
+        let (_test_db, conn) = &mut test_db_connection();
+
+        let user = NewUser::new(2, "login", None, None, "access_token")
+            .create_or_update(None, &Emails::new_in_memory(), conn)
+            .unwrap();
+
+        let krate = NewCrate {
+            name: "foo",
+            ..Default::default()
+        }
+        .create(conn, user.id)
+        .unwrap();
+
+        let version = NewVersion::new(
+            krate.id,
+            &semver::Version::parse("1.0.0").unwrap(),
+            &BTreeMap::new(),
+            None,
+            0,
+            user.id,
+            "0000000000000000000000000000000000000000000000000000000000000000".to_string(),
+            None,
+            None,
+            None,
+        )
+        .unwrap()
+        .save(conn, "someone@example.com")
+        .unwrap();
+
+        let ids = version_and_crate_ids("foo", "1.0.0", conn).unwrap();
+        assert_eq!(ids, (version.id, krate.id));
+
+        let result = version_and_crate_ids("foo", "2.0.0", conn);
+        assert_eq!(result, Err(diesel::NotFound));
+
+        let result = version_and_crate_ids("bar", "1.0.0", conn);
+        assert_eq!(result, Err(diesel::NotFound));
+    }
+}
@@ -3,69 +3,2432 @@
 //! Crate level functionality is located in `krate::downloads`.
 
 use super::version_and_crate;
-use crate::controllers::prelude::*;
-use crate::models::VersionDownload;
+use crate::auth::AuthCheck;
+use crate::controllers::frontend_prelude::*;
+use crate::controllers::helpers::pagination::PaginationOptions;
+use crate::download_events::DownloadEvent;
+use crate::headers::{X_CRATE_CHECKSUM, X_CRATE_YANKED, X_DOWNLOAD_ID, X_REQUEST_ID};
+use crate::middleware::log_request::RequestLogExt;
+use crate::middleware::real_ip::RealIp;
+use crate::models::token::EndpointScope;
+use crate::models::{ApiToken, Crate, Rights, Version, VersionDownload};
 use crate::schema::*;
-use crate::util::errors::version_not_found;
-use crate::views::EncodableVersionDownload;
-use chrono::{Duration, NaiveDate, Utc};
+use crate::storage::crate_file_key;
+use crate::util::errors::{
+    crate_not_found, custom, forbidden, internal, localized_crate_not_found,
+    localized_version_deleted, not_found, version_deleted, version_not_found,
+    version_not_found_with_latest, version_not_found_with_suggestions, DownloadRateLimited, Locale,
+};
+use crate::util::rfc3339;
+use crate::views::{
+    CompactDownloadsResponse, DownloadsMeta, DownloadsResponse, EncodableVersionDownload,
+};
+use crate::worker::jobs::HOURLY_DOWNLOADS_RETENTION_HOURS;
+use axum::body::Body;
+use axum::response::AppendHeaders;
+use axum::BoxError;
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use chrono_tz::Tz;
+use crates_io_cdn_logs::{is_valid_target, DownloadSource};
+use futures_util::stream;
+use indexmap::IndexMap;
+use schemars::JsonSchema;
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::time::Instant;
+use tokio::runtime::Handle;
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+/// Number of days of download history returned by the download endpoints,
+/// unless a different window is requested or the crate has a custom
+/// `download_retention_days` setting.
+pub(crate) const DEFAULT_DOWNLOADS_DAYS: i64 = 90;
+
+/// Maximum number of days of download history that can be requested via the
+/// `days` parameter or configured via `download_retention_days`. Larger
+/// values are clamped to this limit.
+const MAX_DOWNLOADS_DAYS: i64 = 365;
+
+/// Number of CSV or NDJSON rows that may be buffered before reading from the
+/// database is paused until the client has caught up.
+const STREAM_CHANNEL_CAPACITY: usize = 100;
+
+/// Number of CSV or NDJSON rows that are loaded from the database at once.
+/// The connection is returned to the pool between these chunks, so that slow
+/// clients don't keep it checked out for the whole export.
+const STREAM_CHUNK_ROWS: i64 = 100;
+
+/// The query parameters of the [`download`] handler.
+///
+/// The handler reads the parameters from the query string itself. This type
+/// only describes them for the OpenAPI document in `controllers::openapi`.
+#[allow(dead_code)] // The fields are only used for the schema
+#[derive(JsonSchema)]
+pub(crate) struct DownloadQuery {
+    /// Name of a configured mirror to redirect to.
+    mirror: Option<String>,
+    /// Target triple the download is counted for.
+    target: Option<String>,
+    /// `backend` to include the storage backend in the JSON response.
+    include: Option<String>,
+    /// `1` or `true` to return the download URL as plain text.
+    no_redirect: Option<String>,
+}
+
+/// Content type of newline-delimited JSON responses.
+const NDJSON_CONTENT_TYPE: &str = "application/x-ndjson";
+
+/// Number of NDJSON rows after which a `{"flushed": N}` progress marker is
+/// streamed, so that slow clients of large exports see progress.
+const NDJSON_FLUSH_MARKER_ROWS: usize = 100;
+
+/// Number of days that are rendered by the `sparkline` endpoint.
+const SPARKLINE_DAYS: i64 = 30;
+
+/// Width and height of the images rendered by the `sparkline` endpoint.
+const SPARKLINE_WIDTH: u32 = 100;
+const SPARKLINE_HEIGHT: u32 = 20;
+
+/// The `Cache-Control` header of the `sparkline` endpoint. Download counts
+/// are processed in batches, so the images may be cached for a while.
+const SPARKLINE_CACHE_CONTROL: &str = "public,max-age=3600";
+
+/// Number of days returned by the `peaks` endpoint when no `limit` parameter
+/// is passed.
+const DEFAULT_PEAKS_LIMIT: i64 = 10;
+
+/// Maximum number of days that can be requested from the `peaks` endpoint.
+/// Larger values are clamped to this limit.
+const MAX_PEAKS_LIMIT: i64 = 30;
+
+/// Fields of [`EncodableVersionDownload`] that can be selected via the
+/// `fields` parameter of the `downloads` endpoint.
+const DOWNLOAD_FIELDS: &[&str] = &[
+    "version",
+    "downloads",
+    "date",
+    "cumulative",
+    "migrated",
+    "label",
+];
+
+/// Maximum number of similar versions suggested when an invalid version is
+/// requested.
+const MAX_VERSION_SUGGESTIONS: usize = 5;
+
+/// How often the `downloads/watch` endpoint checks for new download data.
+const WATCH_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
 
 /// Handles the `GET /crates/:crate_id/:version/download` route.
 /// This returns a URL to the location where the crate is stored.
+///
+/// JSON responses include the kind of storage backend that the URL points to
+/// when `include=backend` is passed, to help with debugging mirror setups.
+///
+/// Passing `mirror=<id>` returns the location on one of the configured
+/// download mirrors instead of the default storage location.
+///
+/// Passing `target=<triple>` forwards the target to the CDN, so that the
+/// download is also counted for that target.
+///
+/// `HEAD` requests are routed here too, so mirrors can probe for the redirect
+/// target without receiving a body. These probes are not counted as
+/// downloads, since download counts are derived from the `GET` requests in
+/// the CDN logs.
+///
+/// Partial versions like `1.2` or `1` are resolved to the highest matching
+/// version that is not yanked or a pre-release, e.g. `1.2.5`. Full versions
+/// are redirected to even if they don't exist in the database.
+///
+/// The `latest` version resolves to the highest version that is not yanked.
+/// If all versions of the crate are yanked, the highest yanked version is
+/// used instead, and the response includes an `X-Crate-Yanked: true` header.
+///
+/// If signed URLs are enabled for the storage backend, the download is
+/// redirected to a pre-signed, time-limited URL of the crate file, and the
+/// JSON response includes the `expires_at` time of the URL.
+///
+/// If a `download_base_url` is configured, relative storage locations are
+/// joined with it, so that clients behind a reverse proxy receive absolute
+/// URLs in both the JSON and the redirect responses.
+///
+/// If a SHA-256 checksum is stored for the version, it is included in the
+/// JSON response, so that clients can verify the downloaded file. Redirects
+/// only include it in the `X-Crate-Checksum` header if
+/// `download_version_lookup` is enabled.
+///
+/// Versions that were permanently deleted respond with `410 Gone`, which is
+/// also only checked for redirects if `download_version_lookup` is enabled.
+///
+/// Passing `no_redirect=1` responds with `200 OK` and the resolved download
+/// URL as plain text instead of redirecting, which is useful for debugging.
+/// These requests don't publish download events.
+///
+/// If download events are enabled, an event is published for every `GET`
+/// request of a version that exists in the database.
+///
+/// If a download rate limit is configured, clients that exceed it receive a
+/// `429 Too Many Requests` response, unless the request is authenticated with
+/// an API token.
+///
+/// Plain redirects of full versions don't query the database, so that they
+/// keep working without a database connection. Partial versions, JSON
+/// responses, download events, rate limited clients, private crate downloads
+/// and `download_version_lookup` all need a database query.
 pub async fn download(
     app: AppState,
     Path((crate_name, version)): Path<(String, String)>,
     req: Parts,
+) -> AppResult<Response> {
+    check_download_rate_limit(&app, &req).await?;
+    check_download_access(&app, &req, &crate_name).await?;
+
+    let (crate_name, version, yanked) = if version == "latest" {
+        resolve_latest_version(&app, crate_name).await?
+    } else if is_version_prefix(&version) {
+        let (crate_name, version) = resolve_version_prefix(&app, crate_name, version).await?;
+        (crate_name, version, false)
+    } else {
+        (crate_name, version, false)
+    };
+
+    download_version(app, req, crate_name, version, yanked).await
+}
+
+/// Responds to a download request of the given, fully resolved version, as
+/// described on the [`download`] handler.
+///
+/// Callers have to run [`check_download_access`] before resolving the
+/// version, so that the responses don't reveal the versions of private crates.
+///
+/// `yanked` is only used for the `X-Crate-Yanked` header of `latest`
+/// downloads, since looking it up would need a database query.
+async fn download_version(
+    app: AppState,
+    req: Parts,
+    crate_name: String,
+    version: String,
+    yanked: bool,
 ) -> AppResult<Response> {
     let wants_json = req.wants_json();
-    let redirect_url = app.storage.crate_location(&crate_name, &version);
-    if wants_json {
-        Ok(Json(json!({ "url": redirect_url })).into_response())
+    let no_redirect = req
+        .query()
+        .get("no_redirect")
+        .is_some_and(|value| value == "1" || value == "true");
+
+    let start_instant = Instant::now();
+    let mut location = app.storage.crate_location_detailed(&crate_name, &version);
+    let signed_location = app
+        .storage
+        .signed_crate_location(&crate_name, &version)
+        .await
+        .map_err(|error| internal(format!("failed to sign download URL: {error}")))?;
+    app.instance_metrics
+        .crate_download_redirect_duration_seconds
+        .with_label_values(&[&wants_json.to_string(), location.backend.as_str()])
+        .observe(start_instant.elapsed().as_secs_f64());
+
+    let mut expires_at = signed_location.as_ref().map(|signed| signed.expires_at);
+    if let Some(signed_location) = signed_location {
+        location.url = signed_location.url;
+    }
+
+    if let Some(base_url) = &app.config.download_base_url {
+        location.url = absolute_download_url(base_url, location.url);
+    }
+
+    if let Some(mirror) = req.query().get("mirror") {
+        let Some(base_url) = app.config.download_mirrors.get(mirror) else {
+            return Err(bad_request(format_args!(
+                "unknown download mirror `{mirror}`"
+            )));
+        };
+        location.url = app
+            .storage
+            .crate_mirror_location(base_url, &crate_name, &version);
+        expires_at = None;
+    }
+    if let Some(target) = req.query().get("target") {
+        if !is_valid_target(target) {
+            return Err(bad_request(format_args!(
+                "invalid `target` parameter `{target}`"
+            )));
+        }
+        // Additional query parameters would invalidate the signature
+        if expires_at.is_some() {
+            return Err(bad_request(
+                "`target` is not supported for signed download URLs",
+            ));
+        }
+        // The CDN logs include the query string, which allows the download to
+        // be counted for the target when the logs are processed.
+        location.url = format!("{}?target={target}", location.url);
+    }
+
+    // The correlation id allows mirror operators to trace a specific download
+    // through our logs and the CDN access records.
+    let correlation_id = req
+        .headers
+        .get(&X_REQUEST_ID)
+        .and_then(|value| value.to_str().ok())
+        .map(ToString::to_string)
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+    req.request_log().add("correlation_id", &correlation_id);
+
+    let needs_version =
+        wants_json || app.download_events.is_enabled() || app.config.download_version_lookup;
+    let downloaded_version = match needs_version {
+        true => load_downloaded_version(&app, &crate_name, &version).await,
+        false => None,
+    };
+    if let Some(downloaded_version) = &downloaded_version {
+        if downloaded_version.deleted {
+            return Err(version_deleted(&crate_name, &version));
+        }
+        if req.method != http::Method::HEAD && !no_redirect && !is_retried_download(&app, &req) {
+            publish_download_event(&app, &req, downloaded_version);
+        }
+    }
+    let checksum = downloaded_version.and_then(|v| v.checksum);
+
+    let response = if no_redirect {
+        let headers = [(header::CONTENT_TYPE, "text/plain; charset=utf-8")];
+        (headers, location.url).into_response()
+    } else if wants_json {
+        let mut json = json!({ "url": location.url });
+        if req.query().get("include").is_some_and(|i| i == "backend") {
+            json["backend"] = json!(location.backend);
+        }
+        if let Some(checksum) = &checksum {
+            json["checksum"] = json!(checksum);
+        }
+        if let Some(expires_at) = expires_at {
+            json["expires_at"] = json!(expires_at);
+        }
+        Json(json).into_response()
     } else {
-        Ok(redirect(redirect_url))
+        let proxy = app.config.proxy_download
+            && location.backend.is_local()
+            && !req.query().contains_key("mirror");
+
+        let mut response = if proxy {
+            proxy_crate_file(&app, &crate_name, &version).await?
+        } else if app.config.download_redirect_307 {
+            temporary_redirect(location.url)
+        } else {
+            redirect(location.url)
+        };
+        if let Some(checksum) = checksum.and_then(|c| header::HeaderValue::from_str(&c).ok()) {
+            response
+                .headers_mut()
+                .insert(X_CRATE_CHECKSUM.clone(), checksum);
+        }
+        response
+    };
+
+    let mut response = ([(X_REQUEST_ID.clone(), correlation_id)], response).into_response();
+    if yanked {
+        let value = header::HeaderValue::from_static("true");
+        response.headers_mut().insert(X_CRATE_YANKED.clone(), value);
     }
+
+    Ok(response)
 }
 
-#[instrument("db.query", skip(conn), fields(message = "SELECT ... FROM versions"))]
-fn get_version_id(krate: &str, version: &str, conn: &mut PgConnection) -> QueryResult<i32> {
-    versions::table
-        .inner_join(crates::table)
-        .select(versions::id)
-        .filter(crates::name.eq(&krate))
-        .filter(versions::num.eq(&version))
-        .first::<i32>(conn)
+/// Joins relative download locations with the configured base URL, without
+/// duplicating the slash between them. Absolute locations, e.g. of a CDN, are
+/// returned unchanged.
+fn absolute_download_url(base_url: &str, url: String) -> String {
+    match url.strip_prefix('/') {
+        Some(path) => format!("{}/{path}", base_url.trim_end_matches('/')),
+        None => url,
+    }
+}
+
+/// Responds with the contents of the crate file from the storage backend.
+///
+/// This is only used for local storage backends in development setups. The
+/// response is compressed by the `CompressionLayer` if the client accepts it.
+async fn proxy_crate_file(app: &AppState, crate_name: &str, version: &str) -> AppResult<Response> {
+    let bytes = match app.storage.download_crate_file(crate_name, version).await {
+        Ok(bytes) => bytes,
+        Err(object_store::Error::NotFound { .. }) => return Err(not_found()),
+        Err(error) => return Err(internal(format!("failed to load crate file: {error}"))),
+    };
+
+    let headers = [(header::CONTENT_TYPE, "application/gzip")];
+    Ok((headers, bytes).into_response())
+}
+
+/// The database record of a downloaded version.
+struct DownloadedVersion {
+    crate_id: i32,
+    version_id: i32,
+    /// The SHA-256 checksum of the crate file, if one is stored.
+    checksum: Option<String>,
+    deleted: bool,
+}
+
+/// Checks whether the `X-Download-Id` header of the request contains an id
+/// that was already seen within the configured `download_id_ttl`, which means
+/// that the download is a retry that must not be counted again.
+///
+/// Requests without a valid UUID in the header are never treated as retries.
+fn is_retried_download(app: &AppState, req: &Parts) -> bool {
+    let Some(id) = req
+        .headers
+        .get(&X_DOWNLOAD_ID)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| Uuid::parse_str(value).ok())
+    else {
+        return false;
+    };
+
+    !app.recent_download_ids
+        .insert(id, app.config.download_id_ttl)
+}
+
+/// Publishes a download event for internal analytics, if enabled.
+///
+/// This never waits for the event to be consumed. If the channel is full,
+/// the event is dropped and counted in the `download_events_dropped_total`
+/// metric.
+fn publish_download_event(app: &AppState, req: &Parts, version: &DownloadedVersion) {
+    if !app.download_events.is_enabled() {
+        return;
+    }
+
+    let source = req
+        .headers
+        .get(header::USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|user_agent| app.config.download_source_classifier.classify(user_agent));
+
+    let event = DownloadEvent {
+        crate_id: version.crate_id,
+        version_id: version.version_id,
+        timestamp: Utc::now(),
+        source,
+    };
+
+    if app.download_events.publish(event).is_err() {
+        app.instance_metrics.download_events_dropped_total.inc();
+    }
+}
+
+/// Loads the ids and the SHA-256 checksum of the crate file of the given
+/// version.
+///
+/// `None` is returned if the version does not exist. The download must not
+/// fail because of this lookup, so database errors are only logged.
+async fn load_downloaded_version(
+    app: &AppState,
+    crate_name: &str,
+    version: &str,
+) -> Option<DownloadedVersion> {
+    let app = app.clone();
+    let crate_name = crate_name.to_string();
+    let version = version.to_string();
+
+    let result = spawn_blocking(move || {
+        let conn = &mut *app.db_read()?;
+        let row: Option<(i32, i32, String, bool)> = versions::table
+            .inner_join(crates::table)
+            .filter(Crate::with_name(&crate_name))
+            .filter(versions::num.eq(&version))
+            .select((
+                versions::crate_id,
+                versions::id,
+                versions::checksum,
+                versions::deleted,
+            ))
+            .first(conn)
+            .optional()?;
+
+        Ok::<_, BoxedAppError>(row)
+    })
+    .await;
+
+    match result {
+        Ok(row) => row.map(|(crate_id, version_id, checksum, deleted)| {
+            let checksum = checksum.trim().to_string();
+            DownloadedVersion {
+                crate_id,
+                version_id,
+                checksum: (!checksum.is_empty()).then_some(checksum),
+                deleted,
+            }
+        }),
+        Err(error) => {
+            warn!(%error, "Failed to load the downloaded version");
+            None
+        }
+    }
+}
+
+/// Checks whether the `version` path segment of the download endpoint is a
+/// partial version consisting of only a major, or a major and minor version.
+fn is_version_prefix(version: &str) -> bool {
+    let parts = version.split('.').collect::<Vec<_>>();
+    parts.len() <= 2
+        && parts
+            .iter()
+            .all(|part| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// Resolves a partial version like `1.2` to the highest non-yanked version of
+/// the crate that matches it, and returns the canonical crate name and the
+/// full version number.
+async fn resolve_version_prefix(
+    app: &AppState,
+    crate_name: String,
+    prefix: String,
+) -> AppResult<(String, String)> {
+    let app = app.clone();
+    spawn_blocking(move || {
+        let conn = &mut *app.db_read()?;
+        let krate: Crate = Crate::by_name(&crate_name)
+            .first(conn)
+            .optional()?
+            .ok_or_else(|| crate_not_found(&crate_name))?;
+
+        let req = semver::VersionReq::parse(&format!("={prefix}"))
+            .map_err(|_| version_not_found(&krate.name, &prefix))?;
+
+        let nums: Vec<String> = Version::belonging_to(&krate)
+            .filter(versions::yanked.eq(false))
+            .filter(versions::deleted.eq(false))
+            .select(versions::num)
+            .load(conn)?;
+
+        let resolved = nums
+            .into_iter()
+            .filter_map(|num| {
+                semver::Version::parse(&num)
+                    .ok()
+                    .map(|semver| (semver, num))
+            })
+            .filter(|(semver, _)| req.matches(semver))
+            .max_by(|(a, _), (b, _)| a.cmp(b))
+            .map(|(_, num)| num)
+            .ok_or_else(|| version_not_found(&krate.name, &prefix))?;
+
+        Ok((krate.name, resolved))
+    })
+    .await
+}
+
+/// Resolves the `latest` version of a crate to its highest non-yanked
+/// version, preferring stable versions over pre-releases, and returns the
+/// canonical crate name, the full version number and whether the version is
+/// yanked.
+///
+/// If all versions are yanked, the highest yanked version is returned.
+async fn resolve_latest_version(
+    app: &AppState,
+    crate_name: String,
+) -> AppResult<(String, String, bool)> {
+    let app = app.clone();
+    spawn_blocking(move || {
+        let conn = &mut *app.db_read()?;
+        let krate: Crate = Crate::by_name(&crate_name)
+            .first(conn)
+            .optional()?
+            .ok_or_else(|| crate_not_found(&crate_name))?;
+
+        let versions: Vec<(String, bool)> = Version::belonging_to(&krate)
+            .filter(versions::deleted.eq(false))
+            .select((versions::num, versions::yanked))
+            .load(conn)?;
+
+        let (_, num, yanked) = versions
+            .into_iter()
+            .filter_map(|(num, yanked)| {
+                semver::Version::parse(&num)
+                    .ok()
+                    .map(|semver| (semver, num, yanked))
+            })
+            .max_by(|(a, _, a_yanked), (b, _, b_yanked)| {
+                let a = (!a_yanked, a.pre.is_empty(), a);
+                let b = (!b_yanked, b.pre.is_empty(), b);
+                a.cmp(&b)
+            })
+            .ok_or_else(|| version_not_found(&krate.name, "latest"))?;
+
+        Ok((krate.name, num, yanked))
+    })
+    .await
+}
+
+/// Checks the download rate limit of the client IP address, if one is
+/// configured. Requests that are authenticated with an API token are exempt.
+async fn check_download_rate_limit(app: &AppState, req: &Parts) -> AppResult<()> {
+    if let Some(real_ip) = req.extensions.get::<RealIp>() {
+        if let Err(retry_after) = app.download_rate_limiter.take_token(**real_ip) {
+            if !has_valid_api_token(app, req).await? {
+                return Err(Box::new(DownloadRateLimited { retry_after }));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks whether the request is allowed to download the given crate.
+///
+/// Public crates, and crates that don't exist, can be downloaded by anyone.
+/// If `private_crate_files` is enabled, downloads of private crates require
+/// an API token with the `download` endpoint scope for the crate, whose user
+/// has at least `Publish` rights for the crate. Cookie sessions and legacy
+/// tokens without endpoint scopes are rejected with a `403 Forbidden`
+/// response, like anonymous requests.
+///
+/// This only protects the crate files. All other endpoints treat private
+/// crates like public ones.
+async fn check_download_access(app: &AppState, req: &Parts, crate_name: &str) -> AppResult<()> {
+    // Skip the database query for the vast majority of downloads
+    if !app.config.private_crate_files {
+        return Ok(());
+    }
+
+    let app = app.clone();
+    let req = req.clone();
+    let crate_name = crate_name.to_string();
+
+    spawn_blocking(move || {
+        let conn = &mut *app.db_read()?;
+        let krate: Option<Crate> = Crate::by_name(&crate_name)
+            .filter(crates::private.eq(true))
+            .first(conn)
+            .optional()?;
+        let Some(krate) = krate else {
+            return Ok(());
+        };
+
+        // Token authentication updates the `last_used_at` of the token
+        let conn = &mut *app.db_write()?;
+        let auth = AuthCheck::default()
+            .with_endpoint_scope(EndpointScope::Download)
+            .for_crate(&crate_name)
+            .check(&req, conn)?;
+
+        // The check above also accepts cookie sessions and legacy tokens,
+        // which don't have any endpoint scopes
+        let has_download_scope = auth
+            .api_token()
+            .and_then(|token| token.endpoint_scopes.as_ref())
+            .is_some_and(|scopes| scopes.contains(&EndpointScope::Download));
+
+        if !has_download_scope {
+            let cause = "private crate downloads require a token with the `download` scope";
+            req.request_log().add("cause", cause);
+            return Err(forbidden());
+        }
+
+        // Crate scopes can't be limited by the token owner, e.g. `*` matches
+        // every crate, so the user has to be allowed to access the crate too
+        let owners = krate.owners(conn)?;
+        if Handle::current().block_on(auth.user().rights(&app, &owners))? < Rights::Publish {
+            let cause = "private crate downloads require the user to be an owner";
+            req.request_log().add("cause", cause);
+            return Err(forbidden());
+        }
+
+        Ok(())
+    })
+    .await
+}
+
+/// Checks whether the request is authenticated with a valid API token.
+///
+/// This is only used for requests that exceeded the download rate limit,
+/// so that the database is not queried for the vast majority of downloads.
+async fn has_valid_api_token(app: &AppState, req: &Parts) -> AppResult<bool> {
+    let Some(header_value) = req.headers.get(header::AUTHORIZATION) else {
+        return Ok(false);
+    };
+    let Ok(header_value) = header_value.to_str().map(ToString::to_string) else {
+        return Ok(false);
+    };
+
+    let app = app.clone();
+    spawn_blocking(move || {
+        let conn = &mut *app.db_write()?;
+        Ok(ApiToken::find_by_api_token(conn, &header_value).is_ok())
+    })
+    .await
+}
+
+/// Handles the `GET /crates/:crate_id/by-checksum/:prefix/download` route.
+///
+/// Redirects to the crate file of the version whose checksum starts with the
+/// given hex `prefix`, which allows reproducible-build tooling to find the
+/// version that a `.crate` file corresponds to. If more than one version
+/// matches, a `409 Conflict` response is returned instead.
+///
+/// Once the version is found, the request is handled like a download of that
+/// version, see [`download`].
+pub async fn download_by_checksum(
+    app: AppState,
+    Path((crate_name, prefix)): Path<(String, String)>,
+    req: Parts,
+) -> AppResult<Response> {
+    let prefix = prefix.to_ascii_lowercase();
+    if prefix.is_empty() || prefix.len() > 64 || !prefix.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(bad_request(format_args!(
+            "invalid checksum prefix `{prefix}`, expected up to 64 hex characters"
+        )));
+    }
+
+    check_download_rate_limit(&app, &req).await?;
+    check_download_access(&app, &req, &crate_name).await?;
+
+    let app_clone = app.clone();
+    let (crate_name, version) = spawn_blocking(move || {
+        let conn = &mut *app_clone.db_read()?;
+        let krate: Crate = Crate::by_name(&crate_name)
+            .first(conn)
+            .optional()?
+            .ok_or_else(|| crate_not_found(&crate_name))?;
+
+        // Only two matches are needed to tell whether the match is unique
+        let versions: Vec<String> = Version::belonging_to(&krate)
+            .filter(versions::deleted.eq(false))
+            .filter(versions::checksum.like(format!("{prefix}%")))
+            .select(versions::num)
+            .limit(2)
+            .load(conn)?;
+
+        match versions.as_slice() {
+            [version] => Ok((krate.name, version.clone())),
+            [] => Err(custom(
+                StatusCode::NOT_FOUND,
+                format!(
+                    "crate `{}` has no version with a checksum starting with `{prefix}`",
+                    krate.name
+                ),
+            )),
+            _ => Err(custom(
+                StatusCode::CONFLICT,
+                format!(
+                    "checksum prefix `{prefix}` matches more than one version of crate `{}`",
+                    krate.name
+                ),
+            )),
+        }
+    })
+    .await?;
+
+    download_version(app, req, crate_name, version, false).await
+}
+
+/// Handles the `GET /crates/:crate_id/:version/storage-key` route.
+///
+/// Returns the key of the crate file inside of the storage bucket, so that
+/// mirror and backup tooling can fetch it from their own copy of the bucket.
+/// The key is derived in the same way as the `download` redirect target.
+pub async fn storage_key(
+    app: AppState,
+    Path((crate_name, version)): Path<(String, String)>,
+    req: Parts,
+) -> AppResult<Json<Value>> {
+    spawn_blocking(move || {
+        let conn = &mut *app.db_read()?;
+        AuthCheck::default().check(&req, conn)?;
+
+        let (version, krate) = version_and_crate(conn, &crate_name, &version)?;
+        let key = crate_file_key(&krate.name, &version.num);
+
+        Ok(Json(json!({ "key": key.to_string() })))
+    })
+    .await
+}
+
+/// The query parameters of the [`downloads`] handler.
+///
+/// The handler reads the parameters from the query string itself. This type
+/// only describes them for the OpenAPI document in `controllers::openapi`.
+#[allow(dead_code)] // The fields are only used for the schema
+#[derive(JsonSchema)]
+pub(crate) struct DownloadsQuery {
+    /// Number of days to return, ending today.
+    days: Option<i64>,
+    /// Last day to return.
+    before_date: Option<NaiveDate>,
+    /// First day to return.
+    after_date: Option<NaiveDate>,
+    /// `true` to exclude the current day.
+    exclude_today: Option<bool>,
+    /// Time zone that determines the current day, e.g. `Europe/Berlin`.
+    tz: Option<String>,
+    /// `true` to include days without downloads.
+    dense: Option<bool>,
+    /// `day`, `week` or `month` buckets.
+    granularity: Option<String>,
+    /// `absolute` (the default) or `delta`.
+    mode: Option<String>,
+    /// `true` to include running totals.
+    cumulative: Option<bool>,
+    /// `true` to return parallel arrays instead of an object per entry.
+    compact: Option<bool>,
+    /// Comma-separated fields to include per entry.
+    fields: Option<String>,
+    /// `iso_week` to label entries with their ISO week.
+    label: Option<String>,
+    /// `iso` (the default) or `epoch`.
+    date_format: Option<String>,
+    /// Name of a JSONP callback function.
+    callback: Option<String>,
+    /// Page to return, enables pagination.
+    page: Option<u32>,
+    /// Number of entries per page.
+    per_page: Option<u32>,
 }
 
 /// Handles the `GET /crates/:crate_id/:version/downloads` route.
+///
+/// Only days with downloads are returned by default. Passing `dense=true`
+/// fills the days without downloads with zero rows, so that the response
+/// contains a contiguous series for the whole requested window.
+///
+/// Passing `compact=true` returns the dates and counts as parallel arrays
+/// instead of an object per entry.
+///
+/// Passing `mode=delta` returns the difference of each row to the previous
+/// returned row instead of the absolute counts, while `meta.total_downloads`
+/// still contains the absolute total.
+///
+/// Passing e.g. `fields=date,downloads` only includes the listed fields in
+/// each entry of `version_downloads`.
+///
+/// Passing `label=iso_week` buckets the downloads by week and labels each
+/// entry with its `YYYY-Www` ISO week.
+///
+/// Passing `date_format=epoch` returns the `date` of each entry as the number
+/// of seconds since the Unix epoch at midnight UTC of that day, instead of a
+/// `YYYY-MM-DD` string (`date_format=iso`, the default).
+///
+/// Passing `callback=<name>` wraps the JSON response in a call of the named
+/// JavaScript function (JSONP), for legacy dashboards that can't use CORS.
+///
+/// If the requested window starts before the earliest available download
+/// data of the version, `meta.window_start` contains the start of the
+/// available data and `meta.window_clamped` is `true`.
+///
+/// Requests with an `Accept: application/x-ndjson` header are handled by
+/// [`downloads_ndjson`] instead.
+///
+/// Non-canonical versions like `1.0` or `v1.0.0` are redirected with
+/// `308 Permanent Redirect` to the URL of the canonical version, if the crate
+/// has such a version.
 pub async fn downloads(
     app: AppState,
     Path((crate_name, version)): Path<(String, String)>,
+    locale: Locale,
     req: Parts,
-) -> AppResult<Json<Value>> {
+) -> AppResult<Response> {
+    if accepts_ndjson(&req) {
+        return downloads_ndjson(app, Path((crate_name, version)), locale, req).await;
+    }
+
     spawn_blocking(move || {
-        if semver::Version::parse(&version).is_err() {
-            return Err(version_not_found(&crate_name, &version));
+        let conn = &mut *app.db_read()?;
+
+        if let Some(canonical) = canonical_version(&version).filter(|c| *c != version) {
+            if version_exists(conn, &crate_name, &canonical)? {
+                let mut location = format!("/api/v1/crates/{crate_name}/{canonical}/downloads");
+                if let Some(query) = req.uri.query() {
+                    location = format!("{location}?{query}");
+                }
+                let headers = [(header::LOCATION, location)];
+                return Ok((StatusCode::PERMANENT_REDIRECT, headers).into_response());
+            }
         }
 
-        let conn = &mut *app.db_read()?;
-        let (version, _) = version_and_crate(conn, &crate_name, &version)?;
+        let version = find_version(conn, &crate_name, &version, locale)?;
 
-        let cutoff_end_date = req
-            .query()
-            .get("before_date")
-            .and_then(|d| NaiveDate::parse_from_str(d, "%F").ok())
-            .unwrap_or_else(|| Utc::now().date_naive());
-        let cutoff_start_date = cutoff_end_date - Duration::days(89);
-
-        let downloads = VersionDownload::belonging_to(&version)
-            .filter(version_downloads::date.between(cutoff_start_date, cutoff_end_date))
-            .order(version_downloads::date)
-            .load(conn)?
+        let query = req.query();
+
+        let iso_week = match query.get("label").map(String::as_str) {
+            None => false,
+            Some("iso_week") => true,
+            Some(label) => {
+                return Err(bad_request(format_args!(
+                    "invalid `label` parameter `{label}`, expected `iso_week`"
+                )))
+            }
+        };
+
+        // ISO week labels imply weekly buckets
+        let granularity = match query.get("granularity") {
+            Some(granularity) => Granularity::parse(granularity)?,
+            None if iso_week => Granularity::Week,
+            None => Granularity::Day,
+        };
+        if iso_week && granularity != Granularity::Week {
+            return Err(bad_request("`label=iso_week` requires `granularity=week`"));
+        }
+
+        let delta = match query.get("mode").map(String::as_str) {
+            None | Some("absolute") => false,
+            Some("delta") => true,
+            Some(mode) => {
+                return Err(bad_request(format_args!(
+                    "invalid `mode` parameter `{mode}`, expected `absolute` or `delta`"
+                )))
+            }
+        };
+
+        let cumulative = query.get("cumulative").is_some_and(|c| c == "true");
+        if delta && cumulative {
+            return Err(bad_request(
+                "`cumulative` can not be combined with `mode=delta`",
+            ));
+        }
+
+        let callback = query.get("callback").map(String::as_str);
+        if let Some(callback) = callback.filter(|callback| !is_valid_jsonp_callback(callback)) {
+            return Err(bad_request(format_args!(
+                "invalid `callback` parameter `{callback}`"
+            )));
+        }
+
+        let compact = query.get("compact").is_some_and(|c| c == "true");
+        let fields = query.get("fields").map(|f| parse_fields(f)).transpose()?;
+        if compact && fields.is_some() {
+            return Err(bad_request(
+                "`fields` can not be combined with `compact=true`",
+            ));
+        }
+        if compact && iso_week {
+            return Err(bad_request(
+                "`label` can not be combined with `compact=true`",
+            ));
+        }
+
+        let epoch_dates = match query.get("date_format").map(String::as_str) {
+            None | Some("iso") => false,
+            Some("epoch") => true,
+            Some(format) => {
+                return Err(bad_request(format_args!(
+                    "invalid `date_format` parameter `{format}`, expected `iso` or `epoch`"
+                )))
+            }
+        };
+        if compact && epoch_dates {
+            return Err(bad_request(
+                "`date_format=epoch` can not be combined with `compact=true`",
+            ));
+        }
+
+        let default_days = default_downloads_days(conn, version.crate_id)?;
+        let (cutoff_start_date, cutoff_end_date) = downloads_window(&query, default_days)?;
+
+        let threshold = app.config.slow_downloads_query_threshold;
+        let dates = (cutoff_start_date, cutoff_end_date);
+        let downloads = load_downloads(conn, &crate_name, &version, dates, threshold)?;
+
+        let etag = downloads_etag(&version, &downloads);
+        let last_modified = match downloads.is_empty() {
+            true => None,
+            false => last_persisted_at(conn)?,
+        };
+        if is_not_modified(&req, &etag, last_modified) {
+            return Ok((StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response());
+        }
+
+        let last_modified = last_modified.map(http_date);
+
+        let downloads = match query.get("dense").is_some_and(|d| d == "true") {
+            true => fill_missing_days(downloads, version.id, cutoff_start_date, cutoff_end_date),
+            false => downloads,
+        };
+
+        let mut downloads = granularity
+            .aggregate(downloads, cutoff_start_date)
             .into_iter()
-            .map(VersionDownload::into)
+            .map(|download| EncodableVersionDownload {
+                label: iso_week.then(|| iso_week_label(download.date)),
+                ..download.into()
+            })
             .collect::<Vec<EncodableVersionDownload>>();
 
-        Ok(Json(json!({ "version_downloads": downloads })))
+        // Downloads from before the version was imported from another
+        // registry are represented by a synthetic entry on its creation day
+        let created_at = version.created_at.date();
+        let window = cutoff_start_date..=cutoff_end_date;
+        if version.extra_downloads > 0 && window.contains(&created_at) {
+            let migrated = EncodableVersionDownload {
+                version: version.id,
+                downloads: version.extra_downloads,
+                date: created_at.to_string(),
+                cumulative: None,
+                migrated: true,
+                label: iso_week.then(|| iso_week_label(created_at)),
+            };
+            downloads.insert(0, migrated);
+        }
+
+        if cumulative {
+            let mut total = 0;
+            for download in &mut downloads {
+                total += i64::from(download.downloads);
+                download.cumulative = Some(total);
+            }
+        }
+
+        // To keep backward compatibility, we paginate only if `page` or
+        // `per_page` is provided
+        let pagination = match query.contains_key("page") || query.contains_key("per_page") {
+            true => Some(PaginationOptions::builder().gather(&req)?),
+            false => None,
+        };
+
+        let mut headers = vec![(header::ETAG, etag)];
+        if let Some(last_modified) = last_modified {
+            headers.push((header::LAST_MODIFIED, last_modified));
+        }
+
+        let total_downloads = downloads
+            .iter()
+            .map(|download| i64::from(download.downloads))
+            .sum();
+
+        if delta {
+            // The synthetic entry of migrated downloads is not part of the
+            // daily series
+            let mut previous = 0;
+            for download in downloads.iter_mut().filter(|download| !download.migrated) {
+                let current = download.downloads;
+                download.downloads = current - previous;
+                previous = current;
+            }
+        }
+
+        let total = downloads.len();
+        let (version_downloads, total) = match pagination {
+            Some(options) => {
+                let offset = options.offset().unwrap_or(0) as usize;
+                let downloads = downloads
+                    .into_iter()
+                    .skip(offset)
+                    .take(options.per_page as usize)
+                    .collect();
+
+                (downloads, Some(total))
+            }
+            None => (downloads, None),
+        };
+
+        let first_date = first_download_date(conn, version.id)?;
+        let window_clamped = first_date.is_some_and(|first_date| first_date > cutoff_start_date);
+        let window_start = match window_clamped {
+            true => first_date.unwrap_or(cutoff_start_date),
+            false => cutoff_start_date,
+        };
+
+        let json = DownloadsResponse {
+            version_downloads,
+            meta: DownloadsMeta {
+                total_downloads,
+                total,
+                last_persisted_at: last_persisted_at(conn)?,
+                window_start: window_start.to_string(),
+                window_clamped,
+            },
+        };
+
+        if compact {
+            let json = CompactDownloadsResponse::from(json);
+            return json_or_jsonp(headers, json, callback);
+        }
+
+        if fields.is_some() || epoch_dates {
+            let mut json = serde_json::to_value(json)?;
+            if let Some(downloads) = json["version_downloads"].as_array_mut() {
+                for download in downloads.iter_mut().filter_map(Value::as_object_mut) {
+                    if epoch_dates {
+                        if let Some(date) = download.get_mut("date") {
+                            *date = date.as_str().and_then(epoch_seconds).into();
+                        }
+                    }
+                    if let Some(fields) = &fields {
+                        download.retain(|field, _| fields.contains(&field.as_str()));
+                    }
+                }
+            }
+            return json_or_jsonp(headers, json, callback);
+        }
+
+        json_or_jsonp(headers, json, callback)
+    })
+    .await
+}
+
+/// Checks whether the `Accept` header of the request asks for newline-delimited
+/// JSON.
+fn accepts_ndjson(req: &Parts) -> bool {
+    req.headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .any(|value| value.contains(NDJSON_CONTENT_TYPE))
+}
+
+/// Responds with the JSON serialization of `json`, or with a JSONP script
+/// calling `callback` with it if a callback is given.
+fn json_or_jsonp<T: serde::Serialize>(
+    headers: Vec<(header::HeaderName, String)>,
+    json: T,
+    callback: Option<&str>,
+) -> AppResult<Response> {
+    let Some(callback) = callback else {
+        return Ok((AppendHeaders(headers), Json(json)).into_response());
+    };
+
+    let script = format!("{callback}({});", serde_json::to_string(&json)?);
+    let content_type = [(header::CONTENT_TYPE, "application/javascript")];
+    Ok((AppendHeaders(headers), content_type, script).into_response())
+}
+
+/// Checks whether `callback` is a valid JavaScript identifier
+/// (`^[a-zA-Z_$][\w$]*$`), so that it can't be used to inject code into the
+/// JSONP response.
+fn is_valid_jsonp_callback(callback: &str) -> bool {
+    let mut chars = callback.chars();
+    let Some(first) = chars.next() else {
+        return false;
+    };
+
+    let is_start = |c: char| c.is_ascii_alphabetic() || c == '_' || c == '$';
+    is_start(first) && chars.all(|c| is_start(c) || c.is_ascii_digit())
+}
+
+/// Handles the `GET /crates/:crate_id/:version/downloads/gaps` route.
+///
+/// Returns the dates within the requested window that have no downloads. The
+/// window is resolved in the same way as for the `downloads` endpoint.
+pub async fn download_gaps(
+    app: AppState,
+    Path((crate_name, version)): Path<(String, String)>,
+    locale: Locale,
+    req: Parts,
+) -> AppResult<Json<Value>> {
+    spawn_blocking(move || {
+        let conn = &mut *app.db_read()?;
+        let version = find_version(conn, &crate_name, &version, locale)?;
+
+        let default_days = default_downloads_days(conn, version.crate_id)?;
+        let (start_date, end_date) = downloads_window(&req.query(), default_days)?;
+
+        let threshold = app.config.slow_downloads_query_threshold;
+        let dates = (start_date, end_date);
+        let present = load_downloads(conn, &crate_name, &version, dates, threshold)?
+            .into_iter()
+            .map(|download| download.date)
+            .collect::<HashSet<_>>();
+
+        let gaps = start_date
+            .iter_days()
+            .take_while(|date| *date <= end_date)
+            .filter(|date| !present.contains(date))
+            .map(|date| date.to_string())
+            .collect::<Vec<_>>();
+
+        Ok(Json(json!({
+            "gaps": gaps,
+            "meta": {
+                "start_date": start_date.to_string(),
+                "end_date": end_date.to_string(),
+            },
+        })))
     })
     .await
 }
+
+/// Adds zero rows for the days within the inclusive date window that have no
+/// downloads. `downloads` must be ordered by date.
+fn fill_missing_days(
+    downloads: Vec<VersionDownload>,
+    version_id: i32,
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+) -> Vec<VersionDownload> {
+    let mut downloads = downloads.into_iter().peekable();
+    start_date
+        .iter_days()
+        .take_while(|date| *date <= end_date)
+        .map(|date| {
+            downloads
+                .next_if(|download| download.date == date)
+                .unwrap_or(VersionDownload {
+                    version_id,
+                    downloads: 0,
+                    counted: 0,
+                    date,
+                    processed: false,
+                })
+        })
+        .collect()
+}
+
+/// Returns the date of the earliest `version_downloads` row of the version,
+/// or `None` if there are no rows.
+///
+/// Rows are removed after their retention period, so this is not necessarily
+/// the date of the first download of the version.
+fn first_download_date(conn: &mut PgConnection, version_id: i32) -> QueryResult<Option<NaiveDate>> {
+    use diesel::dsl::min;
+
+    version_downloads::table
+        .filter(version_downloads::version_id.eq(version_id))
+        .select(min(version_downloads::date))
+        .get_result(conn)
+}
+
+/// Returns the time at which downloads were last saved to the database.
+///
+/// Download counts are saved in the same transaction that marks a CDN log
+/// file as processed, so the most recent `processed_log_files` entry tells
+/// clients how up-to-date the download numbers are. `None` is returned if no
+/// log files have been processed yet.
+fn last_persisted_at(conn: &mut PgConnection) -> QueryResult<Option<DateTime<Utc>>> {
+    use diesel::dsl::max;
+
+    processed_log_files::table
+        .select(max(processed_log_files::time))
+        .get_result(conn)
+}
+
+/// Handles the `GET /crates/:crate_id/:version/downloads.csv` route.
+///
+/// This returns the same daily download counts as the `downloads` endpoint
+/// as `date,downloads` rows. The rows are streamed from the database to the
+/// client as they are read, so that large date ranges don't need to be
+/// buffered in memory.
+pub async fn downloads_csv(
+    app: AppState,
+    path: Path<(String, String)>,
+    locale: Locale,
+    req: Parts,
+) -> AppResult<Response> {
+    let first_line = Some("date,downloads\n".to_string());
+    let format_row = |row: VersionDownload| Some(format!("{},{}\n", row.date, row.downloads));
+    let content_type = "text/csv; charset=utf-8";
+    let stream = DownloadsStream {
+        first_line,
+        flush_marker_rows: None,
+        content_type,
+    };
+    stream_downloads(app, path, locale, req, stream, format_row).await
+}
+
+/// Handles the `GET /crates/:crate_id/:version/downloads.ndjson` route, and
+/// `GET /crates/:crate_id/:version/downloads` requests that only accept
+/// `application/x-ndjson`.
+///
+/// Like the CSV export, this streams the daily download counts of the
+/// `downloads` endpoint, but as one `EncodableVersionDownload` JSON object
+/// per line.
+///
+/// After every 100 rows, a `{"flushed": N}` line is streamed to show the
+/// progress of large exports. Clients can tell these apart from the rows by
+/// their `flushed` field.
+pub async fn downloads_ndjson(
+    app: AppState,
+    path: Path<(String, String)>,
+    locale: Locale,
+    req: Parts,
+) -> AppResult<Response> {
+    let format_row = |row: VersionDownload| {
+        let download = EncodableVersionDownload::from(row);
+        match serde_json::to_string(&download) {
+            Ok(line) => Some(line + "\n"),
+            Err(error) => {
+                warn!(%error, "Failed to serialize a download row, skipping it");
+                None
+            }
+        }
+    };
+    let stream = DownloadsStream {
+        first_line: None,
+        flush_marker_rows: Some(NDJSON_FLUSH_MARKER_ROWS),
+        content_type: NDJSON_CONTENT_TYPE,
+    };
+    stream_downloads(app, path, locale, req, stream, format_row).await
+}
+
+/// The format of a streamed download export, apart from its rows.
+struct DownloadsStream {
+    /// Line that is streamed before the rows, e.g. a CSV header.
+    first_line: Option<String>,
+    /// Number of rows after which a `{"flushed": N}` marker is streamed.
+    flush_marker_rows: Option<usize>,
+    content_type: &'static str,
+}
+
+/// Streams the download rows of the requested window to the client, as
+/// formatted by `format_row` and framed as described by `stream`. Rows for
+/// which `format_row` returns `None` are skipped.
+async fn stream_downloads(
+    app: AppState,
+    Path((crate_name, version)): Path<(String, String)>,
+    locale: Locale,
+    req: Parts,
+    stream: DownloadsStream,
+    format_row: impl Fn(VersionDownload) -> Option<String> + Send + 'static,
+) -> AppResult<Response> {
+    let DownloadsStream {
+        first_line,
+        flush_marker_rows,
+        content_type,
+    } = stream;
+
+    let app_clone = app.clone();
+    let (version, (cutoff_start_date, cutoff_end_date)) = spawn_blocking(move || {
+        let conn = &mut *app_clone.db_read()?;
+        let version = find_version(conn, &crate_name, &version, locale)?;
+        let default_days = default_downloads_days(conn, version.crate_id)?;
+        let window = downloads_window(&req.query(), default_days)?;
+        Ok::<_, BoxedAppError>((version, window))
+    })
+    .await?;
+
+    let (tx, rx) = mpsc::channel::<Result<String, BoxError>>(STREAM_CHANNEL_CAPACITY);
+
+    tokio::task::spawn_blocking(move || {
+        let load_chunk = |start_date: NaiveDate| -> Result<Vec<VersionDownload>, BoxError> {
+            let conn = &mut *app.db_read()?;
+            let rows = VersionDownload::belonging_to(&version)
+                .filter(version_downloads::date.between(start_date, cutoff_end_date))
+                .order(version_downloads::date)
+                .limit(STREAM_CHUNK_ROWS)
+                .load(conn)?;
+            Ok(rows)
+        };
+
+        // Stops reading from the database if the client went away
+        let send = |line| tx.blocking_send(line).is_ok();
+
+        if let Some(first_line) = first_line {
+            if !send(Ok(first_line)) {
+                return;
+            }
+        }
+
+        let mut start_date = cutoff_start_date;
+        let mut row_count = 0;
+        loop {
+            let rows = match load_chunk(start_date) {
+                Ok(rows) => rows,
+                Err(error) => {
+                    send(Err(error));
+                    return;
+                }
+            };
+
+            let is_last_chunk = rows.len() < STREAM_CHUNK_ROWS as usize;
+            if let Some(last) = rows.last() {
+                start_date = last.date + Duration::days(1);
+            }
+
+            for row in rows {
+                row_count += 1;
+                if let Some(line) = format_row(row) {
+                    if !send(Ok(line)) {
+                        return;
+                    }
+                }
+
+                let marker = flush_marker_rows.filter(|every| row_count % every == 0);
+                if marker.is_some() && !send(Ok(format!("{}\n", json!({ "flushed": row_count })))) {
+                    return;
+                }
+            }
+
+            if is_last_chunk {
+                return;
+            }
+        }
+    });
+
+    let stream = stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|line| (line, rx))
+    });
+
+    let headers = [(header::CONTENT_TYPE, content_type)];
+    Ok((headers, Body::from_stream(stream)).into_response())
+}
+
+/// Handles the `GET /crates/:crate_id/:version/downloads/sparkline.svg` route.
+///
+/// Renders the daily downloads of the last 30 days (including today) as a
+/// small SVG line chart, e.g. for embedding in READMEs. Days without
+/// downloads are drawn as zero, so a version without any downloads results
+/// in a flat baseline.
+pub async fn downloads_sparkline(
+    app: AppState,
+    Path((crate_name, version)): Path<(String, String)>,
+    locale: Locale,
+) -> AppResult<Response> {
+    spawn_blocking(move || {
+        let conn = &mut *app.db_read()?;
+        let version = find_version(conn, &crate_name, &version, locale)?;
+
+        let end_date = Utc::now().date_naive();
+        let start_date = end_date - Duration::days(SPARKLINE_DAYS - 1);
+
+        let mut daily_downloads = vec![0; SPARKLINE_DAYS as usize];
+        let threshold = app.config.slow_downloads_query_threshold;
+        let dates = (start_date, end_date);
+        for download in load_downloads(conn, &crate_name, &version, dates, threshold)? {
+            let index = (download.date - start_date).num_days() as usize;
+            daily_downloads[index] = download.downloads;
+        }
+
+        let headers = [
+            (header::CONTENT_TYPE, "image/svg+xml"),
+            (header::CACHE_CONTROL, SPARKLINE_CACHE_CONTROL),
+        ];
+        Ok((headers, render_sparkline(&daily_downloads)).into_response())
+    })
+    .await
+}
+
+/// Renders the given values as a `<polyline>` that spans the whole width of
+/// the image, scaled so that the largest value touches the top.
+fn render_sparkline(values: &[i32]) -> String {
+    let width = f64::from(SPARKLINE_WIDTH);
+    let height = f64::from(SPARKLINE_HEIGHT);
+
+    // Keep a pixel of padding, so that the line isn't cut off at the edges
+    let max = f64::from(values.iter().copied().max().unwrap_or(0).max(1));
+    let x_step = width / (values.len().max(2) - 1) as f64;
+
+    let points = values
+        .iter()
+        .enumerate()
+        .map(|(index, value)| {
+            let x = index as f64 * x_step;
+            let y = height - 1.0 - f64::from(*value) / max * (height - 2.0);
+            format!("{x:.1},{y:.1}")
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{SPARKLINE_WIDTH}" height="{SPARKLINE_HEIGHT}" viewBox="0 0 {SPARKLINE_WIDTH} {SPARKLINE_HEIGHT}"><polyline points="{points}" fill="none" stroke="currentColor" stroke-width="1"/></svg>"#
+    )
+}
+
+/// Loads the daily downloads of the version within the inclusive date window,
+/// ordered by date.
+///
+/// Queries that take at least `slow_threshold` are logged with a warning, so
+/// that the crates causing slow download stats can be found.
+fn load_downloads(
+    conn: &mut PgConnection,
+    crate_name: &str,
+    version: &Version,
+    (start_date, end_date): (NaiveDate, NaiveDate),
+    slow_threshold: std::time::Duration,
+) -> QueryResult<Vec<VersionDownload>> {
+    let start_instant = Instant::now();
+
+    let downloads = VersionDownload::belonging_to(version)
+        .filter(version_downloads::date.between(start_date, end_date))
+        .order(version_downloads::date)
+        .load(conn)?;
+
+    let duration = start_instant.elapsed();
+    if duration >= slow_threshold {
+        warn!(
+            crate_name,
+            version = %version.num,
+            window = %format_args!("{start_date}..={end_date}"),
+            rows = downloads.len(),
+            duration_ms = duration.as_millis() as u64,
+            "Slow download stats query"
+        );
+    }
+
+    Ok(downloads)
+}
+
+/// Resolves the inclusive date window requested via the `days`,
+/// `before_date` and `after_date` query parameters. `default_days` is used if
+/// neither `days` nor `after_date` is passed.
+///
+/// Passing `exclude_today=true` additionally drops the current day from the
+/// window, since its download count is still incomplete.
+///
+/// "Today" is the current UTC date, unless an IANA time zone name like
+/// `America/New_York` is passed as the `tz` parameter.
+///
+/// Like `days`, windows requested via `after_date` are limited to the last
+/// [`MAX_DOWNLOADS_DAYS`] days before their end.
+fn downloads_window(
+    query: &IndexMap<String, String>,
+    default_days: i64,
+) -> AppResult<(NaiveDate, NaiveDate)> {
+    let days = match query.get("days") {
+        Some(days) => parse_days(days)?,
+        None => default_days,
+    };
+
+    // Download counts are stored per UTC day, but the default end of the
+    // window can be shifted to the current date in the client's time zone
+    let today = match query.get("tz") {
+        Some(tz) => Utc::now().with_timezone(&parse_time_zone(tz)?).date_naive(),
+        None => Utc::now().date_naive(),
+    };
+
+    let cutoff_end_date = match query.get("before_date") {
+        Some(before_date) => parse_date_param("before_date", before_date)?,
+        None => today,
+    };
+
+    let cutoff_start_date = match query.get("after_date") {
+        Some(after_date) => parse_date_param("after_date", after_date)?,
+        None => cutoff_end_date
+            .checked_sub_signed(Duration::days(days - 1))
+            .ok_or_else(|| {
+                bad_request("the requested date range starts before the earliest supported date")
+            })?,
+    };
+
+    if cutoff_start_date > cutoff_end_date {
+        let message = "`after_date` must not be later than `before_date`";
+        return Err(bad_request(message));
+    }
+    let cutoff_start_date =
+        cutoff_start_date.max(cutoff_end_date - Duration::days(MAX_DOWNLOADS_DAYS - 1));
+
+    let yesterday = today - Duration::days(1);
+    let cutoff_end_date = match query.get("exclude_today").is_some_and(|e| e == "true") {
+        true => cutoff_end_date.min(yesterday),
+        false => cutoff_end_date,
+    };
+
+    Ok((cutoff_start_date, cutoff_end_date))
+}
+
+/// Returns the number of days of download history that are returned for the
+/// versions of a crate when no `days` parameter is passed.
+///
+/// Crates can have an extended `download_retention_days` setting, which is
+/// capped at [`MAX_DOWNLOADS_DAYS`].
+fn default_downloads_days(conn: &mut PgConnection, crate_id: i32) -> QueryResult<i64> {
+    let retention_days: Option<i32> = crates::table
+        .find(crate_id)
+        .select(crates::download_retention_days)
+        .first(conn)?;
+
+    Ok(retention_days.map_or(DEFAULT_DOWNLOADS_DAYS, |days| {
+        i64::from(days).clamp(1, MAX_DOWNLOADS_DAYS)
+    }))
+}
+
+/// Builds a weak `ETag` for the download stats of a version.
+///
+/// Late log processing can update the counts of earlier days too, so the
+/// `ETag` is derived from a hash of all rows and the `extra_downloads` of the
+/// version.
+fn downloads_etag(version: &Version, downloads: &[VersionDownload]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(version.extra_downloads.to_be_bytes());
+    for row in downloads {
+        hasher.update(row.date.num_days_from_ce().to_be_bytes());
+        hasher.update(row.downloads.to_be_bytes());
+    }
+
+    let hash = hex::encode(&hasher.finalize()[..8]);
+    format!(r#"W/"{}-{hash}""#, version.id)
+}
+
+/// Formats the given time as an HTTP date, e.g. for use in the
+/// `Last-Modified` header.
+fn http_date(time: DateTime<Utc>) -> String {
+    time.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+/// Checks whether the client already has the current download stats, based
+/// on the `If-None-Match` and `If-Modified-Since` request headers.
+///
+/// As recommended by RFC 9110, `If-Modified-Since` is ignored if the request
+/// also contains an `If-None-Match` header. The counts of the latest day keep
+/// changing until the day is over, so `last_modified` has to be the time at
+/// which downloads were last saved, compared with the one second precision of
+/// HTTP dates.
+fn is_not_modified(req: &Parts, etag: &str, last_modified: Option<DateTime<Utc>>) -> bool {
+    if req.headers.contains_key(header::IF_NONE_MATCH) {
+        return is_etag_match(req, etag);
+    }
+
+    let if_modified_since = req
+        .headers
+        .get(header::IF_MODIFIED_SINCE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| DateTime::parse_from_rfc2822(value).ok());
+
+    match (last_modified, if_modified_since) {
+        (Some(last_modified), Some(if_modified_since)) => {
+            last_modified.timestamp() <= if_modified_since.timestamp()
+        }
+        _ => false,
+    }
+}
+
+/// Checks whether any of the `If-None-Match` request headers matches the
+/// given `etag`, using the weak comparison function.
+fn is_etag_match(req: &Parts, etag: &str) -> bool {
+    let strip_weak = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    let etag = strip_weak(etag);
+
+    req.headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|tag| tag.trim() == "*" || strip_weak(tag) == etag)
+}
+
+/// Handles the `GET /crates/:crate_id/:version/downloads/total` route.
+///
+/// Unlike the `downloads` endpoint this returns the all-time total, which is
+/// not limited to any date window.
+pub async fn total_downloads(
+    app: AppState,
+    Path((crate_name, version)): Path<(String, String)>,
+    locale: Locale,
+) -> AppResult<Json<Value>> {
+    spawn_blocking(move || {
+        use diesel::dsl::sum;
+
+        let conn = &mut *app.db_read()?;
+        let version = find_version(conn, &crate_name, &version, locale)?;
+
+        let total_downloads: Option<i64> = VersionDownload::belonging_to(&version)
+            .select(sum(version_downloads::downloads))
+            .get_result(conn)?;
+
+        // Downloads from before the version was imported from another
+        // registry, or that were pruned after their retention period, are not
+        // part of `version_downloads`
+        let total_downloads = total_downloads.unwrap_or(0)
+            + i64::from(version.extra_downloads)
+            + i64::from(version.pruned_downloads);
+
+        Ok(Json(json!({ "total_downloads": total_downloads })))
+    })
+    .await
+}
+
+/// Handles the `POST /crates/:crate_id/:version/downloads/recompute` route.
+///
+/// Recomputes the cached `versions.downloads` total from the raw
+/// `version_downloads` rows, for the rare cases where the two have drifted
+/// apart. Downloads that were already pruned from `version_downloads` are
+/// included via `versions.pruned_downloads`. The rows are marked as counted,
+/// so that they are not added again by the next `update_downloads` run, and
+/// their uncounted downloads are added to the crate and site-wide totals
+/// instead, like the `update_downloads` job would. Only admins may use this
+/// endpoint.
+pub async fn recompute_downloads(
+    app: AppState,
+    Path((crate_name, version)): Path<(String, String)>,
+    req: Parts,
+) -> AppResult<Json<Value>> {
+    spawn_blocking(move || {
+        let conn = &mut *app.db_write()?;
+        let auth = AuthCheck::default().check(&req, conn)?;
+        let user = auth.user();
+        if !user.is_admin {
+            return Err(custom(
+                StatusCode::FORBIDDEN,
+                "must be an admin to recompute download counts",
+            ));
+        }
+
+        let (version, krate) = version_and_crate(conn, &crate_name, &version)?;
+
+        let (before, after) = conn.transaction(|conn| {
+            let (before, pruned): (i32, i32) = versions::table
+                .find(version.id)
+                .select((versions::downloads, versions::pruned_downloads))
+                .for_update()
+                .get_result(conn)?;
+
+            // The rows are locked, so that `update_downloads` can't count
+            // them between reading and marking them as counted
+            let rows: Vec<(i32, i32)> = VersionDownload::belonging_to(&version)
+                .select((version_downloads::downloads, version_downloads::counted))
+                .for_update()
+                .load(conn)?;
+            let downloads: i64 = rows
+                .iter()
+                .map(|(downloads, _)| i64::from(*downloads))
+                .sum();
+            let uncounted: i64 = rows
+                .iter()
+                .map(|(downloads, counted)| i64::from(downloads - counted))
+                .sum();
+
+            let after = i32::try_from(downloads + i64::from(pruned))
+                .map_err(|_| bad_request("the recomputed download count is out of range"))?;
+
+            diesel::update(VersionDownload::belonging_to(&version))
+                .filter(version_downloads::counted.ne(version_downloads::downloads))
+                .set(version_downloads::counted.eq(version_downloads::downloads))
+                .execute(conn)?;
+
+            diesel::update(versions::table.find(version.id))
+                .set(versions::downloads.eq(after))
+                .execute(conn)?;
+
+            if uncounted != 0 {
+                diesel::update(crate_downloads::table.find(krate.id))
+                    .set(crate_downloads::downloads.eq(crate_downloads::downloads + uncounted))
+                    .execute(conn)?;
+
+                diesel::update(metadata::table)
+                    .set(metadata::total_downloads.eq(metadata::total_downloads + uncounted))
+                    .execute(conn)?;
+            }
+
+            Ok::<_, BoxedAppError>((before, after))
+        })?;
+
+        warn!(
+            "Admin {} recomputed the downloads of {}@{}: {before} -> {after}",
+            user.gh_login, krate.name, version.num
+        );
+
+        Ok(Json(json!({ "before": before, "after": after })))
+    })
+    .await
+}
+
+/// Handles the `GET /crates/:crate_id/:version/downloads/pending` route.
+///
+/// Returns the number of downloads that were saved to `version_downloads`,
+/// but not counted into the `versions.downloads` total by the
+/// `update_downloads` job yet. This explains why freshly recorded downloads
+/// don't show up in the totals right away. Only admins may use this endpoint.
+pub async fn pending_downloads(
+    app: AppState,
+    Path((crate_name, version)): Path<(String, String)>,
+    req: Parts,
+) -> AppResult<Json<Value>> {
+    spawn_blocking(move || {
+        use diesel::dsl::sum;
+
+        let conn = &mut *app.db_read_prefer_primary()?;
+        let auth = AuthCheck::default().check(&req, conn)?;
+        if !auth.user().is_admin {
+            return Err(custom(
+                StatusCode::FORBIDDEN,
+                "must be an admin to view pending download counts",
+            ));
+        }
+
+        let (version, _) = version_and_crate(conn, &crate_name, &version)?;
+
+        let pending: Option<i64> = VersionDownload::belonging_to(&version)
+            .filter(version_downloads::counted.ne(version_downloads::downloads))
+            .select(sum(
+                version_downloads::downloads - version_downloads::counted
+            ))
+            .get_result(conn)?;
+
+        Ok(Json(json!({ "pending": pending.unwrap_or(0) })))
+    })
+    .await
+}
+
+/// Handles the `GET /crates/:crate_id/:version/downloads/watch` route.
+///
+/// Holds the request until download data that is newer than the `since`
+/// timestamp has been persisted, as tracked by the `last_persisted_at` time
+/// of the processed CDN log files. The response then contains the downloads
+/// of the version since the day of `since`, together with the new
+/// `meta.last_persisted_at`, which can be passed as `since` to the next
+/// request.
+///
+/// If no newer data is persisted within the configured timeout, the request
+/// responds with `204 No Content`.
+///
+/// All waiting requests share a single query of the `last_persisted_at` time
+/// per poll interval, and only query the downloads of their version once
+/// newer data was persisted.
+pub async fn watch_downloads(
+    app: AppState,
+    Path((crate_name, version)): Path<(String, String)>,
+    locale: Locale,
+    req: Parts,
+) -> AppResult<Response> {
+    let since = match req.query().get("since") {
+        Some(since) => since.parse::<DateTime<Utc>>().map_err(|_| {
+            bad_request(format_args!(
+                "invalid `since` parameter `{since}`, expected an RFC 3339 timestamp"
+            ))
+        })?,
+        None => return Err(bad_request("missing `since` parameter")),
+    };
+
+    let version_id = {
+        let app = app.clone();
+        spawn_blocking(move || {
+            let conn = &mut *app.db_read()?;
+            let version = find_version(conn, &crate_name, &version, locale)?;
+            Ok::<_, BoxedAppError>(version.id)
+        })
+        .await?
+    };
+
+    let deadline = Instant::now() + app.config.downloads_watch_timeout;
+    loop {
+        let app = app.clone();
+        let changes = spawn_blocking(move || {
+            let last_persisted_at = app
+                .last_persisted_at
+                .get_or_try_insert_with(WATCH_POLL_INTERVAL, || -> AppResult<_> {
+                    Ok(last_persisted_at(&mut *app.db_read_prefer_primary()?)?)
+                })?
+                .filter(|time| *time > since);
+            let Some(last_persisted_at) = last_persisted_at else {
+                return Ok::<_, BoxedAppError>(None);
+            };
+
+            let conn = &mut *app.db_read_prefer_primary()?;
+            let downloads: Vec<VersionDownload> = version_downloads::table
+                .filter(version_downloads::version_id.eq(version_id))
+                .filter(version_downloads::date.ge(since.date_naive()))
+                .order(version_downloads::date)
+                .load(conn)?;
+
+            Ok(Some((downloads, last_persisted_at)))
+        })
+        .await?;
+
+        if let Some((downloads, last_persisted_at)) = changes {
+            let downloads = downloads
+                .into_iter()
+                .map(EncodableVersionDownload::from)
+                .collect::<Vec<_>>();
+
+            let json = json!({
+                "version_downloads": downloads,
+                "meta": { "last_persisted_at": last_persisted_at },
+            });
+            return Ok(Json(json).into_response());
+        }
+
+        let now = Instant::now();
+        if now >= deadline {
+            return Ok(StatusCode::NO_CONTENT.into_response());
+        }
+        tokio::time::sleep(WATCH_POLL_INTERVAL.min(deadline - now)).await;
+    }
+}
+
+/// Handles the `GET /crates/:crate_id/:version/downloads/peaks` route.
+///
+/// Returns the days with the most downloads within the last 90 days, sorted
+/// by the number of downloads in descending order.
+pub async fn download_peaks(
+    app: AppState,
+    Path((crate_name, version)): Path<(String, String)>,
+    locale: Locale,
+    req: Parts,
+) -> AppResult<Json<Value>> {
+    spawn_blocking(move || {
+        use self::version_downloads::dsl::*;
+
+        let conn = &mut *app.db_read()?;
+        let version = find_version(conn, &crate_name, &version, locale)?;
+
+        let limit = req
+            .query()
+            .get("limit")
+            .map(|limit| parse_peaks_limit(limit))
+            .transpose()?
+            .unwrap_or(DEFAULT_PEAKS_LIMIT);
+
+        let end_date = Utc::now().date_naive();
+        let start_date = end_date - Duration::days(DEFAULT_DOWNLOADS_DAYS - 1);
+
+        let peaks: Vec<EncodableVersionDownload> = VersionDownload::belonging_to(&version)
+            .filter(date.between(start_date, end_date))
+            .order_by(downloads.desc())
+            .then_order_by(date.desc())
+            .limit(limit)
+            .load::<VersionDownload>(conn)?
+            .into_iter()
+            .map(VersionDownload::into)
+            .collect();
+
+        Ok(Json(json!({ "version_downloads": peaks })))
+    })
+    .await
+}
+
+/// The response of the `downloads_by_target` endpoint.
+///
+/// This is not serialized via [`Value`] to retain the order of the targets.
+#[derive(Serialize)]
+pub struct DownloadsByTarget {
+    targets: IndexMap<String, i64>,
+}
+
+/// Handles the `GET /crates/:crate_id/:version/downloads/by_target` route.
+///
+/// Returns the download totals of the last 90 days for each target that was
+/// passed to the `download` endpoint. Downloads without a target are counted
+/// under `unknown`, ordered by the number of downloads like the others.
+/// Targets that are not tier 1 or tier 2 targets of the Rust compiler are
+/// counted under `other` when the CDN logs are processed.
+pub async fn downloads_by_target(
+    app: AppState,
+    Path((crate_name, version)): Path<(String, String)>,
+    locale: Locale,
+) -> AppResult<Json<DownloadsByTarget>> {
+    spawn_blocking(move || {
+        use diesel::dsl::sum;
+
+        let conn = &mut *app.db_read()?;
+        let version = find_version(conn, &crate_name, &version, locale)?;
+
+        let end_date = Utc::now().date_naive();
+        let start_date = end_date - Duration::days(DEFAULT_DOWNLOADS_DAYS - 1);
+
+        let total_downloads: Option<i64> = VersionDownload::belonging_to(&version)
+            .filter(version_downloads::date.between(start_date, end_date))
+            .select(sum(version_downloads::downloads))
+            .get_result(conn)?;
+
+        let mut targets: Vec<(String, i64)> = version_downloads_by_target::table
+            .filter(version_downloads_by_target::version_id.eq(version.id))
+            .filter(version_downloads_by_target::date.between(start_date, end_date))
+            .group_by(version_downloads_by_target::target)
+            .select((
+                version_downloads_by_target::target,
+                sum(version_downloads_by_target::downloads).assume_not_null(),
+            ))
+            .load(conn)?;
+
+        let known_downloads: i64 = targets.iter().map(|(_, downloads)| downloads).sum();
+        let unknown_downloads = total_downloads.unwrap_or(0) - known_downloads;
+        if unknown_downloads > 0 {
+            targets.push(("unknown".to_string(), unknown_downloads));
+        }
+
+        targets.sort_by(|(a, a_downloads), (b, b_downloads)| {
+            b_downloads.cmp(a_downloads).then_with(|| a.cmp(b))
+        });
+
+        let targets = targets.into_iter().collect();
+        Ok(Json(DownloadsByTarget { targets }))
+    })
+    .await
+}
+
+#[derive(Serialize)]
+pub struct DownloadsBySource {
+    sources: DownloadSources,
+}
+
+#[derive(Serialize, Default)]
+struct DownloadSources {
+    human: i64,
+    ci: i64,
+    mirror: i64,
+    unknown: i64,
+}
+
+/// Handles the `GET /crates/:crate_id/:version/downloads/by_source` route.
+///
+/// Returns the download totals of the last 90 days for each kind of client,
+/// as classified by the `User-Agent` of the downloads in the CDN logs.
+/// Downloads that could not be classified are counted under `unknown`, so
+/// that the sum of all sources matches the regular download count.
+pub async fn downloads_by_source(
+    app: AppState,
+    Path((crate_name, version)): Path<(String, String)>,
+    locale: Locale,
+) -> AppResult<Json<DownloadsBySource>> {
+    spawn_blocking(move || {
+        use diesel::dsl::sum;
+
+        let conn = &mut *app.db_read()?;
+        let version = find_version(conn, &crate_name, &version, locale)?;
+
+        let end_date = Utc::now().date_naive();
+        let start_date = end_date - Duration::days(DEFAULT_DOWNLOADS_DAYS - 1);
+
+        let total_downloads: Option<i64> = VersionDownload::belonging_to(&version)
+            .filter(version_downloads::date.between(start_date, end_date))
+            .select(sum(version_downloads::downloads))
+            .get_result(conn)?;
+
+        let source_downloads: Vec<(String, i64)> = version_downloads_by_source::table
+            .filter(version_downloads_by_source::version_id.eq(version.id))
+            .filter(version_downloads_by_source::date.between(start_date, end_date))
+            .group_by(version_downloads_by_source::source)
+            .select((
+                version_downloads_by_source::source,
+                sum(version_downloads_by_source::downloads).assume_not_null(),
+            ))
+            .load(conn)?;
+
+        let mut sources = DownloadSources::default();
+        for (source, downloads) in source_downloads {
+            match source.parse::<DownloadSource>() {
+                Ok(DownloadSource::Human) => sources.human += downloads,
+                Ok(DownloadSource::Ci) => sources.ci += downloads,
+                Ok(DownloadSource::Mirror) => sources.mirror += downloads,
+                Err(error) => warn!(%source, %error, "Unexpected download source"),
+            }
+        }
+
+        let known_downloads = sources.human + sources.ci + sources.mirror;
+        sources.unknown = (total_downloads.unwrap_or(0) - known_downloads).max(0);
+
+        Ok(Json(DownloadsBySource { sources }))
+    })
+    .await
+}
+
+#[derive(Serialize)]
+pub struct HourlyDownloads {
+    version_downloads: Vec<HourlyDownload>,
+}
+
+#[derive(Serialize, Queryable)]
+struct HourlyDownload {
+    #[serde(with = "rfc3339")]
+    hour: NaiveDateTime,
+    downloads: i32,
+}
+
+/// Handles the `GET /crates/:crate_id/:version/downloads/hourly` route.
+///
+/// Returns the downloads per hour of the last 48 hours, as counted from the
+/// CDN logs. Hours without any downloads are omitted.
+pub async fn downloads_hourly(
+    app: AppState,
+    Path((crate_name, version)): Path<(String, String)>,
+    locale: Locale,
+) -> AppResult<Json<HourlyDownloads>> {
+    spawn_blocking(move || {
+        let conn = &mut *app.db_read()?;
+        let version = find_version(conn, &crate_name, &version, locale)?;
+
+        let cutoff = Utc::now().naive_utc() - Duration::hours(HOURLY_DOWNLOADS_RETENTION_HOURS);
+
+        // Pruning only happens when new log files are processed, so older
+        // rows are filtered out here as well.
+        let version_downloads = version_downloads_by_hour::table
+            .filter(version_downloads_by_hour::version_id.eq(version.id))
+            .filter(version_downloads_by_hour::hour.ge(cutoff))
+            .select((
+                version_downloads_by_hour::hour,
+                version_downloads_by_hour::downloads,
+            ))
+            .order(version_downloads_by_hour::hour)
+            .load(conn)?;
+
+        Ok(Json(HourlyDownloads { version_downloads }))
+    })
+    .await
+}
+
+/// Looks up the version of the crate.
+///
+/// If `version` is not a valid semver version a "not found" error is
+/// returned, which includes the closest existing versions of the crate. If
+/// the crate exists but the version doesn't, the error includes the latest
+/// stable version of the crate instead. The error messages are translated to
+/// the given `locale`.
+fn find_version(
+    conn: &mut PgConnection,
+    crate_name: &str,
+    version: &str,
+    locale: Locale,
+) -> AppResult<Version> {
+    if semver::Version::parse(version).is_err() {
+        let suggestions = suggest_versions(conn, crate_name, version)?;
+        return Err(version_not_found_with_suggestions(
+            crate_name,
+            version,
+            &suggestions,
+            locale,
+        ));
+    }
+
+    let krate: Crate = Crate::by_name(crate_name)
+        .first(conn)
+        .optional()?
+        .ok_or_else(|| localized_crate_not_found(crate_name, locale))?;
+
+    let found: Option<Version> = Version::belonging_to(&krate)
+        .filter(versions::num.eq(version))
+        .first(conn)
+        .optional()?;
+
+    match found {
+        Some(found) if found.deleted => Err(localized_version_deleted(crate_name, version, locale)),
+        Some(found) => Ok(found),
+        None => {
+            let latest_stable = latest_stable_version(conn, krate.id)?;
+            let latest_stable = latest_stable.as_deref();
+            Err(version_not_found_with_latest(
+                crate_name,
+                version,
+                latest_stable,
+                locale,
+            ))
+        }
+    }
+}
+
+/// Returns the canonical form of a possibly incomplete `version`, e.g.
+/// `1.0.0` for `1.0` or `v1.0.0`, or `None` if it can't be parsed even
+/// leniently.
+fn canonical_version(version: &str) -> Option<String> {
+    let version = version.trim_start_matches(['v', 'V', '=']);
+
+    // Missing minor and patch components default to zero
+    let core_end = version.find(['-', '+']).unwrap_or(version.len());
+    let (core, suffix) = version.split_at(core_end);
+    let padding = ".0".repeat(2usize.saturating_sub(core.matches('.').count()));
+
+    let version = format!("{core}{padding}{suffix}");
+    semver::Version::parse(&version).ok().map(|v| v.to_string())
+}
+
+/// Checks whether the crate has a version with exactly the given number,
+/// which has not been deleted.
+fn version_exists(conn: &mut PgConnection, crate_name: &str, version: &str) -> QueryResult<bool> {
+    use diesel::dsl::exists;
+
+    let query = versions::table
+        .inner_join(crates::table)
+        .filter(Crate::with_name(crate_name))
+        .filter(versions::num.eq(version))
+        .filter(versions::deleted.eq(false));
+
+    diesel::select(exists(query)).get_result(conn)
+}
+
+/// Returns the highest non-yanked, non-prerelease version of the crate.
+fn latest_stable_version(conn: &mut PgConnection, crate_id: i32) -> QueryResult<Option<String>> {
+    versions::table
+        .filter(versions::crate_id.eq(crate_id))
+        .filter(versions::yanked.eq(false))
+        .filter(versions::deleted.eq(false))
+        .filter(versions::semver_no_prerelease.is_not_null())
+        .order(versions::semver_no_prerelease.desc())
+        .select(versions::num)
+        .first(conn)
+        .optional()
+}
+
+/// Returns up to `MAX_VERSION_SUGGESTIONS` existing versions of the crate that
+/// are closest to the unparseable `version` string.
+///
+/// `version` is parsed leniently (e.g. `1.2` or `v1.2.3`) and the versions
+/// are ranked by their distance in the major, minor and patch components.
+/// If no version components can be extracted at all, the newest versions
+/// are suggested instead. Nothing is suggested if the crate does not exist.
+fn suggest_versions(
+    conn: &mut PgConnection,
+    crate_name: &str,
+    version: &str,
+) -> QueryResult<Vec<String>> {
+    let crate_id: Option<i32> = Crate::by_name(crate_name)
+        .select(crates::id)
+        .first(conn)
+        .optional()?;
+
+    let Some(crate_id) = crate_id else {
+        return Ok(Vec::new());
+    };
+
+    let mut versions = versions::table
+        .filter(versions::crate_id.eq(crate_id))
+        .filter(versions::deleted.eq(false))
+        .select(versions::num)
+        .load::<String>(conn)?
+        .into_iter()
+        .filter_map(|num| semver::Version::parse(&num).ok())
+        .collect::<Vec<_>>();
+
+    // Newest versions first, so that they win any ties in the distance
+    versions.sort_by(|a, b| b.cmp(a));
+
+    if let Some((major, minor, patch)) = lenient_version_components(version) {
+        versions.sort_by_key(|v| {
+            (
+                v.major.abs_diff(major),
+                v.minor.abs_diff(minor),
+                v.patch.abs_diff(patch),
+            )
+        });
+    }
+
+    Ok(versions
+        .into_iter()
+        .take(MAX_VERSION_SUGGESTIONS)
+        .map(|v| v.to_string())
+        .collect())
+}
+
+/// Extracts the major, minor and patch components from a string that is not
+/// necessarily a valid semver version. Missing components default to zero.
+fn lenient_version_components(version: &str) -> Option<(u64, u64, u64)> {
+    let version = version.trim_start_matches(['v', 'V', '=']);
+
+    let components = version
+        .split('.')
+        .take(3)
+        .map_while(|part| {
+            let digits = part.split(|c: char| !c.is_ascii_digit()).next()?;
+            digits.parse::<u64>().ok()
+        })
+        .collect::<Vec<_>>();
+
+    match components[..] {
+        [] => None,
+        [major] => Some((major, 0, 0)),
+        [major, minor] => Some((major, minor, 0)),
+        [major, minor, patch, ..] => Some((major, minor, patch)),
+    }
+}
+
+/// Parses the `days` query parameter, clamping it to `MAX_DOWNLOADS_DAYS`.
+fn parse_days(days: &str) -> AppResult<i64> {
+    match days.parse::<i64>() {
+        Ok(days) if days > 0 => Ok(days.min(MAX_DOWNLOADS_DAYS)),
+        _ => Err(bad_request(format_args!(
+            "invalid `days` parameter `{days}`, expected a positive integer"
+        ))),
+    }
+}
+
+/// Parses the comma-separated list of the `fields` parameter, rejecting
+/// names that are not in [`DOWNLOAD_FIELDS`].
+fn parse_fields(fields: &str) -> AppResult<Vec<&'static str>> {
+    fields
+        .split(',')
+        .map(|field| {
+            let field = field.trim();
+            DOWNLOAD_FIELDS
+                .iter()
+                .find(|known| **known == field)
+                .copied()
+                .ok_or_else(|| bad_request(format_args!("unknown field `{field}` in `fields`")))
+        })
+        .collect()
+}
+
+/// Parses the `limit` query parameter of the `peaks` endpoint, clamping it to
+/// [`MAX_PEAKS_LIMIT`].
+fn parse_peaks_limit(limit: &str) -> AppResult<i64> {
+    match limit.parse::<i64>() {
+        Ok(limit) if limit > 0 => Ok(limit.min(MAX_PEAKS_LIMIT)),
+        _ => Err(bad_request(format_args!(
+            "invalid `limit` parameter `{limit}`, expected a positive integer"
+        ))),
+    }
+}
+
+/// Parses the `name` query parameter as a `YYYY-MM-DD` date.
+fn parse_date_param(name: &str, value: &str) -> AppResult<NaiveDate> {
+    NaiveDate::parse_from_str(value, "%F").map_err(|_| {
+        bad_request(format_args!(
+            "invalid `{name}` parameter `{value}`, expected a date in `YYYY-MM-DD` format"
+        ))
+    })
+}
+
+/// Parses the `tz` query parameter as an IANA time zone name.
+fn parse_time_zone(value: &str) -> AppResult<Tz> {
+    value.parse().map_err(|_| {
+        bad_request(format_args!(
+            "invalid `tz` parameter `{value}`, expected an IANA time zone name"
+        ))
+    })
+}
+
+/// Formats the ISO week that `date` belongs to as `YYYY-Www`, e.g. `2024-W05`.
+///
+/// The year is the ISO week-numbering year, which differs from the calendar
+/// year for some days around New Year.
+fn iso_week_label(date: NaiveDate) -> String {
+    let week = date.iso_week();
+    format!("{}-W{:02}", week.year(), week.week())
+}
+
+/// Converts a `YYYY-MM-DD` date into the number of seconds since the Unix
+/// epoch at midnight UTC of that day.
+fn epoch_seconds(date: &str) -> Option<i64> {
+    let date = date.parse::<NaiveDate>().ok()?;
+    Some(date.and_time(NaiveTime::MIN).and_utc().timestamp())
+}
+
+/// The bucket size used to aggregate the daily download counts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Granularity {
+    Day,
+    /// ISO weeks, starting on Monday.
+    Week,
+    /// Calendar months.
+    Month,
+}
+
+impl Granularity {
+    fn parse(granularity: &str) -> AppResult<Self> {
+        match granularity {
+            "day" => Ok(Self::Day),
+            "week" => Ok(Self::Week),
+            "month" => Ok(Self::Month),
+            _ => Err(bad_request(format_args!(
+                "invalid `granularity` parameter `{granularity}`, expected `day`, `week` or `month`"
+            ))),
+        }
+    }
+
+    /// Returns the first date of the bucket that `date` belongs to.
+    fn bucket_start(self, date: NaiveDate) -> NaiveDate {
+        match self {
+            Self::Day => date,
+            Self::Week => date - Duration::days(date.weekday().num_days_from_monday().into()),
+            Self::Month => date - Duration::days(date.day0().into()),
+        }
+    }
+
+    /// Sums up the date-ordered `downloads` rows per bucket.
+    ///
+    /// Each resulting row is dated at the start of its bucket, unless the
+    /// bucket is only partially covered by the requested window, in which
+    /// case `window_start` is used instead so that no returned date lies
+    /// outside of the window.
+    fn aggregate(
+        self,
+        downloads: Vec<VersionDownload>,
+        window_start: NaiveDate,
+    ) -> Vec<VersionDownload> {
+        if self == Self::Day {
+            return downloads;
+        }
+
+        let mut buckets: Vec<VersionDownload> = Vec::new();
+        for download in downloads {
+            let date = self.bucket_start(download.date).max(window_start);
+            match buckets.last_mut() {
+                Some(bucket) if bucket.date == date => {
+                    bucket.downloads += download.downloads;
+                    bucket.counted += download.counted;
+                    bucket.processed &= download.processed;
+                }
+                _ => buckets.push(VersionDownload { date, ..download }),
+            }
+        }
+
+        buckets
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::email::Emails;
+    use crate::models::{NewCrate, NewUser, NewVersion};
+    use crate::test_util::test_db_connection;
+    use std::collections::BTreeMap;
+    use std::io;
+    use std::sync::{Arc, Mutex};
+
+    /// A [`tracing_subscriber`] writer that collects the log output in memory.
+    #[derive(Clone, Default)]
+    struct LogBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for LogBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_load_downloads_logs_slow_queries() {
+        let (_test_db, conn) = &mut test_db_connection();
+
+        let user = NewUser::new(2, "login", None, None, "access_token")
+            .create_or_update(None, &Emails::new_in_memory(), conn)
+            .unwrap();
+
+        let krate = NewCrate {
+            name: "foo",
+            ..Default::default()
+        }
+        .create(conn, user.id)
+        .unwrap();
+
+        let version = NewVersion::new(
+            krate.id,
+            &semver::Version::parse("1.0.0").unwrap(),
+            &BTreeMap::new(),
+            None,
+            0,
+            user.id,
+            "0000000000000000000000000000000000000000000000000000000000000000".to_string(),
+            None,
+            None,
+            None,
+        )
+        .unwrap()
+        .save(conn, "someone@example.com")
+        .unwrap();
+
+        let buffer = LogBuffer::default();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer({
+                let buffer = buffer.clone();
+                move || buffer.clone()
+            })
+            .with_ansi(false)
+            .without_time()
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let start = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let end = NaiveDate::from_ymd_opt(2024, 1, 31).unwrap();
+
+        // Fast queries are not logged
+        let threshold = std::time::Duration::from_secs(3600);
+        load_downloads(conn, "foo", &version, (start, end), threshold).unwrap();
+        assert!(buffer.0.lock().unwrap().is_empty());
+
+        let threshold = std::time::Duration::ZERO;
+        load_downloads(conn, "foo", &version, (start, end), threshold).unwrap();
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        assert!(output.contains("WARN"), "{output}");
+        assert!(output.contains("Slow download stats query"), "{output}");
+        assert!(output.contains(r#"crate_name="foo""#), "{output}");
+        assert!(output.contains("version=1.0.0"), "{output}");
+        assert!(
+            output.contains("window=2024-01-01..=2024-01-31"),
+            "{output}"
+        );
+        assert!(output.contains("rows=0"), "{output}");
+        assert!(output.contains("duration_ms="), "{output}");
+    }
+}
@@ -5,8 +5,11 @@
 //! `Cargo.toml` file.
 
 use crate::controllers::frontend_prelude::*;
+use chrono::NaiveDate;
+use diesel::dsl::min;
 
-use crate::models::VersionOwnerAction;
+use crate::models::{VersionDownload, VersionOwnerAction};
+use crate::schema::version_downloads;
 use crate::util::errors::version_not_found;
 use crate::views::{EncodableDependency, EncodableVersion};
 
@@ -56,6 +59,10 @@ pub async fn authors() -> Json<Value> {
 ///
 /// The frontend doesn't appear to hit this endpoint, but our tests do, and it seems to be a useful
 /// API route to have.
+///
+/// In addition to the regular version data, this includes the
+/// `first_download_date` of the version, which is `null` if the version was
+/// never downloaded.
 pub async fn show(
     state: AppState,
     Path((crate_name, version)): Path<(String, String)>,
@@ -70,7 +77,12 @@ pub async fn show(
         let published_by = version.published_by(conn);
         let actions = VersionOwnerAction::by_version(conn, &version)?;
 
-        let version = EncodableVersion::from(version, &krate.name, published_by, actions);
+        let first_download_date = VersionDownload::belonging_to(&version)
+            .select(min(version_downloads::date))
+            .get_result::<Option<NaiveDate>>(conn)?;
+
+        let mut version = EncodableVersion::from(version, &krate.name, published_by, actions);
+        version.first_download_date = Some(first_download_date);
         Ok(Json(json!({ "version": version })))
     })
     .await
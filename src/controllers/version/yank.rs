@@ -3,15 +3,34 @@
 use super::version_and_crate;
 use crate::auth::AuthCheck;
 use crate::controllers::cargo_prelude::*;
+use crate::controllers::util::RequestPartsExt;
 use crate::models::token::EndpointScope;
-use crate::models::Rights;
 use crate::models::{insert_version_owner_action, VersionAction};
+use crate::models::{Crate, Rights, User, Version};
 use crate::rate_limiter::LimitedAction;
 use crate::schema::versions;
-use crate::util::errors::{custom, version_not_found};
+use crate::util::errors::{bad_request, crate_not_found, custom, version_not_found};
 use crate::worker::jobs;
 use tokio::runtime::Handle;
 
+/// Maximum number of characters accepted for a yank reason.
+const MAX_YANK_REASON_LENGTH: usize = 500;
+
+/// Maximum number of versions that can be yanked at once by the
+/// `POST /crates/:crate_id/yank` endpoint.
+const MAX_BATCH_YANK_VERSIONS: usize = 100;
+
+#[derive(Deserialize)]
+struct YankRequest {
+    reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct BatchYankRequest {
+    versions: Vec<String>,
+    reason: Option<String>,
+}
+
 /// Handles the `DELETE /crates/:crate_id/:version/yank` route.
 /// This does not delete a crate version, it makes the crate
 /// version accessible only to crates that already have a
@@ -21,12 +40,19 @@ use tokio::runtime::Handle;
 /// Crate deletion is not implemented to avoid breaking builds,
 /// and the goal of yanking a crate is to prevent crates
 /// beginning to depend on the yanked crate version.
+///
+/// An optional `{ "reason": "..." }` JSON body may be sent to record why
+/// the version was yanked.
 pub async fn yank(
     app: AppState,
     Path((crate_name, version)): Path<(String, String)>,
-    req: Parts,
+    req: BytesRequest,
 ) -> AppResult<Response> {
-    spawn_blocking(move || modify_yank(&crate_name, &version, &app, &req, true)).await
+    spawn_blocking(move || {
+        let reason = parse_yank_reason(req.body())?;
+        modify_yank(&crate_name, &version, &app, &req, true, reason)
+    })
+    .await
 }
 
 /// Handles the `PUT /crates/:crate_id/:version/unyank` route.
@@ -35,16 +61,51 @@ pub async fn unyank(
     Path((crate_name, version)): Path<(String, String)>,
     req: Parts,
 ) -> AppResult<Response> {
-    spawn_blocking(move || modify_yank(&crate_name, &version, &app, &req, false)).await
+    spawn_blocking(move || modify_yank(&crate_name, &version, &app, &req, false, None)).await
+}
+
+/// Extracts the optional yank reason from the request body.
+///
+/// Empty bodies and blank reasons are treated as "no reason given".
+fn parse_yank_reason(body: &[u8]) -> AppResult<Option<String>> {
+    if body.iter().all(u8::is_ascii_whitespace) {
+        return Ok(None);
+    }
+
+    let request: YankRequest =
+        serde_json::from_slice(body).map_err(|_| bad_request("invalid json request"))?;
+
+    normalize_yank_reason(request.reason)
+}
+
+/// Trims the yank reason, treats blank reasons as "no reason given" and
+/// enforces the maximum reason length.
+fn normalize_yank_reason(reason: Option<String>) -> AppResult<Option<String>> {
+    let reason = reason
+        .map(|reason| reason.trim().to_string())
+        .filter(|reason| !reason.is_empty());
+
+    if let Some(reason) = &reason {
+        if reason.chars().count() > MAX_YANK_REASON_LENGTH {
+            return Err(bad_request(format!(
+                "yank reason must not be longer than {MAX_YANK_REASON_LENGTH} characters"
+            )));
+        }
+    }
+
+    Ok(reason)
 }
 
 /// Changes `yanked` flag on a crate version record
-fn modify_yank(
+///
+/// The yank reason is stored alongside the flag and cleared again on unyank.
+fn modify_yank<T: RequestPartsExt>(
     crate_name: &str,
     version: &str,
     state: &AppState,
-    req: &Parts,
+    req: &T,
     yanked: bool,
+    reason: Option<String>,
 ) -> AppResult<Response> {
     // FIXME: Should reject bad requests before authentication, but can't due to
     // lifetime issues with `req`.
@@ -67,14 +128,52 @@ fn modify_yank(
     let (version, krate) = version_and_crate(conn, crate_name, version)?;
     let api_token_id = auth.api_token_id();
     let user = auth.user();
+    check_yank_rights(state, conn, user, &krate, &version.num, yanked)?;
+
+    if version.yanked == yanked {
+        // The crate is already in the state requested, nothing to do
+        return ok_true();
+    }
+
+    diesel::update(&version)
+        .set((
+            versions::yanked.eq(yanked),
+            versions::yank_reason.eq(reason),
+        ))
+        .execute(conn)?;
+
+    let action = if yanked {
+        VersionAction::Yank
+    } else {
+        VersionAction::Unyank
+    };
+
+    insert_version_owner_action(conn, version.id, user.id, api_token_id, action)?;
+
+    jobs::enqueue_sync_to_index(&krate.name, conn)?;
+
+    ok_true()
+}
+
+/// Checks that the user is allowed to yank or unyank versions of the crate.
+///
+/// Admins are allowed to do so for any crate, which is logged as a warning.
+fn check_yank_rights(
+    state: &AppState,
+    conn: &mut PgConnection,
+    user: &User,
+    krate: &Crate,
+    version: &str,
+    yanked: bool,
+) -> AppResult<()> {
     let owners = krate.owners(conn)?;
 
     if Handle::current().block_on(user.rights(state, &owners))? < Rights::Publish {
         if user.is_admin {
             let action = if yanked { "yanking" } else { "unyanking" };
             warn!(
-                "Admin {} is {action} {}@{}",
-                user.gh_login, krate.name, version.num
+                "Admin {} is {action} {}@{version}",
+                user.gh_login, krate.name
             );
         } else {
             return Err(custom(
@@ -84,24 +183,131 @@ fn modify_yank(
         }
     }
 
-    if version.yanked == yanked {
-        // The crate is already in the state requested, nothing to do
-        return ok_true();
-    }
+    Ok(())
+}
 
-    diesel::update(&version)
-        .set(versions::yanked.eq(yanked))
-        .execute(conn)?;
+/// Handles the `POST /crates/:crate_id/yank` route.
+///
+/// Yanks all versions listed in the `{ "versions": [...], "reason": "..." }`
+/// JSON body within a single transaction. The response lists whether each
+/// version could be yanked. If any of them could not be yanked, none of the
+/// versions are changed and the request fails with `400 Bad Request`. The
+/// other versions are then reported with a `rolled back` error.
+///
+/// Like for single versions, yanking only changes the `yanked` flag and
+/// leaves the download counts of the versions untouched.
+pub async fn batch_yank(
+    app: AppState,
+    Path(crate_name): Path<String>,
+    req: BytesRequest,
+) -> AppResult<Response> {
+    spawn_blocking(move || {
+        let request: BatchYankRequest =
+            serde_json::from_slice(req.body()).map_err(|_| bad_request("invalid json request"))?;
 
-    let action = if yanked {
-        VersionAction::Yank
-    } else {
-        VersionAction::Unyank
+        if request.versions.is_empty() {
+            return Err(bad_request("at least one version has to be given"));
+        }
+        if request.versions.len() > MAX_BATCH_YANK_VERSIONS {
+            return Err(bad_request(format_args!(
+                "at most {MAX_BATCH_YANK_VERSIONS} versions can be yanked at once"
+            )));
+        }
+
+        let reason = normalize_yank_reason(request.reason)?;
+
+        let conn = &mut *app.db_write()?;
+
+        let auth = AuthCheck::default()
+            .with_endpoint_scope(EndpointScope::Yank)
+            .for_crate(&crate_name)
+            .check(&req, conn)?;
+
+        app.rate_limiter
+            .check_rate_limit(auth.user_id(), LimitedAction::YankUnyank, conn)?;
+
+        let krate: Crate = Crate::by_name(&crate_name)
+            .first(conn)
+            .optional()?
+            .ok_or_else(|| crate_not_found(&crate_name))?;
+
+        let api_token_id = auth.api_token_id();
+        let user = auth.user();
+
+        let versions = request.versions.join(", ");
+        check_yank_rights(&app, conn, user, &krate, &versions, true)?;
+
+        let mut results = Vec::with_capacity(request.versions.len());
+        let transaction = conn.transaction(|conn| {
+            for num in &request.versions {
+                let error = yank_in_batch(conn, &krate, num, &reason, user, api_token_id)?;
+                results.push((num, error));
+            }
+
+            // Roll back the whole batch if any of the versions failed
+            match results.iter().any(|(_, error)| error.is_some()) {
+                true => Err(diesel::result::Error::RollbackTransaction),
+                false => Ok(()),
+            }
+        });
+
+        let status = match transaction {
+            Ok(()) => {
+                jobs::enqueue_sync_to_index(&krate.name, conn)?;
+                StatusCode::OK
+            }
+            Err(diesel::result::Error::RollbackTransaction) => StatusCode::BAD_REQUEST,
+            Err(error) => return Err(error.into()),
+        };
+
+        let versions = results
+            .into_iter()
+            .map(|(num, error)| match error {
+                None if status == StatusCode::OK => json!({ "version": num, "ok": true }),
+                None => json!({ "version": num, "ok": false, "error": "rolled back" }),
+                Some(error) => json!({ "version": num, "ok": false, "error": error }),
+            })
+            .collect::<Vec<_>>();
+
+        let json = json!({ "ok": status == StatusCode::OK, "versions": versions });
+        Ok((status, Json(json)).into_response())
+    })
+    .await
+}
+
+/// Yanks a single version as part of a [`batch_yank`] transaction.
+///
+/// Returns an error message if the version can not be yanked.
+fn yank_in_batch(
+    conn: &mut PgConnection,
+    krate: &Crate,
+    num: &str,
+    reason: &Option<String>,
+    user: &User,
+    api_token_id: Option<i32>,
+) -> QueryResult<Option<String>> {
+    let version: Option<Version> = versions::table
+        .filter(versions::crate_id.eq(krate.id))
+        .filter(versions::num.eq(num))
+        .first(conn)
+        .optional()?;
+
+    let version = match version {
+        Some(version) if !version.deleted => version,
+        Some(_) => return Ok(Some("version has been deleted".to_string())),
+        None => return Ok(Some("version does not exist".to_string())),
     };
 
-    insert_version_owner_action(conn, version.id, user.id, api_token_id, action)?;
+    if version.yanked {
+        // The version is already yanked, nothing to do
+        return Ok(None);
+    }
 
-    jobs::enqueue_sync_to_index(&krate.name, conn)?;
+    diesel::update(&version)
+        .set((versions::yanked.eq(true), versions::yank_reason.eq(reason)))
+        .execute(conn)?;
 
-    ok_true()
+    insert_version_owner_action(conn, version.id, user.id, api_token_id, VersionAction::Yank)?;
+
+    Ok(None)
 }
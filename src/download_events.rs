@@ -0,0 +1,240 @@
+//! Publishing of raw download events for internal analytics.
+//!
+//! The download endpoint publishes an event for every download of a known
+//! version into a bounded channel. A background thread consumes the channel
+//! and passes the events on to the configured [`DownloadEventSink`].
+//!
+//! Publishing never blocks the download response. If the channel is full,
+//! the event is dropped instead.
+
+use chrono::{DateTime, Utc};
+use crates_io_cdn_logs::DownloadSource;
+use std::io::Write;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// Number of events that may be buffered before new events are dropped.
+const CHANNEL_CAPACITY: usize = 1024;
+
+/// A single download of a crate version.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DownloadEvent {
+    pub crate_id: i32,
+    pub version_id: i32,
+    pub timestamp: DateTime<Utc>,
+    /// The kind of client that requested the download, or `None` if its
+    /// `User-Agent` could not be classified.
+    pub source: Option<DownloadSource>,
+}
+
+/// Consumer of the published download events.
+///
+/// Sinks are called from a dedicated thread, so they may block.
+pub trait DownloadEventSink: Send + 'static {
+    fn consume(&mut self, event: DownloadEvent);
+}
+
+/// Writes the download events to stdout, as one JSON object per line.
+pub struct StdoutSink;
+
+impl DownloadEventSink for StdoutSink {
+    fn consume(&mut self, event: DownloadEvent) {
+        let json = json!({
+            "crate_id": event.crate_id,
+            "version_id": event.version_id,
+            "timestamp": event.timestamp,
+            "source": event.source.map(|source| source.as_str()),
+        });
+
+        if let Err(error) = writeln!(std::io::stdout().lock(), "{json}") {
+            warn!(%error, "Failed to write download event");
+        }
+    }
+}
+
+/// Collects the download events in memory, so that tests can assert that
+/// they were published. Waiting tests are notified of every consumed event.
+#[derive(Debug, Clone, Default)]
+struct InMemorySink {
+    events: Arc<Mutex<Vec<DownloadEvent>>>,
+    consumed: Arc<Condvar>,
+}
+
+impl InMemorySink {
+    fn events(&self) -> Vec<DownloadEvent> {
+        self.events.lock().unwrap().clone()
+    }
+
+    fn wait_for_events(&self, count: usize, timeout: Duration) -> Vec<DownloadEvent> {
+        let events = self.events.lock().unwrap();
+        let (events, _) = self
+            .consumed
+            .wait_timeout_while(events, timeout, |events| events.len() < count)
+            .unwrap();
+        events.clone()
+    }
+}
+
+impl DownloadEventSink for InMemorySink {
+    fn consume(&mut self, event: DownloadEvent) {
+        self.events.lock().unwrap().push(event);
+        self.consumed.notify_all();
+    }
+}
+
+/// The publishing side of the download event channel.
+///
+/// If no sink is configured, all events are discarded without being
+/// buffered.
+#[derive(Debug, Default)]
+pub struct DownloadEvents {
+    sender: Option<mpsc::Sender<DownloadEvent>>,
+    memory: Option<InMemorySink>,
+}
+
+/// Error returned when an event could not be published because the channel
+/// is full or its consumer has stopped.
+#[derive(Debug)]
+pub struct EventDropped;
+
+impl DownloadEvents {
+    /// Creates a new channel, and spawns a background thread that passes the
+    /// published events on to the given sink.
+    ///
+    /// The thread stops once the `DownloadEvents` instance is dropped.
+    pub fn new(mut sink: impl DownloadEventSink) -> Self {
+        let (sender, mut receiver) = mpsc::channel(CHANNEL_CAPACITY);
+
+        thread::Builder::new()
+            .name("download-events".into())
+            .spawn(move || {
+                while let Some(event) = receiver.blocking_recv() {
+                    sink.consume(event);
+                }
+            })
+            .expect("failed to spawn the download events thread");
+
+        let sender = Some(sender);
+        Self {
+            sender,
+            memory: None,
+        }
+    }
+
+    /// Creates an instance that discards all events.
+    pub fn disabled() -> Self {
+        Self::default()
+    }
+
+    /// Create a new test instance that stores all the consumed events in
+    /// memory, allowing for tests to later assert the events were published.
+    pub fn new_in_memory() -> Self {
+        let sink = InMemorySink::default();
+        let memory = Some(sink.clone());
+        Self {
+            memory,
+            ..Self::new(sink)
+        }
+    }
+
+    /// This is supposed to be used only during tests, to retrieve the events
+    /// consumed by the "memory" sink. It's not cfg'd away because our
+    /// integration tests need to access this.
+    pub fn events_in_memory(&self) -> Option<Vec<DownloadEvent>> {
+        self.memory.as_ref().map(InMemorySink::events)
+    }
+
+    /// Like [`DownloadEvents::events_in_memory`], but first waits until the
+    /// "memory" sink has consumed at least `count` events, or until the
+    /// `timeout` has passed.
+    pub fn wait_for_events_in_memory(
+        &self,
+        count: usize,
+        timeout: Duration,
+    ) -> Option<Vec<DownloadEvent>> {
+        let memory = self.memory.as_ref()?;
+        Some(memory.wait_for_events(count, timeout))
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.sender.is_some()
+    }
+
+    /// Publishes an event without waiting for the sink to consume it.
+    pub fn publish(&self, event: DownloadEvent) -> Result<(), EventDropped> {
+        let Some(sender) = &self.sender else {
+            return Ok(());
+        };
+
+        sender.try_send(event).map_err(|_| EventDropped)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+
+    fn event(version_id: i32) -> DownloadEvent {
+        DownloadEvent {
+            crate_id: 1,
+            version_id,
+            timestamp: Utc::now(),
+            source: Some(DownloadSource::Human),
+        }
+    }
+
+    /// Sink that blocks until it is allowed to consume the next event.
+    struct BlockingSink(Receiver<()>);
+
+    impl DownloadEventSink for BlockingSink {
+        fn consume(&mut self, _event: DownloadEvent) {
+            let _ = self.0.recv();
+        }
+    }
+
+    #[test]
+    fn test_disabled() {
+        let events = DownloadEvents::disabled();
+        assert!(!events.is_enabled());
+        assert_ok!(events.publish(event(1)));
+        assert_none!(events.events_in_memory());
+    }
+
+    #[test]
+    fn test_publish() {
+        let events = DownloadEvents::new_in_memory();
+        assert!(events.is_enabled());
+
+        assert_ok!(events.publish(event(1)));
+        assert_ok!(events.publish(event(2)));
+
+        for _ in 0..100 {
+            if events.events_in_memory().unwrap().len() == 2 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        let consumed = events.events_in_memory().unwrap();
+        let version_ids = consumed.iter().map(|e| e.version_id).collect::<Vec<_>>();
+        assert_eq!(version_ids, [1, 2]);
+    }
+
+    #[test]
+    fn test_drops_events_when_full() {
+        let (unblock, blocked): (SyncSender<()>, _) = sync_channel(0);
+        let events = DownloadEvents::new(BlockingSink(blocked));
+
+        // One event is taken by the blocked sink, the rest fills the channel
+        let published = (0..CHANNEL_CAPACITY as i32 + 10)
+            .filter(|id| events.publish(event(*id)).is_ok())
+            .count();
+
+        assert!(published <= CHANNEL_CAPACITY + 1);
+        assert_err!(events.publish(event(0)));
+        drop(unblock);
+    }
+}
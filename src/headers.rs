@@ -1,7 +1,10 @@
 use axum_extra::headers::{Error, Header};
 use http::header::{HeaderName, HeaderValue};
 
-static X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+pub static X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+pub static X_CRATE_CHECKSUM: HeaderName = HeaderName::from_static("x-crate-checksum");
+pub static X_CRATE_YANKED: HeaderName = HeaderName::from_static("x-crate-yanked");
+pub static X_DOWNLOAD_ID: HeaderName = HeaderName::from_static("x-download-id");
 
 pub struct XRequestId(String);
 
@@ -38,6 +38,7 @@ pub mod cloudfront;
 pub mod config;
 pub mod controllers;
 pub mod db;
+pub mod download_events;
 pub mod email;
 pub mod external_urls;
 pub mod fastly;
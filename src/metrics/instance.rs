@@ -43,6 +43,11 @@ metrics! {
         pub response_times: HistogramVec["endpoint"],
         /// Nmber of responses per status code
         pub responses_by_status_code_total: IntCounterVec["status"],
+
+        /// Amount of time required to build the redirect URL of a crate download
+        pub crate_download_redirect_duration_seconds: HistogramVec["wants_json", "backend"],
+        /// Number of download events that were dropped because the channel was full
+        pub download_events_dropped_total: IntCounter,
     }
 
     // All instance metrics will be prefixed with this namespace.
@@ -3,6 +3,7 @@ mod balance_capacity;
 mod block_traffic;
 pub mod cargo_compat;
 mod common_headers;
+mod cors;
 mod debug;
 mod ember_html;
 pub mod log_request;
@@ -73,6 +74,7 @@ pub fn apply_axum_middleware(state: AppState, router: Router<()>) -> Router {
             require_user_agent::require_user_agent,
         ))
         .layer(from_fn_with_state(state.clone(), block_traffic::middleware))
+        .layer(from_fn_with_state(state.clone(), cors::middleware))
         .layer(from_fn_with_state(
             state.clone(),
             common_headers::add_common_headers,
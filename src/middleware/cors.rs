@@ -0,0 +1,95 @@
+//! Middleware that handles CORS requests to the read-only download stats endpoints
+//!
+//! All other endpoints allow any origin via the `common_headers` middleware. The download stats
+//! endpoints only allow the origins that are listed in `DOWNLOADS_CORS_ALLOWED_ORIGINS`, and are
+//! limited to same-origin requests otherwise. The middleware has to run before `common_headers`,
+//! so that it can replace the `Access-Control-Allow-Origin` header set there.
+
+use crate::app::AppState;
+use axum::extract::{MatchedPath, Request};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use http::{header, HeaderValue, Method, StatusCode};
+
+const ALLOWED_METHODS: &str = "GET, HEAD";
+const ALLOWED_HEADERS: &str = "Accept, If-None-Match, If-Modified-Since";
+
+/// How long browsers may cache the preflight response, in seconds.
+const PREFLIGHT_MAX_AGE: &str = "86400";
+
+pub async fn middleware(
+    matched_path: Option<MatchedPath>,
+    state: AppState,
+    req: Request,
+    next: Next,
+) -> Response {
+    let is_download_stats = matched_path.is_some_and(|path| is_download_stats_route(path.as_str()));
+    if !is_download_stats {
+        return next.run(req).await;
+    }
+
+    let allowed_origin = req
+        .headers()
+        .get(header::ORIGIN)
+        .filter(|origin| state.config.downloads_cors_origins.contains(origin))
+        .cloned();
+
+    let is_preflight = req.method() == Method::OPTIONS;
+    let mut response = match is_preflight {
+        true => StatusCode::NO_CONTENT.into_response(),
+        false => next.run(req).await,
+    };
+
+    let headers = response.headers_mut();
+    headers.remove(header::ACCESS_CONTROL_ALLOW_ORIGIN);
+    headers.append(header::VARY, HeaderValue::from_static("Origin"));
+
+    if let Some(origin) = allowed_origin {
+        headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+        if is_preflight {
+            let v = HeaderValue::from_static;
+            headers.insert(header::ACCESS_CONTROL_ALLOW_METHODS, v(ALLOWED_METHODS));
+            headers.insert(header::ACCESS_CONTROL_ALLOW_HEADERS, v(ALLOWED_HEADERS));
+            headers.insert(header::ACCESS_CONTROL_MAX_AGE, v(PREFLIGHT_MAX_AGE));
+        }
+    }
+
+    response
+}
+
+/// Whether the matched route is one of the crate or version download stats endpoints.
+///
+/// The download endpoint itself and the `downloads/recompute` endpoint are not included, since
+/// they are not read-only stats.
+fn is_download_stats_route(path: &str) -> bool {
+    path.starts_with("/api/v1/crates/")
+        && path.contains("/downloads")
+        && !path.ends_with("/recompute")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_download_stats_route() {
+        assert!(is_download_stats_route("/api/v1/crates/downloads"));
+        assert!(is_download_stats_route(
+            "/api/v1/crates/:crate_id/downloads"
+        ));
+        assert!(is_download_stats_route(
+            "/api/v1/crates/:crate_id/:version/downloads"
+        ));
+        assert!(is_download_stats_route(
+            "/api/v1/crates/:crate_id/:version/downloads.csv"
+        ));
+
+        assert!(!is_download_stats_route(
+            "/api/v1/crates/:crate_id/:version/download"
+        ));
+        assert!(!is_download_stats_route(
+            "/api/v1/crates/:crate_id/:version/downloads/recompute"
+        ));
+        assert!(!is_download_stats_route("/api/v1/users/:user_id/downloads"));
+    }
+}
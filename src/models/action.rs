@@ -1,4 +1,4 @@
-use crate::models::{ApiToken, User, Version};
+use crate::models::{ApiToken, Crate, OwnerKind, User, Version};
 use crate::schema::*;
 use crate::sql::pg_enum;
 use chrono::NaiveDateTime;
@@ -95,3 +95,77 @@ pub fn insert_version_owner_action(
         ))
         .get_result(conn)
 }
+
+pg_enum! {
+    pub enum OwnerAction {
+        Add = 0,
+        Remove = 1,
+    }
+}
+
+impl From<OwnerAction> for &'static str {
+    fn from(action: OwnerAction) -> Self {
+        match action {
+            OwnerAction::Add => "add",
+            OwnerAction::Remove => "remove",
+        }
+    }
+}
+
+impl From<OwnerAction> for String {
+    fn from(action: OwnerAction) -> Self {
+        let string: &'static str = action.into();
+
+        string.into()
+    }
+}
+
+/// An owner that was added to or removed from a crate.
+#[derive(Debug, Clone, Copy, Queryable, Identifiable, Associations)]
+#[diesel(
+    table_name = crate_owner_actions,
+    check_for_backend(diesel::pg::Pg),
+    belongs_to(Crate),
+    belongs_to(User, foreign_key = user_id),
+)]
+pub struct CrateOwnerAction {
+    pub id: i32,
+    pub crate_id: i32,
+    pub owner_id: i32,
+    pub owner_kind: OwnerKind,
+    /// The user that performed the action.
+    pub user_id: i32,
+    pub action: OwnerAction,
+    pub time: NaiveDateTime,
+}
+
+impl CrateOwnerAction {
+    /// Returns the actions of the given crate in chronological order,
+    /// together with the users that performed them.
+    pub fn by_crate(conn: &mut PgConnection, crate_id: i32) -> QueryResult<Vec<(Self, User)>> {
+        crate_owner_actions::table
+            .filter(crate_owner_actions::crate_id.eq(crate_id))
+            .inner_join(users::table)
+            .order(crate_owner_actions::id)
+            .load(conn)
+    }
+}
+
+pub fn insert_crate_owner_action(
+    conn: &mut PgConnection,
+    crate_id: i32,
+    owner_id: i32,
+    owner_kind: OwnerKind,
+    user_id: i32,
+    action: OwnerAction,
+) -> QueryResult<CrateOwnerAction> {
+    diesel::insert_into(crate_owner_actions::table)
+        .values((
+            crate_owner_actions::crate_id.eq(crate_id),
+            crate_owner_actions::owner_id.eq(owner_id),
+            crate_owner_actions::owner_kind.eq(owner_kind),
+            crate_owner_actions::user_id.eq(user_id),
+            crate_owner_actions::action.eq(action),
+        ))
+        .get_result(conn)
+}
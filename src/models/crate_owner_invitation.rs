@@ -4,7 +4,7 @@ use http::StatusCode;
 use secrecy::SecretString;
 
 use crate::config;
-use crate::models::{CrateOwner, OwnerKind};
+use crate::models::{insert_crate_owner_action, CrateOwner, OwnerAction, OwnerKind};
 use crate::schema::{crate_owner_invitations, crate_owners, crates};
 use crate::util::errors::{custom, AppResult};
 
@@ -125,6 +125,15 @@ impl CrateOwnerInvitation {
                 .set(crate_owners::deleted.eq(false))
                 .execute(conn)?;
 
+            insert_crate_owner_action(
+                conn,
+                self.crate_id,
+                self.invited_user_id,
+                OwnerKind::User,
+                self.invited_by_user_id,
+                OwnerAction::Add,
+            )?;
+
             diesel::delete(&self).execute(conn)?;
 
             Ok(())
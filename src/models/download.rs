@@ -1,6 +1,8 @@
 use crate::models::Version;
-use crate::schema::version_downloads;
+use crate::schema::{version_downloads, versions};
 use chrono::NaiveDate;
+use diesel::prelude::*;
+use std::ops::RangeInclusive;
 
 #[derive(Queryable, Identifiable, Associations, Debug, Clone, Copy)]
 #[diesel(primary_key(version_id, date), belongs_to(Version))]
@@ -11,3 +13,27 @@ pub struct VersionDownload {
     pub date: NaiveDate,
     pub processed: bool,
 }
+
+impl VersionDownload {
+    /// Loads the daily downloads of all versions of a crate within the given
+    /// date window in a single query, ordered by date and then by version id
+    /// in descending order.
+    pub fn belonging_to_crate(
+        crate_id: i32,
+        window: RangeInclusive<NaiveDate>,
+        conn: &mut PgConnection,
+    ) -> QueryResult<Vec<VersionDownload>> {
+        let version_ids = versions::table
+            .filter(versions::crate_id.eq(crate_id))
+            .select(versions::id);
+
+        version_downloads::table
+            .filter(version_downloads::version_id.eq_any(version_ids))
+            .filter(version_downloads::date.between(*window.start(), *window.end()))
+            .order((
+                version_downloads::date.asc(),
+                version_downloads::version_id.desc(),
+            ))
+            .load(conn)
+    }
+}
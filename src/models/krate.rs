@@ -12,8 +12,9 @@ use crate::controllers::helpers::pagination::*;
 use crate::email::Email;
 use crate::models::version::TopVersions;
 use crate::models::{
-    CrateOwner, CrateOwnerInvitation, Dependency, NewCrateOwnerInvitationOutcome, Owner, OwnerKind,
-    ReverseDependency, User, Version,
+    insert_crate_owner_action, CrateOwner, CrateOwnerInvitation, Dependency,
+    NewCrateOwnerInvitationOutcome, Owner, OwnerAction, OwnerKind, ReverseDependency, User,
+    Version,
 };
 use crate::util::errors::{version_not_found, AppResult};
 
@@ -410,6 +411,15 @@ impl Crate {
                     .set(crate_owners::deleted.eq(false))
                     .execute(conn)?;
 
+                insert_crate_owner_action(
+                    conn,
+                    self.id,
+                    owner.id(),
+                    OwnerKind::Team,
+                    req_user.id,
+                    OwnerAction::Add,
+                )?;
+
                 Ok(format!(
                     "team {} has been added as an owner of crate {}",
                     owner.login(),
@@ -419,14 +429,19 @@ impl Crate {
         }
     }
 
-    pub fn owner_remove(&self, conn: &mut PgConnection, login: &str) -> AppResult<()> {
+    /// Removes the user or team with the given login from the owners of the
+    /// crate, and returns the removed owner. If they are not an owner of the
+    /// crate, nothing is changed and `None` is returned.
+    pub fn owner_remove(&self, conn: &mut PgConnection, login: &str) -> AppResult<Option<Owner>> {
         let owner = Owner::find_by_login(conn, login)?;
 
-        let target = crate_owners::table.find((self.id(), owner.id(), owner.kind()));
-        diesel::update(target)
+        let target = crate_owners::table
+            .find((self.id(), owner.id(), owner.kind()))
+            .filter(crate_owners::deleted.eq(false));
+        let removed = diesel::update(target)
             .set(crate_owners::deleted.eq(true))
             .execute(conn)?;
-        Ok(())
+        Ok((removed > 0).then_some(owner))
     }
 
     /// Returns (dependency, dependent crate name, dependent crate downloads)
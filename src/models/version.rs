@@ -29,6 +29,20 @@ pub struct Version {
     pub links: Option<String>,
     pub rust_version: Option<String>,
     pub semver_no_prerelease: Option<Triple>,
+    pub yank_reason: Option<String>,
+    /// Number of downloads before the version was imported from another
+    /// registry, which are not part of `version_downloads`.
+    pub extra_downloads: i32,
+    /// Whether the version was permanently deleted, e.g. for legal or
+    /// security reasons. Deleted versions can not be downloaded anymore.
+    pub deleted: bool,
+    /// Number of downloads that were pruned from `version_downloads` after
+    /// their retention period. These are still part of `downloads`.
+    pub pruned_downloads: i32,
+    /// The Rust edition declared in the `Cargo.toml` file of the version
+    /// (`2015` if none was declared), or `None` if the version was published
+    /// before the edition was recorded.
+    pub edition: Option<String>,
 }
 
 #[derive(Insertable, Debug)]
@@ -43,6 +57,7 @@ pub struct NewVersion {
     checksum: String,
     links: Option<String>,
     rust_version: Option<String>,
+    edition: Option<String>,
 }
 
 /// The highest version (semver order) and the most recently updated version.
@@ -140,6 +155,7 @@ impl NewVersion {
         checksum: String,
         links: Option<String>,
         rust_version: Option<String>,
+        edition: Option<String>,
     ) -> AppResult<Self> {
         let features = serde_json::to_value(features)?;
 
@@ -153,6 +169,7 @@ impl NewVersion {
             checksum,
             links,
             rust_version,
+            edition,
         })
     }
 
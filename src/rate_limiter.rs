@@ -6,8 +6,10 @@ use diesel::dsl::IntervalDsl;
 use diesel::prelude::*;
 use diesel::sql_types::Interval;
 use std::borrow::Cow;
-use std::collections::HashMap;
-use std::time::Duration;
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
 
 pg_enum! {
     pub enum LimitedAction {
@@ -165,6 +167,90 @@ impl RateLimiter {
     }
 }
 
+/// Maximum number of buckets that the [`IpRateLimiter`] keeps in memory. When
+/// this limit is reached, the oldest bucket is evicted for every new one.
+const MAX_IP_BUCKETS: usize = 100_000;
+
+/// An in-memory token bucket rate limiter that is keyed by client IP address.
+///
+/// Unlike [`RateLimiter`] this does not use the database, so that it can be
+/// used on hot paths like the download endpoint. The buckets are not shared
+/// between server instances.
+#[derive(Debug)]
+pub struct IpRateLimiter {
+    config: Option<RateLimiterConfig>,
+    buckets: Mutex<IpBuckets>,
+}
+
+#[derive(Debug, Default)]
+struct IpBuckets {
+    buckets: HashMap<IpAddr, IpBucket>,
+    /// The keys of `buckets`, in the order in which they were inserted.
+    order: VecDeque<IpAddr>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct IpBucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl IpRateLimiter {
+    /// Creates a new rate limiter. If `config` is `None`, all requests are
+    /// allowed.
+    pub fn new(config: Option<RateLimiterConfig>) -> Self {
+        let buckets = Mutex::new(IpBuckets::default());
+        Self { config, buckets }
+    }
+
+    /// Takes a token from the bucket of the given IP address.
+    ///
+    /// If the bucket is empty, the time until the next token is available is
+    /// returned as the error.
+    pub fn take_token(&self, ip: IpAddr) -> Result<(), Duration> {
+        self.take_token_at(ip, Instant::now())
+    }
+
+    fn take_token_at(&self, ip: IpAddr, now: Instant) -> Result<(), Duration> {
+        let Some(config) = self.config else {
+            return Ok(());
+        };
+
+        let burst = f64::from(config.burst.max(1));
+        let seconds_per_token = config.rate.as_secs_f64();
+        let refilled = |bucket: &IpBucket| {
+            let elapsed = now.saturating_duration_since(bucket.last_refill);
+            (bucket.tokens + elapsed.as_secs_f64() / seconds_per_token).min(burst)
+        };
+
+        let mut inner = self.buckets.lock().unwrap_or_else(PoisonError::into_inner);
+        let IpBuckets { buckets, order } = &mut *inner;
+        if !buckets.contains_key(&ip) {
+            if buckets.len() >= MAX_IP_BUCKETS {
+                if let Some(oldest) = order.pop_front() {
+                    buckets.remove(&oldest);
+                }
+            }
+            order.push_back(ip);
+        }
+
+        let bucket = buckets.entry(ip).or_insert(IpBucket {
+            tokens: burst,
+            last_refill: now,
+        });
+        bucket.tokens = refilled(bucket);
+        bucket.last_refill = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            let missing = 1.0 - bucket.tokens;
+            Err(Duration::from_secs_f64(missing * seconds_per_token))
+        }
+    }
+}
+
 #[derive(Queryable, Insertable, Debug, PartialEq, Clone, Copy)]
 #[diesel(table_name = publish_limit_buckets, check_for_backend(diesel::pg::Pg))]
 #[allow(dead_code)] // Most fields only read in tests
@@ -180,6 +266,74 @@ mod tests {
     use super::*;
     use crate::email::Emails;
     use crate::test_util::*;
+    use claims::{assert_err, assert_err_eq, assert_ok};
+
+    #[test]
+    fn ip_rate_limiter_exhausts_and_refills() {
+        let config = RateLimiterConfig {
+            rate: Duration::from_secs(10),
+            burst: 2,
+        };
+        let rate = IpRateLimiter::new(Some(config));
+        let ip = IpAddr::from([192, 0, 2, 1]);
+        let other_ip = IpAddr::from([192, 0, 2, 2]);
+        let now = Instant::now();
+
+        assert_ok!(rate.take_token_at(ip, now));
+        assert_ok!(rate.take_token_at(ip, now));
+        assert_err_eq!(rate.take_token_at(ip, now), Duration::from_secs(10));
+
+        // Other IP addresses have their own bucket
+        assert_ok!(rate.take_token_at(other_ip, now));
+
+        let later = now + Duration::from_secs(5);
+        assert_err_eq!(rate.take_token_at(ip, later), Duration::from_secs(5));
+
+        let later = now + Duration::from_secs(10);
+        assert_ok!(rate.take_token_at(ip, later));
+        assert_err!(rate.take_token_at(ip, later));
+
+        // Buckets are not refilled beyond the burst
+        let later = now + Duration::from_secs(3600);
+        assert_ok!(rate.take_token_at(ip, later));
+        assert_ok!(rate.take_token_at(ip, later));
+        assert_err!(rate.take_token_at(ip, later));
+    }
+
+    #[test]
+    fn ip_rate_limiter_is_limited() {
+        let config = RateLimiterConfig {
+            rate: Duration::from_secs(10),
+            burst: 1,
+        };
+        let rate = IpRateLimiter::new(Some(config));
+        let first_ip = IpAddr::from([0; 16]);
+        let now = Instant::now();
+
+        assert_ok!(rate.take_token_at(first_ip, now));
+        assert_err!(rate.take_token_at(first_ip, now));
+
+        for ip in 1..MAX_IP_BUCKETS as u128 {
+            assert_ok!(rate.take_token_at(IpAddr::from(ip.to_be_bytes()), now));
+        }
+        assert_err!(rate.take_token_at(first_ip, now));
+
+        // New IP addresses evict the oldest bucket
+        let ip = IpAddr::from(u128::MAX.to_be_bytes());
+        assert_ok!(rate.take_token_at(ip, now));
+        assert_err!(rate.take_token_at(ip, now));
+        assert_eq!(rate.buckets.lock().unwrap().buckets.len(), MAX_IP_BUCKETS);
+        assert_ok!(rate.take_token_at(first_ip, now));
+    }
+
+    #[test]
+    fn ip_rate_limiter_without_config() {
+        let rate = IpRateLimiter::new(None);
+        let ip = IpAddr::from([192, 0, 2, 1]);
+        for _ in 0..100 {
+            assert_ok!(rate.take_token(ip));
+        }
+    }
 
     #[test]
     fn default_rate_limits() -> QueryResult<()> {
@@ -32,6 +32,10 @@ pub fn build_axum_router(state: AppState) -> Router<()> {
             "/api/v1/crates/:crate_id/:version/yank",
             delete(version::yank::yank),
         )
+        .route(
+            "/api/v1/crates/:crate_id/yank",
+            post(version::yank::batch_yank),
+        )
         .route(
             "/api/v1/crates/:crate_id/:version/unyank",
             put(version::yank::unyank),
@@ -40,8 +44,28 @@ pub fn build_axum_router(state: AppState) -> Router<()> {
             "/api/v1/crates/:crate_id/:version/download",
             get(version::downloads::download),
         )
+        .route(
+            "/api/v1/crates/:crate_id/by-checksum/:prefix/download",
+            get(version::downloads::download_by_checksum),
+        )
         // Routes used by the frontend
         .route("/api/v1/crates/:crate_id", get(krate::metadata::show))
+        .route(
+            "/api/v1/crates/:crate_id/summary",
+            get(krate::metadata::summary),
+        )
+        .route(
+            "/api/v1/crates/:crate_id/health",
+            get(krate::metadata::health),
+        )
+        .route(
+            "/api/v1/crates/downloads",
+            post(krate::downloads::batch_downloads).get(krate::metadata::show_downloads),
+        )
+        .route(
+            "/api/v1/crates/downloads/by_edition",
+            get(krate::downloads::downloads_by_edition),
+        )
         .route(
             "/api/v1/crates/:crate_id/:version",
             get(version::metadata::show),
@@ -58,6 +82,58 @@ pub fn build_axum_router(state: AppState) -> Router<()> {
             "/api/v1/crates/:crate_id/:version/downloads",
             get(version::downloads::downloads),
         )
+        .route(
+            "/api/v1/crates/:crate_id/:version/downloads.csv",
+            get(version::downloads::downloads_csv),
+        )
+        .route(
+            "/api/v1/crates/:crate_id/:version/downloads.ndjson",
+            get(version::downloads::downloads_ndjson),
+        )
+        .route(
+            "/api/v1/crates/:crate_id/:version/downloads/total",
+            get(version::downloads::total_downloads),
+        )
+        .route(
+            "/api/v1/crates/:crate_id/:version/downloads/peaks",
+            get(version::downloads::download_peaks),
+        )
+        .route(
+            "/api/v1/crates/:crate_id/:version/downloads/by_target",
+            get(version::downloads::downloads_by_target),
+        )
+        .route(
+            "/api/v1/crates/:crate_id/:version/downloads/by_source",
+            get(version::downloads::downloads_by_source),
+        )
+        .route(
+            "/api/v1/crates/:crate_id/:version/downloads/recompute",
+            post(version::downloads::recompute_downloads),
+        )
+        .route(
+            "/api/v1/crates/:crate_id/:version/downloads/pending",
+            get(version::downloads::pending_downloads),
+        )
+        .route(
+            "/api/v1/crates/:crate_id/:version/downloads/watch",
+            get(version::downloads::watch_downloads),
+        )
+        .route(
+            "/api/v1/crates/:crate_id/:version/downloads/gaps",
+            get(version::downloads::download_gaps),
+        )
+        .route(
+            "/api/v1/crates/:crate_id/:version/downloads/hourly",
+            get(version::downloads::downloads_hourly),
+        )
+        .route(
+            "/api/v1/crates/:crate_id/:version/downloads/sparkline.svg",
+            get(version::downloads::downloads_sparkline),
+        )
+        .route(
+            "/api/v1/crates/:crate_id/:version/storage-key",
+            get(version::downloads::storage_key),
+        )
         .route(
             "/api/v1/crates/:crate_id/:version/authors",
             get(version::metadata::authors),
@@ -66,10 +142,54 @@ pub fn build_axum_router(state: AppState) -> Router<()> {
             "/api/v1/crates/:crate_id/downloads",
             get(krate::downloads::downloads),
         )
+        .route(
+            "/api/v1/crates/:crate_id/downloads/by_version",
+            get(krate::downloads::downloads_by_version),
+        )
+        .route(
+            "/api/v1/crates/:crate_id/downloads/by_major",
+            get(krate::downloads::downloads_by_major),
+        )
+        .route(
+            "/api/v1/crates/:crate_id/downloads/trend",
+            get(krate::downloads::downloads_trend),
+        )
+        .route(
+            "/api/v1/crates/:crate_id/downloads/matrix",
+            get(krate::downloads::downloads_matrix),
+        )
+        .route(
+            "/api/v1/crates/:crate_id/downloads/on/:date",
+            get(krate::downloads::downloads_on_date),
+        )
+        .route(
+            "/api/v1/crates/:crate_id/downloads/rank",
+            get(krate::downloads::downloads_rank),
+        )
+        .route(
+            "/api/v1/crates/:crate_id/downloads/version-spread",
+            get(krate::downloads::version_spread),
+        )
+        .route(
+            "/api/v1/crates/:crate_id/downloads/effective",
+            get(krate::downloads::effective_downloads),
+        )
+        .route(
+            "/api/v1/crates/:crate_id/downloads/by_feature/:feature",
+            get(krate::downloads::downloads_by_feature),
+        )
+        .route(
+            "/api/v1/crates/:crate_id/compare-downloads",
+            get(krate::downloads::compare_downloads),
+        )
         .route(
             "/api/v1/crates/:crate_id/versions",
             get(krate::versions::versions),
         )
+        .route(
+            "/api/v1/crates/:crate_id/versions/popular",
+            get(krate::versions::popular_versions),
+        )
         .route(
             "/api/v1/crates/:crate_id/follow",
             put(krate::follow::follow).delete(krate::follow::unfollow),
@@ -78,6 +198,10 @@ pub fn build_axum_router(state: AppState) -> Router<()> {
             "/api/v1/crates/:crate_id/following",
             get(krate::follow::following),
         )
+        .route(
+            "/api/v1/crates/:crate_id/owner-history",
+            get(krate::owners::owner_history),
+        )
         .route(
             "/api/v1/crates/:crate_id/owner_team",
             get(krate::owners::owner_team),
@@ -94,12 +218,20 @@ pub fn build_axum_router(state: AppState) -> Router<()> {
         .route("/api/v1/keywords/:keyword_id", get(keyword::show))
         .route("/api/v1/categories", get(category::index))
         .route("/api/v1/categories/:category_id", get(category::show))
+        .route(
+            "/api/v1/categories/:category_id/downloads",
+            get(category::downloads),
+        )
         .route("/api/v1/category_slugs", get(category::slugs))
         .route(
             "/api/v1/users/:user_id",
             get(user::other::show).put(user::me::update_user),
         )
         .route("/api/v1/users/:user_id/stats", get(user::other::stats))
+        .route(
+            "/api/v1/users/:user_id/downloads",
+            get(user::other::downloads),
+        )
         .route("/api/v1/teams/:team_id", get(team::show_team))
         .route("/api/v1/me", get(user::me::me))
         .route("/api/v1/me/updates", get(user::me::updates))
@@ -135,6 +267,7 @@ pub fn build_axum_router(state: AppState) -> Router<()> {
             "/api/v1/site_metadata",
             get(site_metadata::show_deployed_sha),
         )
+        .route("/api/v1/openapi/downloads.json", get(openapi::downloads))
         // Session management
         .route("/api/private/session/begin", get(user::session::begin))
         .route(
@@ -191,6 +191,26 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    /// Audit log of the owners that were added to or removed from crates.
+    crate_owner_actions (id) {
+        /// Unique identifier of the action.
+        id -> Int4,
+        /// Reference to the crate whose owners were modified.
+        crate_id -> Int4,
+        /// Reference to the user or team that was added or removed, depending on `owner_kind`.
+        owner_id -> Int4,
+        /// Whether the owner is a user (`0`) or a team (`1`).
+        owner_kind -> Int4,
+        /// Reference to the user that performed the action.
+        user_id -> Int4,
+        /// Whether the owner was added (`0`) or removed (`1`).
+        action -> Int4,
+        /// Time at which the action was performed.
+        time -> Timestamp,
+    }
+}
+
 diesel::table! {
     /// Representation of the `crate_owner_invitations` table.
     ///
@@ -369,6 +389,12 @@ diesel::table! {
         ///
         /// (Automatically generated by Diesel.)
         max_features -> Nullable<Int2>,
+        /// Number of days of download history that are returned for the versions of this crate by default. Falls back to the server default if `NULL`.
+        download_retention_days -> Nullable<Int4>,
+        /// Opt-in URL that receives a `POST` request whenever a version of this crate reaches a download milestone.
+        download_milestone_webhook_url -> Nullable<Varchar>,
+        /// Whether downloads of this crate require an API token with the `download` scope, e.g. for private crates of a self-hosted registry.
+        private -> Bool,
     }
 }
 
@@ -810,6 +836,18 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    /// Download milestones that versions of crates with a `download_milestone_webhook_url` have reached. Used to notify the webhook only once per milestone.
+    version_download_milestones (version_id, milestone) {
+        /// Reference to the version that reached the milestone.
+        version_id -> Int4,
+        /// The number of downloads of the milestone, e.g. `1000000`.
+        milestone -> Int8,
+        /// The time at which the milestone was detected.
+        reached_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     /// Representation of the `version_downloads` table.
     ///
@@ -848,6 +886,46 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    /// Number of downloads per version and hour. Only the last 48 hours are retained.
+    version_downloads_by_hour (version_id, hour) {
+        /// Reference to the version that this row belongs to.
+        version_id -> Int4,
+        /// The start of the hour (in UTC) that the downloads were counted in.
+        hour -> Timestamp,
+        /// The number of downloads of this version in this hour.
+        downloads -> Int4,
+    }
+}
+
+diesel::table! {
+    /// Number of downloads per version, source and day, for downloads whose `User-Agent` could be classified.
+    version_downloads_by_source (version_id, source, date) {
+        /// Reference to the version that this row belongs to.
+        version_id -> Int4,
+        /// The kind of client that requested the downloads (`human`, `ci` or `mirror`).
+        source -> Varchar,
+        /// The day that the downloads were counted on.
+        date -> Date,
+        /// The number of downloads of this version from this source on this day.
+        downloads -> Int4,
+    }
+}
+
+diesel::table! {
+    /// Number of downloads per version, target and day, for downloads that specified a `target`.
+    version_downloads_by_target (version_id, target, date) {
+        /// Reference to the version that this row belongs to.
+        version_id -> Int4,
+        /// The target triple that was passed to the download endpoint.
+        target -> Varchar,
+        /// The day that the downloads were counted on.
+        date -> Date,
+        /// The number of downloads of this version for this target on this day.
+        downloads -> Int4,
+    }
+}
+
 diesel::table! {
     /// Representation of the `version_owner_actions` table.
     ///
@@ -991,6 +1069,20 @@ diesel::table! {
         ///
         /// (Automatically generated by Diesel.)
         semver_no_prerelease -> Nullable<SemverTriple>,
+        /// The `yank_reason` column of the `versions` table.
+        ///
+        /// Its SQL type is `Nullable<Text>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        yank_reason -> Nullable<Text>,
+        /// Number of downloads of the version before it was imported from another registry. These downloads are not part of `version_downloads`.
+        extra_downloads -> Int4,
+        /// Whether the version was permanently deleted, e.g. for legal or security reasons. Unlike yanked versions, deleted versions can not be downloaded anymore.
+        deleted -> Bool,
+        /// Number of downloads of the version that were pruned from `version_downloads` after their retention period.
+        pruned_downloads -> Int4,
+        /// The Rust edition of the version, as declared in its `Cargo.toml` file, or `2015` if none was declared. `NULL` for versions that were published before the edition was recorded.
+        edition -> Nullable<Varchar>,
     }
 }
 
@@ -1016,6 +1108,8 @@ diesel::table! {
 
 diesel::joinable!(api_tokens -> users (user_id));
 diesel::joinable!(crate_downloads -> crates (crate_id));
+diesel::joinable!(crate_owner_actions -> crates (crate_id));
+diesel::joinable!(crate_owner_actions -> users (user_id));
 diesel::joinable!(crate_owner_invitations -> crates (crate_id));
 diesel::joinable!(crate_owners -> crates (crate_id));
 diesel::joinable!(crate_owners -> teams (owner_id));
@@ -1033,7 +1127,11 @@ diesel::joinable!(publish_limit_buckets -> users (user_id));
 diesel::joinable!(publish_rate_overrides -> users (user_id));
 diesel::joinable!(readme_renderings -> versions (version_id));
 diesel::joinable!(recent_crate_downloads -> crates (crate_id));
+diesel::joinable!(version_download_milestones -> versions (version_id));
 diesel::joinable!(version_downloads -> versions (version_id));
+diesel::joinable!(version_downloads_by_hour -> versions (version_id));
+diesel::joinable!(version_downloads_by_source -> versions (version_id));
+diesel::joinable!(version_downloads_by_target -> versions (version_id));
 diesel::joinable!(version_owner_actions -> api_tokens (api_token_id));
 diesel::joinable!(version_owner_actions -> users (user_id));
 diesel::joinable!(version_owner_actions -> versions (version_id));
@@ -1046,6 +1144,7 @@ diesel::allow_tables_to_appear_in_same_query!(
     background_jobs,
     categories,
     crate_downloads,
+    crate_owner_actions,
     crate_owner_invitations,
     crate_owners,
     crates,
@@ -1064,7 +1163,11 @@ diesel::allow_tables_to_appear_in_same_query!(
     reserved_crate_names,
     teams,
     users,
+    version_download_milestones,
     version_downloads,
+    version_downloads_by_hour,
+    version_downloads_by_source,
+    version_downloads_by_target,
     version_owner_actions,
     versions,
     versions_published_by,
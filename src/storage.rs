@@ -1,5 +1,6 @@
 use anyhow::Context;
-use crates_io_env_vars::required_var;
+use chrono::{DateTime, Utc};
+use crates_io_env_vars::{required_var, var_parsed};
 use futures_util::{StreamExt, TryStreamExt};
 use hyper::body::Bytes;
 use object_store::aws::{AmazonS3, AmazonS3Builder};
@@ -7,13 +8,16 @@ use object_store::local::LocalFileSystem;
 use object_store::memory::InMemory;
 use object_store::path::Path;
 use object_store::prefix::PrefixStore;
+use object_store::signer::Signer;
 use object_store::{ClientOptions, ObjectStore, Result};
 use reqwest::header::CACHE_CONTROL;
 use reqwest::header::{HeaderMap, HeaderValue};
+use reqwest::Method;
 use secrecy::{ExposeSecret, SecretString};
 use std::fs;
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::fs::File;
 use tokio::io::AsyncWriteExt;
 
@@ -27,6 +31,7 @@ const CONTENT_TYPE_README: &str = "text/html";
 const CACHE_CONTROL_IMMUTABLE: &str = "public,max-age=31536000,immutable";
 const CACHE_CONTROL_INDEX: &str = "public,max-age=600";
 const CACHE_CONTROL_README: &str = "public,max-age=604800";
+const DEFAULT_SIGNED_URL_TTL: Duration = Duration::from_secs(5 * 60);
 
 type StdPath = std::path::Path;
 
@@ -34,6 +39,12 @@ type StdPath = std::path::Path;
 pub struct StorageConfig {
     backend: StorageBackend,
     pub cdn_prefix: Option<String>,
+    /// Whether crate downloads use pre-signed, time-limited URLs of the S3
+    /// bucket instead of the public CDN URLs. This is only supported by the
+    /// S3 backend, and is ignored for all other backends.
+    pub signed_urls: bool,
+    /// How long the pre-signed crate download URLs are valid for.
+    pub signed_url_ttl: Duration,
 }
 
 #[derive(Debug)]
@@ -44,6 +55,59 @@ pub enum StorageBackend {
     InMemory,
 }
 
+impl StorageBackend {
+    pub fn kind(&self) -> StorageBackendKind {
+        match self {
+            StorageBackend::S3 { .. } => StorageBackendKind::S3,
+            StorageBackend::LocalFileSystem { .. } => StorageBackendKind::LocalFileSystem,
+            StorageBackend::InMemory => StorageBackendKind::InMemory,
+        }
+    }
+}
+
+/// The kind of [StorageBackend] that is used by [Storage], without any of the
+/// backend configuration details.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageBackendKind {
+    S3,
+    LocalFileSystem,
+    InMemory,
+}
+
+impl StorageBackendKind {
+    /// Whether the files are stored by this process itself, instead of being
+    /// served by an external service like S3.
+    pub fn is_local(&self) -> bool {
+        !matches!(self, StorageBackendKind::S3)
+    }
+
+    /// Returns the same name that is used when serializing the backend kind.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StorageBackendKind::S3 => "s3",
+            StorageBackendKind::LocalFileSystem => "local_file_system",
+            StorageBackendKind::InMemory => "in_memory",
+        }
+    }
+}
+
+/// The URL of an uploaded crate's version archive, together with the kind of
+/// storage backend that the URL points to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CrateLocation {
+    pub url: String,
+    pub backend: StorageBackendKind,
+}
+
+/// A pre-signed URL of an uploaded crate's version archive, together with the
+/// time at which the URL expires.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedLocation {
+    pub url: String,
+    pub expires_at: DateTime<Utc>,
+}
+
 #[derive(Debug)]
 pub struct S3Config {
     bucket: String,
@@ -52,11 +116,38 @@ pub struct S3Config {
     secret_key: SecretString,
 }
 
+impl S3Config {
+    pub fn new(
+        bucket: String,
+        region: Option<String>,
+        access_key: String,
+        secret_key: SecretString,
+    ) -> Self {
+        Self {
+            bucket,
+            region,
+            access_key,
+            secret_key,
+        }
+    }
+}
+
 impl StorageConfig {
     pub fn in_memory() -> Self {
         Self {
             backend: StorageBackend::InMemory,
             cdn_prefix: None,
+            signed_urls: false,
+            signed_url_ttl: DEFAULT_SIGNED_URL_TTL,
+        }
+    }
+
+    pub fn s3(default: S3Config, index: S3Config, cdn_prefix: Option<String>) -> Self {
+        Self {
+            backend: StorageBackend::S3 { default, index },
+            cdn_prefix,
+            signed_urls: false,
+            signed_url_ttl: DEFAULT_SIGNED_URL_TTL,
         }
     }
 
@@ -71,26 +162,16 @@ impl StorageConfig {
             let access_key = required_var("AWS_ACCESS_KEY").unwrap();
             let secret_key: SecretString = required_var("AWS_SECRET_KEY").unwrap().into();
 
-            let default = S3Config {
-                bucket,
-                region,
-                access_key: access_key.clone(),
-                secret_key: secret_key.clone(),
-            };
-
-            let index = S3Config {
-                bucket: index_bucket,
-                region: index_region,
-                access_key,
-                secret_key,
-            };
-
-            let backend = StorageBackend::S3 { default, index };
-
-            return Self {
-                backend,
-                cdn_prefix,
-            };
+            let default = S3Config::new(bucket, region, access_key.clone(), secret_key.clone());
+            let index = S3Config::new(index_bucket, index_region, access_key, secret_key);
+
+            let mut config = Self::s3(default, index, cdn_prefix);
+            config.signed_urls = dotenvy::var("S3_SIGNED_URLS").is_ok();
+            if let Some(ttl) = var_parsed("S3_SIGNED_URL_TTL_SECONDS").unwrap() {
+                config.signed_url_ttl = Duration::from_secs(ttl);
+            }
+
+            return config;
         }
 
         let current_dir = std::env::current_dir()
@@ -104,13 +185,19 @@ impl StorageConfig {
         Self {
             backend,
             cdn_prefix: None,
+            signed_urls: false,
+            signed_url_ttl: DEFAULT_SIGNED_URL_TTL,
         }
     }
 }
 
 pub struct Storage {
+    backend: StorageBackendKind,
     cdn_prefix: Option<String>,
 
+    signer: Option<AmazonS3>,
+    signed_url_ttl: Duration,
+
     store: Box<dyn ObjectStore>,
     crate_upload_store: Box<dyn ObjectStore>,
     readme_upload_store: Box<dyn ObjectStore>,
@@ -126,7 +213,9 @@ impl Storage {
     }
 
     pub fn from_config(config: &StorageConfig) -> Self {
+        let backend = config.backend.kind();
         let cdn_prefix = config.cdn_prefix.clone();
+        let signed_url_ttl = config.signed_url_ttl;
 
         match &config.backend {
             StorageBackend::S3 { default, index } => {
@@ -149,11 +238,18 @@ impl Storage {
                 let options = client_options(CONTENT_TYPE_INDEX, CACHE_CONTROL_INDEX);
                 let index_upload_store = build_s3(index, options);
 
+                let signer = config
+                    .signed_urls
+                    .then(|| build_s3(default, ClientOptions::default()));
+
                 if cdn_prefix.is_none() {
                     panic!("Missing S3_CDN environment variable");
                 }
 
                 Self {
+                    backend,
+                    signer,
+                    signed_url_ttl,
                     store: Box::new(store),
                     crate_upload_store: Box::new(crate_upload_store),
                     readme_upload_store: Box::new(readme_upload_store),
@@ -185,6 +281,9 @@ impl Storage {
                 let index_store: Arc<dyn ObjectStore> = Arc::new(local_index);
 
                 Self {
+                    backend,
+                    signer: None,
+                    signed_url_ttl,
                     store: Box::new(store.clone()),
                     crate_upload_store: Box::new(store.clone()),
                     readme_upload_store: Box::new(store.clone()),
@@ -200,6 +299,9 @@ impl Storage {
                 let store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
 
                 Self {
+                    backend,
+                    signer: None,
+                    signed_url_ttl,
                     store: Box::new(store.clone()),
                     crate_upload_store: Box::new(store.clone()),
                     readme_upload_store: Box::new(store.clone()),
@@ -216,7 +318,59 @@ impl Storage {
     ///
     /// The function doesn't check for the existence of the file.
     pub fn crate_location(&self, name: &str, version: &str) -> String {
-        apply_cdn_prefix(&self.cdn_prefix, &crate_file_path(name, version)).replace('+', "%2B")
+        apply_cdn_prefix(&self.cdn_prefix, &crate_file_key(name, version)).replace('+', "%2B")
+    }
+
+    /// Returns the URL of an uploaded crate's version archive, together with
+    /// the kind of storage backend that it is stored in.
+    ///
+    /// The function doesn't check for the existence of the file.
+    pub fn crate_location_detailed(&self, name: &str, version: &str) -> CrateLocation {
+        CrateLocation {
+            url: self.crate_location(name, version),
+            backend: self.backend,
+        }
+    }
+
+    /// Returns a pre-signed URL of an uploaded crate's version archive, which
+    /// is valid for the configured TTL.
+    ///
+    /// `None` is returned if signed URLs are not enabled, in which case the
+    /// public [`Storage::crate_location`] should be used instead.
+    ///
+    /// The function doesn't check for the existence of the file.
+    #[instrument(skip(self))]
+    pub async fn signed_crate_location(
+        &self,
+        name: &str,
+        version: &str,
+    ) -> Result<Option<SignedLocation>> {
+        let Some(signer) = &self.signer else {
+            return Ok(None);
+        };
+
+        // Calculate the expiry before signing, so that the returned time is
+        // never later than the actual expiry of the URL
+        let expires_at = Utc::now() + self.signed_url_ttl;
+
+        let path = crate_file_key(name, version);
+        let url = signer
+            .signed_url(Method::GET, &path, self.signed_url_ttl)
+            .await?;
+
+        Ok(Some(SignedLocation {
+            url: url.to_string(),
+            expires_at,
+        }))
+    }
+
+    /// Returns the URL of an uploaded crate's version archive on a mirror
+    /// with the given base URL.
+    ///
+    /// The function doesn't check for the existence of the file.
+    pub fn crate_mirror_location(&self, base_url: &str, name: &str, version: &str) -> String {
+        let path = crate_file_key(name, version);
+        format!("{base_url}/{path}").replace('+', "%2B")
     }
 
     /// Returns the URL of an uploaded crate's version readme.
@@ -240,7 +394,7 @@ impl Storage {
 
     #[instrument(skip(self))]
     pub async fn delete_crate_file(&self, name: &str, version: &str) -> Result<()> {
-        let path = crate_file_path(name, version);
+        let path = crate_file_key(name, version);
         self.store.delete(&path).await
     }
 
@@ -250,9 +404,16 @@ impl Storage {
         self.store.delete(&path).await
     }
 
+    /// Loads the contents of an uploaded crate's version archive.
+    #[instrument(skip(self))]
+    pub async fn download_crate_file(&self, name: &str, version: &str) -> Result<Bytes> {
+        let path = crate_file_key(name, version);
+        self.store.get(&path).await?.bytes().await
+    }
+
     #[instrument(skip(self, bytes))]
     pub async fn upload_crate_file(&self, name: &str, version: &str, bytes: Bytes) -> Result<()> {
-        let path = crate_file_path(name, version);
+        let path = crate_file_key(name, version);
         self.crate_upload_store.put(&path, bytes).await?;
         Ok(())
     }
@@ -339,7 +500,11 @@ fn build_s3(config: &S3Config, client_options: ClientOptions) -> AmazonS3 {
         .unwrap()
 }
 
-fn crate_file_path(name: &str, version: &str) -> Path {
+/// Returns the key of an uploaded crate's version archive inside of the
+/// storage bucket, e.g. `crates/foo/foo-1.0.0.crate`.
+///
+/// All crate file locations are derived from this key.
+pub fn crate_file_key(name: &str, version: &str) -> Path {
     format!("{PREFIX_CRATES}/{name}/{name}-{version}.crate").into()
 }
 
@@ -422,6 +587,78 @@ mod tests {
         }
     }
 
+    #[test]
+    fn crate_mirror_location() {
+        let storage = Storage::from_config(&StorageConfig::in_memory());
+        assert_eq!(
+            storage.crate_mirror_location("https://mirror.example.com", "foo", "1.2.3+0"),
+            "https://mirror.example.com/crates/foo/foo-1.2.3%2B0.crate"
+        );
+    }
+
+    #[test]
+    fn crate_location_detailed() {
+        let storage = Storage::from_config(&StorageConfig::in_memory());
+        let location = storage.crate_location_detailed("foo", "1.2.3");
+        assert_eq!(location.url, "/crates/foo/foo-1.2.3.crate");
+        assert_eq!(location.backend, StorageBackendKind::InMemory);
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let config = StorageConfig {
+            backend: StorageBackend::LocalFileSystem {
+                path: temp_dir.path().to_path_buf(),
+            },
+            cdn_prefix: None,
+            signed_urls: false,
+            signed_url_ttl: DEFAULT_SIGNED_URL_TTL,
+        };
+        let storage = Storage::from_config(&config);
+        let location = storage.crate_location_detailed("foo", "1.2.3");
+        assert_eq!(location.url, "/crates/foo/foo-1.2.3.crate");
+        assert_eq!(location.backend, StorageBackendKind::LocalFileSystem);
+
+        let s3_config = || {
+            let secret_key = "secret-key".to_string().into();
+            S3Config::new("crates-io".into(), None, "access-key".into(), secret_key)
+        };
+        let cdn_prefix = Some("static.crates.io".to_string());
+        let config = StorageConfig::s3(s3_config(), s3_config(), cdn_prefix);
+        let storage = Storage::from_config(&config);
+        let location = storage.crate_location_detailed("foo", "1.2.3");
+        assert_eq!(
+            location.url,
+            "https://static.crates.io/crates/foo/foo-1.2.3.crate"
+        );
+        assert_eq!(location.backend, StorageBackendKind::S3);
+    }
+
+    #[tokio::test]
+    async fn signed_crate_location() {
+        let storage = Storage::from_config(&StorageConfig::in_memory());
+        assert_eq!(
+            storage.signed_crate_location("foo", "1.2.3").await.unwrap(),
+            None
+        );
+
+        let s3_config = || {
+            let secret_key = "secret-key".to_string().into();
+            S3Config::new("crates-io".into(), None, "access-key".into(), secret_key)
+        };
+        let cdn_prefix = Some("static.crates.io".to_string());
+        let mut config = StorageConfig::s3(s3_config(), s3_config(), cdn_prefix);
+        config.signed_urls = true;
+
+        let storage = Storage::from_config(&config);
+        let location = storage.signed_crate_location("foo", "1.2.3").await.unwrap();
+        let location = location.unwrap();
+        assert!(location.url.starts_with(
+            "https://s3.us-west-1.amazonaws.com/crates-io/crates/foo/foo-1.2.3.crate?"
+        ));
+        assert!(location.url.contains("X-Amz-Expires=300"));
+        assert!(location.url.contains("X-Amz-Signature="));
+        assert!(location.expires_at > Utc::now());
+    }
+
     #[test]
     fn cdn_prefix() {
         assert_eq!(apply_cdn_prefix(&None, &"foo".into()), "/foo");
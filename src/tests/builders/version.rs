@@ -1,11 +1,11 @@
 use crates_io::{
     models::{Crate, NewVersion, Version},
-    schema::{dependencies, versions},
+    schema::{dependencies, version_downloads, versions},
     util::errors::AppResult,
 };
 use std::collections::BTreeMap;
 
-use chrono::NaiveDateTime;
+use chrono::{NaiveDate, NaiveDateTime};
 use diesel::prelude::*;
 
 /// A builder to create version records for the purpose of inserting directly into the database.
@@ -17,9 +17,12 @@ pub struct VersionBuilder<'a> {
     num: semver::Version,
     size: i32,
     yanked: bool,
+    deleted: bool,
     checksum: String,
     links: Option<String>,
     rust_version: Option<String>,
+    edition: Option<String>,
+    downloads: Vec<(NaiveDate, i32)>,
 }
 
 #[allow(dead_code)]
@@ -43,9 +46,12 @@ impl<'a> VersionBuilder<'a> {
             num,
             size: 0,
             yanked: false,
+            deleted: false,
             checksum: String::new(),
             links: None,
             rust_version: None,
+            edition: None,
+            downloads: Vec::new(),
         }
     }
 
@@ -55,6 +61,13 @@ impl<'a> VersionBuilder<'a> {
         self
     }
 
+    /// Adds a feature with the given dependencies to the version's `features`.
+    pub fn feature(mut self, name: &str, dependencies: &[&str]) -> Self {
+        let dependencies = dependencies.iter().map(ToString::to_string).collect();
+        self.features.insert(name.to_string(), dependencies);
+        self
+    }
+
     /// Sets the version's `license` value.
     pub fn license(mut self, license: Option<&'a str>) -> Self {
         self.license = license;
@@ -78,6 +91,11 @@ impl<'a> VersionBuilder<'a> {
         Self { yanked, ..self }
     }
 
+    /// Sets the version's `deleted` value.
+    pub fn deleted(self, deleted: bool) -> Self {
+        Self { deleted, ..self }
+    }
+
     /// Sets the version's size.
     pub fn size(mut self, size: i32) -> Self {
         self.size = size;
@@ -90,6 +108,18 @@ impl<'a> VersionBuilder<'a> {
         self
     }
 
+    /// Sets the version's `edition` value.
+    pub fn edition(mut self, edition: &str) -> Self {
+        self.edition = Some(edition.to_owned());
+        self
+    }
+
+    /// Adds a `version_downloads` row with the given number of downloads on `date`.
+    pub fn downloads_on(mut self, date: NaiveDate, downloads: i32) -> Self {
+        self.downloads.push((date, downloads));
+        self
+    }
+
     pub fn build(
         self,
         crate_id: i32,
@@ -110,6 +140,7 @@ impl<'a> VersionBuilder<'a> {
             self.checksum,
             self.links,
             self.rust_version,
+            self.edition,
         )?
         .save(connection, "someone@example.com")?;
 
@@ -119,6 +150,12 @@ impl<'a> VersionBuilder<'a> {
                 .get_result(connection)?;
         }
 
+        if self.deleted {
+            vers = update(&vers)
+                .set(versions::deleted.eq(true))
+                .get_result(connection)?;
+        }
+
         if let Some(created_at) = self.created_at {
             vers = update(&vers)
                 .set(versions::created_at.eq(created_at))
@@ -144,6 +181,21 @@ impl<'a> VersionBuilder<'a> {
             .values(&new_deps)
             .execute(connection)?;
 
+        let new_downloads = self
+            .downloads
+            .into_iter()
+            .map(|(date, downloads)| {
+                (
+                    version_downloads::version_id.eq(vers.id),
+                    version_downloads::date.eq(date),
+                    version_downloads::downloads.eq(downloads),
+                )
+            })
+            .collect::<Vec<_>>();
+        insert_into(version_downloads::table)
+            .values(&new_downloads)
+            .execute(connection)?;
+
         Ok(vers)
     }
 
@@ -130,3 +130,43 @@ fn invalid_rust_version() {
     assert_eq!(response.status(), StatusCode::BAD_REQUEST);
     assert_json_snapshot!(response.json());
 }
+
+#[test]
+fn edition() {
+    use crates_io::schema::versions;
+    use diesel::prelude::*;
+
+    let (app, _anon, _cookie, token) = TestApp::full().with_token();
+
+    let manifest = |version: &str, edition: &str| {
+        format!(
+            "[package]\nname = \"foo\"\nversion = \"{version}\"\ndescription = \"description\"\nlicense = \"MIT\"\n{edition}"
+        )
+    };
+
+    let response = token.publish_crate(
+        PublishBuilder::new("foo", "1.0.0")
+            .custom_manifest(manifest("1.0.0", "edition = \"2021\"\n")),
+    );
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // Cargo defaults to the 2015 edition if none is declared
+    let response = token
+        .publish_crate(PublishBuilder::new("foo", "1.1.0").custom_manifest(manifest("1.1.0", "")));
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let editions: Vec<(String, Option<String>)> = app.db(|conn| {
+        versions::table
+            .select((versions::num, versions::edition))
+            .order(versions::num)
+            .load(conn)
+            .unwrap()
+    });
+    assert_eq!(
+        editions,
+        [
+            ("1.0.0".to_string(), Some("2021".to_string())),
+            ("1.1.0".to_string(), Some("2015".to_string())),
+        ]
+    );
+}
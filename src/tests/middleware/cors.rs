@@ -0,0 +1,96 @@
+use crate::builders::{CrateBuilder, VersionBuilder};
+use crate::util::{MockAnonymousUser, MockRequestExt, RequestHelper, TestApp};
+use crates_io::config::AllowedOrigins;
+use http::{header, Method, StatusCode};
+
+const DASHBOARD: &str = "https://dashboard.example.com";
+const DOWNLOADS: &str = "/api/v1/crates/foo/1.0.0/downloads";
+
+fn app() -> (TestApp, MockAnonymousUser) {
+    let (app, anon, user) = TestApp::init()
+        .with_config(|config| {
+            config.downloads_cors_origins = AllowedOrigins::new(vec![DASHBOARD.into()]);
+        })
+        .with_user();
+
+    app.db(|conn| {
+        CrateBuilder::new("foo", user.as_model().id)
+            .version(VersionBuilder::new("1.0.0"))
+            .expect_build(conn);
+    });
+
+    (app, anon)
+}
+
+#[test]
+fn allowed_origin() {
+    let (_app, anon) = app();
+
+    let mut request = anon.get_request(DOWNLOADS);
+    request.header(header::ORIGIN, DASHBOARD);
+    let response = anon.run::<()>(request);
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let headers = response.headers();
+    assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_ORIGIN], DASHBOARD);
+    let vary = headers.get_all(header::VARY).iter().collect::<Vec<_>>();
+    assert!(vary.iter().any(|value| *value == "Origin"));
+}
+
+#[test]
+fn disallowed_origin() {
+    let (_app, anon) = app();
+
+    let mut request = anon.get_request(DOWNLOADS);
+    request.header(header::ORIGIN, "https://evil.example.com");
+    let response = anon.run::<()>(request);
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(!response
+        .headers()
+        .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+
+    // Without configured origins, only same-origin requests are allowed
+    let (_app, anon) = TestApp::init().empty();
+    let mut request = anon.get_request("/api/v1/crates/downloads");
+    request.header(header::ORIGIN, DASHBOARD);
+    let response = anon.run::<()>(request);
+    assert!(!response
+        .headers()
+        .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+
+    // Other endpoints still allow any origin
+    let response = anon.get::<()>("/api/v1/summary");
+    assert_eq!(response.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+}
+
+#[test]
+fn preflight() {
+    let (_app, anon) = app();
+
+    let mut request = anon.request_builder(Method::OPTIONS, DOWNLOADS);
+    request.header(header::ORIGIN, DASHBOARD);
+    request.header(header::ACCESS_CONTROL_REQUEST_METHOD, "GET");
+    let response = anon.run::<()>(request);
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    let headers = response.headers();
+    assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_ORIGIN], DASHBOARD);
+    assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_METHODS], "GET, HEAD");
+    assert_eq!(
+        headers[header::ACCESS_CONTROL_ALLOW_HEADERS],
+        "Accept, If-None-Match, If-Modified-Since"
+    );
+    assert_eq!(headers[header::ACCESS_CONTROL_MAX_AGE], "86400");
+
+    let mut request = anon.request_builder(Method::OPTIONS, DOWNLOADS);
+    request.header(header::ORIGIN, "https://evil.example.com");
+    request.header(header::ACCESS_CONTROL_REQUEST_METHOD, "GET");
+    let response = anon.run::<()>(request);
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    assert!(!response
+        .headers()
+        .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+    assert!(!response
+        .headers()
+        .contains_key(header::ACCESS_CONTROL_ALLOW_METHODS));
+}
@@ -0,0 +1,43 @@
+use crate::builders::CrateBuilder;
+use crate::routes::crates::downloads::save_version_downloads_on;
+use crate::TestApp;
+use chrono::{Duration, Utc};
+use crates_io::models::VersionDownload;
+
+#[test]
+fn belonging_to_crate() {
+    let (app, _, user) = TestApp::init().with_user();
+    let user = user.as_model();
+
+    app.db(|conn| {
+        let krate = CrateBuilder::new("foo", user.id)
+            .version("1.0.0")
+            .version("1.1.0")
+            .version("2.0.0")
+            .expect_build(conn);
+
+        CrateBuilder::new("bar", user.id)
+            .version("1.0.0")
+            .expect_build(conn);
+
+        let today = Utc::now().date_naive();
+        let yesterday = today - Duration::days(1);
+        save_version_downloads_on("foo", "1.0.0", 1, yesterday, conn);
+        save_version_downloads_on("foo", "1.1.0", 2, today, conn);
+        save_version_downloads_on("foo", "2.0.0", 3, today, conn);
+        save_version_downloads_on("foo", "2.0.0", 4, today - Duration::days(10), conn);
+        save_version_downloads_on("bar", "1.0.0", 5, today, conn);
+
+        let window = yesterday..=today;
+        let downloads = VersionDownload::belonging_to_crate(krate.id, window, conn).unwrap();
+        let downloads = downloads
+            .into_iter()
+            .map(|download| (download.date, download.downloads))
+            .collect::<Vec<_>>();
+
+        // Rows of all versions are returned, ordered by date and then by
+        // version id in descending order
+        let expected = vec![(yesterday, 1), (today, 3), (today, 2)];
+        assert_eq!(downloads, expected);
+    });
+}
@@ -1 +1,2 @@
+mod download;
 mod krate;
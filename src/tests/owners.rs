@@ -7,6 +7,7 @@ use crate::{
 };
 use crates_io::{
     models::Crate,
+    schema::users,
     views::{
         EncodableCrateOwnerInvitationV1, EncodableOwner, EncodablePublicUser, InvitationResponse,
     },
@@ -196,6 +197,64 @@ fn owners_can_remove_self() {
     );
 }
 
+#[test]
+fn owner_history() {
+    let (app, anon, user, token) = TestApp::init().with_token();
+    let username = &user.as_model().gh_login;
+
+    let krate =
+        app.db(|conn| CrateBuilder::new("owner_history", user.as_model().id).expect_build(conn));
+
+    let url = "/api/v1/crates/owner_history/owner-history";
+    let json = user.get::<()>(url).json();
+    assert_eq!(json, json!({ "history": [] }));
+
+    let second = create_and_add_owner(&app, &token, "secondowner", &krate);
+    token
+        .remove_named_owner("owner_history", "secondowner")
+        .good();
+
+    // Removing a login that is no longer an owner is not recorded again
+    token
+        .remove_named_owner("owner_history", "secondowner")
+        .good();
+
+    let json = user.get::<()>(url).json();
+    let history = json["history"].as_array().unwrap();
+    assert_eq!(history.len(), 2);
+
+    assert_eq!(history[0]["action"], "add");
+    assert_eq!(history[0]["owner"]["login"], "secondowner");
+    assert_eq!(history[0]["owner"]["kind"], "user");
+    assert_eq!(history[0]["user"]["login"], username.as_str());
+    assert_eq!(history[1]["action"], "remove");
+    assert_eq!(history[1]["owner"]["login"], "secondowner");
+    assert_eq!(history[1]["user"]["login"], username.as_str());
+    assert!(history[0]["time"].as_str().unwrap() <= history[1]["time"].as_str().unwrap());
+
+    // Former owners, other users and anonymous users can't see the history
+    let response = second.get::<()>(url);
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert_snapshot!(
+        response.text(),
+        @r###"{"errors":[{"detail":"only owners have permission to view the ownership history"}]}"###
+    );
+
+    let response = anon.get::<()>(url);
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    // Admins can see the history of all crates
+    let admin = app.db_new_user("admin");
+    app.db(|conn| {
+        diesel::update(admin.as_model())
+            .set(users::is_admin.eq(true))
+            .execute(conn)
+            .unwrap();
+    });
+    let json = admin.get::<()>(url).json();
+    assert_eq!(json["history"].as_array().unwrap().len(), 2);
+}
+
 /// Verify consistency when adidng or removing multiple owners in a single request.
 #[test]
 fn modify_multiple_owners() {
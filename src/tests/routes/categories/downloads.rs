@@ -0,0 +1,74 @@
+use crate::builders::CrateBuilder;
+use crate::new_category;
+use crate::routes::crates::downloads::save_version_downloads_on;
+use crate::util::{RequestHelper, TestApp};
+use chrono::{Duration, Utc};
+use serde_json::Value;
+
+#[test]
+fn category_downloads() {
+    let (app, anon, user) = TestApp::init().with_user();
+    let user_id = user.as_model().id;
+
+    app.db(|conn| {
+        assert_ok!(new_category("Cat 1", "cat1", "Category 1 crates").create_or_update(conn));
+        assert_ok!(new_category("Cat 2", "cat2", "Category 2 crates").create_or_update(conn));
+
+        CrateBuilder::new("foo", user_id)
+            .version("1.0.0")
+            .version("1.1.0")
+            .category("cat1")
+            .expect_build(conn);
+        CrateBuilder::new("bar", user_id)
+            .version("0.1.0")
+            .category("cat1")
+            .expect_build(conn);
+        CrateBuilder::new("baz", user_id)
+            .version("1.0.0")
+            .category("cat2")
+            .expect_build(conn);
+
+        let today = Utc::now().date_naive();
+        save_version_downloads_on("foo", "1.0.0", 3, today, conn);
+        save_version_downloads_on("foo", "1.1.0", 4, today - Duration::days(10), conn);
+        save_version_downloads_on("bar", "0.1.0", 5, today - Duration::days(1), conn);
+        save_version_downloads_on("baz", "1.0.0", 100, today, conn);
+
+        // Downloads outside of the window are ignored
+        save_version_downloads_on("bar", "0.1.0", 50, today - Duration::days(30), conn);
+    });
+
+    let url = "/api/v1/categories/cat1/downloads";
+
+    let json: Value = anon.get(url).good();
+    assert_eq!(
+        json,
+        json!({
+            "total_downloads": 12,
+            "top_crates": [
+                { "name": "foo", "downloads": 7 },
+                { "name": "bar", "downloads": 5 },
+            ],
+            "meta": { "window": 30 },
+        })
+    );
+
+    let json: Value = anon.get_with_query(url, "window=2").good();
+    assert_eq!(
+        json,
+        json!({
+            "total_downloads": 8,
+            "top_crates": [
+                { "name": "bar", "downloads": 5 },
+                { "name": "foo", "downloads": 3 },
+            ],
+            "meta": { "window": 2 },
+        })
+    );
+
+    let json: Value = anon.get_with_query(url, "window=31").good();
+    assert_eq!(json["total_downloads"], 62);
+
+    anon.get::<()>("/api/v1/categories/unknown/downloads")
+        .assert_not_found();
+}
@@ -1,2 +1,3 @@
+pub mod downloads;
 pub mod get;
 pub mod list;
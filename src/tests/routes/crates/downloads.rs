@@ -1,17 +1,47 @@
 use crate::builders::{CrateBuilder, VersionBuilder};
-use crate::util::{MockAnonymousUser, RequestHelper, TestApp};
-use chrono::{Duration, Utc};
-use crates_io::schema::{crates, version_downloads, versions};
+use crate::util::{MockAnonymousUser, MockCookieUser, MockRequestExt, RequestHelper, TestApp};
+use chrono::{DateTime, Duration, DurationRound, NaiveDate, Utc};
+use crates_io::models::DependencyKind;
+use crates_io::schema::{
+    crate_downloads, crates, dependencies, metadata, processed_log_files, users, version_downloads,
+    version_downloads_by_hour, version_downloads_by_source, version_downloads_by_target, versions,
+};
 use crates_io::views::EncodableVersionDownload;
+use crates_io::worker::jobs::UpdateDownloads;
+use crates_io_worker::BackgroundJob;
 use diesel::prelude::*;
-use http::StatusCode;
+use http::{header, StatusCode};
+use indexmap::IndexMap;
 use insta::{assert_json_snapshot, assert_snapshot};
+use serde_json::Value;
 
+/// The parts of `crates_io::views::DownloadsResponse` that most of the tests
+/// in this file are interested in.
 #[derive(Deserialize)]
 struct Downloads {
     version_downloads: Vec<EncodableVersionDownload>,
 }
 
+#[derive(Deserialize)]
+struct DownloadsMatrix {
+    dates: Vec<String>,
+    versions: IndexMap<String, Vec<i32>>,
+}
+
+#[derive(Deserialize)]
+struct DownloadsComparison {
+    dates: Vec<String>,
+    a: ComparedVersion,
+    b: ComparedVersion,
+}
+
+#[derive(Deserialize)]
+struct ComparedVersion {
+    version: String,
+    downloads: Vec<i32>,
+    total: i64,
+}
+
 fn save_version_downloads(
     crate_name: &str,
     version: &str,
@@ -35,6 +65,72 @@ fn save_version_downloads(
         .unwrap();
 }
 
+pub fn save_version_downloads_on(
+    crate_name: &str,
+    version: &str,
+    num_downloads: i32,
+    date: NaiveDate,
+    conn: &mut PgConnection,
+) {
+    let version_id = versions::table
+        .select(versions::id)
+        .left_join(crates::table)
+        .filter(crates::name.eq(crate_name))
+        .filter(versions::num.eq(version))
+        .first::<i32>(conn)
+        .unwrap();
+
+    diesel::insert_into(version_downloads::table)
+        .values((
+            version_downloads::version_id.eq(version_id),
+            version_downloads::downloads.eq(num_downloads),
+            version_downloads::date.eq(date),
+        ))
+        .execute(conn)
+        .unwrap();
+}
+
+/// Inserts a `processed_log_files` row, which marks the time at which
+/// downloads were last saved to the database.
+fn mark_log_file_processed(path: &str, time: DateTime<Utc>, conn: &mut PgConnection) {
+    diesel::insert_into(processed_log_files::table)
+        .values((
+            processed_log_files::path.eq(path),
+            processed_log_files::time.eq(time),
+        ))
+        .execute(conn)
+        .unwrap();
+}
+
+/// Creates a version builder with a `version_downloads` row for each of the
+/// given `(days_ago, downloads)` pairs.
+fn version_with_downloads(
+    num: &str,
+    downloads: impl IntoIterator<Item = (i64, i32)>,
+) -> VersionBuilder<'_> {
+    let today = Utc::now().date_naive();
+    downloads.into_iter().fold(
+        VersionBuilder::new(num),
+        |version, (days_ago, downloads)| {
+            version.downloads_on(today - Duration::days(days_ago), downloads)
+        },
+    )
+}
+
+/// Publishes the `foo` crate with a single `1.0.0` version that has a
+/// `version_downloads` row for each of the given `(days_ago, downloads)` pairs.
+fn publish_foo_with_downloads(
+    app: &TestApp,
+    user: &MockCookieUser,
+    downloads: impl IntoIterator<Item = (i64, i32)>,
+) {
+    app.db(|conn| {
+        CrateBuilder::new("foo", user.as_model().id)
+            .version(version_with_downloads("1.0.0", downloads))
+            .expect_build(conn);
+    });
+}
+
 #[track_caller]
 pub fn assert_dl_count(
     anon: &MockAnonymousUser,
@@ -183,6 +279,7 @@ fn test_version_downloads() {
     assert_eq!(response.status(), StatusCode::OK);
     let json = response.json();
     assert_json_snapshot!(json, {
+        ".meta.window_start" => "[date]",
         ".version_downloads[].date" => "[date]",
     });
 
@@ -204,7 +301,7 @@ fn test_version_downloads() {
     assert_eq!(response.status(), StatusCode::NOT_FOUND);
     assert_snapshot!(
         response.text(),
-        @r###"{"errors":[{"detail":"crate `foo` does not have a version `2.0.0`"}]}"###
+        @r###"{"errors":[{"detail":"crate `foo` does not have a version `2.0.0`. The latest stable version is `1.1.0`."}]}"###
     );
 
     // check invalid version
@@ -212,6 +309,2788 @@ fn test_version_downloads() {
     assert_eq!(response.status(), StatusCode::NOT_FOUND);
     assert_snapshot!(
         response.text(),
-        @r###"{"errors":[{"detail":"crate `foo` does not have a version `invalid-version`"}]}"###
+        @r###"{"errors":[{"detail":"crate `foo` does not have a version `invalid-version`. Did you mean one of: `1.1.0`, `1.0.0`?"}]}"###
+    );
+}
+
+#[test]
+fn test_version_downloads_deleted() {
+    let (app, anon, cookie) = TestApp::init().with_user();
+
+    app.db(|conn| {
+        let user_id = cookie.as_model().id;
+        CrateBuilder::new("foo", user_id)
+            .version("1.0.0")
+            .version(VersionBuilder::new("1.1.0").deleted(true))
+            .expect_build(conn);
+    });
+
+    // Deleted versions are gone, while missing versions were never found
+    let response = anon.get::<()>("/api/v1/crates/foo/1.1.0/downloads");
+    assert_eq!(response.status(), StatusCode::GONE);
+    assert_snapshot!(
+        response.text(),
+        @r###"{"errors":[{"detail":"version `1.1.0` of crate `foo` has been deleted and is no longer available"}]}"###
+    );
+
+    let response = anon.get::<()>("/api/v1/crates/foo/2.0.0/downloads");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_snapshot!(
+        response.text(),
+        @r###"{"errors":[{"detail":"crate `foo` does not have a version `2.0.0`. The latest stable version is `1.0.0`."}]}"###
+    );
+
+    // The version metadata is gone too
+    let response = anon.get::<()>("/api/v1/crates/foo/1.1.0");
+    assert_eq!(response.status(), StatusCode::GONE);
+
+    let response = anon.get::<()>("/api/v1/crates/foo/1.0.0/downloads");
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[test]
+fn test_version_downloads_days() {
+    let (app, anon, cookie) = TestApp::init().with_user();
+
+    publish_foo_with_downloads(
+        &app,
+        &cookie,
+        [0, 89, 90, 364, 365].map(|days_ago| (days_ago, 1)),
+    );
+
+    // Without `days` the last 90 days are returned
+    assert_dl_count(&anon, "foo/1.0.0", None, 2);
+
+    assert_dl_count(&anon, "foo/1.0.0", Some("days=1"), 1);
+    assert_dl_count(&anon, "foo/1.0.0", Some("days=91"), 3);
+    assert_dl_count(&anon, "foo/1.0.0", Some("days=365"), 4);
+
+    // Values above the maximum are clamped to 365 days
+    assert_dl_count(&anon, "foo/1.0.0", Some("days=366"), 4);
+    assert_dl_count(&anon, "foo/1.0.0", Some("days=100000"), 4);
+
+    let url = "/api/v1/crates/foo/1.0.0/downloads";
+
+    let response = anon.get_with_query::<()>(url, "days=0");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_snapshot!(
+        response.text(),
+        @r###"{"errors":[{"detail":"invalid `days` parameter `0`, expected a positive integer"}]}"###
+    );
+
+    let response = anon.get_with_query::<()>(url, "days=-7");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = anon.get_with_query::<()>(url, "days=foo");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_snapshot!(
+        response.text(),
+        @r###"{"errors":[{"detail":"invalid `days` parameter `foo`, expected a positive integer"}]}"###
+    );
+}
+
+#[test]
+fn test_version_downloads_dense() {
+    let (app, anon, cookie) = TestApp::init().with_user();
+
+    let today = Utc::now().date_naive();
+    publish_foo_with_downloads(&app, &cookie, [(0, 1), (3, 4), (40, 2)]);
+
+    let url = "/api/v1/crates/foo/1.0.0/downloads";
+
+    // The sparse series is returned by default
+    let downloads: Downloads = anon.get(url).good();
+    assert_eq!(downloads.version_downloads.len(), 3);
+
+    let downloads: Downloads = anon.get_with_query(url, "dense=true").good();
+    let series = downloads
+        .version_downloads
+        .iter()
+        .map(|vd| (vd.date.as_str(), vd.downloads))
+        .collect::<Vec<_>>();
+
+    let expected_dates = (0..90)
+        .rev()
+        .map(|days_ago| (today - Duration::days(days_ago)).to_string())
+        .collect::<Vec<_>>();
+    let dates = series.iter().map(|(date, _)| *date).collect::<Vec<_>>();
+    assert_eq!(dates, expected_dates);
+
+    let counts = series.iter().map(|(_, count)| *count).collect::<Vec<_>>();
+    let mut expected_counts = vec![0; 90];
+    expected_counts[49] = 2;
+    expected_counts[86] = 4;
+    expected_counts[89] = 1;
+    assert_eq!(counts, expected_counts);
+
+    // The filled window ends at `before_date`
+    let before_date = today - Duration::days(3);
+    let query = format!("dense=true&days=10&before_date={before_date}");
+    let downloads: Downloads = anon.get_with_query(url, &query).good();
+    assert_eq!(downloads.version_downloads.len(), 10);
+    let last = downloads.version_downloads.last().unwrap();
+    assert_eq!(last.date, before_date.to_string());
+    assert_eq!(last.downloads, 4);
+    let first = downloads.version_downloads.first().unwrap();
+    assert_eq!(first.date, (before_date - Duration::days(9)).to_string());
+}
+
+#[test]
+fn test_version_downloads_delta() {
+    let (app, anon, cookie) = TestApp::init().with_user();
+
+    publish_foo_with_downloads(&app, &cookie, [(4, 10), (3, 15), (1, 7), (0, 7)]);
+
+    let url = "/api/v1/crates/foo/1.0.0/downloads";
+
+    let absolute: Downloads = anon.get_with_query(url, "mode=absolute").good();
+    let absolute = absolute
+        .version_downloads
+        .iter()
+        .map(|vd| vd.downloads)
+        .collect::<Vec<_>>();
+    assert_eq!(absolute, [10, 15, 7, 7]);
+
+    let mut expected = vec![absolute[0]];
+    expected.extend(absolute.windows(2).map(|pair| pair[1] - pair[0]));
+
+    let response = anon.get_with_query::<Value>(url, "mode=delta");
+    assert_eq!(response.status(), StatusCode::OK);
+    let json = response.json();
+    let deltas = json["version_downloads"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|vd| vd["downloads"].as_i64().unwrap() as i32)
+        .collect::<Vec<_>>();
+    assert_eq!(deltas, expected);
+    assert_eq!(deltas, [10, 5, -8, 0]);
+
+    // The total still contains the absolute downloads
+    assert_eq!(json["meta"]["total_downloads"], 39);
+
+    // Migrated downloads are neither part of the deltas nor changed by them
+    app.db(|conn| {
+        diesel::update(versions::table)
+            .set(versions::extra_downloads.eq(1000))
+            .execute(conn)
+            .unwrap();
+    });
+
+    let json = anon.get_with_query::<Value>(url, "mode=delta").json();
+    let deltas = json["version_downloads"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|vd| (vd["downloads"].as_i64().unwrap(), vd["migrated"] == true))
+        .collect::<Vec<_>>();
+    let expected = [
+        (1000, true),
+        (10, false),
+        (5, false),
+        (-8, false),
+        (0, false),
+    ];
+    assert_eq!(deltas, expected);
+
+    let response = anon.get_with_query::<()>(url, "mode=relative");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_snapshot!(response.text(), @r###"{"errors":[{"detail":"invalid `mode` parameter `relative`, expected `absolute` or `delta`"}]}"###);
+
+    let response = anon.get_with_query::<()>(url, "mode=delta&cumulative=true");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_snapshot!(response.text(), @r###"{"errors":[{"detail":"`cumulative` can not be combined with `mode=delta`"}]}"###);
+}
+
+#[test]
+fn test_version_downloads_compact() {
+    let (app, anon, cookie) = TestApp::init().with_user();
+
+    publish_foo_with_downloads(&app, &cookie, [(20, 3), (4, 10), (0, 7)]);
+
+    let url = "/api/v1/crates/foo/1.0.0/downloads";
+
+    let default = anon.get::<Value>(url).json();
+    let compact = anon.get_with_query::<Value>(url, "compact=true").json();
+    assert_eq!(compact["meta"], default["meta"]);
+    assert_eq!(compact.get("cumulative"), None);
+
+    let dates = compact["dates"].as_array().unwrap();
+    let counts = compact["counts"].as_array().unwrap();
+    assert_eq!(dates.len(), counts.len());
+
+    // The parallel arrays contain the same data as the default form
+    let reconstructed = dates
+        .iter()
+        .zip(counts)
+        .map(|(date, count)| (date.clone(), count.clone()))
+        .collect::<Vec<_>>();
+    let expected = default["version_downloads"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|download| (download["date"].clone(), download["downloads"].clone()))
+        .collect::<Vec<_>>();
+    assert_eq!(reconstructed, expected);
+    assert_eq!(reconstructed.len(), 3);
+
+    let compact = anon.get_with_query::<Value>(url, "compact=true&cumulative=true");
+    assert_eq!(compact.json()["cumulative"], json!([3, 13, 20]));
+}
+
+#[test]
+fn test_version_downloads_fields() {
+    let (app, anon, cookie) = TestApp::init().with_user();
+
+    publish_foo_with_downloads(&app, &cookie, [(4, 10), (0, 7)]);
+
+    let url = "/api/v1/crates/foo/1.0.0/downloads";
+
+    let default = anon.get::<Value>(url).json();
+    let json = anon.get_with_query::<Value>(url, "fields=downloads").json();
+    assert_eq!(json["meta"], default["meta"]);
+    assert_eq!(
+        json["version_downloads"],
+        json!([{ "downloads": 10 }, { "downloads": 7 }])
+    );
+
+    let json = anon
+        .get_with_query::<Value>(url, "fields=date,downloads")
+        .json();
+    let first = &json["version_downloads"][0];
+    assert_eq!(first["downloads"], 10);
+    assert_eq!(first.as_object().unwrap().len(), 2);
+
+    let response = anon.get_with_query::<()>(url, "fields=date,size");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_snapshot!(
+        response.text(),
+        @r###"{"errors":[{"detail":"unknown field `size` in `fields`"}]}"###
+    );
+
+    let response = anon.get_with_query::<()>(url, "fields=date&compact=true");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[test]
+fn test_version_downloads_jsonp() {
+    let (app, anon, cookie) = TestApp::init().with_user();
+
+    publish_foo_with_downloads(&app, &cookie, [(0, 5)]);
+
+    let url = "/api/v1/crates/foo/1.0.0/downloads";
+    let json = anon.get::<()>(url).text();
+
+    let response = anon.get_with_query::<()>(url, "callback=$render_downloads1");
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()[header::CONTENT_TYPE],
+        "application/javascript"
+    );
+    assert_eq!(response.text(), format!("$render_downloads1({json});"));
+
+    let response = anon.get_with_query::<()>(url, "callback=alert(1)//");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_snapshot!(
+        response.text(),
+        @r###"{"errors":[{"detail":"invalid `callback` parameter `alert(1)//`"}]}"###
+    );
+
+    for callback in ["", "1abc", "foo.bar", "foo%3Bbar"] {
+        let response = anon.get_with_query::<()>(url, &format!("callback={callback}"));
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
+
+#[test]
+fn test_version_download_gaps() {
+    let (app, anon, cookie) = TestApp::init().with_user();
+
+    let today = Utc::now().date_naive();
+    publish_foo_with_downloads(&app, &cookie, [0, 1, 3, 100].map(|days_ago| (days_ago, 1)));
+
+    let url = "/api/v1/crates/foo/1.0.0/downloads/gaps";
+
+    let json = anon.get::<Value>(url).json();
+    let gaps = json["gaps"].as_array().unwrap();
+    assert_eq!(gaps.len(), 87);
+    assert_eq!(
+        json["meta"]["start_date"],
+        (today - Duration::days(89)).to_string()
+    );
+    assert_eq!(json["meta"]["end_date"], today.to_string());
+
+    let expected = (0..90)
+        .rev()
+        .filter(|days_ago| ![0, 1, 3].contains(days_ago))
+        .map(|days_ago| (today - Duration::days(days_ago)).to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(json["gaps"], json!(expected));
+
+    let json = anon.get_with_query::<Value>(url, "days=5").json();
+    let expected = [4, 2].map(|days_ago| (today - Duration::days(days_ago)).to_string());
+    assert_eq!(json["gaps"], json!(expected));
+
+    let response = anon.get::<()>("/api/v1/crates/foo/2.0.0/downloads/gaps");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn test_version_downloads_retention_days() {
+    let (app, anon, cookie) = TestApp::init().with_user();
+
+    publish_foo_with_downloads(
+        &app,
+        &cookie,
+        [0, 100, 179, 180, 364, 365].map(|days_ago| (days_ago, 1)),
+    );
+
+    let set_retention_days = |days: i32| {
+        app.db(|conn| {
+            diesel::update(crates::table)
+                .filter(crates::name.eq("foo"))
+                .set(crates::download_retention_days.eq(days))
+                .execute(conn)
+                .unwrap();
+        });
+    };
+
+    // Without a crate setting the last 90 days are returned
+    assert_dl_count(&anon, "foo/1.0.0", None, 1);
+
+    set_retention_days(180);
+    assert_dl_count(&anon, "foo/1.0.0", None, 3);
+
+    // The CSV export uses the same window
+    let csv = anon
+        .get::<()>("/api/v1/crates/foo/1.0.0/downloads.csv")
+        .text();
+    assert_eq!(csv.lines().count(), 1 + 3);
+
+    // Explicit `days` values take precedence
+    assert_dl_count(&anon, "foo/1.0.0", Some("days=1"), 1);
+
+    // Settings above the maximum are clamped to 365 days
+    set_retention_days(1000);
+    assert_dl_count(&anon, "foo/1.0.0", None, 5);
+}
+
+#[test]
+fn test_version_downloads_pagination() {
+    let (app, anon, cookie) = TestApp::init().with_user();
+
+    let today = Utc::now().date_naive();
+    publish_foo_with_downloads(
+        &app,
+        &cookie,
+        (0..5).map(|days_ago| (days_ago, days_ago as i32 + 1)),
+    );
+
+    let url = "/api/v1/crates/foo/1.0.0/downloads";
+
+    // Without paging parameters all rows are returned without a `total`
+    let json: Value = anon.get(url).good();
+    assert_eq!(json["version_downloads"].as_array().unwrap().len(), 5);
+    assert_eq!(json["meta"].get("total"), None);
+
+    let dates = |json: &Value| {
+        json["version_downloads"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|download| download["date"].as_str().unwrap().to_string())
+            .collect::<Vec<_>>()
+    };
+
+    let json: Value = anon.get_with_query(url, "per_page=2").good();
+    assert_eq!(json["meta"]["total"], 5);
+    assert_eq!(json["meta"]["total_downloads"], 15);
+    let expected = [4, 3].map(|days_ago| (today - Duration::days(days_ago)).to_string());
+    assert_eq!(dates(&json), expected);
+
+    let json: Value = anon.get_with_query(url, "per_page=2&page=2").good();
+    assert_eq!(json["meta"]["total"], 5);
+    let expected = [2, 1].map(|days_ago| (today - Duration::days(days_ago)).to_string());
+    assert_eq!(dates(&json), expected);
+
+    let json: Value = anon.get_with_query(url, "per_page=2&page=3").good();
+    assert_eq!(json["meta"]["total"], 5);
+    assert_eq!(dates(&json), [today.to_string()]);
+
+    let json: Value = anon.get_with_query(url, "per_page=2&page=4").good();
+    assert_eq!(json["meta"]["total"], 5);
+    assert_eq!(json["version_downloads"], json!([]));
+
+    let response = anon.get_with_query::<()>(url, "page=0");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[test]
+fn test_version_downloads_watch() {
+    use chrono::SecondsFormat;
+    use std::thread;
+
+    let (app, anon, cookie) = TestApp::init()
+        .with_config(|config| config.downloads_watch_timeout = std::time::Duration::from_secs(10))
+        .with_user();
+
+    let today = Utc::now().date_naive();
+    publish_foo_with_downloads(&app, &cookie, [(1, 3)]);
+
+    let since = Utc::now().to_rfc3339_opts(SecondsFormat::Micros, true);
+    let url = "/api/v1/crates/foo/1.0.0/downloads/watch";
+    let query = format!("since={since}");
+
+    let inner = app.as_inner();
+    let response = thread::scope(|scope| {
+        // Persist new download data while the request is waiting
+        scope.spawn(|| {
+            thread::sleep(std::time::Duration::from_millis(500));
+
+            let conn = &mut *inner.db_write().unwrap();
+            save_version_downloads_on("foo", "1.0.0", 5, today, conn);
+            diesel::insert_into(processed_log_files::table)
+                .values((
+                    processed_log_files::path.eq("cloudfront/a.log"),
+                    processed_log_files::time.eq(Utc::now()),
+                ))
+                .execute(conn)
+                .unwrap();
+        });
+
+        anon.get_with_query::<()>(url, &query)
+    });
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let json = response.json();
+    assert_eq!(
+        json["version_downloads"],
+        json!([{ "version": 1, "downloads": 5, "date": today.to_string() }])
+    );
+
+    let last_persisted_at = json["meta"]["last_persisted_at"].as_str().unwrap();
+    let last_persisted_at = last_persisted_at.parse::<DateTime<Utc>>().unwrap();
+    assert!(last_persisted_at > since.parse::<DateTime<Utc>>().unwrap());
+
+    let response = anon.get::<()>(url);
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_snapshot!(
+        response.text(),
+        @r###"{"errors":[{"detail":"missing `since` parameter"}]}"###
     );
 }
+
+#[test]
+fn test_version_downloads_watch_timeout() {
+    let (app, anon, cookie) = TestApp::init().with_user();
+
+    app.db(|conn| {
+        let user_id = cookie.as_model().id;
+        CrateBuilder::new("foo", user_id)
+            .version("1.0.0")
+            .expect_build(conn);
+
+        // Data that was persisted before `since` does not unblock the request
+        diesel::insert_into(processed_log_files::table)
+            .values((
+                processed_log_files::path.eq("cloudfront/a.log"),
+                processed_log_files::time.eq(Utc::now() - Duration::hours(1)),
+            ))
+            .execute(conn)
+            .unwrap();
+    });
+
+    let url = "/api/v1/crates/foo/1.0.0/downloads/watch";
+    let query = format!("since={}", Utc::now().format("%Y-%m-%dT%H:%M:%SZ"));
+
+    let start = std::time::Instant::now();
+    let response = anon.get_with_query::<()>(url, &query);
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    assert!(start.elapsed() >= std::time::Duration::from_secs(1));
+    assert_eq!(response.text(), "");
+}
+
+#[test]
+fn test_version_downloads_last_persisted_at() {
+    let (app, anon, cookie) = TestApp::init().with_user();
+
+    app.db(|conn| {
+        let user_id = cookie.as_model().id;
+        CrateBuilder::new("foo", user_id)
+            .version("1.0.0")
+            .expect_build(conn);
+    });
+
+    let url = "/api/v1/crates/foo/1.0.0/downloads";
+
+    // No CDN log files have been processed yet
+    let json: Value = anon.get(url).good();
+    assert_eq!(json["meta"]["last_persisted_at"], Value::Null);
+
+    let older = "2024-03-01T12:00:00Z".parse::<DateTime<Utc>>().unwrap();
+    let newer = "2024-03-02T08:30:00Z".parse::<DateTime<Utc>>().unwrap();
+    app.db(|conn| {
+        diesel::insert_into(processed_log_files::table)
+            .values(&vec![
+                (
+                    processed_log_files::path.eq("cloudfront/a.log"),
+                    processed_log_files::time.eq(newer),
+                ),
+                (
+                    processed_log_files::path.eq("cloudfront/b.log"),
+                    processed_log_files::time.eq(older),
+                ),
+            ])
+            .execute(conn)
+            .unwrap();
+    });
+
+    let json: Value = anon.get(url).good();
+    let last_persisted_at = json["meta"]["last_persisted_at"].as_str().unwrap();
+    let last_persisted_at = last_persisted_at.parse::<DateTime<Utc>>().unwrap();
+    assert_eq!(last_persisted_at, newer);
+
+    // The timestamp is also included in paginated responses
+    let json: Value = anon.get_with_query(url, "per_page=10").good();
+    assert_eq!(json["meta"]["total"], 0);
+    assert_eq!(json["meta"]["last_persisted_at"], "2024-03-02T08:30:00Z");
+}
+
+#[test]
+fn test_version_downloads_time_zone() {
+    let (app, anon, cookie) = TestApp::init().with_user();
+
+    let today = Utc::now().date_naive();
+    publish_foo_with_downloads(&app, &cookie, [(1, 1), (0, 2), (-1, 3)]);
+
+    let url = "/api/v1/crates/foo/1.0.0/downloads";
+    let last_date = |query: &str| {
+        let downloads: Downloads = anon.get_with_query(url, query).good();
+        downloads.version_downloads.last().unwrap().date.clone()
+    };
+
+    assert_eq!(last_date("days=1"), today.to_string());
+    assert_eq!(last_date("days=1&tz=UTC"), today.to_string());
+
+    // UTC+14 is always at least as late as UTC, and UTC-11 at least as early
+    let tz: chrono_tz::Tz = "Pacific/Kiritimati".parse().unwrap();
+    let local_today = Utc::now().with_timezone(&tz).date_naive();
+    assert!(local_today >= today);
+    let query = "days=1&tz=Pacific/Kiritimati";
+    assert_eq!(last_date(query), local_today.to_string());
+
+    let tz: chrono_tz::Tz = "Pacific/Pago_Pago".parse().unwrap();
+    let local_today = Utc::now().with_timezone(&tz).date_naive();
+    assert!(local_today <= today);
+    let query = "days=1&tz=Pacific/Pago_Pago";
+    assert_eq!(last_date(query), local_today.to_string());
+
+    // The two time zones are 25 hours apart, so their dates always differ
+    let kiritimati = last_date("days=1&tz=Pacific/Kiritimati");
+    let pago_pago = last_date("days=1&tz=Pacific/Pago_Pago");
+    assert_ne!(kiritimati, pago_pago);
+
+    // `before_date` takes precedence over the time zone
+    let query = format!("days=1&tz=Pacific/Kiritimati&before_date={today}");
+    assert_eq!(last_date(&query), today.to_string());
+
+    let response = anon.get_with_query::<()>(url, "tz=Mars/Olympus_Mons");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_snapshot!(
+        response.text(),
+        @r###"{"errors":[{"detail":"invalid `tz` parameter `Mars/Olympus_Mons`, expected an IANA time zone name"}]}"###
+    );
+}
+
+#[test]
+fn test_version_downloads_after_date() {
+    let (app, anon, cookie) = TestApp::init().with_user();
+
+    let today = Utc::now().date_naive();
+
+    publish_foo_with_downloads(&app, &cookie, [0, 5, 10, 100].map(|days_ago| (days_ago, 1)));
+
+    let format_days_ago = |days| (today - Duration::days(days)).format("%F");
+
+    // Both `after_date` and `before_date` are inclusive
+    let query = format!(
+        "after_date={}&before_date={}",
+        format_days_ago(10),
+        format_days_ago(5)
+    );
+    assert_dl_count(&anon, "foo/1.0.0", Some(&query), 2);
+
+    // `after_date` may reach further back than the default 90 day window
+    let query = format!(
+        "after_date={}&before_date={}",
+        format_days_ago(200),
+        format_days_ago(1)
+    );
+    assert_dl_count(&anon, "foo/1.0.0", Some(&query), 3);
+
+    // Without `before_date` the window ends today
+    let query = format!("after_date={}", format_days_ago(5));
+    assert_dl_count(&anon, "foo/1.0.0", Some(&query), 2);
+
+    let url = "/api/v1/crates/foo/1.0.0/downloads";
+
+    // Inverted ranges are rejected
+    let query = format!(
+        "after_date={}&before_date={}",
+        format_days_ago(5),
+        format_days_ago(10)
+    );
+    let response = anon.get_with_query::<()>(url, &query);
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_snapshot!(
+        response.text(),
+        @r###"{"errors":[{"detail":"`after_date` must not be later than `before_date`"}]}"###
+    );
+
+    let response = anon.get_with_query::<()>(url, "after_date=yesterday");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_snapshot!(
+        response.text(),
+        @r###"{"errors":[{"detail":"invalid `after_date` parameter `yesterday`, expected a date in `YYYY-MM-DD` format"}]}"###
+    );
+}
+
+#[test]
+fn test_version_downloads_exclude_today() {
+    let (app, anon, cookie) = TestApp::init().with_user();
+
+    let today = Utc::now().date_naive();
+    publish_foo_with_downloads(&app, &cookie, (0..3).map(|days_ago| (days_ago, 1)));
+
+    let url = "/api/v1/crates/foo/1.0.0/downloads";
+    let dates = |query: &str| {
+        let downloads: Downloads = anon.get_with_query(url, query).good();
+        downloads
+            .version_downloads
+            .into_iter()
+            .map(|download| download.date)
+            .collect::<Vec<_>>()
+    };
+
+    let yesterday = today - Duration::days(1);
+    assert_eq!(dates("").len(), 3);
+    assert_eq!(dates("exclude_today=false").len(), 3);
+    assert_eq!(dates("exclude_today=true").len(), 2);
+    assert!(!dates("exclude_today=true").contains(&today.to_string()));
+
+    // Earlier `before_date` values are not affected
+    let query = format!("before_date={yesterday}&exclude_today=true");
+    assert_eq!(dates(&query).len(), 2);
+
+    let query = format!("after_date={today}&exclude_today=true");
+    assert_eq!(dates(&query), Vec::<String>::new());
+}
+
+#[test]
+fn test_version_downloads_cumulative() {
+    let (app, anon, cookie) = TestApp::init().with_user();
+
+    publish_foo_with_downloads(&app, &cookie, [(10, 3), (5, 0), (2, 7), (0, 1)]);
+
+    let url = "/api/v1/crates/foo/1.0.0/downloads";
+
+    // The field is omitted unless explicitly requested
+    let json = anon.get::<()>(url).json();
+    let version_downloads = json["version_downloads"].as_array().unwrap();
+    assert_eq!(version_downloads.len(), 4);
+    assert!(version_downloads
+        .iter()
+        .all(|vd| vd.get("cumulative").is_none()));
+
+    let downloads: Downloads = anon.get_with_query(url, "cumulative=true").good();
+    let cumulative = downloads
+        .version_downloads
+        .iter()
+        .map(|vd| vd.cumulative.unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(cumulative, vec![3, 3, 10, 11]);
+    assert!(cumulative.windows(2).all(|w| w[0] <= w[1]));
+}
+
+#[test]
+fn test_version_downloads_granularity() {
+    let (app, anon, cookie) = TestApp::init().with_user();
+
+    app.db(|conn| {
+        let downloads = [
+            // outside of the window
+            ("2023-12-31", 1),
+            // first day of the window, a Tuesday
+            ("2024-01-02", 2),
+            ("2024-01-05", 3),
+            ("2024-01-08", 5),
+            // Thursday and Sunday of the same week
+            ("2024-02-01", 7),
+            ("2024-02-04", 11),
+            // last day of the window
+            ("2024-03-31", 13),
+        ];
+        let version = downloads.into_iter().fold(
+            VersionBuilder::new("1.0.0"),
+            |version, (date, downloads)| {
+                let date = NaiveDate::parse_from_str(date, "%F").unwrap();
+                version.downloads_on(date, downloads)
+            },
+        );
+
+        CrateBuilder::new("foo", cookie.as_model().id)
+            .version(version)
+            .expect_build(conn);
+    });
+
+    let get_series = |granularity: &str| {
+        let url = "/api/v1/crates/foo/1.0.0/downloads";
+        let query = format!("before_date=2024-03-31&granularity={granularity}");
+        let downloads: Downloads = anon.get_with_query(url, &query).good();
+        downloads
+            .version_downloads
+            .into_iter()
+            .map(|vd| (vd.date, vd.downloads))
+            .collect::<Vec<_>>()
+    };
+
+    let total = |series: &[(String, i32)]| series.iter().map(|(_, d)| d).sum::<i32>();
+
+    let days = get_series("day");
+    assert_eq!(days.len(), 6);
+    assert_eq!(total(&days), 41);
+
+    // Partial buckets at the start of the window are dated at the window start
+    let weeks = get_series("week");
+    let expected = vec![
+        ("2024-01-02".to_string(), 5),
+        ("2024-01-08".to_string(), 5),
+        ("2024-01-29".to_string(), 18),
+        ("2024-03-25".to_string(), 13),
+    ];
+    assert_eq!(weeks, expected);
+    assert_eq!(total(&weeks), total(&days));
+
+    let months = get_series("month");
+    let expected = vec![
+        ("2024-01-02".to_string(), 10),
+        ("2024-02-01".to_string(), 18),
+        ("2024-03-01".to_string(), 13),
+    ];
+    assert_eq!(months, expected);
+    assert_eq!(total(&months), total(&days));
+
+    let url = "/api/v1/crates/foo/1.0.0/downloads";
+    let response = anon.get_with_query::<()>(url, "granularity=year");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_snapshot!(
+        response.text(),
+        @r###"{"errors":[{"detail":"invalid `granularity` parameter `year`, expected `day`, `week` or `month`"}]}"###
+    );
+}
+
+#[test]
+fn test_version_downloads_epoch_dates() {
+    let (app, anon, cookie) = TestApp::init().with_user();
+
+    let date = NaiveDate::parse_from_str("2024-03-01", "%F").unwrap();
+    app.db(|conn| {
+        CrateBuilder::new("foo", cookie.as_model().id)
+            .version(VersionBuilder::new("1.0.0").downloads_on(date, 5))
+            .expect_build(conn);
+    });
+
+    let url = "/api/v1/crates/foo/1.0.0/downloads";
+    let window = "after_date=2024-02-28&before_date=2024-03-02";
+
+    let json: Value = anon.get_with_query(url, window).good();
+    assert_eq!(json["version_downloads"][0]["date"], "2024-03-01");
+
+    let query = format!("{window}&date_format=iso");
+    let json: Value = anon.get_with_query(url, &query).good();
+    assert_eq!(json["version_downloads"][0]["date"], "2024-03-01");
+
+    let midnight = date.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp();
+    assert_eq!(midnight, 1709251200);
+
+    let query = format!("{window}&date_format=epoch");
+    let json: Value = anon.get_with_query(url, &query).good();
+    assert_eq!(json["version_downloads"][0]["date"], midnight);
+    assert_eq!(json["version_downloads"][0]["downloads"], 5);
+
+    // The conversion also applies when only some fields are requested
+    let query = format!("{window}&date_format=epoch&fields=date");
+    let json: Value = anon.get_with_query(url, &query).good();
+    assert_eq!(
+        json["version_downloads"][0],
+        serde_json::json!({ "date": midnight })
+    );
+
+    let query = format!("{window}&date_format=unix");
+    let response = anon.get_with_query::<()>(url, &query);
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_snapshot!(
+        response.text(),
+        @r###"{"errors":[{"detail":"invalid `date_format` parameter `unix`, expected `iso` or `epoch`"}]}"###
+    );
+
+    let query = format!("{window}&date_format=epoch&compact=true");
+    let response = anon.get_with_query::<()>(url, &query);
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[test]
+fn test_version_downloads_iso_week_label() {
+    let (app, anon, cookie) = TestApp::init().with_user();
+
+    app.db(|conn| {
+        let downloads = [
+            ("2024-12-27", 1),
+            // ISO week 1 of 2025 starts on Monday, December 30
+            ("2024-12-31", 2),
+            ("2025-01-03", 3),
+            ("2025-01-29", 7),
+        ];
+        let version = downloads.into_iter().fold(
+            VersionBuilder::new("1.0.0"),
+            |version, (date, downloads)| {
+                let date = NaiveDate::parse_from_str(date, "%F").unwrap();
+                version.downloads_on(date, downloads)
+            },
+        );
+
+        CrateBuilder::new("foo", cookie.as_model().id)
+            .version(version)
+            .expect_build(conn);
+    });
+
+    let url = "/api/v1/crates/foo/1.0.0/downloads";
+    let window = "after_date=2024-12-23&before_date=2025-02-02";
+
+    let query = format!("{window}&label=iso_week");
+    let downloads: Downloads = anon.get_with_query(url, &query).good();
+    let series = downloads
+        .version_downloads
+        .into_iter()
+        .map(|vd| (vd.date, vd.label.unwrap(), vd.downloads))
+        .collect::<Vec<_>>();
+    let expected = vec![
+        ("2024-12-23".to_string(), "2024-W52".to_string(), 1),
+        ("2024-12-30".to_string(), "2025-W01".to_string(), 5),
+        ("2025-01-27".to_string(), "2025-W05".to_string(), 7),
+    ];
+    assert_eq!(series, expected);
+
+    // The same buckets are returned with an explicit weekly granularity
+    let query = format!("{window}&label=iso_week&granularity=week");
+    let json = anon.get_with_query::<Value>(url, &query).good();
+    assert_eq!(json["version_downloads"][1]["label"], "2025-W01");
+
+    // Labels are only included when requested
+    let query = format!("{window}&granularity=week");
+    let json = anon.get_with_query::<Value>(url, &query).good();
+    assert_eq!(json["version_downloads"][0].get("label"), None);
+
+    let query = format!("{window}&label=iso_week&granularity=month");
+    let response = anon.get_with_query::<()>(url, &query);
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_snapshot!(
+        response.text(),
+        @r###"{"errors":[{"detail":"`label=iso_week` requires `granularity=week`"}]}"###
+    );
+
+    let response = anon.get_with_query::<()>(url, "label=week");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_snapshot!(
+        response.text(),
+        @r###"{"errors":[{"detail":"invalid `label` parameter `week`, expected `iso_week`"}]}"###
+    );
+}
+
+#[test]
+fn test_version_downloads_canonical_redirect() {
+    let (app, anon, cookie) = TestApp::init().with_user();
+
+    app.db(|conn| {
+        let user_id = cookie.as_model().id;
+        CrateBuilder::new("foo", user_id)
+            .version("1.0.0")
+            .version("2.0.0-beta.1")
+            .expect_build(conn);
+    });
+
+    // Exact matches are served directly
+    let response = anon.get::<()>("/api/v1/crates/foo/1.0.0/downloads");
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // Non-canonical versions are redirected, keeping the query string
+    let response = anon.get_with_query::<()>("/api/v1/crates/foo/1.0/downloads", "days=7");
+    assert_eq!(response.status(), StatusCode::PERMANENT_REDIRECT);
+    assert_eq!(
+        response.headers()[header::LOCATION],
+        "/api/v1/crates/foo/1.0.0/downloads?days=7"
+    );
+
+    let response = anon.get::<()>("/api/v1/crates/foo/v1/downloads");
+    assert_eq!(response.status(), StatusCode::PERMANENT_REDIRECT);
+    assert_eq!(
+        response.headers()[header::LOCATION],
+        "/api/v1/crates/foo/1.0.0/downloads"
+    );
+
+    let response = anon.get::<()>("/api/v1/crates/foo/v2.0.0-beta.1/downloads");
+    assert_eq!(response.status(), StatusCode::PERMANENT_REDIRECT);
+    assert_eq!(
+        response.headers()[header::LOCATION],
+        "/api/v1/crates/foo/2.0.0-beta.1/downloads"
+    );
+
+    // Versions that don't exist in their canonical form are not found
+    let response = anon.get::<()>("/api/v1/crates/foo/1.1/downloads");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn test_version_total_downloads() {
+    let (app, anon, cookie) = TestApp::init().with_user();
+
+    app.db(|conn| {
+        let user_id = cookie.as_model().id;
+        CrateBuilder::new("foo", user_id)
+            .version("1.0.0")
+            .version("1.1.0")
+            .expect_build(conn);
+    });
+
+    let url = "/api/v1/crates/foo/1.0.0/downloads/total";
+
+    let json: Value = anon.get(url).good();
+    assert_eq!(json, json!({ "total_downloads": 0 }));
+
+    download(&anon, "foo/1.0.0");
+    download(&anon, "foo/1.0.0");
+    download(&anon, "foo/1.1.0");
+
+    app.db(|conn| {
+        save_version_downloads("foo", "1.0.0", 2, conn);
+        save_version_downloads("foo", "1.1.0", 1, conn);
+
+        // Downloads outside of the 90 day window are included in the total
+        let date = Utc::now().date_naive() - Duration::days(365);
+        save_version_downloads_on("foo", "1.0.0", 40, date, conn);
+    });
+
+    let json: Value = anon.get(url).good();
+    assert_eq!(json, json!({ "total_downloads": 42 }));
+
+    let response = anon.get::<()>("/api/v1/crates/foo/2.0.0/downloads/total");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let response = anon.get::<()>("/api/v1/crates/foo/invalid-version/downloads/total");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_snapshot!(
+        response.text(),
+        @r###"{"errors":[{"detail":"crate `foo` does not have a version `invalid-version`. Did you mean one of: `1.1.0`, `1.0.0`?"}]}"###
+    );
+}
+
+#[test]
+fn test_version_downloads_before_date() {
+    let (app, anon, cookie) = TestApp::init().with_user();
+
+    app.db(|conn| {
+        let version = VersionBuilder::new("1.0.0")
+            .downloads_on(NaiveDate::from_ymd_opt(2024, 3, 1).unwrap(), 1)
+            .downloads_on(NaiveDate::from_ymd_opt(2024, 3, 3).unwrap(), 1);
+
+        CrateBuilder::new("foo", cookie.as_model().id)
+            .version(version)
+            .expect_build(conn);
+
+        let persisted_at = "2024-03-05T12:34:56Z".parse().unwrap();
+        mark_log_file_processed("cloudfront/a.log", persisted_at, conn);
+    });
+
+    let url = "/api/v1/crates/foo/1.0.0/downloads";
+
+    // `Last-Modified` is the time at which downloads were last saved
+    let response = anon.get_with_query::<()>(url, "before_date=2024-03-10");
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()[header::LAST_MODIFIED],
+        "Tue, 05 Mar 2024 12:34:56 GMT"
+    );
+    assert_eq!(
+        response.json()["version_downloads"]
+            .as_array()
+            .unwrap()
+            .len(),
+        2
+    );
+
+    let response = anon.get_with_query::<()>(url, "before_date=2024-03-02");
+    assert_eq!(
+        response.headers()[header::LAST_MODIFIED],
+        "Tue, 05 Mar 2024 12:34:56 GMT"
+    );
+
+    // No `Last-Modified` header is sent without any download data
+    let response = anon.get_with_query::<()>(url, "before_date=2024-01-01");
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(!response.headers().contains_key(header::LAST_MODIFIED));
+
+    let response = anon.get_with_query::<()>(url, "before_date=garbage");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_snapshot!(
+        response.text(),
+        @r###"{"errors":[{"detail":"invalid `before_date` parameter `garbage`, expected a date in `YYYY-MM-DD` format"}]}"###
+    );
+
+    // The start of the window would be before `NaiveDate::MIN`
+    let response = anon.get_with_query::<()>(url, "before_date=-262143-01-05");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_snapshot!(
+        response.text(),
+        @r###"{"errors":[{"detail":"the requested date range starts before the earliest supported date"}]}"###
+    );
+
+    let response = anon.get_with_query::<()>(url, "before_date=-262143-03-01&days=365");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = anon.get_with_query::<()>(url, "before_date=0001-01-01");
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[test]
+fn test_version_downloads_window_clamped() {
+    let (app, anon, cookie) = TestApp::init().with_user();
+    let today = Utc::now().date_naive();
+
+    publish_foo_with_downloads(&app, &cookie, [10, 5].map(|days_ago| (days_ago, 1)));
+
+    let url = "/api/v1/crates/foo/1.0.0/downloads";
+    let first_date = (today - Duration::days(10)).to_string();
+
+    // The window ends before the earliest available data
+    let before_date = today - Duration::days(20);
+    let query = format!("before_date={before_date}&days=30");
+    let json = anon.get_with_query::<Value>(url, &query).good();
+    assert_eq!(json["version_downloads"].as_array().unwrap().len(), 0);
+    assert_eq!(json["meta"]["window_start"], first_date);
+    assert_eq!(json["meta"]["window_clamped"], true);
+
+    // The window starts before the earliest available data
+    let json = anon.get::<Value>(url).good();
+    assert_eq!(json["version_downloads"].as_array().unwrap().len(), 2);
+    assert_eq!(json["meta"]["window_start"], first_date);
+    assert_eq!(json["meta"]["window_clamped"], true);
+
+    // The window starts within the available data
+    let after_date = today - Duration::days(7);
+    let query = format!("after_date={after_date}");
+    let json = anon.get_with_query::<Value>(url, &query).good();
+    assert_eq!(json["version_downloads"].as_array().unwrap().len(), 1);
+    assert_eq!(json["meta"]["window_start"], after_date.to_string());
+    assert_eq!(json["meta"]["window_clamped"], false);
+}
+
+#[test]
+fn test_version_extra_downloads() {
+    let (app, anon, cookie) = TestApp::init().with_user();
+
+    let today = Utc::now().date_naive();
+    app.db(|conn| {
+        let user_id = cookie.as_model().id;
+        CrateBuilder::new("foo", user_id)
+            .version(version_with_downloads("1.0.0", [(0, 3)]))
+            .version(version_with_downloads("2.0.0", [(0, 5)]))
+            .expect_build(conn);
+
+        diesel::update(versions::table)
+            .filter(versions::num.eq("1.0.0"))
+            .set(versions::extra_downloads.eq(1000))
+            .execute(conn)
+            .unwrap();
+
+        // The imported downloads of versions created before the 90 day
+        // window are only included in the version total
+        diesel::update(versions::table)
+            .filter(versions::num.eq("2.0.0"))
+            .set((
+                versions::extra_downloads.eq(200),
+                versions::created_at.eq((Utc::now() - Duration::days(200)).naive_utc()),
+            ))
+            .execute(conn)
+            .unwrap();
+    });
+
+    let url = "/api/v1/crates/foo/1.0.0/downloads/total";
+    let json: Value = anon.get(url).good();
+    assert_eq!(json, json!({ "total_downloads": 1003 }));
+
+    let url = "/api/v1/crates/foo/2.0.0/downloads/total";
+    let json: Value = anon.get(url).good();
+    assert_eq!(json, json!({ "total_downloads": 205 }));
+
+    // The imported downloads are prepended as a labeled entry
+    let json: Value = anon.get("/api/v1/crates/foo/1.0.0/downloads").good();
+    let series = json["version_downloads"].as_array().unwrap();
+    assert_eq!(series.len(), 2);
+    assert_eq!(series[0]["downloads"], 1000);
+    assert_eq!(series[0]["date"], today.to_string());
+    assert_eq!(series[0]["migrated"], true);
+    assert_eq!(series[1]["downloads"], 3);
+    assert_eq!(series[1].get("migrated"), None);
+    assert_eq!(json["meta"]["total_downloads"], 1003);
+
+    let json: Value = anon.get("/api/v1/crates/foo/2.0.0/downloads").good();
+    let series = json["version_downloads"].as_array().unwrap();
+    assert_eq!(series.len(), 1);
+    assert_eq!(series[0].get("migrated"), None);
+
+    let url = "/api/v1/crates/foo/downloads";
+    let json: Value = anon.get_with_query(url, "split_yanked=true").good();
+    assert_eq!(json["meta"]["downloads"], 1008);
+    assert_eq!(json["meta"]["yanked_downloads"], 0);
+}
+
+#[test]
+fn test_version_download_peaks() {
+    let (app, anon, cookie) = TestApp::init().with_user();
+
+    let today = Utc::now().date_naive();
+
+    // Downloads outside of the 90 day window are ignored
+    let downloads = [(1, 5), (2, 50), (3, 20), (4, 35), (5, 10), (100, 1000)];
+    publish_foo_with_downloads(&app, &cookie, downloads);
+
+    let url = "/api/v1/crates/foo/1.0.0/downloads/peaks";
+
+    let peaks = |json: Value| -> Vec<(String, i64)> {
+        json["version_downloads"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|d| {
+                let date = d["date"].as_str().unwrap().to_string();
+                (date, d["downloads"].as_i64().unwrap())
+            })
+            .collect()
+    };
+    let days_ago = |days: i64| (today - Duration::days(days)).to_string();
+
+    let json: Value = anon.get(url).good();
+    assert_eq!(
+        peaks(json),
+        vec![
+            (days_ago(2), 50),
+            (days_ago(4), 35),
+            (days_ago(3), 20),
+            (days_ago(5), 10),
+            (days_ago(1), 5),
+        ]
+    );
+
+    let json: Value = anon.get_with_query(url, "limit=2").good();
+    assert_eq!(peaks(json), vec![(days_ago(2), 50), (days_ago(4), 35)]);
+
+    // Limits above the maximum are clamped
+    let json: Value = anon.get_with_query(url, "limit=1000").good();
+    assert_eq!(peaks(json).len(), 5);
+
+    let response = anon.get_with_query::<()>(url, "limit=0");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_snapshot!(
+        response.text(),
+        @r###"{"errors":[{"detail":"invalid `limit` parameter `0`, expected a positive integer"}]}"###
+    );
+}
+
+#[test]
+fn test_version_downloads_by_target() {
+    let (app, anon, cookie) = TestApp::init().with_user();
+
+    app.db(|conn| {
+        let user_id = cookie.as_model().id;
+        CrateBuilder::new("foo", user_id)
+            .version("1.0.0")
+            .expect_build(conn);
+    });
+
+    let url = "/api/v1/crates/foo/1.0.0/download";
+    let linux = "x86_64-unknown-linux-gnu";
+    let windows = "x86_64-pc-windows-msvc";
+
+    // The target is forwarded to the CDN, where the download is counted
+    let response = anon.get_with_query::<()>(url, &format!("target={linux}"));
+    assert_eq!(response.status(), StatusCode::FOUND);
+    assert_eq!(
+        response.headers()[header::LOCATION],
+        format!("https://static.crates.io/crates/foo/foo-1.0.0.crate?target={linux}")
+    );
+
+    let response = anon.get::<()>(url);
+    assert_eq!(
+        response.headers()[header::LOCATION],
+        "https://static.crates.io/crates/foo/foo-1.0.0.crate"
+    );
+
+    let response = anon.get_with_query::<()>(url, "target=not%20a%20target");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_snapshot!(
+        response.text(),
+        @r###"{"errors":[{"detail":"invalid `target` parameter `not a target`"}]}"###
+    );
+
+    // Simulate the persisted counts of the CDN log processing
+    app.db(|conn| {
+        let today = Utc::now().date_naive();
+        save_version_downloads("foo", "1.0.0", 10, conn);
+
+        let version_id = versions::table
+            .select(versions::id)
+            .filter(versions::num.eq("1.0.0"))
+            .first::<i32>(conn)
+            .unwrap();
+
+        for (target, downloads, days_ago) in [(linux, 3, 0), (linux, 2, 1), (windows, 1, 0)] {
+            diesel::insert_into(version_downloads_by_target::table)
+                .values((
+                    version_downloads_by_target::version_id.eq(version_id),
+                    version_downloads_by_target::target.eq(target),
+                    version_downloads_by_target::date.eq(today - Duration::days(days_ago)),
+                    version_downloads_by_target::downloads.eq(downloads),
+                ))
+                .execute(conn)
+                .unwrap();
+        }
+    });
+
+    // Targets are ordered by the number of downloads
+    let response = anon.get::<()>("/api/v1/crates/foo/1.0.0/downloads/by_target");
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_snapshot!(
+        response.text(),
+        @r###"{"targets":{"x86_64-unknown-linux-gnu":5,"unknown":4,"x86_64-pc-windows-msvc":1}}"###
+    );
+}
+
+#[test]
+fn test_version_downloads_by_source() {
+    let (app, anon, cookie) = TestApp::init().with_user();
+
+    app.db(|conn| {
+        let user_id = cookie.as_model().id;
+        CrateBuilder::new("foo", user_id)
+            .version("1.0.0")
+            .expect_build(conn);
+    });
+
+    let url = "/api/v1/crates/foo/1.0.0/downloads/by_source";
+
+    let response = anon.get::<()>(url);
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_snapshot!(
+        response.text(),
+        @r###"{"sources":{"human":0,"ci":0,"mirror":0,"unknown":0}}"###
+    );
+
+    // Simulate the persisted counts of the CDN log processing
+    app.db(|conn| {
+        let today = Utc::now().date_naive();
+        save_version_downloads("foo", "1.0.0", 10, conn);
+
+        let version_id = versions::table
+            .select(versions::id)
+            .filter(versions::num.eq("1.0.0"))
+            .first::<i32>(conn)
+            .unwrap();
+
+        for (source, downloads, days_ago) in [("human", 3, 0), ("human", 2, 1), ("ci", 4, 0)] {
+            diesel::insert_into(version_downloads_by_source::table)
+                .values((
+                    version_downloads_by_source::version_id.eq(version_id),
+                    version_downloads_by_source::source.eq(source),
+                    version_downloads_by_source::date.eq(today - Duration::days(days_ago)),
+                    version_downloads_by_source::downloads.eq(downloads),
+                ))
+                .execute(conn)
+                .unwrap();
+        }
+    });
+
+    // The sources add up to the regular download count
+    let response = anon.get::<()>(url);
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_snapshot!(
+        response.text(),
+        @r###"{"sources":{"human":5,"ci":4,"mirror":0,"unknown":1}}"###
+    );
+}
+
+#[test]
+fn test_version_downloads_etag() {
+    let (app, anon, cookie) = TestApp::init().with_user();
+
+    let today = Utc::now().date_naive();
+    publish_foo_with_downloads(&app, &cookie, [(1, 3)]);
+
+    let url = "/api/v1/crates/foo/1.0.0/downloads";
+
+    let get_etag = || {
+        let response = anon.get::<()>(url);
+        assert_eq!(response.status(), StatusCode::OK);
+        let etag = response.headers().get(header::ETAG).unwrap();
+        etag.to_str().unwrap().to_string()
+    };
+
+    let get_with_etag = |etag: &str| {
+        let mut request = anon.get_request(url);
+        request.header(header::IF_NONE_MATCH, etag);
+        anon.run::<()>(request)
+    };
+
+    // The ETag is stable as long as the data doesn't change
+    let etag = get_etag();
+    assert!(etag.starts_with("W/"));
+    assert_eq!(get_etag(), etag);
+
+    let response = get_with_etag(&etag);
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(response.text(), "");
+
+    // A new day of downloads changes the ETag
+    app.db(|conn| save_version_downloads_on("foo", "1.0.0", 1, today, conn));
+
+    let new_etag = get_etag();
+    assert_ne!(new_etag, etag);
+
+    let response = get_with_etag(&etag);
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = get_with_etag(&new_etag);
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+    // Late updates of earlier days change the ETag too
+    let yesterday = today - Duration::days(1);
+    app.db(|conn| {
+        diesel::update(version_downloads::table)
+            .filter(version_downloads::date.eq(yesterday))
+            .set(version_downloads::downloads.eq(4))
+            .execute(conn)
+            .unwrap();
+    });
+
+    let late_etag = get_etag();
+    assert_ne!(late_etag, new_etag);
+
+    // ... as do migrated downloads
+    app.db(|conn| {
+        diesel::update(versions::table)
+            .set(versions::extra_downloads.eq(10))
+            .execute(conn)
+            .unwrap();
+    });
+    assert_ne!(get_etag(), late_etag);
+}
+
+#[test]
+fn test_version_downloads_if_modified_since() {
+    let (app, anon, cookie) = TestApp::init().with_user();
+
+    let today = Utc::now().date_naive();
+    publish_foo_with_downloads(&app, &cookie, [(1, 3)]);
+
+    let persisted_at = Utc::now() - Duration::hours(2);
+    app.db(|conn| mark_log_file_processed("cloudfront/a.log", persisted_at, conn));
+
+    let url = "/api/v1/crates/foo/1.0.0/downloads";
+
+    let get_if_modified_since = |date: &str| {
+        let mut request = anon.get_request(url);
+        request.header(header::IF_MODIFIED_SINCE, date);
+        anon.run::<()>(request)
+    };
+
+    let response = anon.get::<()>(url);
+    assert_eq!(response.status(), StatusCode::OK);
+    let last_modified = response.headers()[header::LAST_MODIFIED]
+        .to_str()
+        .unwrap()
+        .to_string();
+
+    // Unchanged data results in an empty `304 Not Modified` response
+    let response = get_if_modified_since(&last_modified);
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(response.text(), "");
+
+    // Timestamps before the downloads were saved are outdated, even on the
+    // same day
+    let earlier = (persisted_at - Duration::seconds(1))
+        .format("%a, %d %b %Y %H:%M:%S GMT")
+        .to_string();
+    let response = get_if_modified_since(&earlier);
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // Unparseable dates are ignored
+    let response = get_if_modified_since("yesterday");
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // Downloads saved later on the same day are returned to the client
+    app.db(|conn| {
+        save_version_downloads_on("foo", "1.0.0", 1, today, conn);
+        mark_log_file_processed("cloudfront/b.log", Utc::now(), conn);
+    });
+
+    let response = get_if_modified_since(&last_modified);
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.json()["version_downloads"]
+            .as_array()
+            .unwrap()
+            .len(),
+        2
+    );
+
+    // `If-None-Match` takes precedence over `If-Modified-Since`
+    let mut request = anon.get_request(url);
+    request.header(header::IF_MODIFIED_SINCE, &last_modified);
+    request.header(header::IF_NONE_MATCH, "W/\"outdated\"");
+    let response = anon.run::<()>(request);
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[test]
+fn test_version_downloads_csv() {
+    let (app, anon, cookie) = TestApp::init().with_user();
+
+    publish_foo_with_downloads(&app, &cookie, [(100, 5), (10, 3), (2, 7), (0, 1)]);
+
+    let yesterday = (Utc::now().date_naive() - Duration::days(1)).format("%F");
+    for query in [String::new(), format!("before_date={yesterday}")] {
+        let json_url = "/api/v1/crates/foo/1.0.0/downloads";
+        let downloads: Downloads = anon.get_with_query(json_url, &query).good();
+
+        let csv_url = "/api/v1/crates/foo/1.0.0/downloads.csv";
+        let response = anon.get_with_query::<()>(csv_url, &query);
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get(header::CONTENT_TYPE).unwrap(),
+            "text/csv; charset=utf-8"
+        );
+
+        let text = response.text();
+        let mut lines = text.lines();
+        assert_eq!(lines.next(), Some("date,downloads"));
+
+        let rows = lines
+            .map(|line| {
+                let (date, downloads) = line.split_once(',').unwrap();
+                (date.to_string(), downloads.parse::<i32>().unwrap())
+            })
+            .collect::<Vec<_>>();
+
+        let expected = downloads
+            .version_downloads
+            .into_iter()
+            .map(|vd| (vd.date, vd.downloads))
+            .collect::<Vec<_>>();
+
+        assert_eq!(rows, expected);
+    }
+
+    let response = anon.get::<()>("/api/v1/crates/foo/2.0.0/downloads.csv");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let response = anon.get::<()>("/api/v1/crates/bar/1.0.0/downloads.csv");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn test_version_downloads_ndjson() {
+    let (app, anon, cookie) = TestApp::init().with_user();
+
+    publish_foo_with_downloads(&app, &cookie, [(100, 5), (10, 3), (2, 7), (0, 1)]);
+
+    let url = "/api/v1/crates/foo/1.0.0/downloads";
+    let json = anon.get::<Value>(url).good();
+    let expected = json["version_downloads"].as_array().unwrap();
+    assert_eq!(expected.len(), 3);
+
+    let parse_lines = |response: crate::util::Response<()>| {
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "application/x-ndjson"
+        );
+
+        let text = response.text();
+        assert!(text.ends_with('\n'));
+        text.lines()
+            .map(|line| serde_json::from_str::<Value>(line).unwrap())
+            .collect::<Vec<_>>()
+    };
+
+    let response = anon.get::<()>("/api/v1/crates/foo/1.0.0/downloads.ndjson");
+    assert_eq!(&parse_lines(response), expected);
+
+    let mut request = anon.get_request(url);
+    request.header(header::ACCEPT, "application/x-ndjson");
+    assert_eq!(&parse_lines(anon.run::<()>(request)), expected);
+
+    let response = anon.get::<()>("/api/v1/crates/foo/2.0.0/downloads.ndjson");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn test_version_downloads_ndjson_flush_markers() {
+    let (app, anon, cookie) = TestApp::init().with_user();
+
+    publish_foo_with_downloads(&app, &cookie, (0..250).map(|days_ago| (days_ago, 1)));
+
+    let response =
+        anon.get_with_query::<()>("/api/v1/crates/foo/1.0.0/downloads.ndjson", "days=365");
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let text = response.text();
+    let lines = text.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 252);
+    let lines = lines
+        .iter()
+        .map(|line| serde_json::from_str::<Value>(line).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(lines[100], json!({ "flushed": 100 }));
+    assert_eq!(lines[201], json!({ "flushed": 200 }));
+
+    let rows = lines.iter().filter(|line| line.get("flushed").is_none());
+    assert_eq!(rows.count(), 250);
+
+    // The CSV export does not include any markers
+    let response = anon.get_with_query::<()>("/api/v1/crates/foo/1.0.0/downloads.csv", "days=365");
+    let text = response.text();
+    assert!(!text.contains("flushed"));
+    assert_eq!(text.lines().count(), 251);
+}
+
+#[test]
+fn test_version_downloads_after_date_is_limited() {
+    let (app, anon, cookie) = TestApp::init().with_user();
+
+    publish_foo_with_downloads(&app, &cookie, [(400, 5), (364, 3), (0, 1)]);
+
+    let after_date = (Utc::now().date_naive() - Duration::days(500)).format("%F");
+    let query = format!("after_date={after_date}");
+
+    let url = "/api/v1/crates/foo/1.0.0/downloads";
+    let downloads: Downloads = anon.get_with_query(url, &query).good();
+    let counts = downloads
+        .version_downloads
+        .iter()
+        .map(|vd| vd.downloads)
+        .collect::<Vec<_>>();
+    assert_eq!(counts, [3, 1]);
+
+    let url = "/api/v1/crates/foo/1.0.0/downloads.csv";
+    let text = anon.get_with_query::<()>(url, &query).text();
+    assert_eq!(text.lines().count(), 3);
+}
+
+#[test]
+fn test_version_downloads_hourly() {
+    let (app, anon, cookie) = TestApp::init().with_user();
+
+    app.db(|conn| {
+        let user_id = cookie.as_model().id;
+        CrateBuilder::new("foo", user_id)
+            .version("1.0.0")
+            .expect_build(conn);
+    });
+
+    let url = "/api/v1/crates/foo/1.0.0/downloads/hourly";
+
+    let response = anon.get::<()>(url);
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_snapshot!(response.text(), @r###"{"version_downloads":[]}"###);
+
+    // Simulate the persisted counts of the CDN log processing
+    let hour = Utc::now().duration_trunc(Duration::hours(1)).unwrap();
+    app.db(|conn| {
+        let version_id = versions::table
+            .select(versions::id)
+            .filter(versions::num.eq("1.0.0"))
+            .first::<i32>(conn)
+            .unwrap();
+
+        for (hours_ago, downloads) in [(60, 7), (5, 2), (1, 3), (0, 1)] {
+            diesel::insert_into(version_downloads_by_hour::table)
+                .values((
+                    version_downloads_by_hour::version_id.eq(version_id),
+                    version_downloads_by_hour::hour
+                        .eq((hour - Duration::hours(hours_ago)).naive_utc()),
+                    version_downloads_by_hour::downloads.eq(downloads),
+                ))
+                .execute(conn)
+                .unwrap();
+        }
+    });
+
+    // Hours outside of the last 48 hours are not included
+    let response = anon.get::<Value>(url);
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let hours = response.json()["version_downloads"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|row| {
+            (
+                row["hour"].as_str().unwrap().to_string(),
+                row["downloads"].as_i64().unwrap(),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let expected = [(5, 2), (1, 3), (0, 1)]
+        .into_iter()
+        .map(|(hours_ago, downloads)| ((hour - Duration::hours(hours_ago)).to_rfc3339(), downloads))
+        .collect::<Vec<_>>();
+
+    assert_eq!(hours, expected);
+
+    let response = anon.get::<()>("/api/v1/crates/foo/2.0.0/downloads/hourly");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn test_version_downloads_recompute() {
+    let (app, anon, user) = TestApp::init().with_user();
+
+    app.db(|conn| {
+        CrateBuilder::new("foo", user.as_model().id)
+            .version(version_with_downloads("1.0.0", [(1, 10), (0, 5)]))
+            .expect_build(conn);
+
+        // Corrupt the cached total
+        diesel::update(versions::table)
+            .set(versions::downloads.eq(999))
+            .execute(conn)
+            .unwrap();
+    });
+
+    let url = "/api/v1/crates/foo/1.0.0/downloads/recompute";
+
+    let response = anon.post::<()>(url, "");
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let response = user.post::<()>(url, "");
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert_snapshot!(response.text(), @r###"{"errors":[{"detail":"must be an admin to recompute download counts"}]}"###);
+
+    let admin = app.db_new_user("admin");
+    app.db(|conn| {
+        diesel::update(admin.as_model())
+            .set(users::is_admin.eq(true))
+            .execute(conn)
+            .unwrap();
+    });
+
+    let response = admin.post::<()>(url, "");
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_snapshot!(response.text(), @r###"{"after":15,"before":999}"###);
+
+    app.db(|conn| {
+        let downloads: i32 = versions::table
+            .select(versions::downloads)
+            .get_result(conn)
+            .unwrap();
+        assert_eq!(downloads, 15);
+
+        // The raw rows are not counted a second time by `update_downloads`
+        let uncounted: i64 = version_downloads::table
+            .filter(version_downloads::counted.ne(version_downloads::downloads))
+            .count()
+            .get_result(conn)
+            .unwrap();
+        assert_eq!(uncounted, 0);
+    });
+
+    let response = admin.post::<()>("/api/v1/crates/foo/2.0.0/downloads/recompute", "");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn test_version_downloads_recompute_crate_total() {
+    let (app, _, user) = TestApp::full().with_user();
+
+    app.db(|conn| {
+        CrateBuilder::new("foo", user.as_model().id)
+            .version(version_with_downloads("1.0.0", [(1, 10), (0, 5)]))
+            .expect_build(conn);
+    });
+
+    let admin = app.db_new_user("admin");
+    app.db(|conn| {
+        diesel::update(admin.as_model())
+            .set(users::is_admin.eq(true))
+            .execute(conn)
+            .unwrap();
+    });
+
+    let totals = || {
+        app.db(|conn| {
+            let version: i32 = versions::table
+                .select(versions::downloads)
+                .get_result(conn)
+                .unwrap();
+            let krate: i64 = crate_downloads::table
+                .select(crate_downloads::downloads)
+                .get_result(conn)
+                .unwrap();
+            let site: i64 = metadata::table
+                .select(metadata::total_downloads)
+                .get_result(conn)
+                .unwrap();
+            (version, krate, site)
+        })
+    };
+
+    let (_, _, site_before) = totals();
+
+    let url = "/api/v1/crates/foo/1.0.0/downloads/recompute";
+    let response = admin.post::<()>(url, "");
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_snapshot!(response.text(), @r###"{"after":15,"before":0}"###);
+
+    // The uncounted downloads are added to the crate and site-wide totals
+    assert_eq!(totals(), (15, 15, site_before + 15));
+
+    // ... and are not added a second time by `update_downloads`
+    app.db(|conn| UpdateDownloads.enqueue(conn).unwrap());
+    app.run_pending_background_jobs();
+    assert_eq!(totals(), (15, 15, site_before + 15));
+}
+
+#[test]
+fn test_version_downloads_pending() {
+    let (app, anon, user) = TestApp::init().with_user();
+
+    app.db(|conn| {
+        CrateBuilder::new("foo", user.as_model().id)
+            .version("1.0.0")
+            .expect_build(conn);
+    });
+
+    let url = "/api/v1/crates/foo/1.0.0/downloads/pending";
+
+    let response = anon.get::<()>(url);
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let response = user.get::<()>(url);
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert_snapshot!(response.text(), @r###"{"errors":[{"detail":"must be an admin to view pending download counts"}]}"###);
+
+    let admin = app.db_new_user("admin");
+    app.db(|conn| {
+        diesel::update(admin.as_model())
+            .set(users::is_admin.eq(true))
+            .execute(conn)
+            .unwrap();
+    });
+
+    let json = admin.get::<Value>(url).good();
+    assert_eq!(json["pending"], 0);
+
+    // Saved downloads are pending until they are counted
+    app.db(|conn| save_version_downloads("foo", "1.0.0", 3, conn));
+    let json = admin.get::<Value>(url).good();
+    assert_eq!(json["pending"], 3);
+
+    // Mimic the `update_downloads` job counting the saved downloads
+    app.db(|conn| {
+        diesel::update(version_downloads::table)
+            .set(version_downloads::counted.eq(version_downloads::downloads))
+            .execute(conn)
+            .unwrap();
+    });
+
+    let json = admin.get::<Value>(url).good();
+    assert_eq!(json["pending"], 0);
+
+    let response = admin.get::<()>("/api/v1/crates/foo/2.0.0/downloads/pending");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn test_version_downloads_sparkline() {
+    let (app, anon, cookie) = TestApp::init().with_user();
+
+    app.db(|conn| {
+        let user_id = cookie.as_model().id;
+        let downloads = [(40, 50), (29, 4), (2, 8), (0, 2)];
+        CrateBuilder::new("foo", user_id)
+            .version(version_with_downloads("1.0.0", downloads))
+            .version("2.0.0")
+            .expect_build(conn);
+    });
+
+    /// Extracts the `(x, y)` coordinates of the single `<polyline>` element
+    /// of the rendered SVG document.
+    fn polyline_points(svg: &str) -> Vec<(f64, f64)> {
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\""));
+        assert!(svg.ends_with("</svg>"));
+        assert_eq!(svg.matches("<polyline ").count(), 1);
+
+        let (_, points) = svg.split_once(r#"points=""#).unwrap();
+        let (points, _) = points.split_once('"').unwrap();
+        points
+            .split(' ')
+            .map(|point| {
+                let (x, y) = point.split_once(',').unwrap();
+                (x.parse().unwrap(), y.parse().unwrap())
+            })
+            .collect()
+    }
+
+    let response = anon.get::<()>("/api/v1/crates/foo/1.0.0/downloads/sparkline.svg");
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers().get(header::CONTENT_TYPE).unwrap(),
+        "image/svg+xml"
+    );
+    assert_eq!(
+        response.headers().get(header::CACHE_CONTROL).unwrap(),
+        "public,max-age=3600"
+    );
+
+    let points = polyline_points(&response.text());
+    assert_eq!(points.len(), 30);
+    assert_eq!(points[0], (0.0, 10.0));
+    assert_eq!(points[27], (93.1, 1.0));
+    assert_eq!(points[29], (100.0, 14.5));
+    assert!(points[1..27].iter().all(|(_, y)| *y == 19.0));
+
+    // Versions without downloads are rendered as a flat baseline
+    let response = anon.get::<()>("/api/v1/crates/foo/2.0.0/downloads/sparkline.svg");
+    assert_eq!(response.status(), StatusCode::OK);
+    let points = polyline_points(&response.text());
+    assert_eq!(points.len(), 30);
+    assert!(points.iter().all(|(_, y)| *y == 19.0));
+
+    let response = anon.get::<()>("/api/v1/crates/foo/3.0.0/downloads/sparkline.svg");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn test_crate_downloads_split_yanked() {
+    let (app, anon, cookie) = TestApp::init().with_user();
+
+    app.db(|conn| {
+        let user_id = cookie.as_model().id;
+        CrateBuilder::new("foo", user_id)
+            .version("1.0.0")
+            .version(VersionBuilder::new("1.1.0").yanked(true))
+            .expect_build(conn);
+
+        save_version_downloads("foo", "1.0.0", 3, conn);
+        save_version_downloads("foo", "1.1.0", 2, conn);
+    });
+
+    let url = "/api/v1/crates/foo/downloads";
+
+    // The totals are only included on request
+    let json: Value = anon.get(url).good();
+    assert_eq!(json["meta"], json!({ "extra_downloads": [] }));
+
+    let json: Value = anon.get_with_query(url, "split_yanked=true").good();
+    assert_eq!(
+        json["meta"],
+        json!({ "extra_downloads": [], "downloads": 3, "yanked_downloads": 2 })
+    );
+    assert_eq!(json["version_downloads"].as_array().unwrap().len(), 2);
+}
+
+#[test]
+fn test_crate_downloads_min_downloads() {
+    let (app, anon, cookie) = TestApp::init().with_user();
+
+    app.db(|conn| {
+        let user_id = cookie.as_model().id;
+        CrateBuilder::new("foo", user_id)
+            .version(version_with_downloads("1.0.0", [(2, 1), (1, 10)]))
+            .version(version_with_downloads("1.1.0", [(0, 4), (1, 5)]))
+            .expect_build(conn);
+    });
+
+    let url = "/api/v1/crates/foo/downloads";
+
+    let json: Value = anon.get(url).good();
+    assert_eq!(json["version_downloads"].as_array().unwrap().len(), 4);
+
+    let json: Value = anon.get_with_query(url, "min_downloads=5").good();
+    let downloads = json["version_downloads"].as_array().unwrap();
+    let downloads = downloads
+        .iter()
+        .map(|download| download["downloads"].as_i64().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(downloads, vec![5, 10]);
+
+    let response = anon.get_with_query::<()>(url, "min_downloads=-1");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_snapshot!(
+        response.text(),
+        @r###"{"errors":[{"detail":"invalid `min_downloads` parameter `-1`, expected a non-negative integer"}]}"###
+    );
+}
+
+#[test]
+fn test_crate_downloads_anomaly() {
+    let (app, anon, cookie) = TestApp::init().with_user();
+
+    app.db(|conn| {
+        let user_id = cookie.as_model().id;
+
+        // A baseline with a mean of 11 and a standard deviation of 1, followed
+        // by a clear spike on the latest finalized day. Today's downloads are
+        // incomplete and not taken into account.
+        let baseline = (2..=31).map(|days_ago| (days_ago, 10 + 2 * (days_ago as i32 % 2)));
+        let downloads = baseline.chain([(1, 100), (0, 5000)]);
+        CrateBuilder::new("foo", user_id)
+            .version(version_with_downloads("1.0.0", downloads))
+            .expect_build(conn);
+
+        // Not enough data for a meaningful score
+        let downloads = (1..=5).map(|days_ago| (days_ago, 10));
+        CrateBuilder::new("bar", user_id)
+            .version(version_with_downloads("1.0.0", downloads))
+            .expect_build(conn);
+    });
+
+    let json: Value = anon.get("/api/v1/crates/foo/downloads").good();
+    assert_eq!(json["meta"].get("anomaly_score"), None);
+
+    let json: Value = anon
+        .get_with_query("/api/v1/crates/foo/downloads", "anomaly=true")
+        .good();
+    let score = json["meta"]["anomaly_score"].as_f64().unwrap();
+    assert!((score - 89.0).abs() < 1e-9, "{score}");
+
+    let json: Value = anon
+        .get_with_query("/api/v1/crates/bar/downloads", "anomaly=true")
+        .good();
+    assert_eq!(json["meta"].get("anomaly_score"), Some(&Value::Null));
+}
+
+#[test]
+fn test_crate_downloads_version_req() {
+    let (app, anon, cookie) = TestApp::init().with_user();
+
+    app.db(|conn| {
+        let user_id = cookie.as_model().id;
+        CrateBuilder::new("foo", user_id)
+            .version(version_with_downloads("1.0.0", [(1, 1), (0, 2)]))
+            .version(version_with_downloads("1.1.0", [(0, 3)]))
+            .version(version_with_downloads("2.0.0", [(0, 100)]))
+            .expect_build(conn);
+    });
+
+    let url = "/api/v1/crates/foo/downloads";
+    let today = Utc::now().date_naive();
+    let yesterday = today - Duration::days(1);
+
+    // Downloads of all matching versions are summed up per day
+    let json: Value = anon.get_with_query(url, "req=%5E1.0").good();
+    assert_eq!(
+        json,
+        json!({
+            "req": "^1.0",
+            "downloads": [
+                { "date": yesterday.to_string(), "downloads": 1 },
+                { "date": today.to_string(), "downloads": 5 },
+            ],
+        })
+    );
+
+    let json: Value = anon.get_with_query(url, "req=%5E3").good();
+    assert_eq!(json, json!({ "req": "^3", "downloads": [] }));
+
+    let response = anon.get_with_query::<()>(url, "req=not-a-req");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_snapshot!(
+        response.text(),
+        @r###"{"errors":[{"detail":"invalid `req` parameter `not-a-req`, expected a semver requirement"}]}"###
+    );
+}
+
+#[test]
+fn test_crate_downloads_by_version() {
+    let (app, anon, cookie) = TestApp::init().with_user();
+
+    app.db(|conn| {
+        let user_id = cookie.as_model().id;
+        CrateBuilder::new("foo", user_id)
+            .version(version_with_downloads("1.0.0", [(0, 2), (1, 3)]))
+            .version(version_with_downloads("1.1.0", [(0, 8)]))
+            // Downloads outside of the 90 day window are ignored
+            .version(version_with_downloads("1.2.0", [(100, 100)]))
+            .expect_build(conn);
+    });
+
+    // Versions are ordered by the number of downloads
+    let response = anon.get::<()>("/api/v1/crates/foo/downloads/by_version");
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_snapshot!(response.text(), @r###"{"versions":{"1.1.0":8,"1.0.0":5}}"###);
+
+    let response = anon.get::<()>("/api/v1/crates/bar/downloads/by_version");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_snapshot!(
+        response.text(),
+        @r###"{"errors":[{"detail":"crate `bar` does not exist"}]}"###
+    );
+}
+
+#[test]
+fn test_crate_downloads_prometheus_format() {
+    let (app, anon, cookie) = TestApp::init().with_user();
+
+    app.db(|conn| {
+        let user_id = cookie.as_model().id;
+        CrateBuilder::new("foo", user_id)
+            .version("1.0.0")
+            .version(VersionBuilder::new("1.1.0").yanked(true))
+            .expect_build(conn);
+
+        save_version_downloads("foo", "1.0.0", 3, conn);
+        save_version_downloads("foo", "1.1.0", 2, conn);
+    });
+
+    let url = "/api/v1/crates/foo/downloads";
+
+    let response = anon.get_with_query::<()>(url, "format=prometheus");
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()[header::CONTENT_TYPE],
+        "text/plain; version=0.0.4"
+    );
+    assert_eq!(response.text(), "crate_downloads_total{crate=\"foo\"} 5\n");
+
+    // The label contains the canonical name of the crate
+    let response = anon.get_with_query::<()>("/api/v1/crates/FOO/downloads", "format=prometheus");
+    assert_eq!(response.text(), "crate_downloads_total{crate=\"foo\"} 5\n");
+
+    // JSON is still returned by default
+    let json: Value = anon.get(url).good();
+    assert_eq!(json["version_downloads"].as_array().unwrap().len(), 2);
+
+    let response = anon.get_with_query::<()>(url, "format=xml");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_snapshot!(
+        response.text(),
+        @r###"{"errors":[{"detail":"invalid `format` parameter `xml`, expected `json` or `prometheus`"}]}"###
+    );
+}
+
+#[test]
+fn test_crate_downloads_matrix() {
+    let (app, anon, cookie) = TestApp::init().with_user();
+
+    app.db(|conn| {
+        let user_id = cookie.as_model().id;
+        // Downloads outside of the 90 day window are ignored
+        CrateBuilder::new("foo", user_id)
+            .version(version_with_downloads("1.0.0", [(89, 3), (0, 1)]))
+            .version(version_with_downloads("1.1.0", [(1, 7), (90, 100)]))
+            .expect_build(conn);
+    });
+
+    let matrix: DownloadsMatrix = anon.get("/api/v1/crates/foo/downloads/matrix").good();
+
+    let today = Utc::now().date_naive();
+    assert_eq!(matrix.dates.len(), 90);
+    assert_eq!(matrix.dates[0], (today - Duration::days(89)).to_string());
+    assert_eq!(matrix.dates[89], today.to_string());
+
+    // Versions are ordered newest first
+    let versions = matrix.versions.keys().collect::<Vec<_>>();
+    assert_eq!(versions, vec!["1.1.0", "1.0.0"]);
+
+    let mut expected = vec![0; 90];
+    expected[0] = 3;
+    expected[89] = 1;
+    assert_eq!(matrix.versions["1.0.0"], expected);
+
+    let mut expected = vec![0; 90];
+    expected[88] = 7;
+    assert_eq!(matrix.versions["1.1.0"], expected);
+}
+
+#[test]
+fn test_crate_compare_downloads() {
+    let (app, anon, cookie) = TestApp::init().with_user();
+
+    app.db(|conn| {
+        let user_id = cookie.as_model().id;
+        // Downloads outside of the 90 day window are ignored
+        CrateBuilder::new("foo", user_id)
+            .version(version_with_downloads("1.0.0", [(89, 3), (1, 5)]))
+            .version(version_with_downloads("2.0.0", [(1, 8), (0, 2), (90, 100)]))
+            .expect_build(conn);
+    });
+
+    let url = "/api/v1/crates/foo/compare-downloads";
+    let comparison: DownloadsComparison = anon.get_with_query(url, "a=1.0.0&b=2.0.0").good();
+
+    let today = Utc::now().date_naive();
+    assert_eq!(comparison.dates.len(), 90);
+    assert_eq!(
+        comparison.dates[0],
+        (today - Duration::days(89)).to_string()
+    );
+    assert_eq!(comparison.dates[89], today.to_string());
+
+    let mut expected = vec![0; 90];
+    expected[0] = 3;
+    expected[88] = 5;
+    assert_eq!(comparison.a.version, "1.0.0");
+    assert_eq!(comparison.a.downloads, expected);
+    assert_eq!(comparison.a.total, 8);
+
+    let mut expected = vec![0; 90];
+    expected[88] = 8;
+    expected[89] = 2;
+    assert_eq!(comparison.b.version, "2.0.0");
+    assert_eq!(comparison.b.downloads, expected);
+    assert_eq!(comparison.b.total, 10);
+
+    let response = anon.get_with_query::<()>(url, "a=1.0.0&b=3.0.0");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_snapshot!(
+        response.text(),
+        @r###"{"errors":[{"detail":"crate `foo` does not have a version `3.0.0`"}]}"###
+    );
+
+    let response = anon.get_with_query::<()>(url, "a=0.1.0&b=2.0.0");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_snapshot!(
+        response.text(),
+        @r###"{"errors":[{"detail":"crate `foo` does not have a version `0.1.0`"}]}"###
+    );
+
+    let response = anon.get_with_query::<()>(url, "a=1.0.0");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_snapshot!(
+        response.text(),
+        @r###"{"errors":[{"detail":"missing `b` parameter"}]}"###
+    );
+
+    let url = "/api/v1/crates/bar/compare-downloads";
+    let response = anon.get_with_query::<()>(url, "a=1.0.0&b=2.0.0");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn test_crate_downloads_on_date() {
+    let (app, anon, cookie) = TestApp::init().with_user();
+
+    let today = Utc::now().date_naive();
+    let date = today - Duration::days(3);
+
+    app.db(|conn| {
+        let user_id = cookie.as_model().id;
+        // Downloads on other days are ignored
+        CrateBuilder::new("foo", user_id)
+            .version(version_with_downloads("1.0.0", [(3, 2), (2, 5)]))
+            .version(version_with_downloads("1.1.0", [(3, 7)]))
+            .version(version_with_downloads("1.2.0", [(4, 1)]))
+            .expect_build(conn);
+    });
+
+    let url = format!("/api/v1/crates/foo/downloads/on/{date}");
+    let json: Value = anon.get(&url).good();
+    assert_eq!(
+        json,
+        json!({
+            "date": date.to_string(),
+            "versions": [
+                { "version": "1.1.0", "downloads": 7 },
+                { "version": "1.0.0", "downloads": 2 },
+            ],
+        })
+    );
+
+    // Dates outside of the retention window are rejected
+    let date = today - Duration::days(90);
+    let url = format!("/api/v1/crates/foo/downloads/on/{date}");
+    let response = anon.get::<()>(&url);
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_snapshot!(
+        response.text(),
+        @r###"{"errors":[{"detail":"downloads are only available for the last 90 days"}]}"###
+    );
+
+    let date = today + Duration::days(1);
+    let url = format!("/api/v1/crates/foo/downloads/on/{date}");
+    let response = anon.get::<()>(&url);
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let response = anon.get::<()>("/api/v1/crates/foo/downloads/on/yesterday");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_snapshot!(
+        response.text(),
+        @r###"{"errors":[{"detail":"invalid date `yesterday`, expected a date in `YYYY-MM-DD` format"}]}"###
+    );
+}
+
+#[test]
+fn test_crate_downloads_rank() {
+    let (app, anon, cookie) = TestApp::init().with_user();
+
+    app.db(|conn| {
+        let user_id = cookie.as_model().id;
+
+        CrateBuilder::new("large", user_id)
+            .version(version_with_downloads("1.0.0", [(1, 100)]))
+            .version("1.1.0")
+            .expect_build(conn);
+
+        // Downloads of all versions are summed up
+        CrateBuilder::new("medium", user_id)
+            .version(version_with_downloads("1.0.0", [(0, 15)]))
+            .version(version_with_downloads("1.1.0", [(6, 5)]))
+            .expect_build(conn);
+
+        // Downloads outside of the window are ignored
+        CrateBuilder::new("small", user_id)
+            .version(version_with_downloads("1.0.0", [(2, 5), (20, 500)]))
+            .version("1.1.0")
+            .expect_build(conn);
+
+        CrateBuilder::new("idle", user_id)
+            .version("1.0.0")
+            .version("1.1.0")
+            .expect_build(conn);
+    });
+
+    let rank = |name: &str, query: &str| -> Value {
+        let url = format!("/api/v1/crates/{name}/downloads/rank");
+        anon.get_with_query(&url, query).good()
+    };
+
+    let expected = json!({ "rank": 1, "total_crates": 4, "downloads": 100, "window": 7 });
+    assert_eq!(rank("large", ""), expected);
+    let expected = json!({ "rank": 2, "total_crates": 4, "downloads": 20, "window": 7 });
+    assert_eq!(rank("medium", "window=7"), expected);
+    let expected = json!({ "rank": 3, "total_crates": 4, "downloads": 5, "window": 7 });
+    assert_eq!(rank("small", ""), expected);
+    let expected = json!({ "rank": 4, "total_crates": 4, "downloads": 0, "window": 7 });
+    assert_eq!(rank("idle", ""), expected);
+
+    // Larger windows include the older downloads
+    let expected = json!({ "rank": 1, "total_crates": 4, "downloads": 505, "window": 30 });
+    assert_eq!(rank("small", "window=30"), expected);
+    let expected = json!({ "rank": 2, "total_crates": 4, "downloads": 100, "window": 90 });
+    assert_eq!(rank("large", "window=1000"), expected);
+
+    // Other windows are rounded up to one of the fixed windows
+    let expected = json!({ "rank": 3, "total_crates": 4, "downloads": 5, "window": 7 });
+    assert_eq!(rank("small", "window=3"), expected);
+    let expected = json!({ "rank": 1, "total_crates": 4, "downloads": 505, "window": 30 });
+    assert_eq!(rank("small", "window=10"), expected);
+
+    let response = anon.get_with_query::<()>("/api/v1/crates/large/downloads/rank", "window=0");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_snapshot!(
+        response.text(),
+        @r###"{"errors":[{"detail":"invalid `window` parameter `0`, expected a positive integer"}]}"###
+    );
+
+    let response = anon.get::<()>("/api/v1/crates/missing/downloads/rank");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn test_crate_downloads_trend() {
+    let (app, anon, cookie) = TestApp::init().with_user();
+
+    app.db(|conn| {
+        let user_id = cookie.as_model().id;
+
+        // Downloads of all versions are summed up
+        CrateBuilder::new("growth", user_id)
+            .version(version_with_downloads(
+                "1.0.0",
+                [(0, 10), (7, 10), (14, 100)],
+            ))
+            .version(version_with_downloads("1.1.0", [(6, 5)]))
+            .expect_build(conn);
+
+        CrateBuilder::new("decline", user_id)
+            .version(version_with_downloads("1.0.0", [(3, 5)]))
+            .version(version_with_downloads("1.1.0", [(13, 20)]))
+            .expect_build(conn);
+
+        CrateBuilder::new("fresh", user_id)
+            .version("1.0.0")
+            .version(version_with_downloads("1.1.0", [(1, 42)]))
+            .expect_build(conn);
+    });
+
+    let json: Value = anon.get("/api/v1/crates/growth/downloads/trend").good();
+    assert_eq!(
+        json,
+        json!({ "current_7d": 15, "previous_7d": 10, "pct_change": 50.0 })
+    );
+
+    let json: Value = anon.get("/api/v1/crates/decline/downloads/trend").good();
+    assert_eq!(
+        json,
+        json!({ "current_7d": 5, "previous_7d": 20, "pct_change": -75.0 })
+    );
+
+    let json: Value = anon.get("/api/v1/crates/fresh/downloads/trend").good();
+    assert_eq!(
+        json,
+        json!({ "current_7d": 42, "previous_7d": 0, "pct_change": null })
+    );
+
+    let response = anon.get::<()>("/api/v1/crates/missing/downloads/trend");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn test_version_downloads_missing_version() {
+    let (app, anon, cookie) = TestApp::init().with_user();
+
+    app.db(|conn| {
+        let user_id = cookie.as_model().id;
+        CrateBuilder::new("foo", user_id)
+            .version("1.0.0")
+            .version("1.2.0")
+            .version(VersionBuilder::new("1.10.0").yanked(true))
+            .version("2.0.0-beta.1")
+            .expect_build(conn);
+
+        CrateBuilder::new("unstable", user_id)
+            .version("0.1.0-alpha.1")
+            .version(VersionBuilder::new("0.1.0").yanked(true))
+            .expect_build(conn);
+
+        CrateBuilder::new("bar", user_id)
+            .version("0.9.0")
+            .version("1.0.0")
+            .version("1.0.1")
+            .version("1.1.0")
+            .version("2.0.0")
+            .version("3.0.0")
+            .expect_build(conn);
+    });
+
+    // Yanked versions and prereleases are not used as a hint
+    let response = anon.get::<()>("/api/v1/crates/foo/1.1.0/downloads");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_snapshot!(
+        response.text(),
+        @r###"{"errors":[{"detail":"crate `foo` does not have a version `1.1.0`. The latest stable version is `1.2.0`."}]}"###
+    );
+
+    // Without any stable versions there is no hint
+    let response = anon.get::<()>("/api/v1/crates/unstable/1.0.0/downloads");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_snapshot!(
+        response.text(),
+        @r###"{"errors":[{"detail":"crate `unstable` does not have a version `1.0.0`"}]}"###
+    );
+
+    let response = anon.get::<()>("/api/v1/crates/missing/1.0.0/downloads");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_snapshot!(
+        response.text(),
+        @r###"{"errors":[{"detail":"crate `missing` does not exist"}]}"###
+    );
+
+    // Versions that can't be parsed get the most similar versions as a hint
+    let response = anon.get::<()>("/api/v1/crates/bar/1.2/downloads");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_snapshot!(
+        response.text(),
+        @r###"{"errors":[{"detail":"crate `bar` does not have a version `1.2`. Did you mean one of: `1.1.0`, `1.0.0`, `1.0.1`, `2.0.0`, `0.9.0`?"}]}"###
+    );
+
+    let response = anon.get::<()>("/api/v1/crates/bar/v4/downloads");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_snapshot!(
+        response.text(),
+        @r###"{"errors":[{"detail":"crate `bar` does not have a version `v4`. Did you mean one of: `3.0.0`, `2.0.0`, `1.0.0`, `1.0.1`, `1.1.0`?"}]}"###
+    );
+
+    // Without a crate there is nothing to suggest
+    let response = anon.get::<()>("/api/v1/crates/missing/1.0/downloads");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_snapshot!(
+        response.text(),
+        @r###"{"errors":[{"detail":"crate `missing` does not have a version `1.0`"}]}"###
+    );
+}
+
+#[test]
+fn test_version_downloads_localized_errors() {
+    let (app, anon, cookie) = TestApp::init().with_user();
+
+    app.db(|conn| {
+        let user_id = cookie.as_model().id;
+        CrateBuilder::new("foo", user_id)
+            .version("1.0.0")
+            .version("1.2.0")
+            .expect_build(conn);
+    });
+
+    let get = |url: &str, accept_language: Option<&str>| {
+        let mut request = anon.get_request(url);
+        if let Some(accept_language) = accept_language {
+            request.header(header::ACCEPT_LANGUAGE, accept_language);
+        }
+        let response = anon.run::<()>(request);
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        response.text()
+    };
+
+    // English is used by default and for unsupported languages
+    let url = "/api/v1/crates/foo/1.1.0/downloads";
+    let expected = r#"{"errors":[{"detail":"crate `foo` does not have a version `1.1.0`. The latest stable version is `1.2.0`."}]}"#;
+    assert_eq!(get(url, None), expected);
+    assert_eq!(get(url, Some("ja, ko;q=0.5")), expected);
+
+    assert_snapshot!(
+        get(url, Some("de-DE, en;q=0.8")),
+        @r###"{"errors":[{"detail":"Crate `foo` hat keine Version `1.1.0`. Die neueste stabile Version ist `1.2.0`."}]}"###
+    );
+    assert_snapshot!(
+        get("/api/v1/crates/foo/1.2/downloads/total", Some("de")),
+        @r###"{"errors":[{"detail":"Crate `foo` hat keine Version `1.2`. Meinten Sie eine von: `1.2.0`, `1.0.0`?"}]}"###
+    );
+    assert_snapshot!(
+        get("/api/v1/crates/missing/1.0.0/downloads", Some("de")),
+        @r###"{"errors":[{"detail":"Crate `missing` existiert nicht"}]}"###
+    );
+}
+
+#[test]
+fn test_version_downloads_compression() {
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    let (app, anon, cookie) = TestApp::init().with_user();
+
+    publish_foo_with_downloads(&app, &cookie, (0..30).map(|days_ago| (days_ago, 1)));
+
+    let url = "/api/v1/crates/foo/1.0.0/downloads";
+
+    let get_with_encoding = |encoding: &str| {
+        let mut request = anon.get_request(url);
+        request.header(header::ACCEPT_ENCODING, encoding);
+        let response = anon.run::<()>(request);
+        assert_eq!(response.status(), StatusCode::OK);
+        response
+    };
+
+    let uncompressed = anon.get::<()>(url);
+    assert_none!(uncompressed.headers().get(header::CONTENT_ENCODING));
+    let uncompressed = uncompressed.body().to_vec();
+
+    let response = get_with_encoding("zstd");
+    assert_eq!(response.headers()[header::CONTENT_ENCODING], "zstd");
+    assert_eq!(response.headers()[header::CONTENT_TYPE], "application/json");
+    let decompressed = zstd::decode_all(&response.body()[..]).unwrap();
+    assert_eq!(decompressed, uncompressed);
+
+    let response = get_with_encoding("gzip");
+    assert_eq!(response.headers()[header::CONTENT_ENCODING], "gzip");
+    let mut decompressed = Vec::new();
+    let mut decoder = GzDecoder::new(&response.body()[..]);
+    decoder.read_to_end(&mut decompressed).unwrap();
+    assert_eq!(decompressed, uncompressed);
+}
+
+#[test]
+fn test_batch_crate_downloads() {
+    let (app, anon, cookie) = TestApp::init().with_user();
+
+    app.db(|conn| {
+        let user_id = cookie.as_model().id;
+        CrateBuilder::new("foo", user_id)
+            .version("1.0.0")
+            .expect_build(conn);
+        CrateBuilder::new("bar", user_id)
+            .version("2.0.0")
+            .expect_build(conn);
+
+        save_version_downloads("foo", "1.0.0", 3, conn);
+        save_version_downloads("bar", "2.0.0", 5, conn);
+    });
+
+    let body = json!({ "crates": ["foo", "missing", "bar"] });
+    let response = anon.post::<()>("/api/v1/crates/downloads", body.to_string());
+    assert_eq!(response.status(), StatusCode::OK);
+    let json = response.json();
+
+    // Each crate gets the same data as the single crate endpoint
+    let foo_downloads = anon.get::<()>("/api/v1/crates/foo/downloads").json();
+    assert_eq!(json["crates"]["foo"], foo_downloads);
+    let bar_downloads = anon.get::<()>("/api/v1/crates/bar/downloads").json();
+    assert_eq!(json["crates"]["bar"], bar_downloads);
+
+    assert_eq!(
+        json["crates"]["missing"],
+        json!({ "errors": [{ "detail": "crate `missing` does not exist" }] })
+    );
+
+    let crates = (0..51).map(|i| format!("crate-{i}")).collect::<Vec<_>>();
+    let body = json!({ "crates": crates });
+    let response = anon.post::<()>("/api/v1/crates/downloads", body.to_string());
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_snapshot!(
+        response.text(),
+        @r###"{"errors":[{"detail":"too many crates requested, the maximum is 50"}]}"###
+    );
+
+    // `GET` requests are still routed to the metadata of a `downloads` crate
+    let response = anon.get::<()>("/api/v1/crates/downloads");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_snapshot!(
+        response.text(),
+        @r###"{"errors":[{"detail":"crate `downloads` does not exist"}]}"###
+    );
+}
+
+#[test]
+fn test_crate_downloads_by_edition() {
+    let (app, anon, cookie) = TestApp::init().with_user();
+
+    app.db(|conn| {
+        let user_id = cookie.as_model().id;
+        // The 45 days old downloads are outside of the default window
+        CrateBuilder::new("foo", user_id)
+            .version(version_with_downloads("1.0.0", [(0, 5), (45, 100)]).edition("2018"))
+            .version(version_with_downloads("2.0.0", [(3, 10)]).edition("2021"))
+            .expect_build(conn);
+        CrateBuilder::new("bar", user_id)
+            .version(version_with_downloads("1.0.0", [(0, 20)]).edition("2021"))
+            .version(version_with_downloads("2.0.0", [(0, 1)]))
+            .expect_build(conn);
+    });
+
+    let url = "/api/v1/crates/downloads/by_edition";
+    let json: Value = anon.get(url).good();
+    assert_eq!(
+        json,
+        json!({
+            "editions": { "2015": 0, "2018": 5, "2021": 30, "2024": 0 },
+            "unknown": 1,
+            "meta": { "window": 30 },
+        })
+    );
+
+    let json: Value = anon.get_with_query(url, "window=2").good();
+    assert_eq!(json["editions"]["2021"], 20);
+    assert_eq!(json["meta"]["window"], 2);
+
+    // Windows larger than the cap are clamped
+    let json: Value = anon.get_with_query(url, "window=365").good();
+    assert_eq!(json["editions"]["2018"], 105);
+    assert_eq!(json["meta"]["window"], 90);
+
+    let response = anon.get_with_query::<()>(url, "window=-1");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    // The sums are cached per window
+    app.db(|conn| {
+        CrateBuilder::new("baz", cookie.as_model().id)
+            .version(version_with_downloads("1.0.0", [(0, 7)]).edition("2024"))
+            .expect_build(conn);
+    });
+    let json: Value = anon.get(url).good();
+    assert_eq!(json["editions"]["2024"], 0);
+    let json: Value = anon.get_with_query(url, "window=3").good();
+    assert_eq!(json["editions"]["2024"], 7);
+}
+
+#[test]
+fn test_crate_downloads_version_spread() {
+    let (app, anon, cookie) = TestApp::init().with_user();
+
+    let today = Utc::now().date_naive();
+    app.db(|conn| {
+        let user_id = cookie.as_model().id;
+        // The 90 days old downloads are outside of the window
+        CrateBuilder::new("foo", user_id)
+            .version(version_with_downloads("1.0.0", [(2, 5), (1, 3), (0, 1)]))
+            .version(version_with_downloads("1.1.0", [(1, 4), (0, 2)]))
+            .version(version_with_downloads("2.0.0", [(0, 9), (90, 7)]))
+            .expect_build(conn);
+
+        // Downloads of other crates are not included
+        CrateBuilder::new("bar", user_id)
+            .version(version_with_downloads("1.0.0", [(2, 7)]))
+            .expect_build(conn);
+    });
+
+    let json: Value = anon
+        .get("/api/v1/crates/foo/downloads/version-spread")
+        .good();
+    assert_eq!(
+        json,
+        json!({
+            "version_spread": [
+                { "date": (today - Duration::days(2)).to_string(), "versions": 1 },
+                { "date": (today - Duration::days(1)).to_string(), "versions": 2 },
+                { "date": today.to_string(), "versions": 3 },
+            ],
+            "meta": { "days": 90 },
+        })
+    );
+
+    let response = anon.get::<()>("/api/v1/crates/missing/downloads/version-spread");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn test_crate_downloads_by_major() {
+    let (app, anon, cookie) = TestApp::init().with_user();
+
+    app.db(|conn| {
+        CrateBuilder::new("foo", cookie.as_model().id)
+            // Outside of the default window
+            .version(version_with_downloads("0.9.0", [(100, 7)]))
+            .version(version_with_downloads("1.0.0", [(1, 5), (0, 3)]))
+            .version(version_with_downloads("1.2.0", [(0, 4)]))
+            .version(version_with_downloads("2.0.0", [(0, 9)]))
+            .expect_build(conn);
+    });
+
+    let json: Value = anon.get("/api/v1/crates/foo/downloads/by_major").good();
+    assert_eq!(
+        json,
+        json!({
+            "majors": [
+                { "major": 1, "downloads": 12 },
+                { "major": 2, "downloads": 9 },
+            ],
+            "meta": { "window": 90 },
+        })
+    );
+
+    let json: Value = anon
+        .get_with_query("/api/v1/crates/foo/downloads/by_major", "window=1")
+        .good();
+    assert_eq!(
+        json["majors"],
+        json!([
+            { "major": 1, "downloads": 7 },
+            { "major": 2, "downloads": 9 },
+        ])
+    );
+
+    // Pre-1.0 versions are grouped as major version zero
+    let json: Value = anon
+        .get_with_query("/api/v1/crates/foo/downloads/by_major", "window=365")
+        .good();
+    assert_eq!(json["majors"][0], json!({ "major": 0, "downloads": 7 }));
+
+    let response = anon.get_with_query::<()>("/api/v1/crates/foo/downloads/by_major", "window=0");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = anon.get::<()>("/api/v1/crates/missing/downloads/by_major");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn test_crate_downloads_effective() {
+    let (app, anon, cookie) = TestApp::init().with_user();
+
+    app.db(|conn| {
+        let user_id = cookie.as_model().id;
+        let krate = CrateBuilder::new("foo", user_id)
+            .version(version_with_downloads("1.0.0", [(0, 10)]))
+            .expect_build(conn);
+
+        // Downloads outside of the window are not included
+        CrateBuilder::new("bar", user_id)
+            .version(version_with_downloads("1.0.0", [(0, 5), (90, 1000)]).dependency(&krate, None))
+            .expect_build(conn);
+        CrateBuilder::new("baz", user_id)
+            .version(version_with_downloads("1.0.0", [(0, 2)]).dependency(&krate, None))
+            .version(version_with_downloads("1.1.0", [(1, 3)]).dependency(&krate, None))
+            .expect_build(conn);
+        CrateBuilder::new("unrelated", user_id)
+            .version(version_with_downloads("1.0.0", [(0, 100)]))
+            .expect_build(conn);
+
+        // Dev-dependencies are not included
+        let dev = CrateBuilder::new("dev", user_id)
+            .version(version_with_downloads("1.0.0", [(0, 50)]).dependency(&krate, None))
+            .expect_build(conn);
+        let dev_versions = versions::table
+            .filter(versions::crate_id.eq(dev.id))
+            .select(versions::id);
+        diesel::update(dependencies::table)
+            .filter(dependencies::version_id.eq_any(dev_versions))
+            .set(dependencies::kind.eq(DependencyKind::Dev))
+            .execute(conn)
+            .unwrap();
+    });
+
+    let json: Value = anon.get("/api/v1/crates/foo/downloads/effective").good();
+    assert_eq!(
+        json,
+        json!({
+            "direct_downloads": 10,
+            "effective_downloads": 20,
+            "meta": { "days": 90, "depth": 1, "dependents": 2, "dependents_capped": false },
+        })
+    );
+
+    // Crates without dependents have the same direct and effective downloads
+    let json: Value = anon.get("/api/v1/crates/bar/downloads/effective").good();
+    assert_eq!(json["direct_downloads"], 5);
+    assert_eq!(json["effective_downloads"], 5);
+
+    let response = anon.get::<()>("/api/v1/crates/missing/downloads/effective");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn test_crate_downloads_by_feature() {
+    let (app, anon, cookie) = TestApp::init().with_user();
+
+    app.db(|conn| {
+        let user_id = cookie.as_model().id;
+        // The 90 days old downloads are outside of the window
+        let v2 = version_with_downloads("2.0.0", [(1, 4), (90, 1000)]);
+        CrateBuilder::new("foo", user_id)
+            .version(version_with_downloads("1.0.0", [(0, 100)]))
+            .version(version_with_downloads("1.1.0", [(0, 3)]).feature("serde", &[]))
+            .version(v2.feature("serde", &["dep:serde"]))
+            .expect_build(conn);
+    });
+
+    let json: Value = anon
+        .get("/api/v1/crates/foo/downloads/by_feature/serde")
+        .good();
+    assert_eq!(
+        json,
+        json!({
+            "feature": "serde",
+            "downloads": 7,
+            "versions": ["2.0.0", "1.1.0"],
+            "meta": { "days": 90 },
+        })
+    );
+
+    let json: Value = anon
+        .get("/api/v1/crates/foo/downloads/by_feature/std")
+        .good();
+    assert_eq!(json["downloads"], 0);
+    assert_eq!(json["versions"], json!([]));
+
+    let response = anon.get::<()>("/api/v1/crates/missing/downloads/by_feature/serde");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
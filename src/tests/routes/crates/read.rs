@@ -144,3 +144,143 @@ fn test_new_name() {
         ".crate.updated_at" => "[datetime]",
     });
 }
+
+#[test]
+fn summary() {
+    use crate::routes::crates::downloads::save_version_downloads_on;
+    use chrono::{Duration, Utc};
+    use serde_json::Value;
+
+    let (app, anon, user) = TestApp::init().with_user();
+
+    app.db(|conn| {
+        CrateBuilder::new("foo_summary", user.as_model().id)
+            .version("1.0.0")
+            .version("1.1.0")
+            .version("2.0.0")
+            .expect_build(conn);
+
+        let today = Utc::now().date_naive();
+        for (version, num_downloads, days_ago) in [
+            ("1.0.0", 3, 0),
+            ("1.0.0", 4, 89),
+            ("1.0.0", 100, 90),
+            ("2.0.0", 5, 1),
+        ] {
+            let date = today - Duration::days(days_ago);
+            save_version_downloads_on("foo_summary", version, num_downloads, date, conn);
+        }
+    });
+
+    let json = anon
+        .get::<Value>("/api/v1/crates/foo_summary/summary")
+        .json();
+    assert_eq!(json["crate"]["name"], "foo_summary");
+    assert_eq!(json["meta"]["days"], 90);
+
+    let versions = json["versions"].as_array().unwrap();
+    let nums = versions.iter().map(|v| v["num"].as_str().unwrap());
+    assert_eq!(nums.collect::<Vec<_>>(), ["2.0.0", "1.1.0", "1.0.0"]);
+
+    let version_downloads = json["version_downloads"].as_array().unwrap();
+    assert_eq!(version_downloads.len(), versions.len());
+
+    // The totals match the ones of the per-version `downloads` endpoint
+    for (version, downloads) in versions.iter().zip(version_downloads) {
+        assert_eq!(downloads["version"], version["id"]);
+
+        let num = version["num"].as_str().unwrap();
+        let url = format!("/api/v1/crates/foo_summary/{num}/downloads");
+        let expected = anon.get::<Value>(&url).json();
+        assert_eq!(
+            downloads["downloads"], expected["meta"]["total_downloads"],
+            "{num}"
+        );
+    }
+
+    let totals = version_downloads.iter().map(|d| d["downloads"].as_i64());
+    assert_eq!(totals.collect::<Vec<_>>(), [Some(5), Some(0), Some(7)]);
+
+    let response = anon.get::<()>("/api/v1/crates/missing/summary");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn health() {
+    use crate::routes::crates::downloads::save_version_downloads_on;
+    use chrono::{Duration, Utc};
+    use serde_json::Value;
+
+    let (app, anon, user) = TestApp::init().with_user();
+
+    app.db(|conn| {
+        let now = Utc::now().naive_utc();
+        CrateBuilder::new("foo_health", user.as_model().id)
+            .version(
+                VersionBuilder::new("1.0.0")
+                    .created_at(now - Duration::days(20))
+                    .yanked(true),
+            )
+            .version(VersionBuilder::new("1.1.0").created_at(now - Duration::days(5)))
+            .expect_build(conn);
+
+        let today = now.date();
+        save_version_downloads_on("foo_health", "1.0.0", 3, today, conn);
+        save_version_downloads_on("foo_health", "1.1.0", 4, today - Duration::days(1), conn);
+
+        // Outside of the window
+        let date = today - Duration::days(90);
+        save_version_downloads_on("foo_health", "1.1.0", 100, date, conn);
+    });
+
+    let json = anon.get::<Value>("/api/v1/crates/foo_health/health").json();
+    assert_eq!(
+        json,
+        json!({
+            "recent_downloads": 7,
+            "yanked_version_ratio": 0.5,
+            "days_since_last_publish": 5,
+            "version_count": 2,
+            "meta": { "days": 90 },
+        })
+    );
+
+    let response = anon.get::<()>("/api/v1/crates/missing/health");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn show_normalized() {
+    use chrono::{Duration, Utc};
+    use serde_json::Value;
+
+    let (app, anon, user) = TestApp::init().with_user();
+    let user = user.as_model();
+
+    app.db(|conn| {
+        let now = Utc::now().naive_utc();
+        CrateBuilder::new("foo_normalized", user.id)
+            .version(VersionBuilder::new("1.0.0").created_at(now - Duration::days(10)))
+            .version(VersionBuilder::new("1.1.0").created_at(now - Duration::days(4)))
+            .downloads(50)
+            .expect_build(conn);
+
+        CrateBuilder::new("foo_new", user.id)
+            .version("1.0.0")
+            .downloads(20)
+            .expect_build(conn);
+    });
+
+    let json = anon.get::<Value>("/api/v1/crates/foo_normalized").json();
+    assert_eq!(json["crate"].get("downloads_per_day_since_publish"), None);
+
+    let url = "/api/v1/crates/foo_normalized?normalized=true";
+    let json = anon.get::<Value>(url).json();
+    assert_eq!(json["crate"]["downloads_per_day_since_publish"], 5.0);
+
+    // Crates published today count as one day old
+    let json = anon
+        .get::<Value>("/api/v1/crates/foo_new?normalized=true")
+        .json();
+    assert_eq!(json["crate"]["downloads_per_day_since_publish"], 20.0);
+}
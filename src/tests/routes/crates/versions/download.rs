@@ -1,5 +1,16 @@
 use crate::builders::{CrateBuilder, VersionBuilder};
-use crate::util::{RequestHelper, TestApp};
+use crate::routes::crates::downloads::assert_dl_count;
+use crate::util::{MockAnonymousUser, MockRequestExt, RequestHelper, TestApp};
+use crates_io::models::token::EndpointScope;
+use crates_io::rate_limiter::RateLimiterConfig;
+use crates_io::schema::crates;
+use crates_io_cdn_logs::DownloadSource;
+use diesel::prelude::*;
+use http::{header, Method, StatusCode};
+use insta::assert_snapshot;
+use serde_json::Value;
+use std::thread;
+use std::time::Duration;
 
 #[test]
 fn test_redirects() {
@@ -28,6 +39,122 @@ fn test_redirects() {
         .assert_redirect_ends_with("/crates/bar-download/bar-download-1.0.0.crate");
 }
 
+#[test]
+fn download_version_prefix() {
+    let (app, anon, user) = TestApp::init().with_user();
+
+    app.db(|conn| {
+        CrateBuilder::new("foo-download", user.as_model().id)
+            .version(VersionBuilder::new("1.2.0"))
+            .version(VersionBuilder::new("1.2.5"))
+            .version(VersionBuilder::new("1.2.7").yanked(true))
+            .version(VersionBuilder::new("1.10.0"))
+            .version(VersionBuilder::new("2.0.0-beta.1"))
+            .expect_build(conn);
+    });
+
+    // Partial versions resolve to the highest non-yanked matching version
+    anon.get::<()>("/api/v1/crates/foo-download/1.2/download")
+        .assert_redirect_ends_with("/crates/foo-download/foo-download-1.2.5.crate");
+    anon.get::<()>("/api/v1/crates/foo-download/1/download")
+        .assert_redirect_ends_with("/crates/foo-download/foo-download-1.10.0.crate");
+
+    // The canonical crate name is used for resolved versions
+    anon.get::<()>("/api/v1/crates/Foo_downloaD/1.2/download")
+        .assert_redirect_ends_with("/crates/foo-download/foo-download-1.2.5.crate");
+
+    // Exact versions keep being redirected without a lookup
+    anon.get::<()>("/api/v1/crates/foo-download/1.2.7/download")
+        .assert_redirect_ends_with("/crates/foo-download/foo-download-1.2.7.crate");
+    anon.get::<()>("/api/v1/crates/foo-download/1.3.0/download")
+        .assert_redirect_ends_with("/crates/foo-download/foo-download-1.3.0.crate");
+
+    // Pre-releases are not considered
+    let response = anon.get::<()>("/api/v1/crates/foo-download/2/download");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_snapshot!(
+        response.text(),
+        @r###"{"errors":[{"detail":"crate `foo-download` does not have a version `2`"}]}"###
+    );
+
+    let response = anon.get::<()>("/api/v1/crates/foo-download/1.3/download");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let response = anon.get::<()>("/api/v1/crates/bar-download/1.2/download");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_snapshot!(
+        response.text(),
+        @r###"{"errors":[{"detail":"crate `bar-download` does not exist"}]}"###
+    );
+}
+
+#[test]
+fn download_latest() {
+    let (app, anon, user) = TestApp::init().with_user();
+
+    app.db(|conn| {
+        CrateBuilder::new("foo-latest", user.as_model().id)
+            .version(VersionBuilder::new("1.2.0"))
+            .version(VersionBuilder::new("1.10.0"))
+            .version(VersionBuilder::new("1.11.0").yanked(true))
+            .version(VersionBuilder::new("2.0.0-beta.1"))
+            .expect_build(conn);
+
+        CrateBuilder::new("foo-yanked", user.as_model().id)
+            .version(VersionBuilder::new("0.1.0").yanked(true))
+            .version(VersionBuilder::new("0.2.0").yanked(true))
+            .expect_build(conn);
+    });
+
+    // `latest` resolves to the highest non-yanked stable version
+    let response = anon.get::<()>("/api/v1/crates/Foo_Latest/latest/download");
+    assert!(response.headers().get("x-crate-yanked").is_none());
+    response.assert_redirect_ends_with("/crates/foo-latest/foo-latest-1.10.0.crate");
+
+    // Crates with only yanked versions fall back to the highest yanked version
+    let response = anon.get::<()>("/api/v1/crates/foo-yanked/latest/download");
+    assert_eq!(response.headers()["x-crate-yanked"], "true");
+    response.assert_redirect_ends_with("/crates/foo-yanked/foo-yanked-0.2.0.crate");
+
+    let response = anon.get::<()>("/api/v1/crates/bar-latest/latest/download");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_snapshot!(
+        response.text(),
+        @r###"{"errors":[{"detail":"crate `bar-latest` does not exist"}]}"###
+    );
+}
+
+#[test]
+fn download_deleted() {
+    let (app, anon, user) = TestApp::init()
+        .with_config(|config| config.download_version_lookup = true)
+        .with_user();
+
+    app.db(|conn| {
+        CrateBuilder::new("foo", user.as_model().id)
+            .version(VersionBuilder::new("1.0.0"))
+            .version(VersionBuilder::new("1.1.0").deleted(true))
+            .expect_build(conn);
+    });
+
+    let response = anon.get::<()>("/api/v1/crates/foo/1.1.0/download");
+    assert_eq!(response.status(), StatusCode::GONE);
+    assert_snapshot!(
+        response.text(),
+        @r###"{"errors":[{"detail":"version `1.1.0` of crate `foo` has been deleted and is no longer available"}]}"###
+    );
+
+    let mut request = anon.get_request("/api/v1/crates/foo/1.1.0/download");
+    request.header(header::ACCEPT, "application/json");
+    assert_eq!(anon.run::<()>(request).status(), StatusCode::GONE);
+
+    // Deleted versions are skipped when resolving partial versions
+    anon.get::<()>("/api/v1/crates/foo/1/download")
+        .assert_redirect_ends_with("/crates/foo/foo-1.0.0.crate");
+    anon.get::<()>("/api/v1/crates/foo/latest/download")
+        .assert_redirect_ends_with("/crates/foo/foo-1.0.0.crate");
+}
+
 #[test]
 fn download_with_build_metadata() {
     let (app, anon, user) = TestApp::init().with_user();
@@ -45,3 +172,835 @@ fn download_with_build_metadata() {
     anon.get::<()>("/api/v1/crates/foo/1.0.0+bar/readme")
         .assert_redirect_ends_with("/readmes/foo/foo-1.0.0%2Bbar.html");
 }
+
+#[test]
+fn head_request() {
+    let (app, anon, user) = TestApp::init().with_user();
+
+    app.db(|conn| {
+        CrateBuilder::new("foo", user.as_model().id)
+            .version(VersionBuilder::new("1.0.0"))
+            .expect_build(conn);
+    });
+
+    let url = "/api/v1/crates/foo/1.0.0/download";
+    let response = anon.run::<()>(anon.request_builder(Method::HEAD, url));
+    assert_eq!(response.status(), StatusCode::FOUND);
+    response.assert_redirect_ends_with("/crates/foo/foo-1.0.0.crate");
+    assert_eq!(response.text(), "");
+
+    let mut request = anon.request_builder(Method::HEAD, url);
+    request.header(header::ACCEPT, "application/json");
+    let response = anon.run::<()>(request);
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.text(), "");
+
+    assert_dl_count(&anon, "foo/1.0.0", None, 0);
+}
+
+#[test]
+fn json_with_backend() {
+    let (app, anon, user) = TestApp::init().with_user();
+
+    app.db(|conn| {
+        CrateBuilder::new("foo", user.as_model().id)
+            .version(VersionBuilder::new("1.0.0"))
+            .expect_build(conn);
+    });
+
+    let url = "/api/v1/crates/foo/1.0.0/download";
+
+    let mut request = anon.get_request(url);
+    request.header(header::ACCEPT, "application/json");
+    let json = anon.run::<()>(request).json();
+    assert_eq!(
+        json,
+        json!({ "url": "https://static.crates.io/crates/foo/foo-1.0.0.crate" })
+    );
+
+    let mut request = anon.get_request(&format!("{url}?include=backend"));
+    request.header(header::ACCEPT, "application/json");
+    let json = anon.run::<()>(request).json();
+    assert_eq!(
+        json,
+        json!({
+            "url": "https://static.crates.io/crates/foo/foo-1.0.0.crate",
+            "backend": "in_memory",
+        })
+    );
+}
+
+#[test]
+fn download_checksum() {
+    const CHECKSUM: &str = "b5f0e7478d9d5c9e6bcea4a2b5d8b5f3deb69fd9f1b7bcb47fa1c3b0e34dbe04";
+
+    let (app, anon, user) = TestApp::init()
+        .with_config(|config| config.download_version_lookup = true)
+        .with_user();
+
+    app.db(|conn| {
+        CrateBuilder::new("foo", user.as_model().id)
+            .version(VersionBuilder::new("1.0.0").checksum(CHECKSUM))
+            .version(VersionBuilder::new("2.0.0"))
+            .expect_build(conn);
+    });
+
+    let mut request = anon.get_request("/api/v1/crates/foo/1.0.0/download");
+    request.header(header::ACCEPT, "application/json");
+    let json = anon.run::<()>(request).json();
+    assert_eq!(
+        json,
+        json!({
+            "url": "https://static.crates.io/crates/foo/foo-1.0.0.crate",
+            "checksum": CHECKSUM,
+        })
+    );
+
+    let response = anon.get::<()>("/api/v1/crates/foo/1.0.0/download");
+    assert_eq!(response.status(), StatusCode::FOUND);
+    assert_eq!(response.headers()["x-crate-checksum"], CHECKSUM);
+
+    // Versions without a stored checksum are downloaded without one
+    let mut request = anon.get_request("/api/v1/crates/foo/2.0.0/download");
+    request.header(header::ACCEPT, "application/json");
+    let json = anon.run::<()>(request).json();
+    assert_eq!(
+        json,
+        json!({ "url": "https://static.crates.io/crates/foo/foo-2.0.0.crate" })
+    );
+
+    let response = anon.get::<()>("/api/v1/crates/foo/2.0.0/download");
+    assert_eq!(response.status(), StatusCode::FOUND);
+    assert!(!response.headers().contains_key("x-crate-checksum"));
+
+    // So are versions that don't exist
+    let response = anon.get::<()>("/api/v1/crates/foo/3.0.0/download");
+    assert_eq!(response.status(), StatusCode::FOUND);
+    assert!(!response.headers().contains_key("x-crate-checksum"));
+}
+
+#[test]
+fn download_without_version_lookup() {
+    const CHECKSUM: &str = "b5f0e7478d9d5c9e6bcea4a2b5d8b5f3deb69fd9f1b7bcb47fa1c3b0e34dbe04";
+
+    let (app, anon, user) = TestApp::init().with_user();
+
+    app.db(|conn| {
+        CrateBuilder::new("foo", user.as_model().id)
+            .version(VersionBuilder::new("1.0.0").checksum(CHECKSUM))
+            .version(VersionBuilder::new("1.1.0").deleted(true))
+            .expect_build(conn);
+    });
+
+    // Plain redirects don't look up the version in the database
+    let response = anon.get::<()>("/api/v1/crates/foo/1.0.0/download");
+    assert_eq!(response.status(), StatusCode::FOUND);
+    assert!(!response.headers().contains_key("x-crate-checksum"));
+
+    let response = anon.get::<()>("/api/v1/crates/foo/1.1.0/download");
+    assert_eq!(response.status(), StatusCode::FOUND);
+
+    // JSON responses always do
+    let mut request = anon.get_request("/api/v1/crates/foo/1.0.0/download");
+    request.header(header::ACCEPT, "application/json");
+    assert_eq!(anon.run::<()>(request).json()["checksum"], CHECKSUM);
+
+    let mut request = anon.get_request("/api/v1/crates/foo/1.1.0/download");
+    request.header(header::ACCEPT, "application/json");
+    assert_eq!(anon.run::<()>(request).status(), StatusCode::GONE);
+}
+
+#[test]
+fn download_by_checksum() {
+    let (app, anon, user) = TestApp::init().with_user();
+
+    app.db(|conn| {
+        CrateBuilder::new("foo", user.as_model().id)
+            .version(VersionBuilder::new("1.0.0").checksum(&format!("abc1{}", "0".repeat(60))))
+            .version(VersionBuilder::new("2.0.0").checksum(&format!("abc2{}", "0".repeat(60))))
+            .expect_build(conn);
+    });
+
+    // A unique prefix redirects to the matching version
+    anon.get::<()>("/api/v1/crates/foo/by-checksum/ABC1/download")
+        .assert_redirect_ends_with("/crates/foo/foo-1.0.0.crate");
+
+    // A prefix matching several versions is ambiguous
+    let response = anon.get::<()>("/api/v1/crates/foo/by-checksum/abc/download");
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    assert_eq!(
+        response.json(),
+        json!({ "errors": [{ "detail": "checksum prefix `abc` matches more than one version of crate `foo`" }] })
+    );
+
+    // A prefix without a match is not found
+    let response = anon.get::<()>("/api/v1/crates/foo/by-checksum/abc3/download");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_eq!(
+        response.json(),
+        json!({ "errors": [{ "detail": "crate `foo` has no version with a checksum starting with `abc3`" }] })
+    );
+
+    let response = anon.get::<()>("/api/v1/crates/foo/by-checksum/xyz/download");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = anon.get::<()>("/api/v1/crates/bar/by-checksum/abc1/download");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn download_by_checksum_like_download() {
+    let checksum = format!("abc1{}", "0".repeat(60));
+
+    let (app, anon, user) = TestApp::init()
+        .with_config(|config| {
+            config.download_redirect_307 = true;
+            config.download_version_lookup = true;
+        })
+        .with_user();
+
+    app.db(|conn| {
+        CrateBuilder::new("foo", user.as_model().id)
+            .version(VersionBuilder::new("1.0.0").checksum(&checksum))
+            .expect_build(conn);
+    });
+
+    let url = "/api/v1/crates/foo/by-checksum/abc1/download";
+
+    // The response is built like the one of the `download` endpoint
+    let response = anon.get::<()>(url);
+    assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+    assert_eq!(response.headers()["x-crate-checksum"], checksum.as_str());
+    assert!(response.headers().contains_key("x-request-id"));
+
+    let mut request = anon.get_request(url);
+    request.header(header::ACCEPT, "application/json");
+    assert_eq!(
+        anon.run::<()>(request).json(),
+        json!({
+            "url": "https://static.crates.io/crates/foo/foo-1.0.0.crate",
+            "checksum": checksum,
+        })
+    );
+}
+
+#[test]
+fn download_private_crate() {
+    let (app, anon, user) = TestApp::init()
+        .with_config(|config| config.private_crate_files = true)
+        .with_user();
+
+    app.db(|conn| {
+        let checksum = format!("abc1{}", "0".repeat(60));
+        let krate = CrateBuilder::new("foo", user.as_model().id)
+            .version(VersionBuilder::new("1.0.0").checksum(&checksum))
+            .expect_build(conn);
+
+        diesel::update(crates::table.find(krate.id))
+            .set(crates::private.eq(true))
+            .execute(conn)
+            .unwrap();
+    });
+
+    let url = "/api/v1/crates/foo/1.0.0/download";
+    let checksum_url = "/api/v1/crates/foo/by-checksum/abc1/download";
+
+    let response = anon.get::<()>(url);
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    let response = anon.get::<()>(checksum_url);
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    // Cookie sessions are not enough
+    let response = user.get::<()>(url);
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    // Legacy tokens don't have the `download` scope
+    let token = user.db_new_token("legacy");
+    let response = token.get::<()>(url);
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    // Tokens need the `download` scope
+    let token = user.db_new_scoped_token("yank", None, Some(vec![EndpointScope::Yank]), None);
+    let response = token.get::<()>(url);
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let endpoint_scopes = Some(vec![EndpointScope::Download]);
+    let token = user.db_new_scoped_token("download", None, endpoint_scopes, None);
+    token
+        .get::<()>(url)
+        .assert_redirect_ends_with("/crates/foo/foo-1.0.0.crate");
+    token
+        .get::<()>(checksum_url)
+        .assert_redirect_ends_with("/crates/foo/foo-1.0.0.crate");
+
+    // Users that don't own the crate can't download it with their own tokens
+    let other = app.db_new_user("other");
+    let endpoint_scopes = Some(vec![EndpointScope::Download]);
+    let token = other.db_new_scoped_token("other", None, endpoint_scopes, None);
+    let response = token.get::<()>(url);
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    // Access is checked before versions are resolved, so that anonymous
+    // requests can't tell which versions exist
+    for url in [
+        "/api/v1/crates/foo/latest/download",
+        "/api/v1/crates/foo/1/download",
+        "/api/v1/crates/foo/2/download",
+        "/api/v1/crates/foo/by-checksum/abc2/download",
+        "/api/v1/crates/foo/1.0.0/download?mirror=unknown",
+    ] {
+        let response = anon.get::<()>(url);
+        assert_eq!(response.status(), StatusCode::FORBIDDEN, "{url}");
+    }
+
+    // Public crates can still be downloaded by anyone
+    app.db(|conn| {
+        CrateBuilder::new("bar", user.as_model().id)
+            .version(VersionBuilder::new("1.0.0"))
+            .expect_build(conn);
+    });
+    anon.get::<()>("/api/v1/crates/bar/1.0.0/download")
+        .assert_redirect_ends_with("/crates/bar/bar-1.0.0.crate");
+}
+
+#[test]
+fn download_events() {
+    let (app, anon, user) = TestApp::init()
+        .with_config(|config| config.download_events = true)
+        .with_user();
+
+    let (krate, version) = app.db(|conn| {
+        let krate = CrateBuilder::new("foo", user.as_model().id)
+            .version(VersionBuilder::new("1.0.0"))
+            .expect_build(conn);
+        let version = VersionBuilder::new("2.0.0").expect_build(krate.id, user.as_model().id, conn);
+        (krate, version)
+    });
+
+    let mut request = anon.get_request("/api/v1/crates/foo/2.0.0/download");
+    request.header(header::USER_AGENT, "cargo 1.76.0 (c84b36747 2024-01-18)");
+    assert_eq!(anon.run::<()>(request).status(), StatusCode::FOUND);
+
+    let mut request = anon.get_request("/api/v1/crates/foo/2.0.0/download");
+    request.header(header::ACCEPT, "application/json");
+    assert_eq!(anon.run::<()>(request).status(), StatusCode::OK);
+
+    // Probes and versions that don't exist are not published
+    let request = anon.request_builder(Method::HEAD, "/api/v1/crates/foo/2.0.0/download");
+    assert_eq!(anon.run::<()>(request).status(), StatusCode::FOUND);
+    let response = anon.get::<()>("/api/v1/crates/foo/3.0.0/download");
+    assert_eq!(response.status(), StatusCode::FOUND);
+
+    // The events are consumed by a background thread
+    let download_events = &app.as_inner().download_events;
+    let mut events = download_events.events_in_memory().unwrap();
+    for _ in 0..100 {
+        if events.len() >= 2 {
+            break;
+        }
+        thread::sleep(Duration::from_millis(10));
+        events = download_events.events_in_memory().unwrap();
+    }
+
+    assert_eq!(events.len(), 2);
+    for event in &events {
+        assert_eq!(event.crate_id, krate.id);
+        assert_eq!(event.version_id, version.id);
+    }
+    assert_eq!(events[0].source, Some(DownloadSource::Human));
+    assert_eq!(events[1].source, None);
+    assert_eq!(
+        app.as_inner()
+            .instance_metrics
+            .download_events_dropped_total
+            .get(),
+        0
+    );
+}
+
+#[test]
+fn download_events_deduplicated_by_download_id() {
+    const FIRST_ID: &str = "0b3b4a4e-6f1c-4b5e-9d1e-3c0a8f1f2a01";
+    const SECOND_ID: &str = "0b3b4a4e-6f1c-4b5e-9d1e-3c0a8f1f2a02";
+    const THIRD_ID: &str = "0b3b4a4e-6f1c-4b5e-9d1e-3c0a8f1f2a03";
+
+    let (app, anon, user) = TestApp::init()
+        .with_config(|config| config.download_events = true)
+        .with_user();
+
+    let (first, second) = app.db(|conn| {
+        let user_id = user.as_model().id;
+        let krate = CrateBuilder::new("foo", user_id).expect_build(conn);
+        let first = VersionBuilder::new("1.0.0").expect_build(krate.id, user_id, conn);
+        let second = VersionBuilder::new("2.0.0").expect_build(krate.id, user_id, conn);
+        (first.id, second.id)
+    });
+
+    let download = |version: &str, download_id: &str| {
+        let url = format!("/api/v1/crates/foo/{version}/download");
+        let mut request = anon.get_request(&url);
+        request.header("x-download-id", download_id);
+        assert_eq!(anon.run::<()>(request).status(), StatusCode::FOUND);
+    };
+
+    // Retries with the same id are counted once
+    download("1.0.0", FIRST_ID);
+    download("1.0.0", FIRST_ID);
+
+    // Different ids are counted separately
+    download("1.0.0", SECOND_ID);
+    download("2.0.0", THIRD_ID);
+
+    // The events are consumed in order by a background thread, so the retry
+    // would be consumed before the download of the other version
+    let timeout = Duration::from_secs(5);
+    let events = app
+        .as_inner()
+        .download_events
+        .wait_for_events_in_memory(3, timeout)
+        .unwrap();
+    let version_ids = events.iter().map(|e| e.version_id).collect::<Vec<_>>();
+    assert_eq!(version_ids, [first, first, second]);
+}
+
+#[test]
+fn download_records_redirect_duration() {
+    let (app, anon, user) = TestApp::init().with_user();
+
+    app.db(|conn| {
+        CrateBuilder::new("foo", user.as_model().id)
+            .version(VersionBuilder::new("1.0.0"))
+            .expect_build(conn);
+    });
+
+    let sample_count = |wants_json: &str| {
+        app.as_inner()
+            .instance_metrics
+            .crate_download_redirect_duration_seconds
+            .with_label_values(&[wants_json, "in_memory"])
+            .get_sample_count()
+    };
+
+    assert_eq!(sample_count("false"), 0);
+    assert_eq!(sample_count("true"), 0);
+
+    let url = "/api/v1/crates/foo/1.0.0/download";
+    let response = anon.get::<()>(url);
+    assert_eq!(response.status(), StatusCode::FOUND);
+    assert_eq!(sample_count("false"), 1);
+    assert_eq!(sample_count("true"), 0);
+
+    let mut request = anon.get_request(url);
+    request.header(header::ACCEPT, "application/json");
+    let response = anon.run::<()>(request);
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(sample_count("false"), 1);
+    assert_eq!(sample_count("true"), 1);
+}
+
+#[test]
+fn download_correlation_id() {
+    let (app, anon, user) = TestApp::init().with_user();
+
+    app.db(|conn| {
+        CrateBuilder::new("foo", user.as_model().id)
+            .version(VersionBuilder::new("1.0.0"))
+            .expect_build(conn);
+    });
+
+    let url = "/api/v1/crates/foo/1.0.0/download";
+
+    // An incoming request id is echoed back on redirects
+    let mut request = anon.get_request(url);
+    request.header("x-request-id", "abcd");
+    let response = anon.run::<()>(request);
+    assert_eq!(response.status(), StatusCode::FOUND);
+    assert_eq!(response.headers()["x-request-id"], "abcd");
+
+    // ... and on JSON responses
+    let mut request = anon.get_request(url);
+    request.header("x-request-id", "abcd");
+    request.header(header::ACCEPT, "application/json");
+    let response = anon.run::<()>(request);
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["x-request-id"], "abcd");
+
+    // Without an incoming request id a new one is generated
+    let response = anon.get::<()>(url);
+    assert_eq!(response.status(), StatusCode::FOUND);
+    let correlation_id = response.headers()["x-request-id"].to_str().unwrap();
+    assert_ok!(correlation_id.parse::<uuid::Uuid>());
+}
+
+#[test]
+fn download_from_mirror() {
+    let (app, anon, user) = TestApp::init()
+        .with_config(|config| {
+            config.download_mirrors.insert(
+                "internal".to_string(),
+                "https://mirror.example.com".to_string(),
+            );
+        })
+        .with_user();
+
+    app.db(|conn| {
+        CrateBuilder::new("foo", user.as_model().id)
+            .version(VersionBuilder::new("1.0.0"))
+            .expect_build(conn);
+    });
+
+    let url = "/api/v1/crates/foo/1.0.0/download";
+
+    // Known mirrors replace the default storage location
+    let response = anon.get_with_query::<()>(url, "mirror=internal");
+    assert_eq!(response.status(), StatusCode::FOUND);
+    assert_eq!(
+        response.headers()[header::LOCATION],
+        "https://mirror.example.com/crates/foo/foo-1.0.0.crate"
+    );
+
+    // Without the parameter the default storage location is used
+    let response = anon.get::<()>(url);
+    assert_eq!(response.status(), StatusCode::FOUND);
+    assert_eq!(
+        response.headers()[header::LOCATION],
+        "https://static.crates.io/crates/foo/foo-1.0.0.crate"
+    );
+
+    // Unknown mirrors are rejected
+    let response = anon.get_with_query::<()>(url, "mirror=unknown");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        response.json(),
+        json!({ "errors": [{ "detail": "unknown download mirror `unknown`" }] })
+    );
+
+    assert_dl_count(&anon, "foo/1.0.0", None, 0);
+}
+
+#[test]
+fn download_base_url() {
+    fn location(anon: &MockAnonymousUser, url: &str) -> (String, Value) {
+        let response = anon.get::<()>(url);
+        assert_eq!(response.status(), StatusCode::FOUND);
+        let location = response.headers()[header::LOCATION].to_str().unwrap();
+        let location = location.to_string();
+
+        let mut request = anon.get_request(url);
+        request.header(header::ACCEPT, "application/json");
+        let response = anon.run::<()>(request);
+        assert_eq!(response.status(), StatusCode::OK);
+
+        (location, response.json()["url"].clone())
+    }
+
+    let url = "/api/v1/crates/foo/1.0.0/download";
+
+    // Without a base URL, the relative storage location is returned
+    let (app, anon, user) = TestApp::init()
+        .with_config(|config| config.storage.cdn_prefix = None)
+        .with_user();
+    app.db(|conn| {
+        CrateBuilder::new("foo", user.as_model().id)
+            .version(VersionBuilder::new("1.0.0"))
+            .expect_build(conn);
+    });
+
+    let (redirect, json) = location(&anon, url);
+    assert_eq!(redirect, "/crates/foo/foo-1.0.0.crate");
+    assert_eq!(json, "/crates/foo/foo-1.0.0.crate");
+
+    // With a base URL, the location is absolute without a double slash
+    let (app, anon, user) = TestApp::init()
+        .with_config(|config| {
+            config.storage.cdn_prefix = None;
+            config.download_base_url = Some("https://crates.example.com/".to_string());
+        })
+        .with_user();
+    app.db(|conn| {
+        CrateBuilder::new("foo", user.as_model().id)
+            .version(VersionBuilder::new("1.0.0"))
+            .expect_build(conn);
+    });
+
+    let expected = "https://crates.example.com/crates/foo/foo-1.0.0.crate";
+    let (redirect, json) = location(&anon, url);
+    assert_eq!(redirect, expected);
+    assert_eq!(json, expected);
+
+    // Absolute CDN locations are not changed
+    let (app, anon, user) = TestApp::init()
+        .with_config(|config| {
+            config.download_base_url = Some("https://crates.example.com".to_string());
+        })
+        .with_user();
+    app.db(|conn| {
+        CrateBuilder::new("foo", user.as_model().id)
+            .version(VersionBuilder::new("1.0.0"))
+            .expect_build(conn);
+    });
+
+    let expected = "https://static.crates.io/crates/foo/foo-1.0.0.crate";
+    let (redirect, json) = location(&anon, url);
+    assert_eq!(redirect, expected);
+    assert_eq!(json, expected);
+}
+
+#[test]
+fn download_signed_urls() {
+    use chrono::{DateTime, Utc};
+    use crates_io::storage::{S3Config, StorageConfig};
+
+    let (app, anon, user) = TestApp::init()
+        .with_config(|config| {
+            let s3_config = || {
+                let secret_key = "secret-key".to_string().into();
+                S3Config::new("crates-io".into(), None, "access-key".into(), secret_key)
+            };
+            let cdn_prefix = Some("static.crates.io".to_string());
+            config.storage = StorageConfig::s3(s3_config(), s3_config(), cdn_prefix);
+            config.storage.signed_urls = true;
+            config.storage.signed_url_ttl = Duration::from_secs(600);
+        })
+        .with_user();
+
+    app.db(|conn| {
+        CrateBuilder::new("foo", user.as_model().id)
+            .version(VersionBuilder::new("1.0.0"))
+            .expect_build(conn);
+    });
+
+    let url = "/api/v1/crates/foo/1.0.0/download";
+
+    let response = anon.get::<()>(url);
+    assert_eq!(response.status(), StatusCode::FOUND);
+    let location = response.headers()[header::LOCATION].to_str().unwrap();
+    assert!(location.contains("/crates/foo/foo-1.0.0.crate?"));
+    assert!(location.contains("X-Amz-Signature="));
+    assert!(location.contains("X-Amz-Expires=600"));
+
+    let before = Utc::now();
+    let mut request = anon.get_request(url);
+    request.header(header::ACCEPT, "application/json");
+    let response = anon.run::<()>(request);
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let json = response.json();
+    assert!(json["url"].as_str().unwrap().contains("X-Amz-Signature="));
+
+    let expires_at = json["expires_at"].as_str().unwrap();
+    let expires_at = expires_at.parse::<DateTime<Utc>>().unwrap();
+    let ttl = (expires_at - before).num_seconds();
+    assert!((599..=601).contains(&ttl), "unexpected TTL: {ttl}");
+
+    // Signatures would be invalidated by additional query parameters
+    let response = anon.get_with_query::<()>(url, "target=x86_64-unknown-linux-gnu");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[test]
+fn download_no_redirect() {
+    let (app, anon, user) = TestApp::init()
+        .with_config(|config| config.download_events = true)
+        .with_user();
+
+    app.db(|conn| {
+        CrateBuilder::new("foo", user.as_model().id)
+            .version(VersionBuilder::new("1.0.0"))
+            .expect_build(conn);
+    });
+
+    let url = "/api/v1/crates/foo/1.0.0/download";
+
+    let response = anon.get_with_query::<()>(url, "no_redirect=1");
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()[header::CONTENT_TYPE],
+        "text/plain; charset=utf-8"
+    );
+    assert_eq!(
+        response.text(),
+        "https://static.crates.io/crates/foo/foo-1.0.0.crate"
+    );
+
+    let events = app.as_inner().download_events.events_in_memory().unwrap();
+    assert_eq!(events.len(), 0);
+    assert_dl_count(&anon, "foo/1.0.0", None, 0);
+
+    // The default behavior is not affected
+    let response = anon.get::<()>(url);
+    assert_eq!(response.status(), StatusCode::FOUND);
+    assert_eq!(
+        response.headers()[header::LOCATION],
+        "https://static.crates.io/crates/foo/foo-1.0.0.crate"
+    );
+}
+
+#[test]
+fn download_redirect_307() {
+    let (app, anon, user) = TestApp::init()
+        .with_config(|config| config.download_redirect_307 = true)
+        .with_user();
+
+    app.db(|conn| {
+        CrateBuilder::new("foo", user.as_model().id)
+            .version(VersionBuilder::new("1.0.0"))
+            .expect_build(conn);
+    });
+
+    let url = "/api/v1/crates/foo/1.0.0/download";
+
+    let response = anon.get::<()>(url);
+    assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+    response.assert_redirect_ends_with("/crates/foo/foo-1.0.0.crate");
+
+    let response = anon.run::<()>(anon.request_builder(Method::HEAD, url));
+    assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+
+    // JSON responses are not affected
+    let mut request = anon.get_request(url);
+    request.header(header::ACCEPT, "application/json");
+    let response = anon.run::<()>(request);
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.json(),
+        json!({ "url": "https://static.crates.io/crates/foo/foo-1.0.0.crate" })
+    );
+}
+
+#[test]
+fn download_proxy() {
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    let (app, anon, user) = TestApp::init()
+        .with_config(|config| config.proxy_download = true)
+        .with_user();
+
+    app.db(|conn| {
+        CrateBuilder::new("foo", user.as_model().id)
+            .version(VersionBuilder::new("1.0.0"))
+            .expect_build(conn);
+    });
+
+    let contents = "crate file contents ".repeat(10);
+    let storage = &app.as_inner().storage;
+    let upload = storage.upload_crate_file("foo", "1.0.0", contents.clone().into());
+    app.runtime().block_on(upload).unwrap();
+
+    let url = "/api/v1/crates/foo/1.0.0/download";
+
+    // The crate file is served directly and compressed for clients that
+    // accept it
+    let mut request = anon.get_request(url);
+    request.header(header::ACCEPT_ENCODING, "gzip");
+    let response = anon.run::<()>(request);
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[header::CONTENT_ENCODING], "gzip");
+    assert_eq!(response.headers()[header::CONTENT_TYPE], "application/gzip");
+
+    let mut decompressed = String::new();
+    GzDecoder::new(response.body().as_ref())
+        .read_to_string(&mut decompressed)
+        .unwrap();
+    assert_eq!(decompressed, contents);
+
+    let response = anon.get::<()>(url);
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(!response.headers().contains_key(header::CONTENT_ENCODING));
+    assert_eq!(response.body().as_ref(), contents.as_bytes());
+
+    // JSON responses are not affected
+    let mut request = anon.get_request(url);
+    request.header(header::ACCEPT, "application/json");
+    let json = anon.run::<()>(request).json();
+    assert_eq!(
+        json,
+        json!({ "url": "https://static.crates.io/crates/foo/foo-1.0.0.crate" })
+    );
+
+    let response = anon.get::<()>("/api/v1/crates/foo/2.0.0/download");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn download_rate_limit() {
+    let config = RateLimiterConfig {
+        rate: Duration::from_secs(1),
+        burst: 2,
+    };
+
+    let (app, anon, user, token) = TestApp::init()
+        .with_config(|c| c.download_rate_limit = Some(config))
+        .with_token();
+
+    app.db(|conn| {
+        CrateBuilder::new("foo", user.as_model().id)
+            .version(VersionBuilder::new("1.0.0"))
+            .expect_build(conn);
+    });
+
+    let url = "/api/v1/crates/foo/1.0.0/download";
+
+    for _ in 0..2 {
+        let response = anon.get::<()>(url);
+        assert_eq!(response.status(), StatusCode::FOUND);
+    }
+
+    let response = anon.get::<()>(url);
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(response.headers()[header::RETRY_AFTER], "1");
+    assert_snapshot!(
+        response.text(),
+        @r###"{"errors":[{"detail":"You have downloaded too many crates in a short period of time. Please try again later, as indicated by the `Retry-After` header, or authenticate with an API token."}]}"###
+    );
+
+    // Requests with a valid API token are exempt from the rate limit
+    let response = token.get::<()>(url);
+    assert_eq!(response.status(), StatusCode::FOUND);
+
+    // Requests with an invalid API token are not
+    let mut request = anon.get_request(url);
+    request.header(header::AUTHORIZATION, "cio1234567890");
+    let response = anon.run::<()>(request);
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+
+    // The bucket is refilled after the configured rate
+    thread::sleep(Duration::from_secs(1));
+    let response = anon.get::<()>(url);
+    assert_eq!(response.status(), StatusCode::FOUND);
+}
+
+#[test]
+fn storage_key() {
+    let (app, anon, user, token) = TestApp::init().with_token();
+
+    app.db(|conn| {
+        CrateBuilder::new("foo_bar", user.as_model().id)
+            .version(VersionBuilder::new("1.0.0"))
+            .expect_build(conn);
+    });
+
+    let url = "/api/v1/crates/foo_bar/1.0.0/storage-key";
+
+    let response = token.get::<()>(url);
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.json(),
+        json!({ "key": "crates/foo_bar/foo_bar-1.0.0.crate" })
+    );
+
+    // The key uses the canonical crate name
+    let response = user.get::<()>("/api/v1/crates/Foo-Bar/1.0.0/storage-key");
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.json(),
+        json!({ "key": "crates/foo_bar/foo_bar-1.0.0.crate" })
+    );
+
+    let response = token.get::<()>("/api/v1/crates/foo_bar/2.0.0/storage-key");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let response = anon.get::<()>(url);
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
@@ -1,11 +1,14 @@
 use crate::builders::{CrateBuilder, VersionBuilder};
+use crate::routes::crates::downloads::save_version_downloads_on;
 use crate::util::{RequestHelper, TestApp};
+use chrono::{Duration, Utc};
 use crates_io::schema::versions;
 use crates_io::views::EncodableVersion;
 use diesel::{prelude::*, update};
 use googletest::prelude::*;
 use http::StatusCode;
 use insta::{assert_json_snapshot, assert_snapshot};
+use serde_json::Value;
 
 #[test]
 fn versions() {
@@ -44,6 +47,63 @@ fn test_unknown_crate() {
     assert_snapshot!(response.text(), @r###"{"errors":[{"detail":"crate `unknown` does not exist"}]}"###);
 }
 
+#[test]
+fn test_popular_versions() {
+    let (app, anon, user) = TestApp::init().with_user();
+    let user = user.as_model();
+    app.db(|conn| {
+        CrateBuilder::new("foo", user.id)
+            .version("1.0.0")
+            .version("1.1.0")
+            .version("1.2.0")
+            .version("2.0.0")
+            .expect_build(conn);
+
+        let today = Utc::now().date_naive();
+        save_version_downloads_on("foo", "1.0.0", 3, today, conn);
+        save_version_downloads_on("foo", "1.1.0", 5, today, conn);
+        save_version_downloads_on("foo", "1.1.0", 5, today - Duration::days(1), conn);
+        save_version_downloads_on("foo", "1.2.0", 1, today, conn);
+        save_version_downloads_on("foo", "1.2.0", 100, today - Duration::days(10), conn);
+    });
+
+    let url = "/api/v1/crates/foo/versions/popular";
+
+    let json: Value = anon.get(url).good();
+    let versions = json["versions"].as_array().unwrap();
+    let versions = versions
+        .iter()
+        .map(|v| {
+            (
+                v["num"].as_str().unwrap(),
+                v["recent_downloads"].as_i64().unwrap(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(versions, vec![("1.1.0", 10), ("1.0.0", 3), ("1.2.0", 1)]);
+
+    // Larger windows include older downloads
+    let json: Value = anon.get_with_query(url, "window=30").good();
+    let versions = json["versions"].as_array().unwrap();
+    let versions = versions
+        .iter()
+        .map(|v| {
+            (
+                v["num"].as_str().unwrap(),
+                v["recent_downloads"].as_i64().unwrap(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(versions, vec![("1.2.0", 101), ("1.1.0", 10), ("1.0.0", 3)]);
+
+    let response = anon.get_with_query::<()>(url, "window=0");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_snapshot!(
+        response.text(),
+        @r###"{"errors":[{"detail":"invalid `window` parameter `0`, expected a positive integer"}]}"###
+    );
+}
+
 #[test]
 fn test_sorting() {
     let (app, anon, user) = TestApp::init().with_user();
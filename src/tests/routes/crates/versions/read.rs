@@ -1,6 +1,8 @@
 use crate::builders::{CrateBuilder, VersionBuilder};
+use crate::routes::crates::downloads::save_version_downloads_on;
 use crate::util::insta::{self, assert_json_snapshot};
 use crate::util::{RequestHelper, TestApp};
+use chrono::{Duration, Utc};
 use diesel::prelude::*;
 use serde_json::Value;
 
@@ -28,6 +30,48 @@ fn show_by_crate_name_and_version() {
     });
 }
 
+#[test]
+fn show_first_download_date() {
+    let (app, anon, user) = TestApp::init().with_user();
+    let user = user.as_model();
+
+    app.db(|conn| {
+        CrateBuilder::new("foo", user.id)
+            .version("1.0.0")
+            .version("1.1.0")
+            .expect_build(conn);
+    });
+
+    // Versions without downloads don't have a first download date
+    let url = "/api/v1/crates/foo/1.0.0";
+    let json: Value = anon.get(url).good();
+    assert_eq!(json["version"]["first_download_date"], Value::Null);
+
+    let today = Utc::now().date_naive();
+    app.db(|conn| {
+        for days_ago in [3, 10, 0] {
+            let date = today - Duration::days(days_ago);
+            save_version_downloads_on("foo", "1.0.0", 1, date, conn);
+        }
+        save_version_downloads_on("foo", "1.1.0", 1, today, conn);
+    });
+
+    let expected = (today - Duration::days(10)).to_string();
+    let json: Value = anon.get(url).good();
+    assert_eq!(json["version"]["first_download_date"], expected);
+
+    let json: Value = anon.get("/api/v1/crates/foo/1.1.0").good();
+    assert_eq!(json["version"]["first_download_date"], today.to_string());
+
+    // Earlier downloads, e.g. from late log processing, change the date
+    let earlier = today - Duration::days(20);
+    app.db(|conn| {
+        save_version_downloads_on("foo", "1.0.0", 1, earlier, conn);
+    });
+    let json: Value = anon.get(url).good();
+    assert_eq!(json["version"]["first_download_date"], earlier.to_string());
+}
+
 #[test]
 fn show_by_crate_name_and_semver_no_published_by() {
     use crates_io::schema::versions;
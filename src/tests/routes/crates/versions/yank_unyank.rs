@@ -1,12 +1,22 @@
 use crate::builders::{CrateBuilder, PublishBuilder};
 use crate::util::{RequestHelper, Response, TestApp};
 use crate::OkBool;
+use bytes::Bytes;
 use http::StatusCode;
 
 pub trait YankRequestHelper {
     /// Yank the specified version of the specified crate and run all pending background jobs
     fn yank(&self, krate_name: &str, version: &str) -> Response<OkBool>;
 
+    /// Yank the specified version of the specified crate with the given request body
+    /// and run all pending background jobs
+    fn yank_with_body(
+        &self,
+        krate_name: &str,
+        version: &str,
+        body: impl Into<Bytes>,
+    ) -> Response<OkBool>;
+
     /// Unyank the specified version of the specified crate and run all pending background jobs
     fn unyank(&self, krate_name: &str, version: &str) -> Response<OkBool>;
 }
@@ -19,6 +29,18 @@ impl<T: RequestHelper> YankRequestHelper for T {
         response
     }
 
+    fn yank_with_body(
+        &self,
+        krate_name: &str,
+        version: &str,
+        body: impl Into<Bytes>,
+    ) -> Response<OkBool> {
+        let url = format!("/api/v1/crates/{krate_name}/{version}/yank");
+        let response = self.delete_with_body(&url, body);
+        self.app().run_pending_background_jobs();
+        response
+    }
+
     fn unyank(&self, krate_name: &str, version: &str) -> Response<OkBool> {
         let url = format!("/api/v1/crates/{krate_name}/{version}/unyank");
         let response = self.put(&url, &[] as &[u8]);
@@ -92,6 +114,160 @@ fn unyank_records_an_audit_action() {
     assert_eq!(action.user.id, token.as_model().user_id);
 }
 
+#[test]
+fn yank_with_reason() {
+    let (_, anon, _, token) = TestApp::full().with_token();
+
+    token
+        .publish_crate(PublishBuilder::new("fyk", "1.0.0"))
+        .good();
+    token
+        .publish_crate(PublishBuilder::new("fyk", "1.0.1"))
+        .good();
+
+    let body = json!({ "reason": "  contains a security vulnerability  " }).to_string();
+    token.yank_with_body("fyk", "1.0.0", body).good();
+    token.yank("fyk", "1.0.1").good();
+
+    let json = anon.show_version("fyk", "1.0.0");
+    assert!(json.version.yanked);
+    assert_eq!(
+        json.version.yank_reason.as_deref(),
+        Some("contains a security vulnerability")
+    );
+
+    let json = anon.show_version("fyk", "1.0.1");
+    assert!(json.version.yanked);
+    assert_eq!(json.version.yank_reason, None);
+
+    // Unyanking clears the reason again
+    token.unyank("fyk", "1.0.0").good();
+
+    let json = anon.show_version("fyk", "1.0.0");
+    assert!(!json.version.yanked);
+    assert_eq!(json.version.yank_reason, None);
+}
+
+#[test]
+fn yank_with_invalid_reason() {
+    let (_, anon, _, token) = TestApp::full().with_token();
+
+    token
+        .publish_crate(PublishBuilder::new("fyk", "1.0.0"))
+        .good();
+
+    let response = token.yank_with_body("fyk", "1.0.0", "{ invalid json");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        response.json(),
+        json!({ "errors": [{ "detail": "invalid json request" }] })
+    );
+
+    let body = json!({ "reason": "x".repeat(501) }).to_string();
+    let response = token.yank_with_body("fyk", "1.0.0", body);
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        response.json(),
+        json!({ "errors": [{ "detail": "yank reason must not be longer than 500 characters" }] })
+    );
+
+    let json = anon.show_version("fyk", "1.0.0");
+    assert!(!json.version.yanked);
+    assert_eq!(json.version.yank_reason, None);
+}
+
+#[test]
+fn batch_yank() {
+    use crate::routes::crates::downloads::save_version_downloads_on;
+    use chrono::Utc;
+    use crates_io::schema::version_downloads;
+    use diesel::prelude::*;
+
+    let (app, anon, _, token) = TestApp::full().with_token();
+
+    for version in ["1.0.0", "1.0.1", "1.1.0"] {
+        token
+            .publish_crate(PublishBuilder::new("fyk", version))
+            .good();
+    }
+
+    let today = Utc::now().date_naive();
+    app.db(|conn| {
+        save_version_downloads_on("fyk", "1.0.0", 10, today, conn);
+        save_version_downloads_on("fyk", "1.0.1", 20, today, conn);
+    });
+
+    let body = json!({ "versions": ["1.0.0", "1.0.1"], "reason": "security issue" });
+    let response = token.post::<()>("/api/v1/crates/fyk/yank", body.to_string());
+    app.run_pending_background_jobs();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.json(),
+        json!({
+            "ok": true,
+            "versions": [
+                { "version": "1.0.0", "ok": true },
+                { "version": "1.0.1", "ok": true },
+            ],
+        })
+    );
+
+    for version in ["1.0.0", "1.0.1"] {
+        let json = anon.show_version("fyk", version);
+        assert!(json.version.yanked);
+        assert_eq!(json.version.yank_reason.as_deref(), Some("security issue"));
+        assert_eq!(json.version.audit_actions.last().unwrap().action, "yank");
+    }
+    assert!(!anon.show_version("fyk", "1.1.0").version.yanked);
+
+    // The download counts are not affected by yanking
+    let downloads: Vec<i32> = app.db(|conn| {
+        version_downloads::table
+            .select(version_downloads::downloads)
+            .order(version_downloads::downloads)
+            .load(conn)
+            .unwrap()
+    });
+    assert_eq!(downloads, [10, 20]);
+}
+
+#[test]
+fn batch_yank_rolls_back_on_failure() {
+    let (app, anon, _, token) = TestApp::full().with_token();
+
+    token
+        .publish_crate(PublishBuilder::new("fyk", "1.0.0"))
+        .good();
+
+    let body = json!({ "versions": ["1.0.0", "2.0.0"] });
+    let response = token.post::<()>("/api/v1/crates/fyk/yank", body.to_string());
+    app.run_pending_background_jobs();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        response.json(),
+        json!({
+            "ok": false,
+            "versions": [
+                { "version": "1.0.0", "ok": false, "error": "rolled back" },
+                { "version": "2.0.0", "ok": false, "error": "version does not exist" },
+            ],
+        })
+    );
+
+    // The existing version was not yanked either
+    let json = anon.show_version("fyk", "1.0.0");
+    assert!(!json.version.yanked);
+    assert_eq!(json.version.audit_actions.len(), 1);
+
+    let body = json!({ "versions": [] });
+    let response = token.post::<()>("/api/v1/crates/fyk/yank", body.to_string());
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        response.json(),
+        json!({ "errors": [{ "detail": "at least one version has to be given" }] })
+    );
+}
+
 mod auth {
     use super::*;
     use crate::util::{MockAnonymousUser, MockCookieUser};
@@ -0,0 +1,95 @@
+use crate::util::{RequestHelper, TestApp};
+use openapiv3::{OpenAPI, ReferenceOr, StatusCode};
+use serde_json::Value;
+
+#[test]
+fn downloads_document() {
+    let (_app, anon) = TestApp::init().empty();
+
+    let response = anon.get::<Value>("/api/v1/openapi/downloads.json");
+    let json: Value = response.good();
+    let document: OpenAPI = serde_json::from_value(json.clone()).unwrap();
+    assert!(document.openapi.starts_with("3.0"));
+
+    let paths = document.paths.paths;
+    let download = "/api/v1/crates/{crate_id}/{version}/download";
+    let downloads = "/api/v1/crates/{crate_id}/{version}/downloads";
+    assert!(paths.contains_key(download));
+    assert!(paths.contains_key(downloads));
+
+    for path in [download, downloads] {
+        let ReferenceOr::Item(item) = &paths[path] else {
+            panic!("unexpected reference for `{path}`");
+        };
+        let operation = item.get.as_ref().unwrap();
+        assert!(operation
+            .responses
+            .responses
+            .contains_key(&StatusCode::Code(200)));
+    }
+
+    // All schema references point into the `components` of the document
+    let schemas = document.components.unwrap().schemas;
+    assert!(schemas.contains_key("EncodableVersionDownload"));
+    for reference in schema_references(&json) {
+        let name = reference.strip_prefix("#/components/schemas/").unwrap();
+        assert!(
+            schemas.contains_key(name),
+            "unresolved reference `{reference}`"
+        );
+    }
+
+    let schema = &json["paths"][downloads]["get"]["responses"]["200"]["content"]
+        ["application/json"]["schema"];
+    assert_eq!(schema["$ref"], "#/components/schemas/DownloadsResponse");
+
+    // The query parameters are derived from the query types of the handlers
+    let parameters = &json["paths"][downloads]["get"]["parameters"];
+    let days = parameters
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|parameter| parameter["name"] == "days")
+        .unwrap();
+    assert_eq!(days["in"], "query");
+    assert_eq!(days["required"], false);
+    assert_eq!(days["schema"]["type"], "integer");
+    assert_eq!(
+        days["description"],
+        "Number of days to return, ending today."
+    );
+
+    let ReferenceOr::Item(item) = &paths[download] else {
+        panic!("unexpected reference for `{download}`");
+    };
+    let operation = item.get.as_ref().unwrap();
+    let parameters = operation
+        .parameters
+        .iter()
+        .filter_map(|parameter| parameter.as_item())
+        .map(|parameter| parameter.parameter_data_ref().name.as_str())
+        .collect::<Vec<_>>();
+    assert!(parameters.contains(&"no_redirect"));
+    for status in [200, 302, 400, 403, 404, 410, 429] {
+        let status = StatusCode::Code(status);
+        assert!(operation.responses.responses.contains_key(&status));
+    }
+    let ReferenceOr::Item(ok) = &operation.responses.responses[&StatusCode::Code(200)] else {
+        panic!("unexpected reference for the `200` response");
+    };
+    assert!(ok.content.contains_key("text/plain"));
+}
+
+fn schema_references(value: &Value) -> Vec<String> {
+    match value {
+        Value::Object(object) => object
+            .iter()
+            .flat_map(|(key, value)| match (key.as_str(), value) {
+                ("$ref", Value::String(reference)) => vec![reference.clone()],
+                _ => schema_references(value),
+            })
+            .collect(),
+        Value::Array(array) => array.iter().flat_map(schema_references).collect(),
+        _ => vec![],
+    }
+}
@@ -1,4 +1,5 @@
 use crate::util::{RequestHelper, TestApp};
+use http::StatusCode;
 
 #[derive(Deserialize)]
 struct UserStats {
@@ -62,3 +63,76 @@ fn user_total_downloads_no_crates() {
     let stats: UserStats = anon.get(&url).good();
     assert_eq!(stats.total_downloads, 0);
 }
+
+#[test]
+fn user_recent_downloads() {
+    use crate::builders::CrateBuilder;
+    use crate::routes::crates::downloads::save_version_downloads_on;
+    use chrono::{Duration, Utc};
+    use serde_json::json;
+
+    let (app, anon, user) = TestApp::init().with_user();
+    let user = user.as_model();
+    let another_user = app.db_new_user("bar");
+    let another_user = another_user.as_model();
+
+    let today = Utc::now().date_naive();
+
+    app.db(|conn| {
+        CrateBuilder::new("small", user.id)
+            .version("1.0.0")
+            .expect_build(conn);
+        CrateBuilder::new("large", user.id)
+            .version("1.0.0")
+            .version("1.1.0")
+            .expect_build(conn);
+        CrateBuilder::new("other", another_user.id)
+            .version("1.0.0")
+            .expect_build(conn);
+
+        save_version_downloads_on("small", "1.0.0", 5, today, conn);
+        save_version_downloads_on("large", "1.0.0", 20, today, conn);
+        save_version_downloads_on("large", "1.1.0", 30, today - Duration::days(10), conn);
+        save_version_downloads_on("other", "1.0.0", 100, today, conn);
+
+        // Outside of the default window
+        save_version_downloads_on("small", "1.0.0", 1000, today - Duration::days(45), conn);
+    });
+
+    let url = format!("/api/v1/users/{}/downloads", user.id);
+    let json = anon.get::<serde_json::Value>(&url).good();
+    assert_eq!(
+        json,
+        json!({
+            "total_downloads": 55,
+            "crates": [
+                { "name": "large", "downloads": 50 },
+                { "name": "small", "downloads": 5 },
+            ],
+            "meta": { "window": 30 },
+        })
+    );
+
+    let json = anon
+        .get_with_query::<serde_json::Value>(&url, "window=7")
+        .good();
+    assert_eq!(json["total_downloads"], 25);
+    assert_eq!(
+        json["crates"][0],
+        json!({ "name": "large", "downloads": 20 })
+    );
+
+    // Windows larger than the cap are clamped
+    let json = anon
+        .get_with_query::<serde_json::Value>(&url, "window=1000")
+        .good();
+    assert_eq!(json["total_downloads"], 1055);
+    assert_eq!(
+        json["crates"][0],
+        json!({ "name": "small", "downloads": 1005 })
+    );
+    assert_eq!(json["meta"]["window"], 90);
+
+    let response = anon.get_with_query::<()>(&url, "window=0");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
@@ -48,10 +48,18 @@ impl<T> Response<T> {
         assert_ok!(from_utf8(bytes)).to_string()
     }
 
+    pub fn body(&self) -> &Bytes {
+        self.response.body()
+    }
+
     pub fn status(&self) -> StatusCode {
         self.response.status()
     }
 
+    pub fn headers(&self) -> &http::HeaderMap {
+        self.response.headers()
+    }
+
     #[track_caller]
     pub fn assert_redirect_ends_with(&self, target: &str) -> &Self {
         let headers = self.response.headers();
@@ -6,6 +6,7 @@ use crates_io::config::{
     self, BalanceCapacityConfig, Base, CdnLogQueueConfig, CdnLogStorageConfig, DatabasePools,
     DbPoolConfig,
 };
+use crates_io::download_events::DownloadEvents;
 use crates_io::middleware::cargo_compat::StatusCodeConfig;
 use crates_io::models::token::{CrateScope, EndpointScope};
 use crates_io::rate_limiter::{LimitedAction, RateLimiterConfig};
@@ -20,7 +21,7 @@ use crates_io_worker::Runner;
 use diesel::PgConnection;
 use futures_util::TryStreamExt;
 use oauth2::{ClientId, ClientSecret};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::{rc::Rc, sync::Arc, time::Duration};
 use tokio::runtime::Runtime;
 use tracing::subscriber::DefaultGuard;
@@ -59,6 +60,11 @@ impl Drop for TestAppInner {
             self.runtime.block_on(handle.wait_for_shutdown());
 
             runner.check_for_failed_jobs().expect("Failed jobs remain");
+
+            // Idle `deadpool` connections that were opened by the jobs can
+            // only be dropped within the runtime
+            let _guard = self.runtime.enter();
+            self.app.deadpool_primary.close();
         }
 
         // Manually verify that all jobs have completed successfully
@@ -455,6 +461,19 @@ fn simple_config() -> config::Server {
         version_id_cache_size: 10000,
         version_id_cache_ttl: Duration::from_secs(5 * 60),
         cdn_user_agent: "Amazon CloudFront".to_string(),
+        download_mirrors: HashMap::new(),
+        download_base_url: None,
+        download_source_classifier: Default::default(),
+        download_redirect_307: false,
+        proxy_download: false,
+        download_rate_limit: None,
+        slow_downloads_query_threshold: Duration::from_secs(1),
+        downloads_watch_timeout: Duration::from_secs(1),
+        download_id_ttl: Duration::from_secs(60),
+        download_version_lookup: false,
+        private_crate_files: false,
+        download_events: false,
+        downloads_cors_origins: Default::default(),
         balance_capacity,
 
         // The middleware has its own unit tests to verify its functionality.
@@ -478,7 +497,15 @@ fn build_app(config: config::Server) -> (Arc<App>, axum::Router) {
     // organizations without actually having to create GitHub accounts.
     let github = Box::new(MockGitHubClient::new(&MOCK_GITHUB_DATA));
 
-    let app = App::new(config, emails, github);
+    let download_events = config.download_events;
+    let mut app = App::new(config, emails, github);
+
+    // Collect the download events in memory instead of writing them to
+    // stdout, allowing tests to assert the events that were published by the
+    // download endpoint.
+    if download_events {
+        app.download_events = DownloadEvents::new_in_memory();
+    }
 
     let app = Arc::new(app);
     let router = crates_io::build_handler(Arc::clone(&app));
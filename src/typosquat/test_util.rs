@@ -75,6 +75,7 @@ impl Faker {
             "0000000000000000000000000000000000000000000000000000000000000000".to_string(),
             None,
             None,
+            None,
         )
         .unwrap()
         .save(conn, "someone@example.com")
@@ -28,11 +28,15 @@ use crate::db::PoolError;
 use crate::middleware::log_request::ErrorField;
 
 mod json;
+mod locale;
 
 use crate::email::EmailError;
 use crates_io_github::GitHubError;
 pub use json::TOKEN_FORMAT_ERROR;
-pub(crate) use json::{custom, InsecurelyGeneratedTokenRevoked, ReadOnlyMode, TooManyRequests};
+pub(crate) use json::{
+    custom, DownloadRateLimited, InsecurelyGeneratedTokenRevoked, ReadOnlyMode, TooManyRequests,
+};
+pub use locale::Locale;
 
 pub type BoxedAppError = Box<dyn AppError>;
 
@@ -75,12 +79,105 @@ pub fn service_unavailable() -> BoxedAppError {
 }
 
 pub fn crate_not_found(krate: &str) -> BoxedAppError {
-    let detail = format!("crate `{krate}` does not exist");
+    localized_crate_not_found(krate, Locale::English)
+}
+
+/// Like [crate_not_found], but with the `detail` translated to `locale`.
+pub fn localized_crate_not_found(krate: &str, locale: Locale) -> BoxedAppError {
+    let detail = match locale {
+        Locale::English => format!("crate `{krate}` does not exist"),
+        Locale::German => format!("Crate `{krate}` existiert nicht"),
+        Locale::French => format!("la crate `{krate}` n'existe pas"),
+    };
     custom(StatusCode::NOT_FOUND, detail)
 }
 
 pub fn version_not_found(krate: &str, version: &str) -> BoxedAppError {
-    let detail = format!("crate `{krate}` does not have a version `{version}`");
+    localized_version_not_found(krate, version, Locale::English)
+}
+
+/// Like [version_not_found], but with the `detail` translated to `locale`.
+pub fn localized_version_not_found(krate: &str, version: &str, locale: Locale) -> BoxedAppError {
+    let detail = version_not_found_detail(krate, version, locale);
+    custom(StatusCode::NOT_FOUND, detail)
+}
+
+fn version_not_found_detail(krate: &str, version: &str, locale: Locale) -> String {
+    match locale {
+        Locale::English => format!("crate `{krate}` does not have a version `{version}`"),
+        Locale::German => format!("Crate `{krate}` hat keine Version `{version}`"),
+        Locale::French => format!("la crate `{krate}` n'a pas de version `{version}`"),
+    }
+}
+
+/// Error for versions that were permanently deleted, e.g. for legal or
+/// security reasons. Unlike [version_not_found], this responds with
+/// `410 Gone`, since the version existed before.
+pub fn version_deleted(krate: &str, version: &str) -> BoxedAppError {
+    localized_version_deleted(krate, version, Locale::English)
+}
+
+/// Like [version_deleted], but with the `detail` translated to `locale`.
+pub fn localized_version_deleted(krate: &str, version: &str, locale: Locale) -> BoxedAppError {
+    let detail = match locale {
+        Locale::English => {
+            format!("version `{version}` of crate `{krate}` has been deleted and is no longer available")
+        }
+        Locale::German => {
+            format!("Version `{version}` der Crate `{krate}` wurde gelöscht und ist nicht mehr verfügbar")
+        }
+        Locale::French => {
+            format!("la version `{version}` de la crate `{krate}` a été supprimée et n'est plus disponible")
+        }
+    };
+    custom(StatusCode::GONE, detail)
+}
+
+/// Like [localized_version_not_found], but hints at similar versions which
+/// the user might have meant instead.
+pub fn version_not_found_with_suggestions(
+    krate: &str,
+    version: &str,
+    suggestions: &[String],
+    locale: Locale,
+) -> BoxedAppError {
+    if suggestions.is_empty() {
+        return localized_version_not_found(krate, version, locale);
+    }
+
+    let suggestions = suggestions
+        .iter()
+        .map(|suggestion| format!("`{suggestion}`"))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let detail = version_not_found_detail(krate, version, locale);
+    let detail = match locale {
+        Locale::English => format!("{detail}. Did you mean one of: {suggestions}?"),
+        Locale::German => format!("{detail}. Meinten Sie eine von: {suggestions}?"),
+        Locale::French => format!("{detail}. Vouliez-vous dire l'une de : {suggestions} ?"),
+    };
+    custom(StatusCode::NOT_FOUND, detail)
+}
+
+/// Like [localized_version_not_found], but hints at the latest stable version
+/// of the crate if there is one.
+pub fn version_not_found_with_latest(
+    krate: &str,
+    version: &str,
+    latest_stable: Option<&str>,
+    locale: Locale,
+) -> BoxedAppError {
+    let Some(latest_stable) = latest_stable else {
+        return localized_version_not_found(krate, version, locale);
+    };
+
+    let detail = version_not_found_detail(krate, version, locale);
+    let detail = match locale {
+        Locale::English => format!("{detail}. The latest stable version is `{latest_stable}`."),
+        Locale::German => format!("{detail}. Die neueste stabile Version ist `{latest_stable}`."),
+        Locale::French => format!("{detail}. La dernière version stable est `{latest_stable}`."),
+    };
     custom(StatusCode::NOT_FOUND, detail)
 }
 
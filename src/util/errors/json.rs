@@ -2,6 +2,7 @@ use axum::response::{IntoResponse, Response};
 use axum::{Extension, Json};
 use std::borrow::Cow;
 use std::fmt;
+use std::time::Duration;
 
 use super::{AppError, BoxedAppError};
 
@@ -96,6 +97,35 @@ impl fmt::Display for TooManyRequests {
     }
 }
 
+/// The download rate limit of the client IP address was exceeded.
+#[derive(Debug)]
+pub(crate) struct DownloadRateLimited {
+    pub retry_after: Duration,
+}
+
+impl AppError for DownloadRateLimited {
+    fn response(&self) -> Response {
+        // `Retry-After` only supports whole seconds, so round up to make
+        // sure that the client does not retry too early
+        let retry_after = self.retry_after.as_secs_f64().ceil() as u64;
+
+        let detail = "You have downloaded too many crates in a short period of time. \
+                      Please try again later, as indicated by the `Retry-After` header, \
+                      or authenticate with an API token.";
+        let mut response = json_error(detail, StatusCode::TOO_MANY_REQUESTS);
+        response
+            .headers_mut()
+            .insert(header::RETRY_AFTER, retry_after.into());
+        response
+    }
+}
+
+impl fmt::Display for DownloadRateLimited {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        "Too many downloads".fmt(f)
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct InsecurelyGeneratedTokenRevoked;
 
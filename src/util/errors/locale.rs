@@ -0,0 +1,103 @@
+use async_trait::async_trait;
+use axum::extract::FromRequestParts;
+use http::header::ACCEPT_LANGUAGE;
+use http::request::Parts;
+use std::convert::Infallible;
+
+/// The languages that localized error messages are available in.
+///
+/// The locale of a request is resolved from its `Accept-Language` header and
+/// falls back to English if none of the requested languages is supported.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Locale {
+    #[default]
+    English,
+    German,
+    French,
+}
+
+impl Locale {
+    /// Returns the supported locale with the highest quality value in the
+    /// given `Accept-Language` header value.
+    ///
+    /// Region subtags are ignored, so e.g. `de-CH` resolves to German.
+    pub fn from_accept_language(value: &str) -> Self {
+        let mut languages = value
+            .split(',')
+            .filter_map(|entry| {
+                let mut parts = entry.split(';');
+                let tag = parts.next()?.trim();
+                let quality = parts
+                    .find_map(|param| param.trim().strip_prefix("q="))
+                    .map_or(Some(1.0), |q| q.trim().parse::<f32>().ok())?;
+
+                (quality > 0.0).then_some((tag, quality))
+            })
+            .collect::<Vec<_>>();
+
+        // `sort_by` is stable, so languages with the same quality keep the
+        // order of the header
+        languages.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+
+        languages
+            .into_iter()
+            .find_map(|(tag, _)| Self::from_language_tag(tag))
+            .unwrap_or_default()
+    }
+
+    fn from_language_tag(tag: &str) -> Option<Self> {
+        let language = tag.split('-').next()?.to_ascii_lowercase();
+        match language.as_str() {
+            "en" | "*" => Some(Locale::English),
+            "de" => Some(Locale::German),
+            "fr" => Some(Locale::French),
+            _ => None,
+        }
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for Locale {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let locale = parts
+            .headers
+            .get(ACCEPT_LANGUAGE)
+            .and_then(|value| value.to_str().ok())
+            .map(Locale::from_accept_language)
+            .unwrap_or_default();
+
+        Ok(locale)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_accept_language() {
+        assert_eq!(Locale::from_accept_language("de"), Locale::German);
+        assert_eq!(Locale::from_accept_language("de-CH"), Locale::German);
+        assert_eq!(Locale::from_accept_language("FR-fr"), Locale::French);
+        assert_eq!(Locale::from_accept_language("en-US,de"), Locale::English);
+
+        // Languages are picked by their quality value
+        let header = "en;q=0.5, fr;q=0.8, de;q=0.7";
+        assert_eq!(Locale::from_accept_language(header), Locale::French);
+
+        // Unsupported languages are skipped
+        let header = "ja, de;q=0.9";
+        assert_eq!(Locale::from_accept_language(header), Locale::German);
+
+        // Languages with a quality of zero are not acceptable
+        let header = "de;q=0, fr;q=0.1";
+        assert_eq!(Locale::from_accept_language(header), Locale::French);
+
+        // Unsupported or invalid values fall back to English
+        assert_eq!(Locale::from_accept_language("ja"), Locale::English);
+        assert_eq!(Locale::from_accept_language(""), Locale::English);
+        assert_eq!(Locale::from_accept_language("de;q=abc"), Locale::English);
+    }
+}
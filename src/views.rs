@@ -1,4 +1,5 @@
-use chrono::NaiveDateTime;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use schemars::JsonSchema;
 use secrecy::ExposeSecret;
 
 use crate::external_urls::remove_blocked_urls;
@@ -150,11 +151,24 @@ impl EncodableDependency {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, JsonSchema)]
 pub struct EncodableVersionDownload {
     pub version: i32,
     pub downloads: i32,
     pub date: String,
+    /// Running total of `downloads` over the returned series. Only included
+    /// when explicitly requested via `cumulative=true`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cumulative: Option<i64>,
+    /// Whether this is a synthetic entry for the downloads that happened
+    /// before the version was imported from another registry. Only included
+    /// for these entries.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub migrated: bool,
+    /// The `YYYY-Www` ISO week of `date`. Only included when explicitly
+    /// requested via `label=iso_week`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
 }
 
 impl From<VersionDownload> for EncodableVersionDownload {
@@ -163,10 +177,77 @@ impl From<VersionDownload> for EncodableVersionDownload {
             version: download.version_id,
             downloads: download.downloads,
             date: download.date.to_string(),
+            cumulative: None,
+            migrated: false,
+            label: None,
+        }
+    }
+}
+
+/// The response of the `GET /crates/:crate_id/:version/downloads` endpoint.
+#[derive(Serialize, Deserialize, Debug, PartialEq, JsonSchema)]
+pub struct DownloadsResponse {
+    pub version_downloads: Vec<EncodableVersionDownload>,
+    pub meta: DownloadsMeta,
+}
+
+/// The `compact=true` form of [`DownloadsResponse`], which uses parallel
+/// arrays instead of an object per entry to reduce the payload size.
+///
+/// Synthetic entries for migrated downloads are included like regular days.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct CompactDownloadsResponse {
+    pub dates: Vec<String>,
+    pub counts: Vec<i32>,
+    /// Only included when explicitly requested via `cumulative=true`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cumulative: Option<Vec<i64>>,
+    pub meta: DownloadsMeta,
+}
+
+impl From<DownloadsResponse> for CompactDownloadsResponse {
+    fn from(response: DownloadsResponse) -> Self {
+        let downloads = response.version_downloads;
+        let cumulative = downloads
+            .iter()
+            .map(|download| download.cumulative)
+            .collect::<Option<Vec<_>>>()
+            .filter(|cumulative| !cumulative.is_empty());
+
+        Self {
+            counts: downloads
+                .iter()
+                .map(|download| download.downloads)
+                .collect(),
+            dates: downloads
+                .into_iter()
+                .map(|download| download.date)
+                .collect(),
+            cumulative,
+            meta: response.meta,
         }
     }
 }
 
+#[derive(Serialize, Deserialize, Debug, PartialEq, JsonSchema)]
+pub struct DownloadsMeta {
+    /// Sum of the downloads in the requested window, across all pages.
+    pub total_downloads: i64,
+    /// Number of entries in the series before pagination. Only included
+    /// when the response is paginated.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total: Option<usize>,
+    /// Time at which downloads were last saved to the database, or `None` if
+    /// no downloads were saved yet.
+    pub last_persisted_at: Option<DateTime<Utc>>,
+    /// Start of the window for which download data is available. This is
+    /// later than the requested start if the version has no data that far
+    /// back, e.g. because it was published later or old data was pruned.
+    pub window_start: String,
+    /// Whether `window_start` was moved forward from the requested start.
+    pub window_clamped: bool,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct EncodableKeyword {
     pub id: String,
@@ -218,6 +299,11 @@ pub struct EncodableCrate {
     pub repository: Option<String>,
     pub links: EncodableCrateLinks,
     pub exact_match: bool,
+    /// The lifetime downloads divided by the number of days since the first
+    /// version was published. Only included by the `GET /crates/:crate_id`
+    /// endpoint if `normalized=true` is requested.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub downloads_per_day_since_publish: Option<f64>,
 }
 
 impl EncodableCrate {
@@ -305,6 +391,7 @@ impl EncodableCrate {
                 owner_user: Some(format!("/api/v1/crates/{name}/owner_user")),
                 reverse_dependencies: format!("/api/v1/crates/{name}/reverse_dependencies"),
             },
+            downloads_per_day_since_publish: None,
         }
     }
 
@@ -340,7 +427,7 @@ pub struct EncodableCrateLinks {
     pub reverse_dependencies: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct EncodableOwner {
     pub id: i32,
     pub login: String,
@@ -542,6 +629,18 @@ pub struct EncodableAuditAction {
     pub time: NaiveDateTime,
 }
 
+/// An owner that was added to or removed from a crate, as returned by the
+/// `owner-history` endpoint.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct EncodableOwnerAction {
+    pub action: String,
+    pub owner: EncodableOwner,
+    /// The user that performed the action.
+    pub user: EncodablePublicUser,
+    #[serde(with = "rfc3339")]
+    pub time: NaiveDateTime,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct EncodableVersion {
     pub id: i32,
@@ -558,6 +657,7 @@ pub struct EncodableVersion {
     pub downloads: i32,
     pub features: serde_json::Value,
     pub yanked: bool,
+    pub yank_reason: Option<String>,
     // NOTE: Used by shields.io, altering `license` requires a PR with shields.io
     pub license: Option<String>,
     pub links: EncodableVersionLinks,
@@ -566,6 +666,11 @@ pub struct EncodableVersion {
     pub audit_actions: Vec<EncodableAuditAction>,
     pub checksum: String,
     pub rust_version: Option<String>,
+    /// The first day on which the version was downloaded, or `None` if it
+    /// was never downloaded. Only included by the `GET /crates/:crate/:version`
+    /// endpoint.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub first_download_date: Option<Option<NaiveDate>>,
 }
 
 impl EncodableVersion {
@@ -583,6 +688,7 @@ impl EncodableVersion {
             downloads,
             features,
             yanked,
+            yank_reason,
             license,
             crate_size,
             checksum,
@@ -607,6 +713,7 @@ impl EncodableVersion {
             downloads,
             features,
             yanked,
+            yank_reason,
             license,
             links,
             crate_size,
@@ -621,6 +728,7 @@ impl EncodableVersion {
                     time: audit_action.time,
                 })
                 .collect(),
+            first_download_date: None,
         }
     }
 }
@@ -727,6 +835,7 @@ mod tests {
             downloads: 0,
             features: serde_json::from_str("{}").unwrap(),
             yanked: false,
+            yank_reason: None,
             license: None,
             links: EncodableVersionLinks {
                 dependencies: "".to_string(),
@@ -751,6 +860,7 @@ mod tests {
                     .and_hms_opt(14, 23, 12)
                     .unwrap(),
             }],
+            first_download_date: None,
         };
         let json = serde_json::to_string(&ver).unwrap();
         assert_some!(json
@@ -797,6 +907,7 @@ mod tests {
                 reverse_dependencies: "".to_string(),
             },
             exact_match: false,
+            downloads_per_day_since_publish: None,
         };
         let json = serde_json::to_string(&crt).unwrap();
         assert_some!(json
@@ -832,4 +943,40 @@ mod tests {
             .as_str()
             .find(r#""expires_at":"2020-10-24T16:30:00+00:00""#));
     }
+
+    #[test]
+    fn downloads_response_round_trip() {
+        let response = DownloadsResponse {
+            version_downloads: vec![EncodableVersionDownload {
+                version: 1,
+                downloads: 42,
+                date: "2024-03-01".to_string(),
+                cumulative: None,
+                migrated: false,
+                label: None,
+            }],
+            meta: DownloadsMeta {
+                total_downloads: 42,
+                total: None,
+                last_persisted_at: Some(
+                    NaiveDate::from_ymd_opt(2024, 3, 2)
+                        .unwrap()
+                        .and_hms_opt(8, 30, 0)
+                        .unwrap()
+                        .and_utc(),
+                ),
+                window_start: "2024-03-01".to_string(),
+                window_clamped: false,
+            },
+        };
+
+        let json = serde_json::to_string(&response).unwrap();
+        assert_eq!(
+            json,
+            r#"{"version_downloads":[{"version":1,"downloads":42,"date":"2024-03-01"}],"meta":{"total_downloads":42,"last_persisted_at":"2024-03-02T08:30:00Z","window_start":"2024-03-01","window_clamped":false}}"#
+        );
+
+        let deserialized: DownloadsResponse = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized, response);
+    }
 }
@@ -1,9 +1,13 @@
 mod clean_processed_log_files;
+mod notify_milestone;
 mod process_log;
+mod prune;
 mod queue;
 mod update_metadata;
 
 pub use clean_processed_log_files::CleanProcessedLogFiles;
-pub use process_log::ProcessCdnLog;
+pub use notify_milestone::NotifyDownloadMilestone;
+pub use process_log::{ProcessCdnLog, HOURLY_DOWNLOADS_RETENTION_HOURS};
+pub use prune::{prune_old_downloads, PruneVersionDownloads, DEFAULT_DOWNLOADS_RETENTION_DAYS};
 pub use queue::ProcessCdnLogQueue;
 pub use update_metadata::UpdateDownloads;
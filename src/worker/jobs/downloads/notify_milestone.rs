@@ -0,0 +1,80 @@
+use crate::schema::{crates, versions};
+use crate::worker::Environment;
+use anyhow::{anyhow, Context};
+use crates_io_worker::BackgroundJob;
+use diesel::prelude::*;
+use std::sync::Arc;
+
+/// Notifies the `download_milestone_webhook_url` of a crate that one of its
+/// versions has reached a download milestone.
+///
+/// These jobs are enqueued by the [`UpdateDownloads`](super::UpdateDownloads)
+/// job, which makes sure that every milestone is only enqueued once.
+#[derive(Serialize, Deserialize)]
+pub struct NotifyDownloadMilestone {
+    version_id: i32,
+    milestone: i64,
+}
+
+impl NotifyDownloadMilestone {
+    pub fn new(version_id: i32, milestone: i64) -> Self {
+        Self {
+            version_id,
+            milestone,
+        }
+    }
+}
+
+impl BackgroundJob for NotifyDownloadMilestone {
+    const JOB_NAME: &'static str = "notify_download_milestone";
+    const QUEUE: &'static str = "downloads";
+
+    type Context = Arc<Environment>;
+
+    #[instrument(skip_all, fields(version_id = self.version_id, milestone = self.milestone))]
+    async fn run(&self, env: Self::Context) -> anyhow::Result<()> {
+        let version_id = self.version_id;
+
+        let conn = env.deadpool.get().await?;
+        let row: Option<(String, String, Option<String>)> = conn
+            .interact(move |conn| {
+                versions::table
+                    .find(version_id)
+                    .inner_join(crates::table)
+                    .select((
+                        crates::name,
+                        versions::num,
+                        crates::download_milestone_webhook_url,
+                    ))
+                    .first(conn)
+                    .optional()
+            })
+            .await
+            .map_err(|err| anyhow!(err.to_string()))??;
+
+        // The version may have been deleted, or the crate may have opted out
+        // since the job was enqueued.
+        let Some((name, version, Some(url))) = row else {
+            info!("Skipping download milestone notification");
+            return Ok(());
+        };
+
+        let body = json!({
+            "crate": name,
+            "version": version,
+            "milestone": self.milestone,
+        });
+
+        env.http_client
+            .post(&url)
+            .json(&body)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .context("Failed to deliver download milestone notification")?;
+
+        info!(%name, %version, "Delivered download milestone notification");
+
+        Ok(())
+    }
+}
@@ -3,8 +3,10 @@ use crate::db::DieselPool;
 use crate::tasks::spawn_blocking;
 use crate::worker::Environment;
 use anyhow::Context;
-use chrono::NaiveDate;
-use crates_io_cdn_logs::{count_downloads, Decompressor, DownloadsMap};
+use chrono::{NaiveDate, NaiveDateTime, TimeDelta, Utc};
+use crates_io_cdn_logs::{
+    count_downloads, Decompressor, DownloadSource, DownloadsMap, SourceClassifier,
+};
 use crates_io_worker::BackgroundJob;
 use diesel::dsl::exists;
 use diesel::prelude::*;
@@ -15,10 +17,15 @@ use object_store::memory::InMemory;
 use object_store::path::Path;
 use object_store::ObjectStore;
 use semver::Version;
+use std::collections::HashSet;
 use std::fmt::Debug;
 use std::sync::Arc;
 use tokio::io::BufReader;
 
+/// The number of hours that the hourly downloads in the
+/// `version_downloads_by_hour` table are retained for.
+pub const HOURLY_DOWNLOADS_RETENTION_HOURS: i64 = 48;
+
 /// A background job that loads a CDN log file from an object store (aka. S3),
 /// counts the number of downloads for each crate and version, and then inserts
 /// the results into the database.
@@ -53,7 +60,8 @@ impl BackgroundJob for ProcessCdnLog {
             .context("Failed to build object store")?;
 
         let db_pool = ctx.connection_pool.clone();
-        run(store, &self.path, db_pool).await
+        let classifier = &ctx.config.download_source_classifier;
+        run(store, &self.path, db_pool, classifier).await
     }
 }
 
@@ -97,7 +105,12 @@ fn build_store(
 /// it can be tested without having to construct a full [`Environment`]
 /// struct.
 #[instrument(skip_all, fields(cdn_log_store.path = %path))]
-async fn run(store: Arc<dyn ObjectStore>, path: &str, db_pool: DieselPool) -> anyhow::Result<()> {
+async fn run(
+    store: Arc<dyn ObjectStore>,
+    path: &str,
+    db_pool: DieselPool,
+    classifier: &SourceClassifier,
+) -> anyhow::Result<()> {
     if already_processed(path, db_pool.clone()).await? {
         warn!("Skipping already processed log file");
         return Ok(());
@@ -106,7 +119,7 @@ async fn run(store: Arc<dyn ObjectStore>, path: &str, db_pool: DieselPool) -> an
     let parsed_path =
         Path::parse(path).with_context(|| format!("Failed to parse path: {path:?}"))?;
 
-    let downloads = load_and_count(&parsed_path, store).await?;
+    let downloads = load_and_count(&parsed_path, store, classifier).await?;
     if downloads.is_empty() {
         info!("No downloads found in log file");
         return Ok(());
@@ -130,7 +143,9 @@ async fn run(store: Arc<dyn ObjectStore>, path: &str, db_pool: DieselPool) -> an
             // file again.
             save_as_processed(&path, conn)?;
 
-            save_downloads(downloads, conn)
+            save_downloads(downloads, conn)?;
+
+            Ok::<_, anyhow::Error>(())
         })?;
 
         Ok::<_, anyhow::Error>(())
@@ -142,7 +157,11 @@ async fn run(store: Arc<dyn ObjectStore>, path: &str, db_pool: DieselPool) -> an
 
 /// Loads the given log file from the object store and counts the number of
 /// downloads for each crate and version.
-async fn load_and_count(path: &Path, store: Arc<dyn ObjectStore>) -> anyhow::Result<DownloadsMap> {
+async fn load_and_count(
+    path: &Path,
+    store: Arc<dyn ObjectStore>,
+    classifier: &SourceClassifier,
+) -> anyhow::Result<DownloadsMap> {
     let meta = store.head(path).await;
     let meta = meta.with_context(|| format!("Failed to request metadata for {path:?}"))?;
 
@@ -150,7 +169,7 @@ async fn load_and_count(path: &Path, store: Arc<dyn ObjectStore>) -> anyhow::Res
     let decompressor = Decompressor::from_extension(reader, path.extension())?;
     let reader = BufReader::new(decompressor);
 
-    count_downloads(reader).await
+    count_downloads(reader, classifier).await
 }
 
 /// Prints the total number of downloads, the number of crates, and the number
@@ -201,8 +220,19 @@ impl From<(String, Version, NaiveDate, u64)> for NewDownload {
     }
 }
 
+/// Summary of the rows written by [`save_downloads()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PersistSummary {
+    /// Number of `version_downloads` rows that were inserted or updated.
+    pub rows_updated: usize,
+    /// Number of crate/version combinations that could not be found in the
+    /// database and were skipped.
+    pub unknown_versions: usize,
+}
+
 /// Saves the downloads from the given [`DownloadsMap`] to the database into
-/// the `version_downloads` table.
+/// the `version_downloads` table and returns a [`PersistSummary`] of the
+/// written rows.
 ///
 /// This function **should be run inside a transaction** to ensure that the
 /// temporary `temp_downloads` table is dropped after the inserts are
@@ -211,7 +241,22 @@ impl From<(String, Version, NaiveDate, u64)> for NewDownload {
 /// The temporary table only exists on the current connection, but if a
 /// connection pool is used, the temporary table will not be dropped when
 /// the connection is returned to the pool.
-pub fn save_downloads(downloads: DownloadsMap, conn: &mut PgConnection) -> anyhow::Result<()> {
+pub fn save_downloads(
+    mut downloads: DownloadsMap,
+    conn: &mut PgConnection,
+) -> anyhow::Result<PersistSummary> {
+    let target_downloads = downloads.take_targets();
+    let source_downloads = downloads.take_sources();
+    let now = Utc::now().naive_utc();
+    let hourly_cutoff = now - TimeDelta::hours(HOURLY_DOWNLOADS_RETENTION_HOURS);
+
+    // Hourly downloads of older log files would be pruned right away, so they
+    // are skipped here.
+    let mut hourly_downloads = downloads.take_hours();
+    hourly_downloads.retain(|(_, _, hour, _)| *hour >= hourly_cutoff);
+
+    let num_rows = downloads.len();
+
     debug!("Creating temp_downloads table");
     create_temp_downloads_table(conn).context("Failed to create temp_downloads table")?;
 
@@ -228,7 +273,36 @@ pub fn save_downloads(downloads: DownloadsMap, conn: &mut PgConnection) -> anyho
         );
     }
 
-    Ok(())
+    if !target_downloads.is_empty() {
+        debug!("Saving per-target downloads to version_downloads_by_target table");
+        save_target_downloads(target_downloads, conn)
+            .context("Failed to save per-target downloads")?;
+    }
+
+    if !source_downloads.is_empty() {
+        debug!("Saving per-source downloads to version_downloads_by_source table");
+        save_source_downloads(source_downloads, conn)
+            .context("Failed to save per-source downloads")?;
+    }
+
+    if !hourly_downloads.is_empty() {
+        debug!("Saving hourly downloads to version_downloads_by_hour table");
+        save_hourly_downloads(hourly_downloads, conn).context("Failed to save hourly downloads")?;
+    }
+
+    debug!("Pruning old hourly downloads from version_downloads_by_hour table");
+    prune_hourly_downloads(hourly_cutoff, conn).context("Failed to prune hourly downloads")?;
+
+    // There is a failed insert for every date of an unknown version
+    let unknown_versions = failed_inserts
+        .iter()
+        .map(|failed| (&failed.name, &failed.version))
+        .collect::<HashSet<_>>();
+
+    Ok(PersistSummary {
+        rows_updated: num_rows - failed_inserts.len(),
+        unknown_versions: unknown_versions.len(),
+    })
 }
 
 /// Creates the temporary `temp_downloads` table that is used to store the
@@ -315,6 +389,239 @@ fn save_to_version_downloads(conn: &mut PgConnection) -> QueryResult<Vec<NameAnd
         .load(conn)
 }
 
+table! {
+    /// Diesel table definition for the temporary `temp_breakdown_downloads`
+    /// table that is created by the [`save_breakdown_downloads`] function.
+    ///
+    /// The primary key does not actually exist, but specifying one is
+    /// required by Diesel.
+    temp_breakdown_downloads (name, version, key, date) {
+        name -> Text,
+        version -> Text,
+        key -> Text,
+        date -> Date,
+        downloads -> BigInt,
+    }
+}
+
+/// Helper struct for inserting downloads into the `temp_breakdown_downloads`
+/// table.
+///
+/// The `key` is the value that the downloads are broken down by, e.g. the
+/// target triple or the download source.
+#[derive(Insertable)]
+#[diesel(table_name = temp_breakdown_downloads)]
+struct NewBreakdownDownload {
+    name: String,
+    version: String,
+    key: String,
+    date: NaiveDate,
+    downloads: i64,
+}
+
+impl<K: ToString> From<(String, Version, K, NaiveDate, u64)> for NewBreakdownDownload {
+    fn from((name, version, key, date, downloads): (String, Version, K, NaiveDate, u64)) -> Self {
+        Self {
+            name,
+            version: version.to_string(),
+            key: key.to_string(),
+            date,
+            downloads: downloads as i64,
+        }
+    }
+}
+
+/// Saves the per-target downloads to the `version_downloads_by_target`
+/// table.
+#[instrument(
+    "db.query",
+    skip_all,
+    fields(message = "INSERT INTO version_downloads_by_target ...")
+)]
+fn save_target_downloads(
+    target_downloads: Vec<(String, Version, String, NaiveDate, u64)>,
+    conn: &mut PgConnection,
+) -> QueryResult<()> {
+    let rows = target_downloads.into_iter().map(Into::into).collect();
+    save_breakdown_downloads(rows, "version_downloads_by_target", "target", conn)
+}
+
+/// Saves the per-source downloads to the `version_downloads_by_source`
+/// table.
+#[instrument(
+    "db.query",
+    skip_all,
+    fields(message = "INSERT INTO version_downloads_by_source ...")
+)]
+fn save_source_downloads(
+    source_downloads: Vec<(String, Version, DownloadSource, NaiveDate, u64)>,
+    conn: &mut PgConnection,
+) -> QueryResult<()> {
+    let rows = source_downloads.into_iter().map(Into::into).collect();
+    save_breakdown_downloads(rows, "version_downloads_by_source", "source", conn)
+}
+
+/// Saves the given downloads to the `table` breakdown table, with the `key`
+/// of each row stored in `column`. This is using the same temporary table
+/// approach as [`save_downloads()`].
+///
+/// Downloads of unknown crates and versions are silently skipped, since they
+/// are already reported by [`save_to_version_downloads()`].
+fn save_breakdown_downloads(
+    rows: Vec<NewBreakdownDownload>,
+    table: &'static str,
+    column: &'static str,
+    conn: &mut PgConnection,
+) -> QueryResult<()> {
+    // We fill five columns per [NewBreakdownDownload], so the batch size is
+    // still well below the Postgres parameter limit.
+    const MAX_BATCH_SIZE: usize = 10_000;
+
+    diesel::sql_query(
+        r#"
+            CREATE TEMPORARY TABLE temp_breakdown_downloads (
+                name VARCHAR NOT NULL,
+                version VARCHAR NOT NULL,
+                key VARCHAR NOT NULL,
+                date DATE NOT NULL,
+                downloads INTEGER NOT NULL
+            ) ON COMMIT DROP;
+        "#,
+    )
+    .execute(conn)?;
+
+    for chunk in rows.chunks(MAX_BATCH_SIZE) {
+        diesel::insert_into(temp_breakdown_downloads::table)
+            .values(chunk)
+            .execute(conn)?;
+    }
+
+    diesel::sql_query(format!(
+        r#"
+            INSERT INTO {table} (version_id, {column}, date, downloads)
+            SELECT versions.id, temp_breakdown_downloads.key, temp_breakdown_downloads.date, temp_breakdown_downloads.downloads
+            FROM temp_breakdown_downloads
+            INNER JOIN crates ON crates.name = temp_breakdown_downloads.name
+            INNER JOIN versions ON versions.num = temp_breakdown_downloads.version AND versions.crate_id = crates.id
+            ORDER BY versions.id, temp_breakdown_downloads.key, temp_breakdown_downloads.date
+            ON CONFLICT (version_id, {column}, date)
+            DO UPDATE SET downloads = {table}.downloads + EXCLUDED.downloads;
+        "#,
+    ))
+    .execute(conn)?;
+
+    // The temporary table has to be dropped explicitly, since this function
+    // may be called multiple times within the same transaction.
+    diesel::sql_query("DROP TABLE temp_breakdown_downloads;").execute(conn)?;
+
+    Ok(())
+}
+
+table! {
+    /// Imaginary table to make Diesel happy when inserting into the
+    /// `temp_hourly_downloads` table.
+    temp_hourly_downloads (name, version, hour) {
+        name -> Text,
+        version -> Text,
+        hour -> Timestamp,
+        downloads -> BigInt,
+    }
+}
+
+/// Helper struct for inserting downloads into the `temp_hourly_downloads`
+/// table.
+#[derive(Insertable)]
+#[diesel(table_name = temp_hourly_downloads)]
+struct NewHourlyDownload {
+    name: String,
+    version: String,
+    hour: NaiveDateTime,
+    downloads: i64,
+}
+
+impl From<(String, Version, NaiveDateTime, u64)> for NewHourlyDownload {
+    fn from((name, version, hour, downloads): (String, Version, NaiveDateTime, u64)) -> Self {
+        Self {
+            name,
+            version: version.to_string(),
+            hour,
+            downloads: downloads as i64,
+        }
+    }
+}
+
+/// Saves the hourly downloads to the `version_downloads_by_hour` table. This
+/// is using the same temporary table approach as
+/// [`save_breakdown_downloads()`].
+#[instrument(
+    "db.query",
+    skip_all,
+    fields(message = "INSERT INTO version_downloads_by_hour ...")
+)]
+fn save_hourly_downloads(
+    hourly_downloads: Vec<(String, Version, NaiveDateTime, u64)>,
+    conn: &mut PgConnection,
+) -> QueryResult<()> {
+    // We fill four columns per [NewHourlyDownload], so the batch size is
+    // still well below the Postgres parameter limit.
+    const MAX_BATCH_SIZE: usize = 10_000;
+
+    let rows = hourly_downloads
+        .into_iter()
+        .map(NewHourlyDownload::from)
+        .collect::<Vec<_>>();
+
+    diesel::sql_query(
+        r#"
+            CREATE TEMPORARY TABLE temp_hourly_downloads (
+                name VARCHAR NOT NULL,
+                version VARCHAR NOT NULL,
+                hour TIMESTAMP NOT NULL,
+                downloads INTEGER NOT NULL
+            ) ON COMMIT DROP;
+        "#,
+    )
+    .execute(conn)?;
+
+    for chunk in rows.chunks(MAX_BATCH_SIZE) {
+        diesel::insert_into(temp_hourly_downloads::table)
+            .values(chunk)
+            .execute(conn)?;
+    }
+
+    diesel::sql_query(
+        r#"
+            INSERT INTO version_downloads_by_hour (version_id, hour, downloads)
+            SELECT versions.id, temp_hourly_downloads.hour, temp_hourly_downloads.downloads
+            FROM temp_hourly_downloads
+            INNER JOIN crates ON crates.name = temp_hourly_downloads.name
+            INNER JOIN versions ON versions.num = temp_hourly_downloads.version AND versions.crate_id = crates.id
+            ORDER BY versions.id, temp_hourly_downloads.hour
+            ON CONFLICT (version_id, hour)
+            DO UPDATE SET downloads = version_downloads_by_hour.downloads + EXCLUDED.downloads;
+        "#,
+    )
+    .execute(conn)?;
+
+    diesel::sql_query("DROP TABLE temp_hourly_downloads;").execute(conn)?;
+
+    Ok(())
+}
+
+/// Deletes the hourly downloads that were counted before the given `cutoff`.
+#[instrument(
+    "db.query",
+    skip_all,
+    fields(message = "DELETE FROM version_downloads_by_hour ...")
+)]
+fn prune_hourly_downloads(cutoff: NaiveDateTime, conn: &mut PgConnection) -> QueryResult<usize> {
+    use crate::schema::version_downloads_by_hour;
+
+    diesel::delete(version_downloads_by_hour::table)
+        .filter(version_downloads_by_hour::hour.lt(cutoff))
+        .execute(conn)
+}
+
 table! {
     /// Imaginary table to make Diesel happy when using the `sql_query` macro in
     /// the [`save_to_version_downloads()`] function.
@@ -385,7 +692,11 @@ fn save_as_processed(path: impl Into<String>, conn: &mut PgConnection) -> QueryR
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::schema::{crates, version_downloads, versions};
+    use crate::schema::{
+        crates, version_downloads, version_downloads_by_hour, version_downloads_by_source,
+        version_downloads_by_target, versions,
+    };
+    use chrono::DurationRound;
     use crates_io_test_db::TestDatabase;
     use diesel::r2d2::{ConnectionManager, Pool};
     use insta::assert_debug_snapshot;
@@ -405,7 +716,8 @@ mod tests {
 
         assert_ok!({
             let store = store.clone();
-            run(store, CLOUDFRONT_PATH, db_pool.clone()).await
+            let classifier = SourceClassifier::default();
+            run(store, CLOUDFRONT_PATH, db_pool.clone(), &classifier).await
         });
         assert_debug_snapshot!(all_version_downloads(db_pool.clone()).await, @r###"
         [
@@ -418,7 +730,8 @@ mod tests {
 
         // Check that processing the same log file again does not insert
         // duplicate data.
-        assert_ok!(run(store, CLOUDFRONT_PATH, db_pool.clone()).await);
+        let classifier = SourceClassifier::default();
+        assert_ok!(run(store, CLOUDFRONT_PATH, db_pool.clone(), &classifier).await);
         assert_debug_snapshot!(all_version_downloads(db_pool).await, @r###"
         [
             "bindgen | 0.65.1 | 1 | 0 | 2024-01-16 | false",
@@ -429,6 +742,176 @@ mod tests {
         "###);
     }
 
+    #[test]
+    fn test_save_downloads_summary() {
+        let test_database = TestDatabase::new();
+        let mut conn = test_database.connect();
+        create_crate_and_version("bindgen", "0.65.1", &mut conn);
+
+        let version = "0.65.1".parse::<Version>().unwrap();
+        let unknown_version = "9.9.9".parse::<Version>().unwrap();
+        let date = "2024-01-16".parse::<NaiveDate>().unwrap();
+        let next_date = "2024-01-17".parse::<NaiveDate>().unwrap();
+
+        let mut downloads = DownloadsMap::new();
+        downloads.add("bindgen".into(), version.clone(), date);
+        downloads.add("bindgen".into(), version.clone(), date);
+        downloads.add("bindgen".into(), version, next_date);
+        downloads.add("bindgen".into(), unknown_version.clone(), date);
+        downloads.add("bindgen".into(), unknown_version, next_date);
+
+        let summary = conn.transaction(|conn| save_downloads(downloads, conn));
+        assert_eq!(
+            assert_ok!(summary),
+            PersistSummary {
+                rows_updated: 2,
+                unknown_versions: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn test_save_target_downloads() {
+        let test_database = TestDatabase::new();
+        let mut conn = test_database.connect();
+        create_crate_and_version("bindgen", "0.65.1", &mut conn);
+
+        let version = "0.65.1".parse::<Version>().unwrap();
+        let unknown_version = "9.9.9".parse::<Version>().unwrap();
+        let date = "2024-01-16".parse::<NaiveDate>().unwrap();
+        let linux = "x86_64-unknown-linux-gnu";
+
+        let mut downloads = DownloadsMap::new();
+        for target in [Some(linux), Some(linux), None, Some("aarch64-apple-darwin")] {
+            if let Some(target) = target {
+                let target = target.to_string();
+                downloads.add_target("bindgen".into(), version.clone(), target, date);
+            }
+            downloads.add("bindgen".into(), version.clone(), date);
+        }
+        downloads.add_target("bindgen".into(), unknown_version, linux.into(), date);
+
+        assert_ok!(conn.transaction(|conn| save_downloads(downloads.clone(), conn)));
+        assert_ok!(conn.transaction(|conn| save_downloads(downloads, conn)));
+
+        let rows: Vec<(String, NaiveDate, i32)> = version_downloads_by_target::table
+            .select((
+                version_downloads_by_target::target,
+                version_downloads_by_target::date,
+                version_downloads_by_target::downloads,
+            ))
+            .order(version_downloads_by_target::target)
+            .load(&mut conn)
+            .unwrap();
+
+        assert_eq!(
+            rows,
+            vec![
+                ("aarch64-apple-darwin".to_string(), date, 2),
+                (linux.to_string(), date, 4),
+            ]
+        );
+
+        let downloads = query_all_version_downloads(&mut conn);
+        assert_eq!(downloads.len(), 1);
+        assert_eq!(downloads[0].2, 8);
+    }
+
+    #[test]
+    fn test_save_source_downloads() {
+        let test_database = TestDatabase::new();
+        let mut conn = test_database.connect();
+        create_crate_and_version("bindgen", "0.65.1", &mut conn);
+
+        let version = "0.65.1".parse::<Version>().unwrap();
+        let date = "2024-01-16".parse::<NaiveDate>().unwrap();
+        let linux = "x86_64-unknown-linux-gnu";
+
+        let mut downloads = DownloadsMap::new();
+        for source in [
+            DownloadSource::Ci,
+            DownloadSource::Ci,
+            DownloadSource::Human,
+        ] {
+            downloads.add_source("bindgen".into(), version.clone(), source, date);
+            downloads.add("bindgen".into(), version.clone(), date);
+        }
+        downloads.add("bindgen".into(), version.clone(), date);
+
+        // Per-target downloads are saved within the same transaction
+        downloads.add_target("bindgen".into(), version, linux.into(), date);
+
+        assert_ok!(conn.transaction(|conn| save_downloads(downloads, conn)));
+
+        let rows: Vec<(String, NaiveDate, i32)> = version_downloads_by_source::table
+            .select((
+                version_downloads_by_source::source,
+                version_downloads_by_source::date,
+                version_downloads_by_source::downloads,
+            ))
+            .order(version_downloads_by_source::source)
+            .load(&mut conn)
+            .unwrap();
+
+        assert_eq!(
+            rows,
+            vec![("ci".to_string(), date, 2), ("human".to_string(), date, 1)]
+        );
+
+        let targets: i64 = version_downloads_by_target::table
+            .count()
+            .get_result(&mut conn)
+            .unwrap();
+        assert_eq!(targets, 1);
+    }
+
+    #[test]
+    fn test_save_hourly_downloads() {
+        let test_database = TestDatabase::new();
+        let mut conn = test_database.connect();
+        create_crate_and_version("bindgen", "0.65.1", &mut conn);
+
+        let version = "0.65.1".parse::<Version>().unwrap();
+        let now = Utc::now().naive_utc();
+        let hour = now.duration_trunc(TimeDelta::hours(1)).unwrap();
+        let previous_hour = hour - TimeDelta::hours(1);
+        let expired_hour = hour - TimeDelta::hours(50);
+
+        // Rows that are older than the retention period are pruned
+        let version_id: i32 = versions::table
+            .select(versions::id)
+            .get_result(&mut conn)
+            .unwrap();
+        diesel::insert_into(version_downloads_by_hour::table)
+            .values((
+                version_downloads_by_hour::version_id.eq(version_id),
+                version_downloads_by_hour::hour.eq(hour - TimeDelta::hours(60)),
+                version_downloads_by_hour::downloads.eq(5),
+            ))
+            .execute(&mut conn)
+            .unwrap();
+
+        let mut downloads = DownloadsMap::new();
+        for time in [previous_hour, previous_hour, hour, expired_hour] {
+            downloads.add_hour("bindgen".into(), version.clone(), time);
+            downloads.add("bindgen".into(), version.clone(), time.date());
+        }
+
+        assert_ok!(conn.transaction(|conn| save_downloads(downloads.clone(), conn)));
+        assert_ok!(conn.transaction(|conn| save_downloads(downloads, conn)));
+
+        let rows: Vec<(NaiveDateTime, i32)> = version_downloads_by_hour::table
+            .select((
+                version_downloads_by_hour::hour,
+                version_downloads_by_hour::downloads,
+            ))
+            .order(version_downloads_by_hour::hour)
+            .load(&mut conn)
+            .unwrap();
+
+        assert_eq!(rows, vec![(previous_hour, 4), (hour, 2)]);
+    }
+
     #[test]
     fn test_build_store_s3() {
         let access_key = "access_key".into();
@@ -0,0 +1,267 @@
+use crate::worker::Environment;
+use anyhow::anyhow;
+use crates_io_worker::BackgroundJob;
+use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Integer};
+use std::sync::Arc;
+
+/// The number of days of `version_downloads` that are kept by default.
+///
+/// This has to cover the longest window that the download endpoints allow
+/// requesting, which is currently 365 days.
+pub const DEFAULT_DOWNLOADS_RETENTION_DAYS: i32 = 365;
+
+/// This job is responsible for deleting `version_downloads` rows that are
+/// older than the retention period.
+///
+/// See [`prune_old_downloads`] for details.
+#[derive(Serialize, Deserialize)]
+pub struct PruneVersionDownloads {
+    older_than_days: i32,
+}
+
+impl PruneVersionDownloads {
+    pub fn new(older_than_days: i32) -> Self {
+        Self { older_than_days }
+    }
+}
+
+impl Default for PruneVersionDownloads {
+    fn default() -> Self {
+        Self::new(DEFAULT_DOWNLOADS_RETENTION_DAYS)
+    }
+}
+
+impl BackgroundJob for PruneVersionDownloads {
+    const JOB_NAME: &'static str = "prune_version_downloads";
+    const QUEUE: &'static str = "downloads";
+
+    type Context = Arc<Environment>;
+
+    async fn run(&self, env: Self::Context) -> anyhow::Result<()> {
+        let older_than_days = self.older_than_days;
+
+        let conn = env.deadpool.get().await?;
+        let count = conn
+            .interact(move |conn| prune_old_downloads(conn, older_than_days))
+            .await
+            .map_err(|err| anyhow!(err.to_string()))??;
+
+        info!("Pruned {count} version_downloads rows older than {older_than_days} days");
+
+        Ok(())
+    }
+}
+
+/// Deletes all `version_downloads` rows that are older than the given number
+/// of days, and returns the number of deleted rows.
+///
+/// Before the rows are deleted, any of their downloads that were not counted
+/// by the `update_downloads` job yet are added to the `downloads` totals of
+/// the versions, crates and the `metadata` table, so that no downloads are
+/// lost. The deleted downloads are also added to `versions.pruned_downloads`,
+/// which allows the lifetime totals to be recomputed from the remaining rows.
+pub fn prune_old_downloads(conn: &mut PgConnection, older_than_days: i32) -> QueryResult<usize> {
+    // We delete the rows in batches to avoid holding locks on the involved
+    // tables for too long.
+    const BATCH_SIZE: i64 = 10_000;
+
+    let mut total = 0;
+    loop {
+        let count = batch_prune(conn, older_than_days, BATCH_SIZE)?;
+        total += count as usize;
+        if count < BATCH_SIZE {
+            return Ok(total);
+        }
+    }
+}
+
+#[derive(QueryableByName)]
+struct SqlQueryResult {
+    #[diesel(sql_type = BigInt)]
+    count: i64,
+}
+
+fn batch_prune(conn: &mut PgConnection, older_than_days: i32, batch_size: i64) -> QueryResult<i64> {
+    let query = diesel::sql_query(
+        r#"
+            WITH pruned AS (
+                -- Delete a batch of rows that are older than the retention
+                -- period, sorted by `version_id` and `date` to avoid deadlocks.
+                DELETE FROM version_downloads
+                WHERE (version_id, date) IN (
+                    SELECT version_id, date
+                    FROM version_downloads
+                    WHERE date < CURRENT_DATE - $1
+                    ORDER BY version_id, date
+                    LIMIT $2
+                    FOR UPDATE
+                )
+                RETURNING version_id, downloads, counted
+            ), pruned_versions AS (
+                -- Group the deleted rows by `version_id`, and calculate how
+                -- many of their downloads were not counted yet.
+                SELECT
+                    pruned.version_id,
+                    versions.crate_id,
+                    SUM(pruned.downloads) AS downloads,
+                    SUM(pruned.downloads - pruned.counted) AS uncounted
+                FROM pruned
+                JOIN versions ON versions.id = pruned.version_id
+                GROUP BY pruned.version_id, versions.crate_id
+            ), updated_versions AS (
+                -- Add the uncounted downloads to the `downloads` total and
+                -- remember how many downloads were pruned for each version.
+                UPDATE versions
+                SET downloads = versions.downloads + pruned_versions.uncounted,
+                    pruned_downloads = versions.pruned_downloads + pruned_versions.downloads
+                FROM pruned_versions
+                WHERE versions.id = pruned_versions.version_id
+            ), pruned_crates AS (
+                SELECT crate_id, SUM(uncounted) AS uncounted
+                FROM pruned_versions
+                GROUP BY crate_id
+            ), updated_crate_downloads AS (
+                -- Add the uncounted downloads to the `downloads` total of
+                -- each crate in the `crate_downloads` table.
+                UPDATE crate_downloads
+                SET downloads = crate_downloads.downloads + pruned_crates.uncounted
+                FROM pruned_crates
+                WHERE crate_downloads.crate_id = pruned_crates.crate_id
+                    AND pruned_crates.uncounted > 0
+            ), updated_metadata AS (
+                -- Add the uncounted downloads to the `total_downloads` count
+                -- in the `metadata` table.
+                UPDATE metadata
+                SET total_downloads = metadata.total_downloads + sum.uncounted
+                FROM (
+                    SELECT COALESCE(SUM(uncounted), 0) AS uncounted
+                    FROM pruned_versions
+                ) sum
+                WHERE sum.uncounted > 0
+            )
+            -- Return the number of deleted rows to determine whether there
+            -- are more rows to prune.
+            SELECT COUNT(*) AS count FROM pruned
+        "#,
+    );
+
+    let result = query
+        .bind::<Integer, _>(older_than_days)
+        .bind::<BigInt, _>(batch_size)
+        .get_result::<SqlQueryResult>(conn)?;
+
+    Ok(result.count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::email::Emails;
+    use crate::models::{Crate, NewCrate, NewUser, NewVersion, Version};
+    use crate::schema::{crate_downloads, metadata, version_downloads, versions};
+    use crate::test_util::test_db_connection;
+    use chrono::{Days, NaiveDate, Utc};
+    use std::collections::BTreeMap;
+
+    fn crate_and_version(conn: &mut PgConnection) -> (Crate, Version) {
+        let user = NewUser::new(2, "login", None, None, "access_token")
+            .create_or_update(None, &Emails::new_in_memory(), conn)
+            .unwrap();
+        let krate = NewCrate {
+            name: "foo",
+            ..Default::default()
+        }
+        .create(conn, user.id)
+        .unwrap();
+        let version = NewVersion::new(
+            krate.id,
+            &semver::Version::parse("1.0.0").unwrap(),
+            &BTreeMap::new(),
+            None,
+            0,
+            user.id,
+            "0000000000000000000000000000000000000000000000000000000000000000".to_string(),
+            None,
+            None,
+            None,
+        )
+        .unwrap();
+        let version = version.save(conn, "someone@example.com").unwrap();
+        (krate, version)
+    }
+
+    fn insert_downloads(
+        conn: &mut PgConnection,
+        version_id: i32,
+        days_ago: u64,
+        downloads: i32,
+        counted: i32,
+    ) {
+        let date = Utc::now().date_naive() - Days::new(days_ago);
+        diesel::insert_into(version_downloads::table)
+            .values((
+                version_downloads::version_id.eq(version_id),
+                version_downloads::date.eq(date),
+                version_downloads::downloads.eq(downloads),
+                version_downloads::counted.eq(counted),
+            ))
+            .execute(conn)
+            .unwrap();
+    }
+
+    #[test]
+    fn prune_old_rows() {
+        let (_test_db, conn) = &mut test_db_connection();
+        let (krate, version) = crate_and_version(conn);
+
+        // Two of the downloads of the oldest row were not counted yet
+        insert_downloads(conn, version.id, 500, 5, 3);
+        insert_downloads(conn, version.id, 400, 10, 10);
+        insert_downloads(conn, version.id, 0, 7, 7);
+
+        diesel::update(versions::table.find(version.id))
+            .set(versions::downloads.eq(20))
+            .execute(conn)
+            .unwrap();
+        diesel::update(crate_downloads::table.find(krate.id))
+            .set(crate_downloads::downloads.eq(20))
+            .execute(conn)
+            .unwrap();
+        diesel::update(metadata::table)
+            .set(metadata::total_downloads.eq(20))
+            .execute(conn)
+            .unwrap();
+
+        assert_eq!(prune_old_downloads(conn, 365).unwrap(), 2);
+
+        let dates: Vec<NaiveDate> = version_downloads::table
+            .select(version_downloads::date)
+            .load(conn)
+            .unwrap();
+        assert_eq!(dates, vec![Utc::now().date_naive()]);
+
+        let version: Version = versions::table.find(version.id).first(conn).unwrap();
+        assert_eq!(version.downloads, 22);
+        assert_eq!(version.pruned_downloads, 15);
+
+        let crate_downloads: i64 = crate_downloads::table
+            .find(krate.id)
+            .select(crate_downloads::downloads)
+            .first(conn)
+            .unwrap();
+        assert_eq!(crate_downloads, 22);
+
+        let total_downloads: i64 = metadata::table
+            .select(metadata::total_downloads)
+            .first(conn)
+            .unwrap();
+        assert_eq!(total_downloads, 22);
+
+        // Running the job again does not change anything
+        assert_eq!(prune_old_downloads(conn, 365).unwrap(), 0);
+        let version: Version = versions::table.find(version.id).first(conn).unwrap();
+        assert_eq!(version.downloads, 22);
+        assert_eq!(version.pruned_downloads, 15);
+    }
+}
@@ -1,4 +1,5 @@
-use crate::schema::version_downloads;
+use crate::schema::{crates, version_download_milestones, version_downloads, versions};
+use crate::worker::jobs::NotifyDownloadMilestone;
 use crate::worker::Environment;
 use anyhow::anyhow;
 use crates_io_worker::BackgroundJob;
@@ -25,7 +26,19 @@ impl BackgroundJob for UpdateDownloads {
     }
 }
 
-fn update(conn: &mut PgConnection) -> QueryResult<()> {
+/// The download counts at which crates with a `download_milestone_webhook_url`
+/// are notified.
+const DOWNLOAD_MILESTONES: [i32; 7] = [
+    1_000,
+    10_000,
+    100_000,
+    1_000_000,
+    10_000_000,
+    100_000_000,
+    1_000_000_000,
+];
+
+fn update(conn: &mut PgConnection) -> anyhow::Result<()> {
     use diesel::dsl::now;
     use diesel::select;
 
@@ -57,6 +70,9 @@ fn update(conn: &mut PgConnection) -> QueryResult<()> {
 
     info!("Finished updating versions");
 
+    let milestone_count = record_download_milestones(conn)?;
+    info!("Recorded {milestone_count} download milestones");
+
     // Anything older than 24 hours ago will be frozen and will not be queried
     // against again.
     diesel::update(version_downloads::table)
@@ -165,6 +181,47 @@ fn batch_update(batch_size: i64, conn: &mut PgConnection) -> QueryResult<i64> {
     Ok(result.count)
 }
 
+/// Records the download milestones that versions of opted-in crates have
+/// reached, and enqueues a [`NotifyDownloadMilestone`] job for each of them.
+///
+/// Milestones that were already recorded are skipped, so that every milestone
+/// is only delivered once. If a crate opts in after reaching a milestone, it
+/// is delivered on the next run.
+fn record_download_milestones(conn: &mut PgConnection) -> anyhow::Result<usize> {
+    let mut count = 0;
+    for milestone in DOWNLOAD_MILESTONES {
+        conn.transaction(|conn| {
+            let reached = versions::table
+                .inner_join(crates::table)
+                .filter(crates::download_milestone_webhook_url.is_not_null())
+                .filter(versions::downloads.ge(milestone))
+                .select((versions::id, i64::from(milestone).into_sql::<BigInt>()));
+
+            let recorded: Vec<(i32, i64)> = diesel::insert_into(version_download_milestones::table)
+                .values(reached)
+                .into_columns((
+                    version_download_milestones::version_id,
+                    version_download_milestones::milestone,
+                ))
+                .on_conflict_do_nothing()
+                .returning((
+                    version_download_milestones::version_id,
+                    version_download_milestones::milestone,
+                ))
+                .get_results(conn)?;
+
+            for (version_id, milestone) in recorded {
+                NotifyDownloadMilestone::new(version_id, milestone).enqueue(conn)?;
+                count += 1;
+            }
+
+            Ok::<_, anyhow::Error>(())
+        })?;
+    }
+
+    Ok(count)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -197,6 +254,7 @@ mod tests {
             "0000000000000000000000000000000000000000000000000000000000000000".to_string(),
             None,
             None,
+            None,
         )
         .unwrap();
         let version = version.save(conn, "someone@example.com").unwrap();
@@ -404,4 +462,69 @@ mod tests {
         assert_eq!(versions_changed, Ok(false));
         assert_eq!(crates_changed, Ok(false));
     }
+
+    #[test]
+    fn download_milestones() {
+        use crates_io_worker::schema::background_jobs;
+        use diesel::dsl::*;
+        use diesel::update;
+
+        let (_test_db, conn) = &mut test_db_connection();
+        let user = user(conn);
+        let (krate, version) = crate_and_version(conn, user.id);
+
+        let add_downloads = |conn: &mut PgConnection, downloads: i32, days_ago: i32| {
+            insert_into(version_downloads::table)
+                .values((
+                    version_downloads::version_id.eq(version.id),
+                    version_downloads::downloads.eq(downloads),
+                    version_downloads::date.eq(date(now - days_ago.days())),
+                ))
+                .execute(conn)
+                .unwrap();
+        };
+
+        add_downloads(conn, 600, 2);
+
+        let milestones = |conn: &mut PgConnection| {
+            version_download_milestones::table
+                .select((
+                    version_download_milestones::version_id,
+                    version_download_milestones::milestone,
+                ))
+                .load::<(i32, i64)>(conn)
+                .unwrap()
+        };
+        let jobs = |conn: &mut PgConnection| {
+            background_jobs::table
+                .filter(background_jobs::job_type.eq(NotifyDownloadMilestone::JOB_NAME))
+                .select(background_jobs::data)
+                .load::<serde_json::Value>(conn)
+                .unwrap()
+        };
+
+        // Crates without a webhook URL are not tracked
+        super::update(conn).unwrap();
+        add_downloads(conn, 500, 1);
+        super::update(conn).unwrap();
+        assert_eq!(milestones(conn), vec![]);
+        assert_eq!(jobs(conn), Vec::<serde_json::Value>::new());
+
+        update(crates::table.find(krate.id))
+            .set(crates::download_milestone_webhook_url.eq("https://example.com/hook"))
+            .execute(conn)
+            .unwrap();
+
+        super::update(conn).unwrap();
+        assert_eq!(milestones(conn), vec![(version.id, 1_000)]);
+        assert_eq!(
+            jobs(conn),
+            vec![json!({ "version_id": version.id, "milestone": 1_000 })]
+        );
+
+        // The milestones are only recorded once
+        super::update(conn).unwrap();
+        assert_eq!(milestones(conn), vec![(version.id, 1_000)]);
+        assert_eq!(jobs(conn).len(), 1);
+    }
 }
@@ -15,7 +15,9 @@ mod typosquat;
 
 pub use self::daily_db_maintenance::DailyDbMaintenance;
 pub use self::downloads::{
-    CleanProcessedLogFiles, ProcessCdnLog, ProcessCdnLogQueue, UpdateDownloads,
+    prune_old_downloads, CleanProcessedLogFiles, NotifyDownloadMilestone, ProcessCdnLog,
+    ProcessCdnLogQueue, PruneVersionDownloads, UpdateDownloads, DEFAULT_DOWNLOADS_RETENTION_DAYS,
+    HOURLY_DOWNLOADS_RETENTION_HOURS,
 };
 pub use self::dump_db::DumpDb;
 pub use self::git::{NormalizeIndex, SquashIndex, SyncToGitIndex, SyncToSparseIndex};